@@ -11,7 +11,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
   tonic_build::configure()
     .build_server(true)
     .file_descriptor_set_path(descriptor_path)
-    .compile_protos(&["v1/todos.proto"], &["src/protocols"])?;
+    .compile_protos(
+      &[
+        "v1/todos.proto",
+        "v1/api_keys.proto",
+        "v1/labels.proto",
+        "v1/todo_lists.proto",
+      ],
+      &["src/protocols"],
+    )?;
 
   Ok(())
 }
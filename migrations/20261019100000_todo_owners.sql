@@ -0,0 +1,28 @@
+-- Every todo is owned by the principal that created it. Existing todos are
+-- assigned to a default owner, which can be configured before running this
+-- migration with, for example:
+--
+--   alter database "todos-service" set app.default_owner_id = 'some-user-id';
+--
+-- If the setting is not present, then the owner defaults to 'default'.
+alter table todos
+  add column owner_id text;
+
+-- The backfill should not count as an update to the todos, so we disable the
+-- trigger that maintains updated_at while it runs.
+alter table todos
+  disable trigger update_timestamp;
+
+update todos
+set owner_id = coalesce(
+  nullif(current_setting('app.default_owner_id', true), ''),
+  'default'
+);
+
+alter table todos
+  enable trigger update_timestamp;
+
+alter table todos
+  alter column owner_id set not null;
+
+create index todos_owner_id_created_at_idx on todos (owner_id, created_at desc);
@@ -0,0 +1,60 @@
+-- Todos are partitioned by tenant using row level security, so that a request
+-- can only ever see the rows of the tenant it was made for, regardless of the
+-- SQL that the application runs. The tenant is read from the `app.tenant_id`
+-- setting, which the server sets at the start of each request's transaction.
+
+-- Requests run as this role, which is subject to row level security even when
+-- the server connects as the owner of the table or as a superuser. The role is
+-- shared by every database in the cluster, so it may already exist.
+do
+$$
+begin
+  create role todos_tenant nologin;
+exception
+  when duplicate_object or unique_violation then null;
+end
+$$;
+
+grant todos_tenant to current_user;
+grant select, insert, update, delete on todos to todos_tenant;
+
+-- Existing todos are assigned to a default tenant, which can be configured
+-- before running this migration with, for example:
+--
+--   alter database "todos-service" set app.default_tenant_id = 'some-tenant';
+--
+-- If the setting is not present, then the tenant defaults to 'default'.
+alter table todos
+  add column tenant_id text;
+
+alter table todos
+  disable trigger update_timestamp;
+
+update todos
+set tenant_id = coalesce(
+  nullif(current_setting('app.default_tenant_id', true), ''),
+  'default'
+);
+
+alter table todos
+  enable trigger update_timestamp;
+
+-- New todos are assigned to the current request's tenant by default, so that
+-- the application does not need to provide it explicitly.
+alter table todos
+  alter column tenant_id set not null,
+  alter column tenant_id set default current_setting('app.tenant_id', true);
+
+drop index todos_owner_id_created_at_idx;
+create index todos_tenant_id_owner_id_created_at_idx
+  on todos (tenant_id, owner_id, created_at desc);
+
+alter table todos
+  enable row level security;
+
+alter table todos
+  force row level security;
+
+create policy todos_tenant_isolation on todos
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
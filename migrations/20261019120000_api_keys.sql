@@ -0,0 +1,51 @@
+-- API keys allow other services to call the API on behalf of the user that
+-- created them. Only a hash of each key is stored, so a key cannot be
+-- recovered from the database.
+create table api_keys
+(
+  api_key_id   text primary key     default gen_random_uuid(),
+  tenant_id    text        not null default current_setting('app.tenant_id', true),
+  owner_id     text        not null,
+  display_name text        not null,
+  key_prefix   text        not null,
+  key_hash     text        not null unique,
+  scopes       text[]      not null,
+  created_at   timestamptz not null default now(),
+  updated_at   timestamptz not null default now(),
+  revoked_at   timestamptz
+);
+
+create index api_keys_tenant_id_owner_id_idx on api_keys (tenant_id, owner_id);
+
+create trigger update_timestamp
+  before update
+  on api_keys
+  for each row
+execute procedure trigger_update_timestamp();
+
+grant select, insert, update, delete on api_keys to todos_tenant;
+
+-- Unlike todos, row level security is not forced for the owner of the table,
+-- so that the function below can find a key before the tenant is known.
+alter table api_keys
+  enable row level security;
+
+create policy api_keys_tenant_isolation on api_keys
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
+
+-- Find an active API key by the hash of the key. This runs as the owner of the
+-- table, so it is not subject to the tenant isolation policy.
+create function find_api_key(key_hash text)
+  returns setof api_keys
+  language sql
+  stable
+  security definer
+  set search_path = public
+as
+$$
+select *
+from api_keys
+where api_keys.key_hash = find_api_key.key_hash
+  and api_keys.revoked_at is null;
+$$;
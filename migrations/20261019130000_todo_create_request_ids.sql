@@ -0,0 +1,8 @@
+-- A create request can carry a request ID, which is stored with the todo that
+-- it created. A retry of the same request then finds that todo and returns it,
+-- rather than failing or creating it twice. See https://google.aip.dev/155.
+alter table todos
+  add column create_request_id text;
+
+create unique index todos_tenant_id_owner_id_create_request_id_idx
+  on todos (tenant_id, owner_id, create_request_id);
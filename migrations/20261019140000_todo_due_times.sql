@@ -0,0 +1,11 @@
+-- Todos can have a time that they are due by, and a time that their owner
+-- wants to be reminded about them. Both are optional.
+alter table todos
+  add column due_time timestamptz,
+  add column reminder_time timestamptz;
+
+create index todos_tenant_id_owner_id_due_time_idx
+  on todos (tenant_id, owner_id, due_time);
+
+create index todos_tenant_id_owner_id_reminder_time_idx
+  on todos (tenant_id, owner_id, reminder_time);
@@ -0,0 +1,102 @@
+-- The reminder scheduler sends a todo's reminder once its `remind_at` time has
+-- passed. This is copied from `reminder_time` whenever the owner sets the
+-- reminder, and is then managed by the scheduler, which moves it forward while
+-- the reminder is being sent or waiting to be retried, and clears it once the
+-- reminder has been sent or abandoned.
+alter table todos
+  add column remind_at timestamptz;
+
+create index todos_remind_at_idx on todos (remind_at)
+  where remind_at is not null;
+
+create function trigger_schedule_reminder()
+  returns trigger as
+$$
+begin
+  if tg_op = 'INSERT' or new.reminder_time is distinct from old.reminder_time then
+    new.remind_at = new.reminder_time;
+  end if;
+  return new;
+end;
+$$ language plpgsql;
+
+create trigger schedule_reminder
+  before insert or update of reminder_time
+  on todos
+  for each row
+execute procedure trigger_schedule_reminder();
+
+-- The scheduler's changes to `remind_at` are not changes to the todo, so they
+-- should not change `updated_at`.
+create or replace function trigger_update_timestamp()
+  returns trigger as
+$$
+begin
+  if to_jsonb(new) - 'remind_at' = to_jsonb(old) - 'remind_at'
+    and to_jsonb(new) -> 'remind_at' is distinct from to_jsonb(old) -> 'remind_at' then
+    return new;
+  end if;
+  new.updated_at = now();
+  return new;
+end;
+$$ language plpgsql;
+
+-- Only reminders in the future are scheduled, so that deploying this migration
+-- does not send reminders that are long overdue.
+alter table todos
+  disable trigger update_timestamp;
+
+update todos
+set remind_at = reminder_time
+where reminder_time > now();
+
+alter table todos
+  enable trigger update_timestamp;
+
+-- The scheduler runs as this role, which can see the todos of every tenant,
+-- but can only change when their reminders are sent. The role is shared by
+-- every database in the cluster, so it may already exist.
+do
+$$
+begin
+  create role todos_scheduler nologin;
+exception
+  when duplicate_object or unique_violation then null;
+end
+$$;
+
+grant todos_scheduler to current_user;
+grant select, update (remind_at) on todos to todos_scheduler;
+
+create policy todos_scheduler_access on todos
+  to todos_scheduler
+  using (true);
+
+-- Every attempt to send a reminder is recorded, with the error if it failed.
+create table reminder_deliveries
+(
+  delivery_id   uuid primary key     default gen_random_uuid(),
+  tenant_id     text        not null,
+  todo_id       text        not null references todos (todo_id) on delete cascade,
+  reminder_time timestamptz not null,
+  attempt       integer     not null,
+  notifier      text        not null,
+  error         text,
+  attempted_at  timestamptz not null default now()
+);
+
+create index reminder_deliveries_todo_id_reminder_time_idx
+  on reminder_deliveries (todo_id, reminder_time);
+
+grant select, insert on reminder_deliveries to todos_scheduler;
+
+alter table reminder_deliveries
+  enable row level security;
+
+alter table reminder_deliveries
+  force row level security;
+
+create policy reminder_deliveries_scheduler_access on reminder_deliveries
+  to todos_scheduler
+  using (true)
+  with check (true);
@@ -0,0 +1,48 @@
+-- A recurring todo is an occurrence of a series, which holds the recurrence
+-- rule and the fields that each new occurrence is created with. Editing a
+-- single occurrence only changes its todo, while editing all future
+-- occurrences also changes the series.
+create table todo_series
+(
+  series_id       text primary key     default gen_random_uuid(),
+  tenant_id       text        not null default current_setting('app.tenant_id', true),
+  owner_id        text        not null,
+  title           text        not null,
+  description     text        not null,
+  recurrence_rule text        not null,
+  start_time      timestamptz not null,
+  reminder_offset interval,
+  created_at      timestamptz not null default now(),
+  updated_at      timestamptz not null default now()
+);
+
+create trigger update_timestamp
+  before update
+  on todo_series
+  for each row
+execute procedure trigger_update_timestamp();
+
+grant select, insert, update on todo_series to todos_tenant;
+
+alter table todo_series
+  enable row level security;
+
+alter table todo_series
+  force row level security;
+
+create policy todo_series_tenant_isolation on todo_series
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
+
+-- Each todo keeps a copy of its series' rule, so that it can be read without
+-- a join, and the time the rule scheduled it for, which the next occurrence is
+-- counted from even if this occurrence was moved.
+alter table todos
+  add column recurrence_rule text not null default '',
+  add column series_id       text references todo_series (series_id),
+  add column occurrence_time timestamptz;
+
+-- A series has at most one todo for each occurrence, so that completing an
+-- occurrence twice does not create the next one twice.
+create unique index todos_series_id_occurrence_time_idx
+  on todos (series_id, occurrence_time);
@@ -0,0 +1,142 @@
+-- Todos have a priority, which is one of the values of the `Priority` enum in
+-- the API, where 0 is unspecified. Each occurrence of a series is created with
+-- the series' priority.
+alter table todos
+  add column priority smallint not null default 0;
+
+alter table todo_series
+  add column priority smallint not null default 0;
+
+-- Todos are ordered by a position key, which is compared byte by byte. See
+-- `src/lib/position.rs` for the format of the keys. The server generates the
+-- key when a todo is moved, and these functions generate the key that follows
+-- the last todo when one is created.
+alter table todos
+  add column position text collate "C";
+
+-- Get a fraction that sorts after the given one.
+create function todo_fraction_after(fraction text)
+  returns text as
+$$
+declare
+  digits constant text := '0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz';
+  digit integer := case when fraction = '' then 0 else strpos(digits, left(fraction, 1)) - 1 end;
+begin
+  if digit < 61 then
+    return substr(digits, (digit + 63) / 2 + 1, 1);
+  end if;
+  return 'z' || todo_fraction_after(substr(fraction, 2));
+end;
+$$ language plpgsql immutable;
+
+-- Get a key that sorts after the given one, by incrementing its integer part,
+-- or the first key if it is null.
+create function todo_position_after(previous text)
+  returns text as
+$$
+declare
+  digits constant text := '0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz';
+  head text;
+  integer_part text;
+  incremented text := '';
+  carry boolean := true;
+  digit integer;
+begin
+  if previous is null then
+    return 'a0';
+  end if;
+
+  -- The first character gives the length of the integer part.
+  head := left(previous, 1);
+  integer_part := left(previous, case
+    when ascii(head) >= ascii('a') then ascii(head) - ascii('a') + 2
+    else ascii('Z') - ascii(head) + 2
+  end);
+
+  for i in reverse length(integer_part)..2 loop
+    digit := strpos(digits, substr(integer_part, i, 1));
+    if carry and digit = 62 then
+      incremented := '0' || incremented;
+    elsif carry then
+      incremented := substr(digits, digit + 1, 1) || incremented;
+      carry := false;
+    else
+      incremented := substr(integer_part, i, 1) || incremented;
+    end if;
+  end loop;
+
+  if not carry then
+    return head || incremented;
+  elsif head = 'Z' then
+    return 'a0';
+  elsif head = 'z' then
+    return integer_part || todo_fraction_after(substr(previous, length(integer_part) + 1));
+  elsif ascii(head) >= ascii('a') then
+    return chr(ascii(head) + 1) || incremented || '0';
+  else
+    return chr(ascii(head) + 1) || left(incremented, -1);
+  end if;
+end;
+$$ language plpgsql immutable;
+
+-- New todos are added to the end of their owner's list. The owner's todos are
+-- locked with the same advisory lock as the server uses when it creates or
+-- moves a todo, so that concurrent requests do not generate the same key.
+create function trigger_set_position()
+  returns trigger as
+$$
+begin
+  if new.position is null then
+    perform pg_advisory_xact_lock(hashtextextended('todos:' || new.tenant_id || '/' || new.owner_id, 0));
+    new.position = todo_position_after((
+      select max(position)
+      from todos
+      where tenant_id = new.tenant_id
+        and owner_id = new.owner_id
+    ));
+  end if;
+  return new;
+end;
+$$ language plpgsql;
+
+create trigger set_position
+  before insert
+  on todos
+  for each row
+execute procedure trigger_set_position();
+
+-- Existing todos keep the order that they were listed in by default, which is
+-- the most recently created first.
+alter table todos
+  disable trigger update_timestamp;
+
+do
+$$
+declare
+  todo record;
+  previous_owner text;
+  previous_position text;
+begin
+  for todo in
+    select todo_id, tenant_id || '/' || owner_id as owner
+    from todos
+    order by tenant_id, owner_id, created_at desc, todo_id
+  loop
+    if todo.owner is distinct from previous_owner then
+      previous_owner := todo.owner;
+      previous_position := null;
+    end if;
+    previous_position := todo_position_after(previous_position);
+    update todos set position = previous_position where todo_id = todo.todo_id;
+  end loop;
+end
+$$;
+
+alter table todos
+  enable trigger update_timestamp;
+
+alter table todos
+  alter column position set not null;
+
+create unique index todos_tenant_id_owner_id_position_idx
+  on todos (tenant_id, owner_id, position);
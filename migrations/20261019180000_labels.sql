@@ -0,0 +1,60 @@
+-- Labels are named by their owner, who can attach any number of them to each
+-- of their todos. A label is identified by its name, which todos refer to, so
+-- renaming a label cascades to the todos that have it.
+create table labels
+(
+  tenant_id  text        not null default current_setting('app.tenant_id', true),
+  owner_id   text        not null,
+  name       text        not null,
+  created_at timestamptz not null default now(),
+  updated_at timestamptz not null default now(),
+  primary key (tenant_id, owner_id, name)
+);
+
+create trigger update_timestamp
+  before update
+  on labels
+  for each row
+execute procedure trigger_update_timestamp();
+
+grant select, insert, update, delete on labels to todos_tenant;
+
+alter table labels
+  enable row level security;
+
+alter table labels
+  force row level security;
+
+create policy labels_tenant_isolation on labels
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
+
+-- The labels of each todo. Deleting either the todo or the label removes the
+-- label from the todo.
+create table todo_labels
+(
+  todo_id   text not null references todos (todo_id) on delete cascade,
+  tenant_id text not null default current_setting('app.tenant_id', true),
+  owner_id  text not null,
+  name      text not null,
+  primary key (todo_id, name),
+  foreign key (tenant_id, owner_id, name)
+    references labels (tenant_id, owner_id, name)
+    on update cascade
+    on delete cascade
+);
+
+create index todo_labels_tenant_id_owner_id_name_idx
+  on todo_labels (tenant_id, owner_id, name);
+
+grant select, insert, delete on todo_labels to todos_tenant;
+
+alter table todo_labels
+  enable row level security;
+
+alter table todo_labels
+  force row level security;
+
+create policy todo_labels_tenant_isolation on todo_labels
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
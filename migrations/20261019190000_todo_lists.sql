@@ -0,0 +1,70 @@
+-- Todos belong to lists, which their owner creates to group them. A list is
+-- identified by an ID that is unique among its owner's lists, so that todos
+-- can be addressed as `lists/{list_id}/todos/{todo_id}`.
+create table todo_lists
+(
+  tenant_id    text        not null default current_setting('app.tenant_id', true),
+  owner_id     text        not null,
+  list_id      text        not null,
+  display_name text        not null,
+  description  text        not null default '',
+  archived     boolean     not null default false,
+  created_at   timestamptz not null default now(),
+  updated_at   timestamptz not null default now(),
+  primary key (tenant_id, owner_id, list_id)
+);
+
+create trigger update_timestamp
+  before update
+  on todo_lists
+  for each row
+execute procedure trigger_update_timestamp();
+
+grant select, insert, update, delete on todo_lists to todos_tenant;
+
+alter table todo_lists
+  enable row level security;
+
+alter table todo_lists
+  force row level security;
+
+create policy todo_lists_tenant_isolation on todo_lists
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
+
+-- Todos that are created without a list are added to their owner's inbox,
+-- which is created with the first such todo.
+create function trigger_create_default_list()
+  returns trigger as
+$$
+begin
+  if new.list_id = 'inbox' then
+    insert into todo_lists (tenant_id, owner_id, list_id, display_name)
+    values (new.tenant_id, new.owner_id, 'inbox', 'Inbox')
+    on conflict do nothing;
+  end if;
+  return new;
+end;
+$$ language plpgsql;
+
+create trigger create_default_list
+  before insert
+  on todos
+  for each row
+execute procedure trigger_create_default_list();
+
+-- Existing todos are moved to their owner's inbox.
+insert into todo_lists (tenant_id, owner_id, list_id, display_name)
+select distinct tenant_id, owner_id, 'inbox', 'Inbox'
+from todos;
+
+alter table todos
+  add column list_id text not null default 'inbox';
+
+-- A list cannot be deleted while it has todos, unless they are deleted first.
+alter table todos
+  add foreign key (tenant_id, owner_id, list_id)
+    references todo_lists (tenant_id, owner_id, list_id);
+
+create index todos_tenant_id_owner_id_list_id_idx
+  on todos (tenant_id, owner_id, list_id);
@@ -0,0 +1,18 @@
+-- Todos can be subtasks of another todo of the same owner, in the same list.
+-- The foreign key includes the owner and list, so the database enforces that,
+-- and deleting a todo deletes its subtasks.
+alter table todos
+  add constraint todos_tenant_id_owner_id_list_id_todo_id_key
+    unique (tenant_id, owner_id, list_id, todo_id);
+
+alter table todos
+  add column parent_todo_id text;
+
+alter table todos
+  add constraint todos_parent_todo_id_fkey
+    foreign key (tenant_id, owner_id, list_id, parent_todo_id)
+      references todos (tenant_id, owner_id, list_id, todo_id)
+      on delete cascade;
+
+create index todos_parent_todo_id_idx
+  on todos (parent_todo_id);
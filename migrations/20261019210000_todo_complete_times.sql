@@ -0,0 +1,38 @@
+-- The time that a todo was completed, which is null while it is open. The
+-- trigger sets it whenever `completed` changes, however the todo is completed,
+-- so that completing a todo that is already completed keeps the first time.
+alter table todos
+  add column complete_time timestamptz;
+
+create function trigger_set_complete_time()
+  returns trigger as
+$$
+begin
+  if tg_op = 'INSERT' or new.completed is distinct from old.completed then
+    new.complete_time = case when new.completed then now() end;
+  end if;
+  return new;
+end;
+$$ language plpgsql;
+
+create trigger set_complete_time
+  before insert or update of completed
+  on todos
+  for each row
+execute procedure trigger_set_complete_time();
+
+-- We do not know when the todos that are already completed were completed, so
+-- we use the last time that they were updated.
+alter table todos
+  disable trigger update_timestamp;
+
+update todos
+set complete_time = updated_at
+where completed;
+
+alter table todos
+  enable trigger update_timestamp;
+
+create index todos_owner_id_complete_time_idx
+  on todos (owner_id, complete_time)
+  where complete_time is not null;
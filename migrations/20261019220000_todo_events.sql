@@ -0,0 +1,224 @@
+-- Every change to a todo is recorded in its history, with who made it and the
+-- todo's fields before and after the change. The events are written by
+-- triggers in the same transaction as the change, so that changes made by the
+-- database, such as deleting subtasks, are recorded too.
+create table todo_events
+(
+  event_id       bigint generated always as identity primary key,
+  tenant_id      text        not null default current_setting('app.tenant_id', true),
+  owner_id       text        not null,
+  todo_id        text        not null,
+  -- The user that made the change, and whether they used an API key. The
+  -- server sets these at the start of each request's transaction, and changes
+  -- made without them are attributed to the owner of the todo.
+  actor_id       text        not null,
+  api_key        boolean     not null default false,
+  action         text        not null check (action in ('create', 'update', 'delete')),
+  changed_fields text[]      not null,
+  -- The fields of the todo before and after the change, which are null
+  -- before it was created and after it was deleted.
+  before         jsonb,
+  after          jsonb,
+  event_time     timestamptz not null default now(),
+  -- Changes to the same todo in one transaction are merged into one event.
+  transaction_id xid8        not null default pg_current_xact_id()
+);
+
+create index todo_events_tenant_id_owner_id_todo_id_event_id_idx
+  on todo_events (tenant_id, owner_id, todo_id, event_id desc);
+
+create index todo_events_transaction_id_todo_id_idx
+  on todo_events (transaction_id, todo_id);
+
+grant select, insert, update, delete on todo_events to todos_tenant;
+
+alter table todo_events
+  enable row level security;
+
+alter table todo_events
+  force row level security;
+
+create policy todo_events_tenant_isolation on todo_events
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
+
+-- The fields of a todo that its history shows, which are those of the API
+-- apart from the ones that the server maintains on every change.
+create function todo_snapshot(todo todos, labels jsonb)
+  returns jsonb as
+$$
+select jsonb_build_object(
+  'title', todo.title,
+  'description', todo.description,
+  'completed', todo.completed,
+  'due_time', todo.due_time,
+  'reminder_time', todo.reminder_time,
+  'recurrence_rule', todo.recurrence_rule,
+  'series_id', todo.series_id,
+  'priority', todo.priority,
+  'position', todo.position,
+  'list_id', todo.list_id,
+  'parent_todo_id', todo.parent_todo_id,
+  'complete_time', todo.complete_time,
+  'labels', labels
+);
+$$ language sql immutable;
+
+-- The names of the labels of a todo, in alphabetical order.
+create function todo_label_names(id text)
+  returns jsonb as
+$$
+select coalesce(jsonb_agg(name order by name), '[]')
+from todo_labels
+where todo_id = id;
+$$ language sql stable;
+
+-- Record a change to a todo, merging it with any earlier change to the todo in
+-- the same transaction. The event is removed if the changes cancel out. The
+-- event takes its tenant from the todo, as the database itself can change
+-- todos outside of a tenant's transaction.
+create function record_todo_event(
+  tenant text,
+  id text,
+  owner text,
+  old_snapshot jsonb,
+  new_snapshot jsonb
+)
+  returns void as
+$$
+declare
+  existing     todo_events;
+  first_fields jsonb;
+  event_action text;
+  changed      text[];
+begin
+  select *
+  into existing
+  from todo_events
+  where transaction_id = pg_current_xact_id()
+    and tenant_id = tenant
+    and todo_id = id
+  for update;
+
+  first_fields = case when existing.event_id is null then old_snapshot else existing.before end;
+  event_action = case
+    when first_fields is null then 'create'
+    when new_snapshot is null then 'delete'
+    else 'update'
+  end;
+
+  select coalesce(array_agg(key order by key), '{}')
+  into changed
+  from jsonb_object_keys(coalesce(first_fields, '{}') || coalesce(new_snapshot, '{}')) key
+  where coalesce(first_fields -> key, 'null') is distinct from coalesce(new_snapshot -> key, 'null');
+
+  if cardinality(changed) = 0 or (first_fields is null and new_snapshot is null) then
+    delete from todo_events where event_id = existing.event_id;
+  elsif existing.event_id is null then
+    insert into todo_events (tenant_id, owner_id, todo_id, actor_id, api_key, action, changed_fields, before, after)
+    values (
+      tenant,
+      owner,
+      id,
+      coalesce(nullif(current_setting('app.actor_id', true), ''), owner),
+      coalesce(nullif(current_setting('app.actor_api_key', true), ''), 'false')::boolean,
+      event_action,
+      changed,
+      first_fields,
+      new_snapshot
+    );
+  else
+    update todo_events
+    set action = event_action,
+        changed_fields = changed,
+        after = new_snapshot
+    where event_id = existing.event_id;
+  end if;
+end;
+$$ language plpgsql;
+
+-- Todos are recorded after they are written, so that the snapshot includes the
+-- changes of other triggers, but before they are deleted, while their labels
+-- still exist.
+create function trigger_record_todo_event()
+  returns trigger as
+$$
+begin
+  if tg_op = 'INSERT' then
+    perform record_todo_event(new.tenant_id, new.todo_id, new.owner_id, null, todo_snapshot(new, todo_label_names(new.todo_id)));
+  elsif tg_op = 'UPDATE' then
+    perform record_todo_event(
+      new.tenant_id,
+      new.todo_id,
+      new.owner_id,
+      todo_snapshot(old, todo_label_names(old.todo_id)),
+      todo_snapshot(new, todo_label_names(new.todo_id))
+    );
+  else
+    perform record_todo_event(old.tenant_id, old.todo_id, old.owner_id, todo_snapshot(old, todo_label_names(old.todo_id)), null);
+    return old;
+  end if;
+  return new;
+end;
+$$ language plpgsql;
+
+create trigger record_todo_event
+  after insert or update of title, description, completed, due_time, reminder_time, recurrence_rule, series_id,
+    priority, position, list_id, parent_todo_id
+  on todos
+  for each row
+execute procedure trigger_record_todo_event();
+
+create trigger record_deleted_todo_event
+  before delete
+  on todos
+  for each row
+execute procedure trigger_record_todo_event();
+
+-- A change to the labels of a todo is recorded as a change to the todo. The
+-- labels before the change are the current labels without the one that was
+-- added, and with the one that was removed.
+create function trigger_record_todo_label_event()
+  returns trigger as
+$$
+declare
+  id     text = case when tg_op = 'DELETE' then old.todo_id else new.todo_id end;
+  todo   todos;
+  labels jsonb;
+begin
+  select * into todo from todos where todo_id = id;
+  -- The labels of a deleted todo are deleted with it, which is not a change.
+  if todo.todo_id is null then
+    return null;
+  end if;
+
+  labels = todo_label_names(id);
+  perform record_todo_event(
+    todo.tenant_id,
+    id,
+    todo.owner_id,
+    todo_snapshot(
+      todo,
+      (
+        select coalesce(jsonb_agg(label order by label), '[]')
+        from (
+          select label
+          from jsonb_array_elements_text(labels) label
+          where tg_op = 'DELETE' or label <> new.name
+          union
+          select old.name
+          where tg_op <> 'INSERT'
+        ) old_labels
+      )
+    ),
+    todo_snapshot(todo, labels)
+  );
+  return null;
+end;
+$$ language plpgsql;
+
+create trigger record_todo_label_event
+  after insert or update or delete
+  on todo_labels
+  for each row
+execute procedure trigger_record_todo_label_event();
@@ -0,0 +1,31 @@
+-- Todos are searched by their title and description, with matches in the
+-- title ranked above matches in the description. Each todo is indexed in the
+-- text search language that the server was configured with when the todo was
+-- created, so that changing the language does not change how existing todos
+-- are stemmed until they are reindexed by setting their `search_language`.
+alter table todos
+  add column search_language regconfig not null default 'english',
+  add column search_vector   tsvector generated always as (
+    setweight(to_tsvector(search_language, title), 'A') ||
+    setweight(to_tsvector(search_language, description), 'B')
+  ) stored;
+
+create index todos_search_vector_idx
+  on todos using gin (search_vector);
+
+-- Generated columns are not computed until after the `before` triggers have
+-- run, so `search_vector` is always null in `new` and must be left out when
+-- checking whether only `remind_at` changed.
+create or replace function trigger_update_timestamp()
+  returns trigger as
+$$
+begin
+  if to_jsonb(new) - 'remind_at' - 'search_vector'
+      = to_jsonb(old) - 'remind_at' - 'search_vector'
+    and to_jsonb(new) -> 'remind_at' is distinct from to_jsonb(old) -> 'remind_at' then
+    return new;
+  end if;
+  new.updated_at = now();
+  return new;
+end;
+$$ language plpgsql;
@@ -0,0 +1,78 @@
+-- Comments hold the discussion of a todo. Each comment is written by the user
+-- that was authenticated when it was created, and is deleted with its todo.
+create table todo_comments
+(
+  comment_id bigint      generated always as identity primary key,
+  todo_id    text        not null references todos (todo_id) on delete cascade,
+  tenant_id  text        not null default current_setting('app.tenant_id', true),
+  author_id  text        not null,
+  body       text        not null,
+  created_at timestamptz not null default now(),
+  updated_at timestamptz not null default now()
+);
+
+create index todo_comments_todo_id_comment_id_idx
+  on todo_comments (todo_id, comment_id);
+
+create trigger update_timestamp
+  before update
+  on todo_comments
+  for each row
+execute procedure trigger_update_timestamp();
+
+grant select, insert, update, delete on todo_comments to todos_tenant;
+
+alter table todo_comments
+  enable row level security;
+
+alter table todo_comments
+  force row level security;
+
+create policy todo_comments_tenant_isolation on todo_comments
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
+
+-- The earlier versions of each comment's body. Each edit records the body that
+-- it replaced and the time that body was written. The edits are written by a
+-- trigger in the same transaction as the change to the comment.
+create table todo_comment_edits
+(
+  edit_id    bigint      generated always as identity primary key,
+  comment_id bigint      not null references todo_comments (comment_id) on delete cascade,
+  tenant_id  text        not null default current_setting('app.tenant_id', true),
+  body       text        not null,
+  created_at timestamptz not null,
+  edit_time  timestamptz not null default now()
+);
+
+create index todo_comment_edits_comment_id_edit_id_idx
+  on todo_comment_edits (comment_id, edit_id);
+
+grant select, insert, delete on todo_comment_edits to todos_tenant;
+
+alter table todo_comment_edits
+  enable row level security;
+
+alter table todo_comment_edits
+  force row level security;
+
+create policy todo_comment_edits_tenant_isolation on todo_comment_edits
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
+
+create function trigger_record_comment_edit()
+  returns trigger as
+$$
+begin
+  insert into todo_comment_edits (comment_id, tenant_id, body, created_at)
+  values (old.comment_id, old.tenant_id, old.body, old.updated_at);
+  return new;
+end;
+$$ language plpgsql;
+
+create trigger record_comment_edit
+  after update of body
+  on todo_comments
+  for each row
+  when (old.body is distinct from new.body)
+execute procedure trigger_record_comment_edit();
@@ -0,0 +1,81 @@
+-- Attachments are files attached to a todo, such as screenshots and PDFs. The
+-- metadata of each attachment is kept here, and its content is kept in the
+-- blob store under the attachment's ID. Attachments are deleted with their
+-- todo.
+create table todo_attachments
+(
+  attachment_id uuid        primary key,
+  todo_id       text        not null references todos (todo_id) on delete cascade,
+  tenant_id     text        not null default current_setting('app.tenant_id', true),
+  uploader_id   text        not null,
+  filename      text        not null,
+  content_type  text        not null,
+  size_bytes    bigint      not null check (size_bytes >= 0),
+  sha256        text        not null,
+  created_at    timestamptz not null default now()
+);
+
+create index todo_attachments_todo_id_created_at_idx
+  on todo_attachments (todo_id, created_at);
+
+grant select, insert, delete on todo_attachments to todos_tenant;
+
+alter table todo_attachments
+  enable row level security;
+
+alter table todo_attachments
+  force row level security;
+
+create policy todo_attachments_tenant_isolation on todo_attachments
+  using (tenant_id = current_setting('app.tenant_id', true))
+  with check (tenant_id = current_setting('app.tenant_id', true));
+
+-- The blobs of deleted attachments, which the server removes from the blob
+-- store in the background. A trigger queues the blob in the same transaction
+-- as the deletion, so that blobs are also removed when their todo is deleted,
+-- and are not removed if the deletion rolls back.
+create table deleted_attachment_blobs
+(
+  attachment_id uuid        primary key,
+  tenant_id     text        not null default current_setting('app.tenant_id', true),
+  deleted_at    timestamptz not null default now()
+);
+
+create index deleted_attachment_blobs_deleted_at_idx
+  on deleted_attachment_blobs (deleted_at);
+
+grant insert on deleted_attachment_blobs to todos_tenant;
+-- Locking the queued blobs with `for update` needs the update privilege.
+grant select, update (deleted_at), delete on deleted_attachment_blobs to todos_scheduler;
+
+alter table deleted_attachment_blobs
+  enable row level security;
+
+alter table deleted_attachment_blobs
+  force row level security;
+
+create policy deleted_attachment_blobs_tenant_isolation on deleted_attachment_blobs
+  to todos_tenant
+  with check (tenant_id = current_setting('app.tenant_id', true));
+
+-- The blob sweeper runs as the scheduler, and removes the blobs of every
+-- tenant.
+create policy deleted_attachment_blobs_scheduler_access on deleted_attachment_blobs
+  to todos_scheduler
+  using (true);
+
+create function trigger_queue_attachment_blob()
+  returns trigger as
+$$
+begin
+  insert into deleted_attachment_blobs (attachment_id, tenant_id)
+  values (old.attachment_id, old.tenant_id);
+  return old;
+end;
+$$ language plpgsql;
+
+create trigger queue_attachment_blob
+  after delete
+  on todo_attachments
+  for each row
+execute procedure trigger_queue_attachment_blob();
@@ -0,0 +1,11 @@
+-- Changes to `complete_time` are recorded in the history even when `completed`
+-- does not change, such as when a completed todo is restored to a revision
+-- that was completed at another time.
+drop trigger record_todo_event on todos;
+
+create trigger record_todo_event
+  after insert or update of title, description, completed, due_time, reminder_time, recurrence_rule, series_id,
+    priority, position, list_id, parent_todo_id, complete_time
+  on todos
+  for each row
+execute procedure trigger_record_todo_event();
@@ -0,0 +1,36 @@
+-- Todos as the API returns them, with the names of their labels and the number
+-- of their comments. Every query that returns todos selects from this view, so
+-- that the columns are only listed once. The view is run as the caller, so the
+-- row level security of the tables still applies.
+create view todos_view with (security_invoker = true) as
+select todo_id,
+       title,
+       description,
+       completed,
+       created_at,
+       updated_at,
+       owner_id,
+       tenant_id,
+       due_time,
+       reminder_time,
+       recurrence_rule,
+       series_id,
+       priority,
+       position,
+       list_id,
+       parent_todo_id,
+       complete_time,
+       array(
+         select name
+         from todo_labels
+         where todo_labels.todo_id = todos.todo_id
+         order by name
+       ) as labels,
+       (
+         select count(*)::integer
+         from todo_comments
+         where todo_comments.todo_id = todos.todo_id
+       ) as comment_count
+from todos;
+
+grant select on todos_view to todos_tenant;
@@ -0,0 +1,290 @@
+-- Todos are identified by their ID among their owner's todos, rather than
+-- among every todo in the database. Callers choose the IDs of their todos, so
+-- an ID that is already used by another owner or tenant must neither fail nor
+-- reveal that the other todo exists. The tables that refer to todos do so by
+-- the same key, as `todo_labels` already does for labels.
+alter table todo_labels
+  drop constraint todo_labels_todo_id_fkey;
+
+alter table todo_comments
+  drop constraint todo_comments_todo_id_fkey;
+
+alter table todo_attachments
+  drop constraint todo_attachments_todo_id_fkey;
+
+alter table reminder_deliveries
+  drop constraint reminder_deliveries_todo_id_fkey;
+
+alter table todos
+  drop constraint todos_pkey;
+
+alter table todos
+  add primary key (tenant_id, owner_id, todo_id);
+
+-- Comments, attachments and reminder deliveries are given the owner of their
+-- todo, which is still unique by its ID while they are backfilled.
+alter table todo_comments
+  add column owner_id text;
+
+alter table todo_comments
+  disable trigger update_timestamp;
+
+update todo_comments
+set owner_id = todos.owner_id
+from todos
+where todos.todo_id = todo_comments.todo_id;
+
+alter table todo_comments
+  enable trigger update_timestamp;
+
+alter table todo_comments
+  alter column owner_id set not null;
+
+alter table todo_attachments
+  add column owner_id text;
+
+update todo_attachments
+set owner_id = todos.owner_id
+from todos
+where todos.todo_id = todo_attachments.todo_id;
+
+alter table todo_attachments
+  alter column owner_id set not null;
+
+alter table reminder_deliveries
+  add column owner_id text;
+
+update reminder_deliveries
+set owner_id = todos.owner_id
+from todos
+where todos.todo_id = reminder_deliveries.todo_id;
+
+alter table reminder_deliveries
+  alter column owner_id set not null;
+
+alter table todo_labels
+  drop constraint todo_labels_pkey;
+
+alter table todo_labels
+  add primary key (tenant_id, owner_id, todo_id, name),
+  add foreign key (tenant_id, owner_id, todo_id)
+    references todos (tenant_id, owner_id, todo_id)
+    on delete cascade;
+
+alter table todo_comments
+  add foreign key (tenant_id, owner_id, todo_id)
+    references todos (tenant_id, owner_id, todo_id)
+    on delete cascade;
+
+drop index todo_comments_todo_id_comment_id_idx;
+create index todo_comments_tenant_id_owner_id_todo_id_comment_id_idx
+  on todo_comments (tenant_id, owner_id, todo_id, comment_id);
+
+alter table todo_attachments
+  add foreign key (tenant_id, owner_id, todo_id)
+    references todos (tenant_id, owner_id, todo_id)
+    on delete cascade;
+
+drop index todo_attachments_todo_id_created_at_idx;
+create index todo_attachments_tenant_id_owner_id_todo_id_created_at_idx
+  on todo_attachments (tenant_id, owner_id, todo_id, created_at);
+
+alter table reminder_deliveries
+  add foreign key (tenant_id, owner_id, todo_id)
+    references todos (tenant_id, owner_id, todo_id)
+    on delete cascade;
+
+drop index reminder_deliveries_todo_id_reminder_time_idx;
+create index reminder_deliveries_todo_key_reminder_time_idx
+  on reminder_deliveries (tenant_id, owner_id, todo_id, reminder_time);
+
+drop index todos_parent_todo_id_idx;
+create index todos_tenant_id_owner_id_parent_todo_id_idx
+  on todos (tenant_id, owner_id, parent_todo_id);
+
+-- The history of a todo is found by the same key.
+create function todo_label_names(tenant text, owner text, id text)
+  returns jsonb as
+$$
+select coalesce(jsonb_agg(name order by name), '[]')
+from todo_labels
+where tenant_id = tenant
+  and owner_id = owner
+  and todo_id = id;
+$$ language sql stable;
+
+create or replace function record_todo_event(
+  tenant text,
+  id text,
+  owner text,
+  old_snapshot jsonb,
+  new_snapshot jsonb
+)
+  returns void as
+$$
+declare
+  existing     todo_events;
+  first_fields jsonb;
+  event_action text;
+  changed      text[];
+begin
+  select *
+  into existing
+  from todo_events
+  where transaction_id = pg_current_xact_id()
+    and tenant_id = tenant
+    and owner_id = owner
+    and todo_id = id
+  for update;
+
+  first_fields = case when existing.event_id is null then old_snapshot else existing.before end;
+  event_action = case
+    when first_fields is null then 'create'
+    when new_snapshot is null then 'delete'
+    else 'update'
+  end;
+
+  select coalesce(array_agg(key order by key), '{}')
+  into changed
+  from jsonb_object_keys(coalesce(first_fields, '{}') || coalesce(new_snapshot, '{}')) key
+  where coalesce(first_fields -> key, 'null') is distinct from coalesce(new_snapshot -> key, 'null');
+
+  if cardinality(changed) = 0 or (first_fields is null and new_snapshot is null) then
+    delete from todo_events where event_id = existing.event_id;
+  elsif existing.event_id is null then
+    insert into todo_events (tenant_id, owner_id, todo_id, actor_id, api_key, action, changed_fields, before, after)
+    values (
+      tenant,
+      owner,
+      id,
+      coalesce(nullif(current_setting('app.actor_id', true), ''), owner),
+      coalesce(nullif(current_setting('app.actor_api_key', true), ''), 'false')::boolean,
+      event_action,
+      changed,
+      first_fields,
+      new_snapshot
+    );
+  else
+    update todo_events
+    set action = event_action,
+        changed_fields = changed,
+        after = new_snapshot
+    where event_id = existing.event_id;
+  end if;
+end;
+$$ language plpgsql;
+
+create or replace function trigger_record_todo_event()
+  returns trigger as
+$$
+begin
+  if tg_op = 'INSERT' then
+    perform record_todo_event(
+      new.tenant_id,
+      new.todo_id,
+      new.owner_id,
+      null,
+      todo_snapshot(new, todo_label_names(new.tenant_id, new.owner_id, new.todo_id))
+    );
+  elsif tg_op = 'UPDATE' then
+    perform record_todo_event(
+      new.tenant_id,
+      new.todo_id,
+      new.owner_id,
+      todo_snapshot(old, todo_label_names(old.tenant_id, old.owner_id, old.todo_id)),
+      todo_snapshot(new, todo_label_names(new.tenant_id, new.owner_id, new.todo_id))
+    );
+  else
+    perform record_todo_event(
+      old.tenant_id,
+      old.todo_id,
+      old.owner_id,
+      todo_snapshot(old, todo_label_names(old.tenant_id, old.owner_id, old.todo_id)),
+      null
+    );
+    return old;
+  end if;
+  return new;
+end;
+$$ language plpgsql;
+
+create or replace function trigger_record_todo_label_event()
+  returns trigger as
+$$
+declare
+  label  todo_labels = case when tg_op = 'DELETE' then old else new end;
+  todo   todos;
+  labels jsonb;
+begin
+  select *
+  into todo
+  from todos
+  where tenant_id = label.tenant_id
+    and owner_id = label.owner_id
+    and todo_id = label.todo_id;
+  -- The labels of a deleted todo are deleted with it, which is not a change.
+  if todo.todo_id is null then
+    return null;
+  end if;
+
+  labels = todo_label_names(todo.tenant_id, todo.owner_id, todo.todo_id);
+  perform record_todo_event(
+    todo.tenant_id,
+    todo.todo_id,
+    todo.owner_id,
+    todo_snapshot(
+      todo,
+      (
+        select coalesce(jsonb_agg(label_name order by label_name), '[]')
+        from (
+          select label_name
+          from jsonb_array_elements_text(labels) label_name
+          where tg_op = 'DELETE' or label_name <> new.name
+          union
+          select old.name
+          where tg_op <> 'INSERT'
+        ) old_labels
+      )
+    ),
+    todo_snapshot(todo, labels)
+  );
+  return null;
+end;
+$$ language plpgsql;
+
+drop function todo_label_names(text);
+
+create or replace view todos_view with (security_invoker = true) as
+select todo_id,
+       title,
+       description,
+       completed,
+       created_at,
+       updated_at,
+       owner_id,
+       tenant_id,
+       due_time,
+       reminder_time,
+       recurrence_rule,
+       series_id,
+       priority,
+       position,
+       list_id,
+       parent_todo_id,
+       complete_time,
+       array(
+         select name
+         from todo_labels
+         where todo_labels.tenant_id = todos.tenant_id
+           and todo_labels.owner_id = todos.owner_id
+           and todo_labels.todo_id = todos.todo_id
+         order by name
+       ) as labels,
+       (
+         select count(*)::integer
+         from todo_comments
+         where todo_comments.tenant_id = todos.tenant_id
+           and todo_comments.owner_id = todos.owner_id
+           and todo_comments.todo_id = todos.todo_id
+       ) as comment_count
+from todos;
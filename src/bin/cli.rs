@@ -4,47 +4,418 @@
 //! cargo or the rust toolchain.
 //!
 //! Where we use third party binaries, these should ideally be wrapped in this
-//! CLI, downloading the appropriate version to this repository's `/bin` 
+//! CLI, downloading the appropriate version to this repository's `/bin`
 //! directory.
 //!
 //! For example, the `protoc-gen-doc` binary is used to generate API
 //! documentation, and this is wrapped in the `generate-api-docs` command.
 //!
+//! The CLI also provides administrative commands that operate directly on the
+//! database, such as the `apikey` commands for managing API keys on behalf of
+//! a user, the `todo list` command for viewing a user's todos, the `history`
+//! command for viewing the changes to a todo, and the `config validate`
+//! command for checking a configuration before it is deployed.
+//!
 //! This ensures that developers have a consistent experience and minimises
 //! effort when setting up a new development environment.
 //!
 
+use anyhow::anyhow;
+use clap::Args as ClapArgs;
 use clap::Parser;
-use clap::ValueEnum;
-use std::future::Future;
+use clap::Subcommand;
+use prost_types::value::Kind;
+use prost_types::Timestamp;
+use std::io::IsTerminal;
+use std::time::SystemTime;
 use todos_service::api_docs;
+use todos_service::auth::Principal;
 use todos_service::common::init_common;
+use todos_service::common::load_dotenv;
+use todos_service::config::Config;
+use todos_service::config::ConfigArgs;
+use todos_service::config::DatabaseConfig;
+use todos_service::database::create_database_pool;
+use todos_service::proto::v1::api_keys::ApiKey;
+use todos_service::proto::v1::api_keys::CreateApiKeyRequest;
+use todos_service::proto::v1::api_keys::ListApiKeysRequest;
+use todos_service::proto::v1::api_keys::RevokeApiKeyRequest;
+use todos_service::proto::v1::api_keys::RotateApiKeyRequest;
+use todos_service::proto::v1::todos::ListTodoHistoryRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::TodoEvent;
+use todos_service::services::api_keys;
+use todos_service::services::todos;
 
 /// CLI Arguments, for the clap argument parser. See:
 /// <https://github.com/clap-rs/clap> for more information.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+  #[command(subcommand)]
   command: Command,
+  #[command(flatten)]
+  config: ConfigArgs,
 }
 
 /// Enum for the different commands the CLI can run.
-#[derive(Debug, ValueEnum, Clone)]
+#[derive(Debug, Subcommand, Clone)]
 enum Command {
+  /// Generate the API documentation in the `docs` directory.
   GenerateApiDocs,
+  /// Manage the API keys of a user.
+  #[command(subcommand)]
+  Apikey(ApiKeyCommand),
+  /// View the todos of a user.
+  #[command(subcommand)]
+  Todo(TodoCommand),
+  /// Show the changes to a todo of a user, most recent first.
+  History {
+    #[command(flatten)]
+    user: UserArgs,
+    /// The ID of the todo, which may have been deleted.
+    todo_id: String,
+  },
+  /// Work with the configuration of the server and the CLI.
+  #[command(subcommand)]
+  Config(ConfigCommand),
+}
+
+/// Enum for the configuration commands.
+#[derive(Debug, Subcommand, Clone)]
+enum ConfigCommand {
+  /// Load the configuration, and report every invalid key.
+  Validate,
+}
+
+/// Enum for the API key management commands.
+#[derive(Debug, Subcommand, Clone)]
+enum ApiKeyCommand {
+  /// Create a new API key, and print the key.
+  Create {
+    #[command(flatten)]
+    user: UserArgs,
+    /// A human readable name for the key.
+    #[arg(long)]
+    name: String,
+    /// A scope to grant to the key, e.g. `todos.read`. May be repeated.
+    #[arg(long = "scope", required = true)]
+    scopes: Vec<String>,
+  },
+  /// List the API keys of a user.
+  List {
+    #[command(flatten)]
+    user: UserArgs,
+  },
+  /// Revoke an API key.
+  Revoke {
+    #[command(flatten)]
+    user: UserArgs,
+    /// The ID of the API key to revoke.
+    api_key_id: String,
+  },
+  /// Replace an API key with a new key, and print the new key.
+  Rotate {
+    #[command(flatten)]
+    user: UserArgs,
+    /// The ID of the API key to rotate.
+    api_key_id: String,
+  },
+}
+
+/// Enum for the todo commands.
+#[derive(Debug, Subcommand, Clone)]
+enum TodoCommand {
+  /// List the todos of a user, one per line. Overdue todos are marked with
+  /// `[!]`, and are shown in red in a terminal.
+  List {
+    #[command(flatten)]
+    user: UserArgs,
+    /// Only list todos that are due before this time, in RFC 3339 format,
+    /// e.g. `2026-11-01T00:00:00Z`.
+    #[arg(long)]
+    due_before: Option<Timestamp>,
+    /// The order to list the todos in, e.g. `due_time, created_at desc`.
+    #[arg(long, default_value = "due_time, created_at")]
+    order_by: String,
+  },
+}
+
+/// The user that an administrative command acts on behalf of.
+#[derive(Debug, ClapArgs, Clone)]
+struct UserArgs {
+  /// The ID of the tenant that the user belongs to.
+  #[arg(long)]
+  tenant_id: String,
+  /// The ID of the user.
+  #[arg(long)]
+  user_id: String,
+}
+
+impl From<UserArgs> for Principal {
+  fn from(args: UserArgs) -> Self {
+    Principal::user(&args.tenant_id, &args.user_id)
+  }
 }
 
 /// Entrypoint for the CLI. We start a tokio runtime so that the CLI can
 /// run any async code if needed.
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-  init_common()?;
-  run_command(Args::parse()).await?;
+  let args = Args::parse();
+
+  match args.command {
+    Command::GenerateApiDocs => {
+      let (_, _telemetry) = init_common(&args.config)?;
+      api_docs::generate_api_docs().await
+    }
+    Command::Apikey(command) => {
+      let (config, _telemetry) = init_common(&args.config)?;
+      run_api_key_command(command, &config.database).await
+    }
+    Command::Todo(command) => {
+      let (config, _telemetry) = init_common(&args.config)?;
+      run_todo_command(command, &config.database).await
+    }
+    Command::History { user, todo_id } => {
+      let (config, _telemetry) = init_common(&args.config)?;
+      print_todo_history(user, todo_id, &config.database).await
+    }
+    // Validating the configuration must not require a valid configuration, so
+    // this does not initialize the common parts of the application.
+    Command::Config(ConfigCommand::Validate) => validate_config(&args.config),
+  }
+}
+
+/// Load the configuration, and print every problem with it.
+fn validate_config(args: &ConfigArgs) -> anyhow::Result<()> {
+  load_dotenv()?;
+
+  match Config::load(args) {
+    Ok(_) => {
+      println!("Configuration is valid.");
+      Ok(())
+    }
+    Err(errors) => {
+      for error in &errors.0 {
+        println!("{}", error);
+      }
+      Err(anyhow!("Found {} configuration errors", errors.0.len()))
+    }
+  }
+}
+
+/// Run one of the API key management commands against the database, using the
+/// same implementation as the `ApiKeyService`.
+async fn run_api_key_command(
+  command: ApiKeyCommand,
+  config: &DatabaseConfig,
+) -> anyhow::Result<()> {
+  let pool = create_database_pool(config).await?;
+
+  match command {
+    ApiKeyCommand::Create { user, name, scopes } => {
+      let request = CreateApiKeyRequest {
+        api_key: Some(ApiKey {
+          display_name: name,
+          scopes,
+          ..Default::default()
+        }),
+      };
+      let response =
+        api_keys::create_api_key(pool, user.into(), request).await?;
+      print_api_key(&response.api_key.unwrap_or_default());
+      println!("key: {}", response.key);
+    }
+    ApiKeyCommand::List { user } => {
+      let response =
+        api_keys::list_api_keys(pool, user.into(), ListApiKeysRequest {})
+          .await?;
+      for api_key in response.api_keys {
+        print_api_key(&api_key);
+        println!();
+      }
+    }
+    ApiKeyCommand::Revoke { user, api_key_id } => {
+      let request = RevokeApiKeyRequest { api_key_id };
+      let response =
+        api_keys::revoke_api_key(pool, user.into(), request).await?;
+      print_api_key(&response.api_key.unwrap_or_default());
+    }
+    ApiKeyCommand::Rotate { user, api_key_id } => {
+      let request = RotateApiKeyRequest { api_key_id };
+      let response =
+        api_keys::rotate_api_key(pool, user.into(), request).await?;
+      print_api_key(&response.api_key.unwrap_or_default());
+      println!("key: {}", response.key);
+    }
+  }
+
   Ok(())
 }
 
-fn run_command(args: Args) -> impl Future<Output = anyhow::Result<()>> {
-  match args.command {
-    Command::GenerateApiDocs => api_docs::generate_api_docs(),
+/// Print the details of an API key, one field per line.
+fn print_api_key(api_key: &ApiKey) {
+  println!("id: {}", api_key.api_key_id);
+  println!("name: {}", api_key.display_name);
+  println!("scopes: {}", api_key.scopes.join(", "));
+  println!("prefix: {}", api_key.key_prefix);
+  if let Some(revoked_at) = &api_key.revoked_at {
+    println!("revoked at: {}", revoked_at);
+  }
+}
+
+/// Run one of the todo commands against the database, using the same
+/// implementation as the `TodoService`.
+async fn run_todo_command(
+  command: TodoCommand,
+  config: &DatabaseConfig,
+) -> anyhow::Result<()> {
+  let pool = create_database_pool(config).await?;
+
+  match command {
+    TodoCommand::List {
+      user,
+      due_before,
+      order_by,
+    } => {
+      let request = ListTodosRequest {
+        due_before,
+        order_by,
+        ..Default::default()
+      };
+      let response = todos::list_todos(pool, user.into(), request).await?;
+      let now = Timestamp::from(SystemTime::now());
+      let color = std::io::stdout().is_terminal();
+      for todo in response.todos {
+        print_todo(&todo, &now, color);
+      }
+    }
+  }
+
+  Ok(())
+}
+
+/// Whether the todo is not completed and was due before the given time.
+fn is_overdue(todo: &Todo, now: &Timestamp) -> bool {
+  match &todo.due_time {
+    Some(due_time) if !todo.completed => {
+      (due_time.seconds, due_time.nanos) < (now.seconds, now.nanos)
+    }
+    _ => false,
+  }
+}
+
+/// Print a todo on a single line, with its completion status, due time and
+/// reminder time. Overdue todos are marked, and are shown in red if `color` is
+/// set.
+fn print_todo(todo: &Todo, now: &Timestamp, color: bool) {
+  let overdue = is_overdue(todo, now);
+  let status = match (todo.completed, overdue) {
+    (true, _) => "[x]",
+    (false, true) => "[!]",
+    (false, false) => "[ ]",
+  };
+
+  let mut line = format!("{} {}  {}", status, todo.todo_id, todo.title);
+  if let Some(due_time) = &todo.due_time {
+    if overdue {
+      line.push_str(&format!("  overdue since {}", due_time));
+    } else {
+      line.push_str(&format!("  due {}", due_time));
+    }
+  }
+  if let Some(reminder_time) = &todo.reminder_time {
+    line.push_str(&format!("  reminder {}", reminder_time));
+  }
+
+  if overdue && color {
+    println!("\x1b[31m{}\x1b[0m", line);
+  } else {
+    println!("{}", line);
+  }
+}
+
+/// Print every change to a todo, most recent first, using the same
+/// implementation as the `TodoService`.
+async fn print_todo_history(
+  user: UserArgs,
+  todo_id: String,
+  config: &DatabaseConfig,
+) -> anyhow::Result<()> {
+  let pool = create_database_pool(config).await?;
+  let principal = Principal::from(user);
+
+  let mut page_token = String::new();
+  loop {
+    let request = ListTodoHistoryRequest {
+      todo_id: todo_id.clone(),
+      page_token,
+      ..Default::default()
+    };
+    let response =
+      todos::list_todo_history(pool.clone(), principal.clone(), request)
+        .await?;
+    for event in &response.events {
+      print_todo_event(event);
+    }
+
+    if response.next_page_token.is_empty() {
+      return Ok(());
+    }
+    page_token = response.next_page_token;
+  }
+}
+
+/// Print a change to a todo, with who made it and when on the first line, and
+/// then each changed field on a line of its own.
+fn print_todo_event(event: &TodoEvent) {
+  let action = event.action().as_str_name();
+  let mut line = format!(
+    "{}  {}  by {}",
+    event.event_time.unwrap_or_default(),
+    action
+      .strip_prefix("TODO_EVENT_ACTION_")
+      .unwrap_or(action)
+      .to_lowercase(),
+    event.actor_id
+  );
+  if event.api_key {
+    line.push_str(" with an API key");
+  }
+  println!("{}", line);
+
+  let get = |values: &Option<prost_types::Struct>, field: &str| {
+    let value = values.as_ref().and_then(|values| values.fields.get(field));
+    format_value(value)
+  };
+  for field in &event.changed_fields {
+    println!(
+      "  {}: {} -> {}",
+      field,
+      get(&event.before, field),
+      get(&event.after, field)
+    );
+  }
+}
+
+/// Format a value of a changed field, with strings quoted so that empty ones
+/// can be seen.
+fn format_value(value: Option<&prost_types::Value>) -> String {
+  match value.and_then(|value| value.kind.as_ref()) {
+    None | Some(Kind::NullValue(_)) => "none".to_string(),
+    Some(Kind::BoolValue(value)) => value.to_string(),
+    Some(Kind::NumberValue(value)) => value.to_string(),
+    Some(Kind::StringValue(value)) => format!("{:?}", value),
+    Some(Kind::ListValue(list)) => {
+      let values = list
+        .values
+        .iter()
+        .map(|value| format_value(Some(value)))
+        .collect::<Vec<_>>();
+      format!("[{}]", values.join(", "))
+    }
+    Some(Kind::StructValue(_)) => "{...}".to_string(),
   }
 }
@@ -4,69 +4,141 @@
 //! - TCP: The server will listen on a TCP port.
 //! - UDS: The server will listen on a Unix Domain Socket.
 //!
-//! The mode is determined by the `SERVER_HOST` environment variable. If the
-//! variable starts with a `/`, then the server will run in UDS mode. Otherwise,
-//! it will run in TCP mode.
+//! The mode is determined by the `server.host` configuration key. If the host
+//! starts with a `/`, then the server will run in UDS mode. Otherwise, it will
+//! run in TCP mode. See [`todos_service::config`] for how the configuration is
+//! loaded.
 //!
 //! The server will also create a reflection server, which can be used to
-//! introspect the gRPC services.
+//! introspect the gRPC services, and a health server, which reports the
+//! services as `NOT_SERVING` until the database is reachable.
+//!
+//! If the `admin.port` configuration key is set, then the server will also
+//! serve Prometheus metrics over HTTP on that port, at `/metrics`.
+//!
+//! Unless `reminders.notifier` is `none`, the server also runs the reminder
+//! scheduler in the background, see [`todos_service::reminders`]. Unless
+//! `attachments.store` is `none`, it also runs the sweeper that removes the
+//! content of deleted attachments, see [`todos_service::blobs`].
 //!
 use anyhow::anyhow;
-use log::info;
-use server::get_server_address_tcp;
+use clap::Parser;
+use server::create_server_builder;
 use server::get_server_uds_stream;
 use server::set_sigint_handler_uds;
+use todos_service::blobs::create_blob_store;
+use todos_service::blobs::run_blob_sweeper;
 use todos_service::common::init_common;
-use todos_service::common::require_environment_variable;
-use todos_service::database::create_database_pool;
+use todos_service::config::ConfigArgs;
+use todos_service::config::Listener;
+use todos_service::database::create_lazy_database_pool;
+use todos_service::health::create_health_service;
+use todos_service::health::serve_when_database_is_ready;
+use todos_service::metrics::serve_admin;
+use todos_service::reminders::create_notifier;
+use todos_service::reminders::run_reminder_scheduler;
 use todos_service::server;
 use todos_service::services::build_server;
+use todos_service::services::ServiceOptions;
+use tracing::error;
+use tracing::info;
+
+/// Server arguments, for the clap argument parser.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+  #[command(flatten)]
+  config: ConfigArgs,
+}
 
 ///
 /// Entrypoint for the server.
 ///
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-  init_common()?;
+  let args = Args::parse();
+  let (config, _telemetry) = init_common(&args.config)?;
 
+  // The pool connects lazily, so the server starts even if the database is
+  // not reachable yet. Until it is, the health service reports NOT_SERVING.
   info!("Creating database pool...");
-  let database_pool = create_database_pool()
-    .await
-    .map_err(|e| anyhow!("Failed to create database pool: {}", e))?;
+  let database_pool = create_lazy_database_pool(&config.database);
+  let (health_reporter, health_server) = create_health_service().await;
+  tokio::spawn(serve_when_database_is_ready(
+    health_reporter,
+    config.database.clone(),
+  ));
+
+  // The blob store is shared by the services, which keep the content of
+  // attachments in it, and by the sweeper:
+  let blob_store = create_blob_store(&config.attachments)?;
+  let service_options = ServiceOptions {
+    blob_store: blob_store.clone(),
+    ..ServiceOptions::from_config(&config)
+  };
+
+  // Start the admin server in the background, if it is configured:
+  if let Some(admin_address) = config.admin.address {
+    let pool = database_pool.clone();
+    tokio::spawn(async move {
+      if let Err(e) = serve_admin(admin_address, pool).await {
+        error!("Admin server failed: {}", e);
+      }
+    });
+  }
+
+  // Start the reminder scheduler in the background, unless it is disabled:
+  if let Some(notifier) = create_notifier(&config.reminders)? {
+    tokio::spawn(run_reminder_scheduler(
+      database_pool.clone(),
+      config.reminders.clone(),
+      notifier,
+    ));
+  }
+
+  // Start the blob sweeper in the background, unless attachments are disabled:
+  if let Some(store) = blob_store {
+    tokio::spawn(run_blob_sweeper(
+      database_pool.clone(),
+      config.attachments.clone(),
+      store,
+    ));
+  }
 
   // Create the reflection server:
   let reflection_server = server::create_reflection_server()
     .map_err(|e| anyhow!("Failed to create reflection server: {}", e))?;
 
-  let host = require_environment_variable("SERVER_HOST")?;
+  let server = create_server_builder(&config.server)
+    .map_err(|e| anyhow!("Failed to create server: {}", e))?;
 
-  // If host begins with a slash, host the server on a unix domain socket.
-  if host.starts_with("/") {
-    let uds_stream = get_server_uds_stream(&host).map_err(|e| {
-      anyhow!("Failed to create unix domain socket stream: {}", e)
-    })?;
+  match &config.server.listener {
+    // If host begins with a slash, host the server on a unix domain socket.
+    Listener::Unix(host) => {
+      let uds_stream = get_server_uds_stream(host).map_err(|e| {
+        anyhow!("Failed to create unix domain socket stream: {}", e)
+      })?;
 
-    set_sigint_handler_uds(host.clone());
+      set_sigint_handler_uds(host.clone());
 
-    info!("Starting server on unix domain socket: {host}...");
-    build_server(&database_pool)
-      .add_service(reflection_server)
-      .serve_with_incoming(uds_stream)
-      .await?;
-    info!("Server stopped.");
-    return Ok(());
+      info!("Starting server on unix domain socket: {host}...");
+      build_server(server, &database_pool, &service_options)
+        .add_service(reflection_server)
+        .add_service(health_server)
+        .serve_with_incoming(uds_stream)
+        .await?;
+    }
+    // Otherwise, host the server on a tcp socket.
+    Listener::Tcp(server_address) => {
+      info!("Starting server on tcp socket: {server_address}...");
+      build_server(server, &database_pool, &service_options)
+        .add_service(reflection_server)
+        .add_service(health_server)
+        .serve(*server_address)
+        .await?;
+    }
   }
 
-  // Otherwise, host the server on a tcp socket.
-  let server_address = get_server_address_tcp()
-    .map_err(|e| anyhow!("Failed to get server address: {}", e))?;
-
-  info!("Starting server on tcp socket: {server_address}...");
-  build_server(&database_pool)
-    .add_service(reflection_server)
-    .serve(server_address)
-    .await?;
-
   info!("Server stopped.");
 
   Ok(())
@@ -10,15 +10,14 @@ use async_compression::tokio::bufread::GzipDecoder;
 use async_tar::Archive;
 use futures::TryStreamExt;
 use glob::GlobError;
-use log::debug;
-use log::info;
 use std::io;
-use std::io::ErrorKind;
 use tempfile::tempdir;
 use tokio::fs;
 use tokio::io::BufReader;
 use tokio_util::compat::TokioAsyncReadCompatExt;
 use tokio_util::io::StreamReader;
+use tracing::debug;
+use tracing::info;
 
 /// This function generates API documentation for the gRPC services defined in
 /// the project.  It does this by downloading the protoc-gen-doc binary, and
@@ -40,9 +39,7 @@ pub async fn generate_api_docs() -> anyhow::Result<()> {
     version, platform
   );
   let response = reqwest::get(url).await?;
-  let reader = response
-    .bytes_stream()
-    .map_err(|e| io::Error::new(ErrorKind::Other, e));
+  let reader = response.bytes_stream().map_err(io::Error::other);
 
   let reader = StreamReader::new(reader);
   let reader = BufReader::new(reader);
@@ -103,7 +100,7 @@ fn get_protoc_gen_doc_url(version: &str, suffix: &str) -> String {
 
 /// Get linux x64 suffix for protoc-gen-doc:
 #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-fn get_protoc_gen_doc_url() -> String {
+fn get_protoc_gen_doc_suffix() -> String {
   "linux_amd64".to_string()
 }
 
@@ -115,7 +112,7 @@ fn get_protoc_gen_doc_suffix() -> String {
 
 /// Get windows suffix for protoc-gen-doc:
 #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-fn get_protoc_gen_doc_url() -> String {
+fn get_protoc_gen_doc_suffix() -> String {
   "darwin_amd64".to_string()
 }
 
@@ -0,0 +1,364 @@
+//! This module contains the types and functions used to authenticate callers
+//! and to identify the principal that a request is made on behalf of.
+//!
+//! Callers are authenticated in one of two ways:
+//! - Users are authenticated by a trusted proxy in front of the service (for
+//!   example, an API gateway that validates the user's token), which asserts
+//!   the user's identity with the `x-user-id` metadata entry, and the
+//!   organisation they belong to with the `x-tenant-id` metadata entry.
+//! - Other services authenticate with an API key in the `x-api-key` metadata
+//!   entry. API keys act on behalf of the user that created them, but are
+//!   limited to the scopes that were granted when they were created.
+//!
+//! Requests that cannot be authenticated are rejected, as are requests for
+//! methods that the caller's API key does not have the scope to call.
+
+use futures::future::BoxFuture;
+use sha2::Digest;
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::fmt;
+use std::str::FromStr;
+use std::task::Context;
+use std::task::Poll;
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::codegen::http::HeaderMap;
+use tonic::codegen::Service;
+use tonic::server::NamedService;
+use tonic::Request;
+use tonic::Status;
+use tower::Layer;
+use tracing::instrument;
+
+/// The metadata key that carries the authenticated user's ID.
+pub const USER_ID_METADATA_KEY: &str = "x-user-id";
+
+/// The metadata key that carries the ID of the tenant that the authenticated
+/// user belongs to.
+pub const TENANT_ID_METADATA_KEY: &str = "x-tenant-id";
+
+/// The metadata key that carries an API key.
+pub const API_KEY_METADATA_KEY: &str = "x-api-key";
+
+/// The scopes that can be granted to an API key. Users are implicitly granted
+/// every scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+  /// Allows reading todos.
+  TodosRead,
+  /// Allows creating, updating and deleting todos.
+  TodosWrite,
+}
+
+impl Scope {
+  /// Get the name of the scope, as used in the API and the database.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Scope::TodosRead => "todos.read",
+      Scope::TodosWrite => "todos.write",
+    }
+  }
+}
+
+impl fmt::Display for Scope {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl FromStr for Scope {
+  type Err = Status;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "todos.read" => Ok(Scope::TodosRead),
+      "todos.write" => Ok(Scope::TodosWrite),
+      _ => Err(Status::invalid_argument(format!("Unknown scope {}", value))),
+    }
+  }
+}
+
+/// The authenticated caller of a request. This is inserted into the request
+/// extensions by the [`AuthLayer`], so that service handlers can scope their
+/// queries to the caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+  /// The unique ID of the caller, used as the owner of any resources it
+  /// creates. For API keys, this is the user that created the key.
+  pub subject: String,
+  /// The ID of the tenant that the caller belongs to. All data access for the
+  /// request is restricted to this tenant by the database.
+  pub tenant_id: String,
+  /// The scopes granted to the caller's API key, or `None` if the caller is a
+  /// user, who is implicitly granted every scope.
+  pub scopes: Option<Vec<Scope>>,
+}
+
+impl Principal {
+  /// Create a principal for a user that was authenticated by the trusted proxy.
+  pub fn user(tenant_id: &str, subject: &str) -> Self {
+    Self {
+      subject: subject.to_string(),
+      tenant_id: tenant_id.to_string(),
+      scopes: None,
+    }
+  }
+
+  /// Whether the caller has been granted the given scope.
+  pub fn has_scope(&self, scope: Scope) -> bool {
+    self
+      .scopes
+      .as_ref()
+      .is_none_or(|scopes| scopes.contains(&scope))
+  }
+
+  /// Whether the caller authenticated with an API key.
+  pub fn is_api_key(&self) -> bool {
+    self.scopes.is_some()
+  }
+}
+
+/// The permission that a caller needs to call a gRPC method.
+enum Permission {
+  /// The caller must be a user, or use an API key with the given scope.
+  Scope(Scope),
+  /// The caller must be a user. API keys cannot call the method.
+  User,
+}
+
+/// Get the permission needed to call the gRPC method with the given path.
+/// Methods that are not listed here can only be called by users, so that new
+/// methods are not accidentally exposed to API keys.
+fn get_required_permission(path: &str) -> Permission {
+  match path {
+    "/example.v1.todos.TodoService/ListTodos"
+    | "/example.v1.todos.TodoService/SearchTodos"
+    | "/example.v1.todos.TodoService/GetTodo"
+    | "/example.v1.todos.TodoService/ListTodoHistory"
+    | "/example.v1.todos.TodoService/ListTodoRevisions"
+    | "/example.v1.todos.TodoService/ListComments"
+    | "/example.v1.todos.TodoService/GetComment"
+    | "/example.v1.todos.TodoService/ListCommentEdits"
+    | "/example.v1.todos.TodoService/ListAttachments"
+    | "/example.v1.todos.TodoService/GetAttachment"
+    | "/example.v1.todos.TodoService/DownloadAttachment"
+    | "/example.v1.todos.TodoService/GetTodoRevision"
+    | "/example.v1.labels.LabelService/ListLabels"
+    | "/example.v1.labels.LabelService/GetLabel"
+    | "/example.v1.todo_lists.TodoListService/ListTodoLists"
+    | "/example.v1.todo_lists.TodoListService/GetTodoList" => {
+      Permission::Scope(Scope::TodosRead)
+    }
+    "/example.v1.todos.TodoService/CreateTodo"
+    | "/example.v1.todos.TodoService/UpdateTodo"
+    | "/example.v1.todos.TodoService/MoveTodo"
+    | "/example.v1.todos.TodoService/CompleteTodo"
+    | "/example.v1.todos.TodoService/ReopenTodo"
+    | "/example.v1.todos.TodoService/RestoreTodoRevision"
+    | "/example.v1.todos.TodoService/CreateComment"
+    | "/example.v1.todos.TodoService/UpdateComment"
+    | "/example.v1.todos.TodoService/DeleteComment"
+    | "/example.v1.todos.TodoService/UploadAttachment"
+    | "/example.v1.todos.TodoService/DeleteAttachment"
+    | "/example.v1.todos.TodoService/DeleteTodo"
+    | "/example.v1.labels.LabelService/CreateLabel"
+    | "/example.v1.labels.LabelService/RenameLabel"
+    | "/example.v1.labels.LabelService/DeleteLabel"
+    | "/example.v1.todo_lists.TodoListService/CreateTodoList"
+    | "/example.v1.todo_lists.TodoListService/UpdateTodoList"
+    | "/example.v1.todo_lists.TodoListService/DeleteTodoList" => {
+      Permission::Scope(Scope::TodosWrite)
+    }
+    _ => Permission::User,
+  }
+}
+
+/// Hash an API key for storage and lookup. API keys are long random strings,
+/// so a fast unsalted hash is sufficient, and allows the key to be looked up
+/// by its hash.
+pub fn hash_api_key(key: &str) -> String {
+  format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// A tower layer that authenticates each request and checks that the caller is
+/// allowed to call the requested method, before passing it to the wrapped
+/// service. Add it to a service with, for example:
+/// ```ignore
+/// AuthLayer::new(pool.clone()).layer(TodoServiceServer::new(handler))
+/// ```
+#[derive(Debug, Clone)]
+pub struct AuthLayer {
+  pool: PgPool,
+}
+
+impl AuthLayer {
+  /// Create a new layer, using the given database pool to look up API keys.
+  pub fn new(pool: PgPool) -> Self {
+    Self { pool }
+  }
+}
+
+impl<S> Layer<S> for AuthLayer {
+  type Service = AuthService<S>;
+
+  fn layer(&self, inner: S) -> Self::Service {
+    AuthService {
+      inner,
+      pool: self.pool.clone(),
+    }
+  }
+}
+
+/// The service created by the [`AuthLayer`].
+#[derive(Debug, Clone)]
+pub struct AuthService<S> {
+  inner: S,
+  pool: PgPool,
+}
+
+impl<S, B> Service<http::Request<B>> for AuthService<S>
+where
+  S: Service<http::Request<B>, Response = http::Response<BoxBody>>
+    + Clone
+    + Send
+    + 'static,
+  S::Future: Send + 'static,
+  B: Send + 'static,
+{
+  type Response = S::Response;
+  type Error = S::Error;
+  type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+    // The inner service has been driven to readiness, so we must call that
+    // instance rather than the clone. See:
+    // <https://docs.rs/tower/latest/tower/trait.Service.html#be-careful-when-cloning-inner-services>
+    let clone = self.inner.clone();
+    let mut inner = std::mem::replace(&mut self.inner, clone);
+    let pool = self.pool.clone();
+
+    Box::pin(async move {
+      let result =
+        authenticate(&pool, request.headers())
+          .await
+          .and_then(|principal| {
+            authorize(&principal, request.uri().path())?;
+            Ok(principal)
+          });
+
+      match result {
+        Ok(principal) => {
+          request.extensions_mut().insert(principal);
+          inner.call(request).await
+        }
+        Err(status) => Ok(status.into_http()),
+      }
+    })
+  }
+}
+
+impl<S: NamedService> NamedService for AuthService<S> {
+  const NAME: &'static str = S::NAME;
+}
+
+/// Identify the caller of a request from its metadata. API keys take
+/// precedence over the identity asserted by the trusted proxy.
+async fn authenticate(
+  pool: &PgPool,
+  headers: &HeaderMap,
+) -> Result<Principal, Status> {
+  if let Some(key) = get_metadata(headers, API_KEY_METADATA_KEY) {
+    return authenticate_api_key(pool, &key).await;
+  }
+
+  let subject = get_required_metadata(headers, USER_ID_METADATA_KEY)?;
+  let tenant_id = get_required_metadata(headers, TENANT_ID_METADATA_KEY)?;
+
+  Ok(Principal::user(&tenant_id, &subject))
+}
+
+/// Look up an API key by its hash. Revoked and unknown keys are rejected with
+/// an `UNAUTHENTICATED` status.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn authenticate_api_key(
+  pool: &PgPool,
+  key: &str,
+) -> Result<Principal, Status> {
+  // The lookup cannot be scoped to a tenant, because we do not know the tenant
+  // until we have found the key, so it uses a security definer function that
+  // bypasses the tenant isolation policy.
+  let row = sqlx::query!(
+    r#"
+    select tenant_id as "tenant_id!",
+           owner_id as "owner_id!",
+           scopes as "scopes!"
+    from find_api_key($1)
+    "#,
+    hash_api_key(key)
+  )
+  .fetch_optional(pool)
+  .await
+  .map_err(|e| Status::internal(format!("Failed to find API key: \n{}", e)))?
+  .ok_or_else(|| Status::unauthenticated("Invalid API key"))?;
+
+  let scopes = row
+    .scopes
+    .iter()
+    .map(|scope| scope.parse())
+    .collect::<Result<Vec<Scope>, Status>>()?;
+
+  Ok(Principal {
+    subject: row.owner_id,
+    tenant_id: row.tenant_id,
+    scopes: Some(scopes),
+  })
+}
+
+/// Check that the caller is allowed to call the method with the given path,
+/// returning a `PERMISSION_DENIED` status if not.
+fn authorize(principal: &Principal, path: &str) -> Result<(), Status> {
+  match get_required_permission(path) {
+    Permission::Scope(scope) if !principal.has_scope(scope) => Err(
+      Status::permission_denied(format!("API key requires scope {}", scope)),
+    ),
+    Permission::User if principal.is_api_key() => Err(
+      Status::permission_denied("API keys cannot call this method"),
+    ),
+    _ => Ok(()),
+  }
+}
+
+/// Get a non-empty ASCII metadata value from the request headers.
+fn get_metadata(headers: &HeaderMap, key: &str) -> Option<String> {
+  headers
+    .get(key)
+    .and_then(|value| value.to_str().ok())
+    .map(|value| value.trim().to_string())
+    .filter(|value| !value.is_empty())
+}
+
+/// Get a non-empty ASCII metadata value from the request headers, or return an
+/// `UNAUTHENTICATED` status if it is not present.
+fn get_required_metadata(
+  headers: &HeaderMap,
+  key: &str,
+) -> Result<String, Status> {
+  get_metadata(headers, key)
+    .ok_or_else(|| Status::unauthenticated(format!("Missing {} metadata", key)))
+}
+
+/// Get the principal that was attached to the request by the [`AuthLayer`].
+/// If the layer has not been applied to the service, then the request is
+/// rejected with an `UNAUTHENTICATED` status.
+pub fn get_principal<T>(request: &Request<T>) -> Result<Principal, Status> {
+  request
+    .extensions()
+    .get::<Principal>()
+    .cloned()
+    .ok_or_else(|| Status::unauthenticated("Request is not authenticated"))
+}
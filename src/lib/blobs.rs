@@ -0,0 +1,160 @@
+//! This module contains the blob stores that keep the content of the files
+//! attached to todos, and the sweeper that removes the content of deleted
+//! attachments.
+//!
+//! The metadata of each attachment is kept in Postgres, and its content is
+//! kept in a [`BlobStore`] under the attachment's ID. Content is written and
+//! read as a stream of chunks, so that a large file is never held in memory
+//! at once. The store is chosen by the `attachments.store` configuration key.
+//! See [`stores`].
+//!
+//! Deleting an attachment, or the todo that it is attached to, queues its
+//! blob in the `deleted_attachment_blobs` table in the same transaction. The
+//! sweeper runs in the background of the server, and removes the queued blobs
+//! from the store. It claims them with `FOR UPDATE SKIP LOCKED`, so that
+//! several replicas of the server can run the sweeper at once.
+
+mod sigv4;
+pub mod stores;
+
+use crate::config::AttachmentsConfig;
+use crate::config::BlobStoreConfig;
+use crate::database::begin_scheduler_transaction;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use sqlx::query;
+use sqlx::PgPool;
+use std::fmt;
+use std::sync::Arc;
+use stores::FilesystemBlobStore;
+use stores::S3BlobStore;
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+use uuid::Uuid;
+
+/// The most blobs that the sweeper claims at once.
+const SWEEP_BATCH_SIZE: i64 = 100;
+
+/// The content of a blob, as a stream of chunks.
+pub type BlobStream = BoxStream<'static, anyhow::Result<Bytes>>;
+
+/// Somewhere that the content of attachments can be kept.
+#[async_trait::async_trait]
+pub trait BlobStore: fmt::Debug + Send + Sync {
+  /// The name of the store, which is logged when the server starts.
+  fn name(&self) -> &'static str;
+
+  /// Write a blob of `size` bytes from a stream of its content, replacing any
+  /// blob with the same key. If the stream fails, or its content is not
+  /// `size` bytes, then this fails and no blob is written.
+  async fn put(
+    &self,
+    key: &str,
+    size: u64,
+    content: BlobStream,
+  ) -> anyhow::Result<()>;
+
+  /// Read the content of a blob as a stream of chunks.
+  async fn get(&self, key: &str) -> anyhow::Result<BlobStream>;
+
+  /// Delete a blob. Deleting a blob that does not exist succeeds, so that the
+  /// sweeper can remove a blob again if it stops before it records that the
+  /// blob was removed.
+  async fn delete(&self, key: &str) -> anyhow::Result<()>;
+}
+
+/// Get the key that the content of an attachment is kept under.
+pub fn get_blob_key(attachment_id: Uuid) -> String {
+  attachment_id.to_string()
+}
+
+/// Create the blob store that the configuration asks for, or `None` if
+/// attachments are disabled.
+pub fn create_blob_store(
+  config: &AttachmentsConfig,
+) -> anyhow::Result<Option<Arc<dyn BlobStore>>> {
+  let store: Arc<dyn BlobStore> = match &config.store {
+    BlobStoreConfig::None => return Ok(None),
+    BlobStoreConfig::Filesystem { path } => {
+      Arc::new(FilesystemBlobStore::new(path))
+    }
+    BlobStoreConfig::S3(s3) => Arc::new(S3BlobStore::new(s3)?),
+  };
+
+  Ok(Some(store))
+}
+
+/// Run the sweeper until the process exits, removing the blobs of deleted
+/// attachments from the given store. The sweeper keeps running if the
+/// database or the store is not reachable, so it should be spawned as a
+/// separate task when the server starts.
+pub async fn run_blob_sweeper(
+  pool: PgPool,
+  config: AttachmentsConfig,
+  store: Arc<dyn BlobStore>,
+) {
+  info!(store = store.name(), "Starting blob sweeper...");
+
+  loop {
+    // If the batch was full, then there may be more blobs waiting, so we look
+    // again straight away.
+    match sweep_deleted_blobs(&pool, store.as_ref()).await {
+      Ok(count) if count >= SWEEP_BATCH_SIZE as usize => continue,
+      Ok(_) => (),
+      Err(e) => warn!(error = %e, "Failed to sweep deleted blobs"),
+    }
+    tokio::time::sleep(config.sweep_interval).await;
+  }
+}
+
+/// Claim up to a batch of the blobs of deleted attachments and remove them
+/// from the store, returning the number of blobs that were removed. A blob
+/// that could not be removed stays queued, and is tried again by a later
+/// sweep.
+#[instrument(skip_all)]
+pub async fn sweep_deleted_blobs(
+  pool: &PgPool,
+  store: &dyn BlobStore,
+) -> anyhow::Result<usize> {
+  let mut transaction = begin_scheduler_transaction(pool).await?;
+
+  let rows = query!(
+    r#"
+    select attachment_id
+    from deleted_attachment_blobs
+    order by deleted_at
+    limit $1
+    for update skip locked
+    "#,
+    SWEEP_BATCH_SIZE
+  )
+  .fetch_all(&mut *transaction)
+  .await?;
+
+  let mut removed = Vec::new();
+  for row in rows {
+    match store.delete(&get_blob_key(row.attachment_id)).await {
+      Ok(()) => removed.push(row.attachment_id),
+      Err(e) => warn!(
+        attachment_id = %row.attachment_id,
+        error = %e,
+        "Failed to remove blob"
+      ),
+    }
+  }
+
+  query!(
+    r#"
+    delete from deleted_attachment_blobs
+    where attachment_id = any($1)
+    "#,
+    &removed
+  )
+  .execute(&mut *transaction)
+  .await?;
+
+  transaction.commit().await?;
+
+  Ok(removed.len())
+}
@@ -0,0 +1,219 @@
+//! The blob stores that can keep the content of attachments. Each one is
+//! chosen by a value of the `attachments.store` configuration key:
+//!
+//! - `filesystem`: [`FilesystemBlobStore`] keeps each blob in a file.
+//! - `s3`: [`S3BlobStore`] keeps each blob in an S3 bucket, or in a bucket of
+//!   an S3-compatible service such as a local MinIO.
+
+use crate::blobs::sigv4;
+use crate::blobs::sigv4::Credentials;
+use crate::blobs::BlobStore;
+use crate::blobs::BlobStream;
+use crate::config::S3Config;
+use anyhow::anyhow;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use reqwest::Method;
+use reqwest::Url;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use tempfile::NamedTempFile;
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use tonic::codegen::http::header::CONTENT_LENGTH;
+
+/// How long the S3 blob store waits to connect to the service.
+const S3_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keeps each blob in a file named after its key, in a directory that is
+/// created when the first blob is written. Blobs are written to a temporary
+/// file in the same directory, which is renamed once it is complete, so that
+/// a partly written blob is never read.
+#[derive(Debug, Clone)]
+pub struct FilesystemBlobStore {
+  path: PathBuf,
+}
+
+impl FilesystemBlobStore {
+  pub fn new(path: &Path) -> Self {
+    Self {
+      path: path.to_path_buf(),
+    }
+  }
+
+  /// Get the path of the file that keeps a blob. Keys are generated by the
+  /// server, but are checked anyway so that they cannot name a file outside
+  /// of the directory.
+  fn get_path(&self, key: &str) -> anyhow::Result<PathBuf> {
+    if key.is_empty()
+      || !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+      return Err(anyhow!("Invalid blob key {}", key));
+    }
+
+    Ok(self.path.join(key))
+  }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for FilesystemBlobStore {
+  fn name(&self) -> &'static str {
+    "filesystem"
+  }
+
+  async fn put(
+    &self,
+    key: &str,
+    size: u64,
+    mut content: BlobStream,
+  ) -> anyhow::Result<()> {
+    let path = self.get_path(key)?;
+    tokio::fs::create_dir_all(&self.path).await?;
+
+    // The temporary file is removed when it is dropped, unless it has been
+    // renamed, so nothing is left behind if the upload fails.
+    let temporary = NamedTempFile::new_in(&self.path)?;
+    let mut file = tokio::fs::File::from_std(temporary.reopen()?);
+    let mut written = 0;
+    while let Some(chunk) = content.try_next().await? {
+      file.write_all(&chunk).await?;
+      written += chunk.len() as u64;
+    }
+    if written != size {
+      return Err(anyhow!("Expected {} bytes, got {}", size, written));
+    }
+    file.sync_all().await?;
+
+    temporary.persist(path)?;
+
+    Ok(())
+  }
+
+  async fn get(&self, key: &str) -> anyhow::Result<BlobStream> {
+    let file = tokio::fs::File::open(self.get_path(key)?).await?;
+
+    Ok(ReaderStream::new(file).map_err(anyhow::Error::from).boxed())
+  }
+
+  async fn delete(&self, key: &str) -> anyhow::Result<()> {
+    match tokio::fs::remove_file(self.get_path(key)?).await {
+      Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Keeps each blob as an object in an S3 bucket, named after its key. The
+/// bucket must already exist. Requests are signed with AWS Signature Version
+/// 4, and address the bucket by path, e.g. `http://localhost:9000/bucket/key`,
+/// which MinIO and other S3-compatible services expect. Uploads are streamed
+/// without signing their content, which S3 allows over both HTTP and HTTPS.
+#[derive(Debug, Clone)]
+pub struct S3BlobStore {
+  client: reqwest::Client,
+  config: S3Config,
+}
+
+impl S3BlobStore {
+  pub fn new(config: &S3Config) -> anyhow::Result<Self> {
+    let client = reqwest::Client::builder()
+      .connect_timeout(S3_CONNECT_TIMEOUT)
+      .build()?;
+
+    Ok(Self {
+      client,
+      config: config.clone(),
+    })
+  }
+
+  /// Create a signed request for the object with the given key.
+  fn request(
+    &self,
+    method: Method,
+    key: &str,
+  ) -> anyhow::Result<reqwest::RequestBuilder> {
+    let url = Url::parse(&format!(
+      "{}/{}/{}",
+      self.config.endpoint.trim_end_matches('/'),
+      self.config.bucket,
+      key
+    ))?;
+    let credentials = Credentials {
+      access_key_id: &self.config.access_key_id,
+      secret_access_key: &self.config.secret_access_key,
+      region: &self.config.region,
+    };
+    let headers = sigv4::sign(
+      &credentials,
+      method.as_str(),
+      &url,
+      sigv4::UNSIGNED_PAYLOAD,
+      OffsetDateTime::now_utc(),
+    );
+
+    Ok(headers.into_iter().fold(
+      self.client.request(method, url),
+      |request, (name, value)| request.header(name, value),
+    ))
+  }
+}
+
+/// Check that S3 accepted a request, including the error that it returned in
+/// the body of the response if it did not.
+async fn check_response(
+  response: reqwest::Response,
+) -> anyhow::Result<reqwest::Response> {
+  let status = response.status();
+  if status.is_success() {
+    return Ok(response);
+  }
+
+  let body = response.text().await.unwrap_or_default();
+  Err(anyhow!("S3 returned {}: {}", status, body))
+}
+
+#[async_trait::async_trait]
+impl BlobStore for S3BlobStore {
+  fn name(&self) -> &'static str {
+    "s3"
+  }
+
+  async fn put(
+    &self,
+    key: &str,
+    size: u64,
+    content: BlobStream,
+  ) -> anyhow::Result<()> {
+    // S3 requires the length of an upload up front. If the content is shorter
+    // or longer, then the request fails and no object is written.
+    let response = self
+      .request(Method::PUT, key)?
+      .header(CONTENT_LENGTH, size)
+      .body(reqwest::Body::wrap_stream(content))
+      .send()
+      .await?;
+    check_response(response).await?;
+
+    Ok(())
+  }
+
+  async fn get(&self, key: &str) -> anyhow::Result<BlobStream> {
+    let response = self.request(Method::GET, key)?.send().await?;
+    let response = check_response(response).await?;
+
+    Ok(response.bytes_stream().map_err(anyhow::Error::from).boxed())
+  }
+
+  async fn delete(&self, key: &str) -> anyhow::Result<()> {
+    // S3 succeeds when deleting an object that does not exist.
+    let response = self.request(Method::DELETE, key)?.send().await?;
+    check_response(response).await?;
+
+    Ok(())
+  }
+}
@@ -1,16 +1,40 @@
 //! Common functions and types used throughout the application.
 
+use crate::config::Config;
+use crate::config::ConfigArgs;
+use crate::telemetry::init_telemetry;
+use crate::telemetry::TelemetryGuard;
 use anyhow::anyhow;
 use sqlx::types::time::OffsetDateTime;
+use tonic::Status;
+use tracing::error;
 
 /// Initialize the common parts of the application, by:
-/// - setting up the environment variables
-/// - initializing the logger
-pub fn init_common() -> anyhow::Result<()> {
-  dotenvy::dotenv()?;
-  env_logger::init();
+/// - setting up the environment variables, see [`load_dotenv`]
+/// - loading the configuration, see [`crate::config`]
+/// - initializing logging and tracing, see [`crate::telemetry`]
+///
+/// The returned guard must be held until the application exits, so that any
+/// remaining spans are exported.
+pub fn init_common(
+  args: &ConfigArgs,
+) -> anyhow::Result<(Config, TelemetryGuard)> {
+  load_dotenv()?;
+  let config = Config::load(args)?;
+  let telemetry = init_telemetry(&config.logging, &config.server.name)?;
 
-  Ok(())
+  Ok((config, telemetry))
+}
+
+/// Load the environment variables from the `.env` file, if there is one.
+/// Deployments usually set the environment variables directly or use a
+/// configuration file, so the `.env` file is optional.
+pub fn load_dotenv() -> anyhow::Result<()> {
+  match dotenvy::dotenv() {
+    Ok(_) => Ok(()),
+    Err(e) if e.not_found() => Ok(()),
+    Err(e) => Err(e.into()),
+  }
 }
 
 /// Require an environment variable to be set.  If the environment variable
@@ -33,3 +57,35 @@ pub fn sql_datetime_to_proto_timestamp(
     nanos: time.nanosecond() as i32,
   }
 }
+
+/// Convert a protobuf `Timestamp` to a SQL `OffsetDateTime`. If the timestamp
+/// is not valid, then an `INVALID_ARGUMENT` status naming the given field is
+/// returned.
+pub fn proto_timestamp_to_sql_datetime(
+  field: &str,
+  timestamp: &prost_types::Timestamp,
+) -> Result<OffsetDateTime, Status> {
+  let invalid =
+    || Status::invalid_argument(format!("{} is not a valid timestamp", field));
+  if !(0..1_000_000_000).contains(&timestamp.nanos) {
+    return Err(invalid());
+  }
+
+  let nanos =
+    i128::from(timestamp.seconds) * 1_000_000_000 + i128::from(timestamp.nanos);
+  OffsetDateTime::from_unix_timestamp_nanos(nanos).map_err(|_| invalid())
+}
+
+/// Convert an error returned by a service handler into a gRPC status. If the
+/// handler returned a `Status` (for example, `NOT_FOUND` when a record does not
+/// exist) then it is passed through unchanged. Any other error is unexpected,
+/// so it is reported as an internal error prefixed with the given message.
+pub fn error_to_status(message: &str, error: anyhow::Error) -> Status {
+  match error.downcast::<Status>() {
+    Ok(status) => status,
+    Err(error) => {
+      error!(error = %error, "{}", message);
+      Status::internal(format!("{}: \n{}", message, error))
+    }
+  }
+}
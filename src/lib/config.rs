@@ -0,0 +1,1113 @@
+//! This module contains the typed configuration of the server and the CLI,
+//! which is loaded in layers. Each layer overrides the keys set by the layers
+//! before it:
+//!
+//! 1. The defaults in this module.
+//! 2. The TOML file given by the `--config` flag, or by the `CONFIG_FILE`
+//!    environment variable.
+//! 3. The environment variables listed in [`KEYS`], including those set in the
+//!    `.env` file.
+//! 4. The `--set key=value` command line flags.
+//!
+//! Keys are named after their place in the TOML file, e.g. `database.url` is
+//! the `url` key in the `[database]` table. Every key is validated after the
+//! layers have been merged, and all of the invalid keys are reported together
+//! rather than one at a time.
+
+use crate::common::require_environment_variable;
+use crate::gateway;
+use crate::grpc_web::parse_allowed_origins;
+use crate::rate_limit::RateLimitConfig;
+use crate::services::todos::parse_search_language;
+use crate::services::todos::SubtaskCompletion;
+use crate::telemetry::LogFormat;
+use anyhow::anyhow;
+use sqlx::postgres::PgConnectOptions;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use tonic::codegen::http::HeaderValue;
+use tonic::transport::Certificate;
+use tonic::transport::Identity;
+use tonic::transport::ServerTlsConfig;
+use tracing_subscriber::EnvFilter;
+
+/// The environment variable that names the configuration file, if the
+/// `--config` flag is not given.
+pub const CONFIG_FILE_ENVIRONMENT_VARIABLE: &str = "CONFIG_FILE";
+
+/// Every configuration key, and the environment variable that sets it.
+pub const KEYS: [(&str, &str); 55] = [
+  ("server.name", "SERVER_NAME"),
+  ("server.host", "SERVER_HOST"),
+  ("server.port", "SERVER_PORT"),
+  ("server.request_timeout", "SERVER_REQUEST_TIMEOUT"),
+  ("server.cors_allowed_origins", "CORS_ALLOWED_ORIGINS"),
+  ("server.gateway_max_body_size", "GATEWAY_MAX_BODY_SIZE"),
+  ("server.tls.cert_path", "TLS_CERT_PATH"),
+  ("server.tls.key_path", "TLS_KEY_PATH"),
+  ("server.tls.client_ca_path", "TLS_CLIENT_CA_PATH"),
+  ("admin.host", "ADMIN_HOST"),
+  ("admin.port", "ADMIN_PORT"),
+  ("database.url", "DATABASE_URL"),
+  ("database.read_url", "DATABASE_READ_URL"),
+  ("database.max_connections", "DATABASE_MAX_CONNECTIONS"),
+  ("database.min_connections", "DATABASE_MIN_CONNECTIONS"),
+  ("database.acquire_timeout", "DATABASE_ACQUIRE_TIMEOUT"),
+  ("database.idle_timeout", "DATABASE_IDLE_TIMEOUT"),
+  ("database.max_lifetime", "DATABASE_MAX_LIFETIME"),
+  ("database.statement_timeout", "DATABASE_STATEMENT_TIMEOUT"),
+  ("database.connect_backoff", "DATABASE_CONNECT_BACKOFF"),
+  (
+    "database.connect_max_backoff",
+    "DATABASE_CONNECT_MAX_BACKOFF",
+  ),
+  ("database.retry_attempts", "DATABASE_RETRY_ATTEMPTS"),
+  ("database.retry_backoff", "DATABASE_RETRY_BACKOFF"),
+  ("database.retry_max_backoff", "DATABASE_RETRY_MAX_BACKOFF"),
+  ("logging.format", "LOG_FORMAT"),
+  ("logging.filter", "RUST_LOG"),
+  ("logging.otlp_endpoint", "OTEL_EXPORTER_OTLP_ENDPOINT"),
+  ("limits.rate_limits", "RATE_LIMITS"),
+  ("limits.peer_rate_limits", "PEER_RATE_LIMITS"),
+  ("limits.max_todos_per_owner", "MAX_TODOS_PER_OWNER"),
+  ("todos.max_subtask_depth", "MAX_SUBTASK_DEPTH"),
+  ("todos.subtask_completion", "SUBTASK_COMPLETION"),
+  ("todos.search_language", "SEARCH_LANGUAGE"),
+  ("reminders.notifier", "REMINDERS_NOTIFIER"),
+  ("reminders.poll_interval", "REMINDERS_POLL_INTERVAL"),
+  ("reminders.batch_size", "REMINDERS_BATCH_SIZE"),
+  ("reminders.lease", "REMINDERS_LEASE"),
+  ("reminders.timeout", "REMINDERS_TIMEOUT"),
+  ("reminders.max_attempts", "REMINDERS_MAX_ATTEMPTS"),
+  ("reminders.retry_backoff", "REMINDERS_RETRY_BACKOFF"),
+  ("reminders.webhook_url", "REMINDERS_WEBHOOK_URL"),
+  ("reminders.smtp_host", "REMINDERS_SMTP_HOST"),
+  ("reminders.smtp_port", "REMINDERS_SMTP_PORT"),
+  ("reminders.email_from", "REMINDERS_EMAIL_FROM"),
+  ("reminders.email_domain", "REMINDERS_EMAIL_DOMAIN"),
+  ("attachments.store", "ATTACHMENTS_STORE"),
+  ("attachments.path", "ATTACHMENTS_PATH"),
+  ("attachments.s3_endpoint", "ATTACHMENTS_S3_ENDPOINT"),
+  ("attachments.s3_bucket", "ATTACHMENTS_S3_BUCKET"),
+  ("attachments.s3_region", "ATTACHMENTS_S3_REGION"),
+  (
+    "attachments.s3_access_key_id",
+    "ATTACHMENTS_S3_ACCESS_KEY_ID",
+  ),
+  (
+    "attachments.s3_secret_access_key",
+    "ATTACHMENTS_S3_SECRET_ACCESS_KEY",
+  ),
+  ("attachments.max_size", "ATTACHMENTS_MAX_SIZE"),
+  ("attachments.chunk_size", "ATTACHMENTS_CHUNK_SIZE"),
+  ("attachments.sweep_interval", "ATTACHMENTS_SWEEP_INTERVAL"),
+];
+
+const DEFAULT_SERVER_NAME: &str = "todos-service";
+const DEFAULT_SERVER_HOST: &str = "0.0.0.0";
+const DEFAULT_SERVER_PORT: u16 = 8080;
+const DEFAULT_ADMIN_HOST: &str = "0.0.0.0";
+const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+const DEFAULT_MIN_CONNECTIONS: u32 = 0;
+const DEFAULT_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const DEFAULT_MAX_LIFETIME: Duration = Duration::from_secs(30 * 60);
+const DEFAULT_CONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_CONNECT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(50);
+const DEFAULT_RETRY_MAX_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_LOG_FILTER: &str = "info";
+const DEFAULT_MAX_SUBTASK_DEPTH: u32 = 3;
+const DEFAULT_SEARCH_LANGUAGE: &str = "english";
+const DEFAULT_REMINDERS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_REMINDERS_BATCH_SIZE: u32 = 100;
+const DEFAULT_REMINDERS_LEASE: Duration = Duration::from_secs(5 * 60);
+const DEFAULT_REMINDERS_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_REMINDERS_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_REMINDERS_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+const DEFAULT_SMTP_HOST: &str = "localhost";
+const DEFAULT_SMTP_PORT: u16 = 25;
+const DEFAULT_ATTACHMENTS_PATH: &str = "attachments";
+const DEFAULT_S3_REGION: &str = "us-east-1";
+const DEFAULT_ATTACHMENTS_MAX_SIZE: u64 = 25 * 1024 * 1024;
+const DEFAULT_ATTACHMENTS_CHUNK_SIZE: usize = 64 * 1024;
+const DEFAULT_ATTACHMENTS_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// The largest chunk that attachments can be downloaded in, which leaves room
+/// in gRPC's default 4 MiB message limit for the rest of the message.
+const MAX_ATTACHMENTS_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The command line flags that add to the configuration. Binaries should
+/// flatten these into their own arguments.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct ConfigArgs {
+  /// The TOML configuration file to load. Overrides `CONFIG_FILE`.
+  #[arg(long = "config", value_name = "PATH", global = true)]
+  pub config_file: Option<PathBuf>,
+  /// Set a configuration key, overriding the configuration file and the
+  /// environment, e.g. `--set database.max_connections=20`. May be repeated.
+  #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+  pub overrides: Vec<String>,
+}
+
+/// The configuration of the server and the CLI.
+#[derive(Debug, Clone)]
+pub struct Config {
+  pub server: ServerConfig,
+  pub admin: AdminConfig,
+  pub database: DatabaseConfig,
+  pub logging: LoggingConfig,
+  pub limits: LimitsConfig,
+  pub todos: TodosConfig,
+  pub reminders: RemindersConfig,
+  pub attachments: AttachmentsConfig,
+}
+
+/// Where the gRPC server listens for connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listener {
+  /// A TCP socket, used when `server.host` is an IP address.
+  Tcp(SocketAddr),
+  /// A unix domain socket, used when `server.host` is a path starting with
+  /// `/`. The port is ignored.
+  Unix(String),
+}
+
+/// The configuration of the gRPC server.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+  /// The name of the server, used as the application name of its database
+  /// connections and the service name of its spans.
+  pub name: String,
+  pub listener: Listener,
+  /// How long a request can take before it fails with `CANCELLED`, or `None`
+  /// if requests can take any amount of time.
+  pub request_timeout: Option<Duration>,
+  /// The origins that browsers can call the server from, see
+  /// [`crate::grpc_web::create_cors_layer`].
+  pub cors_allowed_origins: Vec<HeaderValue>,
+  /// The largest body in bytes that the REST/JSON gateway reads, see
+  /// [`crate::gateway`].
+  pub gateway_max_body_size: usize,
+  /// The TLS certificate of the server, or `None` to serve plain text.
+  pub tls: Option<TlsConfig>,
+}
+
+/// The files that the server's TLS configuration is loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsConfig {
+  /// The PEM encoded certificate chain of the server.
+  pub cert_path: PathBuf,
+  /// The PEM encoded private key of the server.
+  pub key_path: PathBuf,
+  /// The PEM encoded certificate authority that client certificates must be
+  /// signed by, or `None` if clients do not need a certificate.
+  pub client_ca_path: Option<PathBuf>,
+}
+
+impl TlsConfig {
+  /// Read the certificate files into a tonic TLS configuration.
+  pub fn load(&self) -> anyhow::Result<ServerTlsConfig> {
+    let cert = std::fs::read(&self.cert_path)?;
+    let key = std::fs::read(&self.key_path)?;
+    let mut config =
+      ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+
+    if let Some(client_ca_path) = &self.client_ca_path {
+      let client_ca = std::fs::read(client_ca_path)?;
+      config = config.client_ca_root(Certificate::from_pem(client_ca));
+    }
+
+    Ok(config)
+  }
+}
+
+/// The configuration of the admin HTTP server, which serves the metrics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminConfig {
+  /// The address to listen on, or `None` if the admin server is disabled,
+  /// which is the case unless `admin.port` is set.
+  pub address: Option<SocketAddr>,
+}
+
+/// The configuration of the database pool.
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+  /// The options parsed from `database.url`, with the application name set to
+  /// `server.name`.
+  pub connect_options: PgConnectOptions,
+  /// The options parsed from `database.read_url`, the read replica that
+  /// read-only requests use, or `None` if there is no replica.
+  pub read_connect_options: Option<PgConnectOptions>,
+  /// The maximum number of connections in the pool, and in the read replica's
+  /// pool.
+  pub max_connections: u32,
+  /// The number of connections that the pool keeps open when idle.
+  pub min_connections: u32,
+  /// How long a request waits for a connection before it fails.
+  pub acquire_timeout: Duration,
+  /// How long a connection can be idle before it is closed, if the pool has
+  /// more than `min_connections`.
+  pub idle_timeout: Duration,
+  /// How long a connection can be open before it is closed and replaced.
+  pub max_lifetime: Duration,
+  /// How long a single statement can run before Postgres cancels it, or
+  /// `None` to use the database's default.
+  pub statement_timeout: Option<Duration>,
+  /// How long the server waits before retrying after failing to connect to
+  /// the database at startup. This doubles after each attempt.
+  pub connect_backoff: Duration,
+  /// The longest that the server waits between attempts to connect.
+  pub connect_max_backoff: Duration,
+  /// How many times a request's database operation is attempted before a
+  /// transient error is returned, see [`crate::retry`].
+  pub retry_attempts: u32,
+  /// How long to wait before the first retry of a transient error. This
+  /// doubles after each attempt, and is jittered.
+  pub retry_backoff: Duration,
+  /// The longest that a request waits between attempts.
+  pub retry_max_backoff: Duration,
+}
+
+/// The configuration of logging and tracing, see [`crate::telemetry`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggingConfig {
+  pub format: LogFormat,
+  /// The filter applied to logs and spans, in the `RUST_LOG` syntax.
+  pub filter: String,
+  /// The OpenTelemetry collector to export spans to, or `None` if spans are
+  /// not exported.
+  pub otlp_endpoint: Option<String>,
+}
+
+/// The configuration of the limits placed on clients.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LimitsConfig {
+  pub rate_limits: RateLimitConfig,
+  /// The rate limits applied to each peer address before requests are
+  /// authenticated.
+  pub peer_rate_limits: RateLimitConfig,
+  /// The maximum number of todos that each user can own, or `None` if there is
+  /// no limit.
+  pub max_todos_per_owner: Option<i64>,
+}
+
+/// The configuration of how todos behave, see [`crate::services::todos`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TodosConfig {
+  /// The deepest that subtasks can be nested, where a subtask of a todo that
+  /// is not itself a subtask has a depth of 1.
+  pub max_subtask_depth: u32,
+  /// What completing a todo that has subtasks does.
+  pub subtask_completion: SubtaskCompletion,
+  /// The PostgreSQL text search configuration that new todos are indexed in,
+  /// and that search queries are parsed with, e.g. `english`.
+  pub search_language: &'static str,
+}
+
+impl Default for TodosConfig {
+  fn default() -> Self {
+    Self {
+      max_subtask_depth: DEFAULT_MAX_SUBTASK_DEPTH,
+      subtask_completion: SubtaskCompletion::default(),
+      search_language: DEFAULT_SEARCH_LANGUAGE,
+    }
+  }
+}
+
+/// The configuration of the reminder scheduler, see [`crate::reminders`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemindersConfig {
+  pub notifier: NotifierConfig,
+  /// How long the scheduler waits before looking for due reminders again,
+  /// after it finds fewer than `batch_size`.
+  pub poll_interval: Duration,
+  /// The most reminders that the scheduler claims at once.
+  pub batch_size: u32,
+  /// How long a claimed reminder is hidden from other schedulers while it is
+  /// sent. If the scheduler stops before it records the attempt, then another
+  /// scheduler sends the reminder after this long.
+  pub lease: Duration,
+  /// How long sending a single reminder can take before it fails.
+  pub timeout: Duration,
+  /// How many times a reminder is sent before the scheduler gives up on it.
+  pub max_attempts: u32,
+  /// How long to wait before sending a reminder again after it failed. This
+  /// doubles after each attempt.
+  pub retry_backoff: Duration,
+}
+
+/// How reminders are sent, which is set by `reminders.notifier`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotifierConfig {
+  /// Reminders are not sent, and the scheduler does not run.
+  None,
+  /// Reminders are written to the log.
+  Log,
+  /// Reminders are posted as JSON to the given URL.
+  Webhook { url: String },
+  /// Reminders are emailed to their owners through an SMTP relay, which must
+  /// accept mail without authentication or TLS. The address of each owner is
+  /// their ID, followed by `@domain` if a domain is set.
+  Email {
+    smtp_host: String,
+    smtp_port: u16,
+    from: String,
+    domain: Option<String>,
+  },
+}
+
+/// The configuration of the files attached to todos, see [`crate::blobs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentsConfig {
+  pub store: BlobStoreConfig,
+  /// The largest file that can be attached, in bytes.
+  pub max_size: u64,
+  /// The largest chunk that attachments are downloaded in, in bytes.
+  pub chunk_size: usize,
+  /// How long the sweeper waits before looking for the blobs of deleted
+  /// attachments again, after it finds no more to remove.
+  pub sweep_interval: Duration,
+}
+
+impl Default for AttachmentsConfig {
+  fn default() -> Self {
+    Self {
+      store: BlobStoreConfig::Filesystem {
+        path: PathBuf::from(DEFAULT_ATTACHMENTS_PATH),
+      },
+      max_size: DEFAULT_ATTACHMENTS_MAX_SIZE,
+      chunk_size: DEFAULT_ATTACHMENTS_CHUNK_SIZE,
+      sweep_interval: DEFAULT_ATTACHMENTS_SWEEP_INTERVAL,
+    }
+  }
+}
+
+/// Where the content of attachments is kept, which is set by
+/// `attachments.store`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobStoreConfig {
+  /// Attachments are disabled, and the sweeper does not run.
+  None,
+  /// The content of each attachment is a file in the given directory, which
+  /// is created if it does not exist.
+  Filesystem { path: PathBuf },
+  /// The content of each attachment is an object in an S3 bucket, or a bucket
+  /// of an S3-compatible service such as MinIO.
+  S3(S3Config),
+}
+
+/// The bucket that the S3 blob store keeps attachments in, and the
+/// credentials that it signs requests with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct S3Config {
+  /// The URL of the service, such as `http://localhost:9000` for a local
+  /// MinIO. Buckets are always addressed by path, rather than by host name.
+  pub endpoint: String,
+  pub bucket: String,
+  pub region: String,
+  pub access_key_id: String,
+  pub secret_access_key: String,
+}
+
+/// The secret access key is left out, so that it is not logged.
+impl fmt::Debug for S3Config {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("S3Config")
+      .field("endpoint", &self.endpoint)
+      .field("bucket", &self.bucket)
+      .field("region", &self.region)
+      .field("access_key_id", &self.access_key_id)
+      .finish_non_exhaustive()
+  }
+}
+
+/// The layer that set the value of a configuration key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+  File(PathBuf),
+  Environment(&'static str),
+  Flag,
+}
+
+impl fmt::Display for Source {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Source::File(path) => write!(f, "file {}", path.display()),
+      Source::Environment(name) => write!(f, "environment variable {}", name),
+      Source::Flag => write!(f, "--set flag"),
+    }
+  }
+}
+
+/// A problem with the configuration, such as an invalid value or an unknown
+/// key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+  /// The key with the problem, or `None` if the problem is with the
+  /// configuration file as a whole.
+  pub key: Option<String>,
+  /// The layer that set the invalid value, or `None` if the key is missing.
+  pub source: Option<Source>,
+  pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(key) = &self.key {
+      write!(f, "{}: ", key)?;
+    }
+    write!(f, "{}", self.message)?;
+    if let Some(source) = &self.source {
+      write!(f, " (from {})", source)?;
+    }
+    Ok(())
+  }
+}
+
+/// Every problem found while loading the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl fmt::Display for ConfigErrors {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Invalid configuration:")?;
+    for error in &self.0 {
+      write!(f, "\n  - {}", error)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+impl Config {
+  /// Load the configuration from the file, the process's environment
+  /// variables and the flags.
+  pub fn load(args: &ConfigArgs) -> Result<Self, ConfigErrors> {
+    Self::load_with_env(args, |name| require_environment_variable(name).ok())
+  }
+
+  /// Load the configuration from the file, the given environment variables
+  /// and the flags. This allows tests to load the configuration without
+  /// changing the environment of the process.
+  pub fn load_with_env(
+    args: &ConfigArgs,
+    env: impl Fn(&str) -> Option<String>,
+  ) -> Result<Self, ConfigErrors> {
+    let mut values = Values::default();
+
+    let config_file = args.config_file.clone().map(|path| (path, Source::Flag));
+    let config_file = config_file.or_else(|| {
+      env(CONFIG_FILE_ENVIRONMENT_VARIABLE).map(|path| {
+        let source = Source::Environment(CONFIG_FILE_ENVIRONMENT_VARIABLE);
+        (PathBuf::from(path), source)
+      })
+    });
+    if let Some((path, source)) = config_file {
+      values.read_file(&path, source);
+    }
+
+    for (key, name) in KEYS {
+      if let Some(value) = env(name) {
+        values.set(key, value, Source::Environment(name));
+      }
+    }
+
+    for entry in &args.overrides {
+      match entry.split_once('=') {
+        Some((key, value)) => {
+          values.set_by_name(key.trim(), value.to_string(), Source::Flag)
+        }
+        None => values.errors.push(ConfigError {
+          key: None,
+          source: Some(Source::Flag),
+          message: format!("Expected key=value, got {}", entry),
+        }),
+      }
+    }
+
+    values.into_config()
+  }
+}
+
+/// A value set by one of the layers.
+#[derive(Debug)]
+struct Value {
+  value: String,
+  source: Source,
+}
+
+/// The merged values of every layer, and the problems found so far.
+#[derive(Debug, Default)]
+struct Values {
+  values: BTreeMap<&'static str, Value>,
+  errors: Vec<ConfigError>,
+}
+
+impl Values {
+  /// Set a key, which must be one of [`KEYS`].
+  fn set(&mut self, key: &'static str, value: String, source: Source) {
+    self.values.insert(key, Value { value, source });
+  }
+
+  /// Set a key by name, reporting an error if the key is not known.
+  fn set_by_name(&mut self, name: &str, value: String, source: Source) {
+    match KEYS.iter().find(|(key, _)| *key == name) {
+      Some((key, _)) => self.set(key, value, source),
+      None => self.errors.push(ConfigError {
+        key: Some(name.to_string()),
+        source: Some(source),
+        message: "Unknown key".to_string(),
+      }),
+    }
+  }
+
+  /// Read the keys from a TOML file.
+  fn read_file(&mut self, path: &Path, source: Source) {
+    let table = std::fs::read_to_string(path)
+      .map_err(anyhow::Error::from)
+      .and_then(|contents| Ok(contents.parse::<toml::Table>()?));
+
+    match table {
+      Ok(table) => self.read_table(path, "", table),
+      Err(e) => self.errors.push(ConfigError {
+        key: None,
+        source: Some(source),
+        message: format!("Failed to read {}: {}", path.display(), e),
+      }),
+    }
+  }
+
+  /// Read the keys from a TOML table, where `prefix` is the key of the table.
+  fn read_table(&mut self, path: &Path, prefix: &str, table: toml::Table) {
+    let source = Source::File(path.to_path_buf());
+
+    for (name, value) in table {
+      let key = format!("{}{}", prefix, name);
+      let value = match value {
+        toml::Value::Table(table) => {
+          self.read_table(path, &format!("{}.", key), table);
+          continue;
+        }
+        toml::Value::String(value) => value,
+        toml::Value::Array(values) => {
+          // Lists are set with comma separated strings in the environment and
+          // flags, so we convert arrays to the same format.
+          let values = values
+            .into_iter()
+            .map(|value| match value {
+              toml::Value::String(value) => value,
+              value => value.to_string(),
+            })
+            .collect::<Vec<_>>();
+          values.join(",")
+        }
+        value => value.to_string(),
+      };
+
+      self.set_by_name(&key, value, source.clone());
+    }
+  }
+
+  /// Parse the value of an optional key, reporting an error if it is invalid.
+  fn get<T>(
+    &mut self,
+    key: &'static str,
+    parse: impl FnOnce(&str) -> anyhow::Result<T>,
+  ) -> Option<T> {
+    let value = self.values.get(key)?;
+
+    match parse(value.value.trim()) {
+      Ok(result) => Some(result),
+      Err(e) => {
+        let error = ConfigError {
+          key: Some(key.to_string()),
+          source: Some(value.source.clone()),
+          message: e.to_string(),
+        };
+        self.errors.push(error);
+        None
+      }
+    }
+  }
+
+  /// Parse the value of a required key, reporting an error if it is missing.
+  fn require<T>(
+    &mut self,
+    key: &'static str,
+    parse: impl FnOnce(&str) -> anyhow::Result<T>,
+  ) -> Option<T> {
+    if !self.values.contains_key(key) {
+      let name = get_environment_variable_name(key);
+      self.error(key, format!("Required, but not set. Set it with {}", name));
+    }
+
+    self.get(key, parse)
+  }
+
+  /// Report an error with a key.
+  fn error(&mut self, key: &'static str, message: String) {
+    let source = self.values.get(key).map(|value| value.source.clone());
+    self.errors.push(ConfigError {
+      key: Some(key.to_string()),
+      source,
+      message,
+    });
+  }
+
+  /// Parse and validate every key, and build the configuration if they are
+  /// all valid.
+  fn into_config(mut self) -> Result<Config, ConfigErrors> {
+    let server = self.get_server_config();
+    let admin = self.get_admin_config();
+    let database = self.get_database_config(&server.name);
+    let logging = self.get_logging_config();
+    let limits = self.get_limits_config();
+    let todos = self.get_todos_config();
+    let reminders = self.get_reminders_config();
+    let attachments = self.get_attachments_config();
+
+    match (self.errors.is_empty(), database) {
+      (true, Some(database)) => Ok(Config {
+        server,
+        admin,
+        database,
+        logging,
+        limits,
+        todos,
+        reminders,
+        attachments,
+      }),
+      _ => Err(ConfigErrors(self.errors)),
+    }
+  }
+
+  fn get_server_config(&mut self) -> ServerConfig {
+    let name = self
+      .get("server.name", parse_non_empty)
+      .unwrap_or_else(|| DEFAULT_SERVER_NAME.to_string());
+    let host = self
+      .get("server.host", parse_non_empty)
+      .unwrap_or_else(|| DEFAULT_SERVER_HOST.to_string());
+    let port = self
+      .get("server.port", parse_port)
+      .unwrap_or(DEFAULT_SERVER_PORT);
+
+    let listener = if host.starts_with('/') {
+      Listener::Unix(host)
+    } else {
+      match parse_address(&host, port) {
+        Ok(address) => Listener::Tcp(address),
+        Err(e) => {
+          self.error("server.host", e.to_string());
+          Listener::Tcp(SocketAddr::from(([0, 0, 0, 0], port)))
+        }
+      }
+    };
+
+    ServerConfig {
+      name,
+      listener,
+      request_timeout: self.get("server.request_timeout", parse_duration),
+      cors_allowed_origins: self
+        .get("server.cors_allowed_origins", parse_allowed_origins)
+        .unwrap_or_default(),
+      gateway_max_body_size: self
+        .get("server.gateway_max_body_size", parse_positive)
+        .unwrap_or(gateway::DEFAULT_MAX_BODY_SIZE),
+      tls: self.get_tls_config(),
+    }
+  }
+
+  fn get_tls_config(&mut self) -> Option<TlsConfig> {
+    const TLS_KEYS: [&str; 3] = [
+      "server.tls.cert_path",
+      "server.tls.key_path",
+      "server.tls.client_ca_path",
+    ];
+
+    // Setting any of the TLS keys enables TLS, which needs a certificate and a
+    // private key.
+    if TLS_KEYS.iter().any(|key| self.values.contains_key(key)) {
+      for key in &TLS_KEYS[..2] {
+        if !self.values.contains_key(key) {
+          let name = get_environment_variable_name(key);
+          self.error(key, format!("Required for TLS. Set it with {}", name));
+        }
+      }
+    }
+
+    let cert_path = self.get(TLS_KEYS[0], parse_existing_file);
+    let key_path = self.get(TLS_KEYS[1], parse_existing_file);
+    let client_ca_path = self.get(TLS_KEYS[2], parse_existing_file);
+
+    Some(TlsConfig {
+      cert_path: cert_path?,
+      key_path: key_path?,
+      client_ca_path,
+    })
+  }
+
+  fn get_admin_config(&mut self) -> AdminConfig {
+    let host = self
+      .get("admin.host", parse_non_empty)
+      .unwrap_or_else(|| DEFAULT_ADMIN_HOST.to_string());
+    let port = self.get("admin.port", parse_port);
+
+    let address = port.and_then(|port| match parse_address(&host, port) {
+      Ok(address) => Some(address),
+      Err(e) => {
+        self.error("admin.host", e.to_string());
+        None
+      }
+    });
+
+    AdminConfig { address }
+  }
+
+  fn get_database_config(
+    &mut self,
+    application_name: &str,
+  ) -> Option<DatabaseConfig> {
+    let connect_options = self.require("database.url", |value| {
+      Ok(value.parse::<PgConnectOptions>()?)
+    });
+    let read_connect_options = self.get("database.read_url", |value| {
+      Ok(value.parse::<PgConnectOptions>()?)
+    });
+    let max_connections = self
+      .get("database.max_connections", parse_positive)
+      .unwrap_or(DEFAULT_MAX_CONNECTIONS);
+    let min_connections = self
+      .get("database.min_connections", |value| Ok(value.parse()?))
+      .unwrap_or(DEFAULT_MIN_CONNECTIONS);
+    let acquire_timeout = self
+      .get("database.acquire_timeout", parse_duration)
+      .unwrap_or(DEFAULT_ACQUIRE_TIMEOUT);
+    let idle_timeout = self
+      .get("database.idle_timeout", parse_duration)
+      .unwrap_or(DEFAULT_IDLE_TIMEOUT);
+    let max_lifetime = self
+      .get("database.max_lifetime", parse_duration)
+      .unwrap_or(DEFAULT_MAX_LIFETIME);
+    let statement_timeout =
+      self.get("database.statement_timeout", parse_duration);
+    let connect_backoff = self
+      .get("database.connect_backoff", parse_duration)
+      .unwrap_or(DEFAULT_CONNECT_BACKOFF);
+    let connect_max_backoff = self
+      .get("database.connect_max_backoff", parse_duration)
+      .unwrap_or(DEFAULT_CONNECT_MAX_BACKOFF);
+    let retry_attempts = self
+      .get("database.retry_attempts", parse_positive)
+      .unwrap_or(DEFAULT_RETRY_ATTEMPTS);
+    let retry_backoff = self
+      .get("database.retry_backoff", parse_duration)
+      .unwrap_or(DEFAULT_RETRY_BACKOFF);
+    let retry_max_backoff = self
+      .get("database.retry_max_backoff", parse_duration)
+      .unwrap_or(DEFAULT_RETRY_MAX_BACKOFF);
+
+    if min_connections > max_connections {
+      self.error(
+        "database.min_connections",
+        format!(
+          "Must not be greater than database.max_connections ({})",
+          max_connections
+        ),
+      );
+    }
+    if connect_backoff.is_zero() {
+      self.error("database.connect_backoff", "Must be positive".to_string());
+    }
+    if connect_max_backoff < connect_backoff {
+      self.error(
+        "database.connect_max_backoff",
+        format!(
+          "Must not be less than database.connect_backoff ({:?})",
+          connect_backoff
+        ),
+      );
+    }
+    if retry_max_backoff < retry_backoff {
+      self.error(
+        "database.retry_max_backoff",
+        format!(
+          "Must not be less than database.retry_backoff ({:?})",
+          retry_backoff
+        ),
+      );
+    }
+
+    Some(DatabaseConfig {
+      connect_options: connect_options?.application_name(application_name),
+      read_connect_options: read_connect_options
+        .map(|options| options.application_name(application_name)),
+      max_connections,
+      min_connections,
+      acquire_timeout,
+      idle_timeout,
+      max_lifetime,
+      statement_timeout,
+      connect_backoff,
+      connect_max_backoff,
+      retry_attempts,
+      retry_backoff,
+      retry_max_backoff,
+    })
+  }
+
+  fn get_logging_config(&mut self) -> LoggingConfig {
+    LoggingConfig {
+      format: self
+        .get("logging.format", |value| value.parse())
+        .unwrap_or_default(),
+      filter: self
+        .get("logging.filter", |value| {
+          EnvFilter::try_new(value)?;
+          Ok(value.to_string())
+        })
+        .unwrap_or_else(|| DEFAULT_LOG_FILTER.to_string()),
+      otlp_endpoint: self.get("logging.otlp_endpoint", parse_non_empty),
+    }
+  }
+
+  fn get_limits_config(&mut self) -> LimitsConfig {
+    LimitsConfig {
+      rate_limits: self
+        .get("limits.rate_limits", |value| value.parse())
+        .unwrap_or_default(),
+      peer_rate_limits: self
+        .get("limits.peer_rate_limits", |value| value.parse())
+        .unwrap_or_default(),
+      max_todos_per_owner: self
+        .get("limits.max_todos_per_owner", parse_positive),
+    }
+  }
+
+  fn get_todos_config(&mut self) -> TodosConfig {
+    TodosConfig {
+      max_subtask_depth: self
+        .get("todos.max_subtask_depth", parse_positive)
+        .unwrap_or(DEFAULT_MAX_SUBTASK_DEPTH),
+      subtask_completion: self
+        .get("todos.subtask_completion", |value| value.parse())
+        .unwrap_or_default(),
+      search_language: self
+        .get("todos.search_language", parse_search_language)
+        .unwrap_or(DEFAULT_SEARCH_LANGUAGE),
+    }
+  }
+
+  fn get_reminders_config(&mut self) -> RemindersConfig {
+    let notifier = match self
+      .get("reminders.notifier", |value| Ok(value.to_string()))
+      .as_deref()
+    {
+      None | Some("log") => NotifierConfig::Log,
+      Some("none") => NotifierConfig::None,
+      Some("webhook") => NotifierConfig::Webhook {
+        url: self
+          .require("reminders.webhook_url", parse_http_url)
+          .unwrap_or_default(),
+      },
+      Some("email") => NotifierConfig::Email {
+        smtp_host: self
+          .get("reminders.smtp_host", parse_non_empty)
+          .unwrap_or_else(|| DEFAULT_SMTP_HOST.to_string()),
+        smtp_port: self
+          .get("reminders.smtp_port", parse_port)
+          .unwrap_or(DEFAULT_SMTP_PORT),
+        from: self
+          .require("reminders.email_from", parse_non_empty)
+          .unwrap_or_default(),
+        domain: self.get("reminders.email_domain", parse_non_empty),
+      },
+      Some(value) => {
+        self.error(
+          "reminders.notifier",
+          format!("Expected none, log, webhook or email, got {}", value),
+        );
+        NotifierConfig::None
+      }
+    };
+    let poll_interval = self
+      .get("reminders.poll_interval", parse_duration)
+      .unwrap_or(DEFAULT_REMINDERS_POLL_INTERVAL);
+    let batch_size = self
+      .get("reminders.batch_size", parse_positive)
+      .unwrap_or(DEFAULT_REMINDERS_BATCH_SIZE);
+    let lease = self
+      .get("reminders.lease", parse_duration)
+      .unwrap_or(DEFAULT_REMINDERS_LEASE);
+    let timeout = self
+      .get("reminders.timeout", parse_duration)
+      .unwrap_or(DEFAULT_REMINDERS_TIMEOUT);
+    let max_attempts = self
+      .get("reminders.max_attempts", parse_positive)
+      .unwrap_or(DEFAULT_REMINDERS_MAX_ATTEMPTS);
+    let retry_backoff = self
+      .get("reminders.retry_backoff", parse_duration)
+      .unwrap_or(DEFAULT_REMINDERS_RETRY_BACKOFF);
+
+    // A reminder that is still being sent when its lease expires could be
+    // claimed and sent again by another scheduler.
+    if lease <= timeout {
+      self.error(
+        "reminders.lease",
+        format!("Must be greater than reminders.timeout ({:?})", timeout),
+      );
+    }
+
+    RemindersConfig {
+      notifier,
+      poll_interval,
+      batch_size,
+      lease,
+      timeout,
+      max_attempts,
+      retry_backoff,
+    }
+  }
+
+  fn get_attachments_config(&mut self) -> AttachmentsConfig {
+    let store = match self
+      .get("attachments.store", |value| Ok(value.to_string()))
+      .as_deref()
+    {
+      None | Some("filesystem") => BlobStoreConfig::Filesystem {
+        path: self
+          .get("attachments.path", parse_non_empty)
+          .map(PathBuf::from)
+          .unwrap_or_else(|| PathBuf::from(DEFAULT_ATTACHMENTS_PATH)),
+      },
+      Some("none") => BlobStoreConfig::None,
+      Some("s3") => BlobStoreConfig::S3(S3Config {
+        endpoint: self
+          .require("attachments.s3_endpoint", parse_http_url)
+          .unwrap_or_default(),
+        bucket: self
+          .require("attachments.s3_bucket", parse_non_empty)
+          .unwrap_or_default(),
+        region: self
+          .get("attachments.s3_region", parse_non_empty)
+          .unwrap_or_else(|| DEFAULT_S3_REGION.to_string()),
+        access_key_id: self
+          .require("attachments.s3_access_key_id", parse_non_empty)
+          .unwrap_or_default(),
+        secret_access_key: self
+          .require("attachments.s3_secret_access_key", parse_non_empty)
+          .unwrap_or_default(),
+      }),
+      Some(value) => {
+        self.error(
+          "attachments.store",
+          format!("Expected none, filesystem or s3, got {}", value),
+        );
+        BlobStoreConfig::None
+      }
+    };
+    let max_size = self
+      .get("attachments.max_size", parse_positive)
+      .unwrap_or(DEFAULT_ATTACHMENTS_MAX_SIZE);
+    let chunk_size = self
+      .get("attachments.chunk_size", parse_positive)
+      .unwrap_or(DEFAULT_ATTACHMENTS_CHUNK_SIZE);
+    let sweep_interval = self
+      .get("attachments.sweep_interval", parse_duration)
+      .unwrap_or(DEFAULT_ATTACHMENTS_SWEEP_INTERVAL);
+
+    if chunk_size > MAX_ATTACHMENTS_CHUNK_SIZE {
+      self.error(
+        "attachments.chunk_size",
+        format!("Must be at most {}", MAX_ATTACHMENTS_CHUNK_SIZE),
+      );
+    }
+
+    AttachmentsConfig {
+      store,
+      max_size,
+      chunk_size,
+      sweep_interval,
+    }
+  }
+}
+
+/// Get the environment variable that sets the given key.
+fn get_environment_variable_name(key: &str) -> &'static str {
+  KEYS
+    .iter()
+    .find(|(name, _)| *name == key)
+    .map(|(_, name)| *name)
+    .unwrap_or_default()
+}
+
+fn parse_non_empty(value: &str) -> anyhow::Result<String> {
+  if value.is_empty() {
+    return Err(anyhow!("Must not be empty"));
+  }
+  Ok(value.to_string())
+}
+
+fn parse_http_url(value: &str) -> anyhow::Result<String> {
+  match reqwest::Url::parse(value) {
+    Ok(url) if matches!(url.scheme(), "http" | "https") => {
+      Ok(value.to_string())
+    }
+    _ => Err(anyhow!("Expected an http or https URL, got {}", value)),
+  }
+}
+
+fn parse_port(value: &str) -> anyhow::Result<u16> {
+  value
+    .parse()
+    .map_err(|_| anyhow!("Expected a port between 0 and 65535, got {}", value))
+}
+
+fn parse_positive<T>(value: &str) -> anyhow::Result<T>
+where
+  T: FromStr + Default + PartialOrd,
+{
+  match value.parse::<T>() {
+    Ok(result) if result > T::default() => Ok(result),
+    _ => Err(anyhow!("Expected a positive integer, got {}", value)),
+  }
+}
+
+/// Parse an IP address and port into a socket address.
+fn parse_address(host: &str, port: u16) -> anyhow::Result<SocketAddr> {
+  format!("{}:{}", host, port).parse().map_err(|_| {
+    anyhow!(
+      "Expected an IP address or a path starting with /, got {}",
+      host
+    )
+  })
+}
+
+/// Parse a duration, either as a number of seconds (e.g. `30` or `0.5`) or
+/// with units (e.g. `30s`, `500ms` or `1m 30s`).
+fn parse_duration(value: &str) -> anyhow::Result<Duration> {
+  if let Ok(seconds) = value.parse::<f64>() {
+    return Duration::try_from_secs_f64(seconds)
+      .map_err(|_| anyhow!("Expected a positive duration, got {}", value));
+  }
+
+  humantime::parse_duration(value).map_err(|e| {
+    anyhow!("Expected a duration such as 30s, got {}: {}", value, e)
+  })
+}
+
+/// Parse the path of a file, which must exist.
+fn parse_existing_file(value: &str) -> anyhow::Result<PathBuf> {
+  let path = PathBuf::from(value);
+  if !path.is_file() {
+    return Err(anyhow!("File {} does not exist", value));
+  }
+  Ok(path)
+}
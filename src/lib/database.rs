@@ -1,21 +1,207 @@
 //! This module contains functions for creating and dropping databases, and for
 //! creating database pools.
+//!
+//! The server can also be configured with a read replica, which read-only
+//! requests use so that they do not add load to the primary. Replicas lag
+//! behind the primary, so clients that need to read their own writes can ask
+//! for [`ReadConsistency::Strong`] reads, which always use the primary.
 
-use crate::common::require_environment_variable;
-use log::info;
+use crate::config::Config;
+use crate::config::ConfigArgs;
+use crate::config::DatabaseConfig;
+use crate::metrics::METRICS;
 use pg_escape::quote_identifier;
 use sqlx::postgres::PgConnectOptions;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::Connection;
+use sqlx::PgConnection;
 use sqlx::PgPool;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::metadata::MetadataMap;
+use tonic::Status;
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+/// The database role that requests run as. Unlike the role that the server
+/// connects as, it is always subject to the row level security policies that
+/// isolate each tenant's data.
+pub const TENANT_ROLE: &str = "todos_tenant";
+
+/// The database role that the reminder scheduler runs as. It can see the todos
+/// of every tenant, but can only change when their reminders are sent. See
+/// [`crate::reminders`].
+pub const SCHEDULER_ROLE: &str = "todos_scheduler";
+
+/// The metadata key that sets the [`ReadConsistency`] of a request.
+pub const READ_CONSISTENCY_METADATA_KEY: &str = "x-read-consistency";
+
+/// How up to date the data returned by a read-only request must be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadConsistency {
+  /// The data can be read from the read replica, so it may not include the
+  /// most recent writes.
+  #[default]
+  Eventual,
+  /// The data is read from the primary, so it includes every committed write.
+  Strong,
+}
+
+impl ReadConsistency {
+  /// Get the consistency that a request asked for with the
+  /// `x-read-consistency` metadata entry, which can be `strong` or `eventual`.
+  /// Requests without the entry get eventual consistency.
+  pub fn from_metadata(metadata: &MetadataMap) -> Result<Self, Status> {
+    let Some(value) = metadata.get(READ_CONSISTENCY_METADATA_KEY) else {
+      return Ok(Self::default());
+    };
+
+    match value.to_str() {
+      Ok("strong") => Ok(Self::Strong),
+      Ok("eventual") => Ok(Self::Eventual),
+      _ => Err(Status::invalid_argument(format!(
+        "{} must be strong or eventual",
+        READ_CONSISTENCY_METADATA_KEY
+      ))),
+    }
+  }
+}
+
+/// Create a new SQLx database pool, sized and connected as configured. This
+/// function connects to the database before it returns, so it fails if the
+/// database is not reachable.
+pub async fn create_database_pool(
+  config: &DatabaseConfig,
+) -> anyhow::Result<PgPool> {
+  let pool = get_pool_options(config)
+    .connect_with(get_connect_options(config, &config.connect_options))
+    .await?;
 
-/// Create a new SQLx database pool. This function reads the DATABASE_URL
-/// environment variable to determine the connection string to use.  If the
-/// environment variable is not set, then the function will return an error.
-pub async fn create_database_pool() -> anyhow::Result<PgPool> {
-  let options = get_database_pool_base_options()?;
-  let pool = PgPool::connect_with(options).await?;
   Ok(pool)
 }
 
+/// Create a new SQLx database pool, sized and connected as configured, without
+/// connecting to the database. Connections are opened when they are first
+/// needed, so this succeeds even if the database is not reachable yet. Use
+/// [`wait_for_database`] to find out when it is.
+pub fn create_lazy_database_pool(config: &DatabaseConfig) -> PgPool {
+  get_pool_options(config)
+    .connect_lazy_with(get_connect_options(config, &config.connect_options))
+}
+
+/// Create a pool for the read replica, if one is configured, without
+/// connecting to it. It is sized in the same way as the primary's pool.
+pub fn create_lazy_read_pool(config: &DatabaseConfig) -> Option<PgPool> {
+  let options = config.read_connect_options.as_ref()?;
+
+  Some(
+    get_pool_options(config)
+      .connect_lazy_with(get_connect_options(config, options)),
+  )
+}
+
+/// Wait until the database can be connected to, retrying with exponential
+/// backoff from `connect_backoff` up to `connect_max_backoff`. This never
+/// gives up, so callers that need a deadline should wrap it in a timeout.
+pub async fn wait_for_database(config: &DatabaseConfig) {
+  let options = get_connect_options(config, &config.connect_options);
+  let mut backoff = config.connect_backoff;
+
+  loop {
+    match PgConnection::connect_with(&options).await {
+      Ok(connection) => {
+        let _ = connection.close().await;
+        info!("Connected to the database.");
+        return;
+      }
+      Err(e) => {
+        warn!(
+          error = %e,
+          retry_in_ms = backoff.as_millis() as u64,
+          "Failed to connect to the database"
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(config.connect_max_backoff);
+      }
+    }
+  }
+}
+
+/// Get the pool options from the configuration.
+fn get_pool_options(config: &DatabaseConfig) -> PgPoolOptions {
+  PgPoolOptions::new()
+    .max_connections(config.max_connections)
+    .min_connections(config.min_connections)
+    .acquire_timeout(config.acquire_timeout)
+    .idle_timeout(config.idle_timeout)
+    .max_lifetime(config.max_lifetime)
+}
+
+/// Get the options for connecting to the primary or the read replica, which
+/// sets the `statement_timeout` of each connection if it is configured.
+fn get_connect_options(
+  config: &DatabaseConfig,
+  options: &PgConnectOptions,
+) -> PgConnectOptions {
+  let options = options.clone();
+
+  match config.statement_timeout {
+    Some(timeout) => {
+      options.options([("statement_timeout", timeout.as_millis().to_string())])
+    }
+    None => options,
+  }
+}
+
+/// Begin a transaction that is scoped to the given tenant. The tenant ID is
+/// stored in the transaction-local `app.tenant_id` setting, which the row level
+/// security policies on each table compare against, and the transaction
+/// switches to the [`TENANT_ROLE`] so that the policies apply. Both are reset
+/// when the transaction ends, so a pooled connection can never leak one
+/// request's tenant into another request.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn begin_tenant_transaction(
+  pool: &PgPool,
+  tenant_id: &str,
+) -> anyhow::Result<Transaction<'static, Postgres>> {
+  let mut transaction = {
+    let _timer = METRICS.start_db_acquire();
+    pool.begin().await?
+  };
+
+  sqlx::query("select set_config('app.tenant_id', $1, true)")
+    .bind(tenant_id)
+    .execute(&mut *transaction)
+    .await?;
+
+  let role = quote_identifier(TENANT_ROLE);
+  sqlx::query(&format!("set local role {role}"))
+    .execute(&mut *transaction)
+    .await?;
+
+  Ok(transaction)
+}
+
+/// Begin a transaction for the reminder scheduler, which switches to the
+/// [`SCHEDULER_ROLE`] until the transaction ends.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn begin_scheduler_transaction(
+  pool: &PgPool,
+) -> anyhow::Result<Transaction<'static, Postgres>> {
+  let mut transaction = {
+    let _timer = METRICS.start_db_acquire();
+    pool.begin().await?
+  };
+
+  let role = quote_identifier(SCHEDULER_ROLE);
+  sqlx::query(&format!("set local role {role}"))
+    .execute(&mut *transaction)
+    .await?;
+
+  Ok(transaction)
+}
+
 /// Create a new database with the given name, using a connection to the
 /// postgres database.
 pub async fn create_database(database_name: &str) -> anyhow::Result<()> {
@@ -43,12 +229,11 @@ pub async fn drop_database(database_name: &str) -> anyhow::Result<()> {
   Ok(())
 }
 
-/// Create a new SQLx database pool for a named database. This function reads
-/// the DATABASE_URL environment variable to determine the connection string to
-/// use, and then connects to the database with the given name. If the
-/// environment variable is not set, then the function will return an error.
-/// If we cannot connect to the database, then the function will return an
-/// error.
+/// Create a new SQLx database pool for a named database. This function loads
+/// the configuration to determine the connection string to use, and then
+/// connects to the database with the given name. If the configuration is
+/// invalid, then the function will return an error. If we cannot connect to
+/// the database, then the function will return an error.
 ///
 /// This function is useful for creating a database pool for a test database,
 /// or for creating a database pool for a specific database.
@@ -62,27 +247,14 @@ pub async fn create_database_pool_for_named_database(
   Ok(pool)
 }
 
-/// Get the pool options from the environment variables, and set the application
-/// name to the SERVER_NAME environment variable. This function should be
-/// extended to set any other standard pool options for the application.
-///
-/// If the DATABASE_URL environment variable is not set, then the function will
-/// return an error.
+/// Get the connection options from the configuration, which is loaded from
+/// the configuration file and the environment variables without any flags.
+/// See [`crate::config`].
 ///
-/// If the SERVER_NAME  environment variable is not set, then the function will
-/// return an error.
+/// If the configuration is invalid, for example because `database.url` is not
+/// set, then the function will return an error.
 pub fn get_database_pool_base_options() -> anyhow::Result<PgConnectOptions> {
-  let options = get_database_options_from_url()?;
-  let application_name = require_environment_variable("SERVER_NAME")?;
-  let options = options.application_name(&application_name);
-
-  Ok(options)
-}
-
-/// Get the database options from the DATABASE_URL environment variable
-fn get_database_options_from_url() -> anyhow::Result<PgConnectOptions> {
-  let connection_string = require_environment_variable("DATABASE_URL")?;
-  let options: PgConnectOptions = connection_string.parse()?;
+  let config = Config::load(&ConfigArgs::default())?;
 
-  Ok(options)
+  Ok(config.database.connect_options)
 }
@@ -0,0 +1,774 @@
+//! This module contains a tower layer that serves the services as a REST/JSON
+//! API, by transcoding HTTP requests into gRPC requests in process.
+//!
+//! The routes are read from the `google.api.http` annotations on each method
+//! in [`crate::proto::FILE_DESCRIPTOR_SET`], so adding an annotation to a
+//! method is enough to expose it over HTTP. Request and response messages use
+//! the [proto3 JSON mapping](https://protobuf.dev/programming-guides/json/),
+//! so for example a `Timestamp` is an RFC 3339 string and a `FieldMask` is a
+//! comma separated string of field names.
+//!
+//! A request is mapped onto a gRPC request as follows:
+//! - fields named in the path template are set from the path, e.g.
+//!   `/v1/todos/{todo_id}`, which may end in a custom verb, e.g.
+//!   `/v1/todos/{todo_id}:move`. A variable can match several segments, e.g.
+//!   `/v1/{name=lists/*/todos/*}` sets `name` to `lists/work/todos/123`;
+//! - the field named by the rule's `body` is set from the JSON body, or the
+//!   whole message if the body is `*`;
+//! - any other fields can be set with query parameters, e.g.
+//!   `?update_mask=title`.
+//!
+//! Request bodies are read into memory before they are transcoded, so they are
+//! limited to `server.gateway_max_body_size` bytes, and longer bodies are
+//! rejected with `413 Payload Too Large`.
+//!
+//! If the request message has an `update_mask` field that was not set, then it
+//! is set to the fields present in the body, so that `PATCH` requests only
+//! update the fields that they send.
+//!
+//! The gRPC request is passed to the wrapped service, so it goes through the
+//! same authentication, rate limiting, tracing and metrics as any other RPC,
+//! and the gRPC status is mapped to the closest HTTP status code. This layer
+//! must be applied outside of [`tonic_web::GrpcWebLayer`], which rejects
+//! HTTP/1.1 requests that are not gRPC-Web requests.
+
+use bytes::Buf;
+use bytes::BufMut;
+use bytes::Bytes;
+use bytes::BytesMut;
+use futures::future::BoxFuture;
+use http_body_util::BodyExt;
+use http_body_util::Full;
+use http_body_util::LengthLimitError;
+use http_body_util::Limited;
+use prost::Message;
+use prost_reflect::DescriptorPool;
+use prost_reflect::DynamicMessage;
+use prost_reflect::Kind;
+use prost_reflect::MessageDescriptor;
+use prost_reflect::SerializeOptions;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::task::Context;
+use std::task::Poll;
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::codegen::http::header;
+use tonic::codegen::http::HeaderMap;
+use tonic::codegen::http::HeaderValue;
+use tonic::codegen::http::Method;
+use tonic::codegen::http::StatusCode;
+use tonic::server::NamedService;
+use tonic::Code;
+use tonic::Status;
+use tonic_types::StatusExt;
+use tower::Layer;
+use tracing::warn;
+
+/// The name of the extension that holds the HTTP rule for a method.
+const HTTP_RULE_EXTENSION: &str = "google.api.http";
+
+/// The name of the request field that is set from the body when it is not set
+/// explicitly.
+const UPDATE_MASK_FIELD: &str = "update_mask";
+
+/// The largest request body that is read by default, which is the same as the
+/// largest message that tonic decodes by default.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// The routes of every annotated method in the compiled descriptor set.
+static ROUTES: LazyLock<Arc<Vec<Route>>> = LazyLock::new(|| {
+  let pool = DescriptorPool::decode(crate::proto::FILE_DESCRIPTOR_SET)
+    .expect("The compiled file descriptor set is valid");
+  Arc::new(get_routes(&pool))
+});
+
+/// A segment of a path template.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+  /// A segment that must match exactly.
+  Literal(String),
+  /// One or more segments that set the request field with the given path,
+  /// e.g. `todo.todo_id`. Each segment of the pattern is either a literal or
+  /// `*`, which matches any single segment.
+  Variable {
+    path: Vec<String>,
+    pattern: Vec<String>,
+  },
+}
+
+/// A path template, made of its segments and an optional custom verb, e.g.
+/// `move` in `/v1/todos/{todo_id}:move`.
+#[derive(Debug, Clone, PartialEq)]
+struct PathTemplate {
+  segments: Vec<Segment>,
+  verb: Option<String>,
+}
+
+/// An HTTP route that is mapped to a gRPC method.
+#[derive(Debug, Clone)]
+struct Route {
+  method: Method,
+  template: PathTemplate,
+  body: Option<String>,
+  grpc_path: String,
+  input: MessageDescriptor,
+  output: MessageDescriptor,
+}
+
+impl Route {
+  /// Match the route against a request, returning the values of the path's
+  /// variables if it matches.
+  fn matches(&self, method: &Method, path: &str) -> Option<Vec<String>> {
+    if method != self.method {
+      return None;
+    }
+
+    let path = path.trim_matches('/');
+    let path = match &self.template.verb {
+      Some(verb) => path.strip_suffix(verb)?.strip_suffix(':')?,
+      None => path,
+    };
+    let mut parts = path.split('/');
+
+    let mut values = Vec::new();
+    for segment in &self.template.segments {
+      match segment {
+        Segment::Literal(literal) => {
+          if parts.next()? != literal {
+            return None;
+          }
+        }
+        Segment::Variable { pattern, .. } => {
+          let mut value = Vec::new();
+          for expected in pattern {
+            let part = parts.next()?;
+            if part.is_empty() || (expected != "*" && expected != part) {
+              return None;
+            }
+            value.push(
+              percent_encoding::percent_decode_str(part)
+                .decode_utf8_lossy()
+                .to_string(),
+            );
+          }
+          values.push(value.join("/"));
+        }
+      }
+    }
+    if parts.next().is_some() {
+      return None;
+    }
+
+    Some(values)
+  }
+}
+
+/// Get the routes for every method in the pool with an HTTP rule. Rules that
+/// cannot be parsed are skipped with a warning.
+fn get_routes(pool: &DescriptorPool) -> Vec<Route> {
+  let Some(extension) = pool.get_extension_by_name(HTTP_RULE_EXTENSION) else {
+    return Vec::new();
+  };
+
+  let mut routes = Vec::new();
+  for service in pool.services() {
+    for method in service.methods() {
+      let options = method.options();
+      if !options.has_extension(&extension) {
+        continue;
+      }
+      let Some(rule) = options.get_extension(&extension).as_message().cloned()
+      else {
+        continue;
+      };
+
+      let mut rules = vec![rule.clone()];
+      if let Some(bindings) = rule
+        .get_field_by_name("additional_bindings")
+        .and_then(|value| value.as_list().map(<[_]>::to_vec))
+      {
+        rules.extend(bindings.iter().filter_map(|b| b.as_message().cloned()));
+      }
+
+      for rule in rules {
+        let grpc_path = format!("/{}/{}", service.full_name(), method.name());
+        match parse_rule(&rule) {
+          Some((http_method, template, body)) => routes.push(Route {
+            method: http_method,
+            template,
+            body,
+            grpc_path,
+            input: method.input(),
+            output: method.output(),
+          }),
+          None => warn!("Skipping unsupported HTTP rule for {}", grpc_path),
+        }
+      }
+    }
+  }
+
+  routes
+}
+
+/// Parse the method, path template, custom verb and body of an HTTP rule.
+/// Variables can match a single segment, e.g. `{todo_id}` or `{todo_id=*}`,
+/// or a fixed number of segments, e.g. `{name=lists/*/todos/*}`, but `**` is
+/// not supported.
+fn parse_rule(
+  rule: &DynamicMessage,
+) -> Option<(Method, PathTemplate, Option<String>)> {
+  let get_string = |name: &str| {
+    rule
+      .get_field_by_name(name)
+      .and_then(|value| value.as_str().map(str::to_string))
+      .filter(|value| !value.is_empty())
+  };
+
+  let (method, template) = [
+    (Method::GET, "get"),
+    (Method::PUT, "put"),
+    (Method::POST, "post"),
+    (Method::DELETE, "delete"),
+    (Method::PATCH, "patch"),
+  ]
+  .into_iter()
+  .find_map(|(method, name)| Some((method, get_string(name)?)))?;
+
+  // A custom verb follows the last segment, e.g. `/v1/todos/{todo_id}:move`.
+  let template = template.trim_matches('/');
+  let (template, verb) = match template.rsplit_once(':') {
+    Some((template, verb)) if !verb.contains(['/', '}']) => {
+      (template, Some(verb.to_string()))
+    }
+    _ => (template, None),
+  };
+
+  let mut segments = Vec::new();
+  let mut parts = template.split('/');
+  while let Some(part) = parts.next() {
+    let Some(variable) = part.strip_prefix('{') else {
+      if part.contains(['{', '}', '*', ':']) {
+        return None;
+      }
+      segments.push(Segment::Literal(part.to_string()));
+      continue;
+    };
+
+    // The pattern of a variable may span several segments.
+    let mut variable = variable.to_string();
+    while !variable.ends_with('}') {
+      variable = format!("{}/{}", variable, parts.next()?);
+    }
+    let variable = &variable[..variable.len() - 1];
+    let (path, pattern) = variable.split_once('=').unwrap_or((variable, "*"));
+    let pattern = pattern.split('/').map(str::to_string).collect::<Vec<_>>();
+    let is_valid = |segment: &String| {
+      segment == "*" || !segment.contains(['{', '}', '*', '=', ':'])
+    };
+    if path.contains(['{', '*', '/'])
+      || pattern
+        .iter()
+        .any(|segment| segment.is_empty() || !is_valid(segment))
+    {
+      return None;
+    }
+    segments.push(Segment::Variable {
+      path: path.split('.').map(str::to_string).collect(),
+      pattern,
+    });
+  }
+
+  Some((method, PathTemplate { segments, verb }, get_string("body")))
+}
+
+/// A tower layer that transcodes REST/JSON requests into gRPC requests. Any
+/// gRPC request is passed to the wrapped service unchanged.
+#[derive(Debug, Clone)]
+pub struct GatewayLayer {
+  routes: Arc<Vec<Route>>,
+  max_body_size: usize,
+}
+
+impl GatewayLayer {
+  /// Create a new layer with the routes from the compiled descriptor set,
+  /// which reads request bodies of up to `max_body_size` bytes.
+  pub fn new(max_body_size: usize) -> Self {
+    Self {
+      routes: Arc::clone(&ROUTES),
+      max_body_size,
+    }
+  }
+}
+
+impl Default for GatewayLayer {
+  fn default() -> Self {
+    Self::new(DEFAULT_MAX_BODY_SIZE)
+  }
+}
+
+impl<S> Layer<S> for GatewayLayer {
+  type Service = GatewayService<S>;
+
+  fn layer(&self, inner: S) -> Self::Service {
+    GatewayService {
+      inner,
+      routes: Arc::clone(&self.routes),
+      max_body_size: self.max_body_size,
+    }
+  }
+}
+
+/// The service created by the [`GatewayLayer`].
+#[derive(Debug, Clone)]
+pub struct GatewayService<S> {
+  inner: S,
+  routes: Arc<Vec<Route>>,
+  max_body_size: usize,
+}
+
+impl<S> tower::Service<http::Request<BoxBody>> for GatewayService<S>
+where
+  S: tower::Service<http::Request<BoxBody>, Response = http::Response<BoxBody>>
+    + Clone
+    + Send
+    + 'static,
+  S::Future: Send + 'static,
+  S::Error: Send,
+{
+  type Response = S::Response;
+  type Error = S::Error;
+  type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+    if is_grpc_request(request.headers()) {
+      return Box::pin(self.inner.call(request));
+    }
+
+    let matched = self.routes.iter().find_map(|route| {
+      let values = route.matches(request.method(), request.uri().path())?;
+      Some((route.clone(), values))
+    });
+    let Some((route, values)) = matched else {
+      let status = Status::not_found(format!(
+        "No route for {} {}",
+        request.method(),
+        request.uri().path()
+      ));
+      return Box::pin(async move { Ok(create_error_response(&status)) });
+    };
+
+    // The inner service has been driven to readiness, so we must call that
+    // instance rather than the clone. See:
+    // <https://docs.rs/tower/latest/tower/trait.Service.html#be-careful-when-cloning-inner-services>
+    let clone = self.inner.clone();
+    let mut inner = std::mem::replace(&mut self.inner, clone);
+    let max_body_size = self.max_body_size;
+
+    Box::pin(async move {
+      let (parts, body) = request.into_parts();
+      let body = match read_body(body, max_body_size).await {
+        Ok(body) => body,
+        Err(response) => return Ok(response),
+      };
+      let grpc_request = match create_grpc_request(&route, values, parts, &body)
+      {
+        Ok(grpc_request) => grpc_request,
+        Err(status) => return Ok(create_error_response(&status)),
+      };
+      let grpc_response = inner.call(grpc_request).await?;
+
+      Ok(create_http_response(&route, grpc_response).await)
+    })
+  }
+}
+
+impl<S: NamedService> NamedService for GatewayService<S> {
+  const NAME: &'static str = S::NAME;
+}
+
+/// Whether the request is a gRPC request, rather than a REST request.
+fn is_grpc_request(headers: &HeaderMap) -> bool {
+  headers
+    .get(header::CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .is_some_and(|value| value.starts_with("application/grpc"))
+}
+
+/// Read the whole body of an HTTP request. If the body is longer than
+/// `max_size` bytes, then reading stops, and the response to send instead is
+/// a `413 Payload Too Large` error.
+async fn read_body(
+  body: BoxBody,
+  max_size: usize,
+) -> Result<Bytes, http::Response<BoxBody>> {
+  match Limited::new(body, max_size).collect().await {
+    Ok(collected) => Ok(collected.to_bytes()),
+    Err(e) if e.is::<LengthLimitError>() => {
+      let status = Status::invalid_argument(format!(
+        "Request body is larger than {} bytes",
+        max_size
+      ));
+      let mut response = create_error_response(&status);
+      *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+      Err(response)
+    }
+    Err(e) => {
+      let status = match e.downcast::<Status>() {
+        Ok(status) => *status,
+        Err(e) => Status::internal(format!("Failed to read body: {}", e)),
+      };
+      Err(create_error_response(&status))
+    }
+  }
+}
+
+/// Transcode an HTTP request into a gRPC request for the route's method. The
+/// headers and extensions of the request are kept, so that the request can be
+/// authenticated and traced.
+fn create_grpc_request(
+  route: &Route,
+  values: Vec<String>,
+  mut parts: http::request::Parts,
+  body: &[u8],
+) -> Result<http::Request<BoxBody>, Status> {
+  let message = create_request_message(route, values, parts.uri.query(), body)?;
+
+  parts.method = Method::POST;
+  parts.uri = route
+    .grpc_path
+    .parse()
+    .map_err(|_| Status::internal("Invalid gRPC path"))?;
+  parts.version = http::Version::HTTP_2;
+  parts.headers.remove(header::CONTENT_LENGTH);
+  parts.headers.insert(
+    header::CONTENT_TYPE,
+    HeaderValue::from_static("application/grpc"),
+  );
+  parts
+    .headers
+    .insert(header::TE, HeaderValue::from_static("trailers"));
+
+  Ok(http::Request::from_parts(
+    parts,
+    full_body(encode_grpc_frame(&message)),
+  ))
+}
+
+/// Create the request message from the path variables, the query string and
+/// the JSON body, using the proto3 JSON mapping.
+fn create_request_message(
+  route: &Route,
+  values: Vec<String>,
+  query: Option<&str>,
+  body: &[u8],
+) -> Result<DynamicMessage, Status> {
+  let mut fields = Map::new();
+  let mut update_mask = None;
+
+  if let Some(body_field) = &route.body {
+    let body = if body.is_empty() {
+      Value::Object(Map::new())
+    } else {
+      serde_json::from_slice(body).map_err(|e| {
+        Status::invalid_argument(format!("Invalid JSON body: {}", e))
+      })?
+    };
+
+    if body_field == "*" {
+      fields = match body {
+        Value::Object(fields) => fields,
+        _ => return Err(Status::invalid_argument("Body must be an object")),
+      };
+    } else {
+      update_mask = get_update_mask(route, body_field, &body);
+      fields.insert(body_field.clone(), body);
+    }
+  }
+
+  let variables =
+    route
+      .template
+      .segments
+      .iter()
+      .filter_map(|segment| match segment {
+        Segment::Variable { path, .. } => Some(path),
+        Segment::Literal(_) => None,
+      });
+  for (path, value) in variables.zip(values) {
+    set_field(&route.input, &mut fields, path, &value, false)?;
+  }
+
+  for (name, value) in form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+    let path = name.split('.').map(str::to_string).collect::<Vec<_>>();
+    set_field(&route.input, &mut fields, &path, &value, true)?;
+  }
+
+  if let Some(update_mask) = update_mask {
+    fields.entry(UPDATE_MASK_FIELD).or_insert(update_mask);
+  }
+
+  DynamicMessage::deserialize(route.input.clone(), Value::Object(fields))
+    .map_err(|e| Status::invalid_argument(format!("Invalid request: {}", e)))
+}
+
+/// Get the update mask for a request whose body is a single field, if the
+/// request has an update mask. The mask contains the fields in the body, as a
+/// proto3 JSON `FieldMask` string.
+fn get_update_mask(
+  route: &Route,
+  body_field: &str,
+  body: &Value,
+) -> Option<Value> {
+  let mask_field = route.input.get_field_by_name(UPDATE_MASK_FIELD)?;
+  let Kind::Message(mask_message) = mask_field.kind() else {
+    return None;
+  };
+  if mask_message.full_name() != "google.protobuf.FieldMask" {
+    return None;
+  }
+  let Kind::Message(body_message) =
+    route.input.get_field_by_name(body_field)?.kind()
+  else {
+    return None;
+  };
+
+  let paths = body
+    .as_object()?
+    .keys()
+    .filter_map(|key| {
+      body_message
+        .get_field_by_json_name(key)
+        .or_else(|| body_message.get_field_by_name(key))
+    })
+    .map(|field| field.json_name().to_string())
+    .collect::<Vec<_>>();
+
+  Some(Value::String(paths.join(",")))
+}
+
+/// Set the field with the given path in the JSON fields of a message, from
+/// the string value of a path variable or query parameter. Query parameters
+/// cannot override fields that are already set, and can be repeated to set
+/// a repeated field.
+fn set_field(
+  descriptor: &MessageDescriptor,
+  fields: &mut Map<String, Value>,
+  path: &[String],
+  value: &str,
+  is_query: bool,
+) -> Result<(), Status> {
+  let unknown =
+    || Status::invalid_argument(format!("Unknown field {}", path.join(".")));
+  let (name, rest) = path.split_first().ok_or_else(unknown)?;
+  let field = descriptor
+    .get_field_by_name(name)
+    .or_else(|| descriptor.get_field_by_json_name(name))
+    .ok_or_else(unknown)?;
+  let key = field.name().to_string();
+
+  if !rest.is_empty() {
+    let Kind::Message(message) = field.kind() else {
+      return Err(unknown());
+    };
+    let entry = fields
+      .entry(key)
+      .or_insert_with(|| Value::Object(Map::new()));
+    let Value::Object(nested) = entry else {
+      return Err(Status::invalid_argument(format!(
+        "Field {} must be an object",
+        name
+      )));
+    };
+    return set_field(&message, nested, rest, value, is_query);
+  }
+
+  let value = match field.kind() {
+    Kind::Bool => Value::Bool(value.parse().map_err(|_| {
+      Status::invalid_argument(format!("Field {} must be a boolean", name))
+    })?),
+    _ => Value::String(value.to_string()),
+  };
+
+  if field.is_list() {
+    let entry = fields.entry(key).or_insert_with(|| json!([]));
+    if let Value::Array(values) = entry {
+      values.push(value);
+    }
+  } else if !is_query {
+    // Path variables take precedence over the same field in the body, which
+    // may use the field's JSON name.
+    fields.remove(field.json_name());
+    fields.insert(key, value);
+  } else if !fields.contains_key(&key) {
+    fields.insert(key, value);
+  } else {
+    return Err(Status::invalid_argument(format!(
+      "Field {} is already set",
+      name
+    )));
+  }
+
+  Ok(())
+}
+
+/// Transcode a gRPC response into an HTTP response, with the response message
+/// as the JSON body. Metadata other than the gRPC headers is kept.
+async fn create_http_response(
+  route: &Route,
+  response: http::Response<BoxBody>,
+) -> http::Response<BoxBody> {
+  let (parts, body) = response.into_parts();
+  let mut response = match get_response_json(route, &parts.headers, body).await
+  {
+    Ok(json) => create_json_response(StatusCode::OK, json),
+    Err(status) => create_error_response(&status),
+  };
+
+  for (name, value) in &parts.headers {
+    if !is_grpc_header(name.as_str()) {
+      response.headers_mut().append(name, value.clone());
+    }
+  }
+
+  response
+}
+
+/// Get the JSON for the message in a gRPC response body, or the status of the
+/// response if it failed.
+async fn get_response_json(
+  route: &Route,
+  headers: &HeaderMap,
+  body: BoxBody,
+) -> Result<Vec<u8>, Status> {
+  let header_status = Status::from_header_map(headers);
+  let collected = body.collect().await?;
+  let trailer_status = collected.trailers().and_then(Status::from_header_map);
+
+  match header_status.or(trailer_status) {
+    Some(status) if status.code() != Code::Ok => return Err(status),
+    None => return Err(Status::internal("Response has no gRPC status")),
+    _ => {}
+  }
+
+  let message = decode_grpc_frame(&route.output, collected.to_bytes())?;
+  let mut serializer = serde_json::Serializer::new(Vec::new());
+  message
+    .serialize_with_options(
+      &mut serializer,
+      &SerializeOptions::new().skip_default_fields(false),
+    )
+    .map_err(|e| Status::internal(format!("Failed to encode JSON: {}", e)))?;
+
+  Ok(serializer.into_inner())
+}
+
+/// Whether the header is part of the gRPC protocol, rather than metadata.
+fn is_grpc_header(name: &str) -> bool {
+  name.starts_with("grpc-")
+    || name == header::CONTENT_TYPE
+    || name == header::CONTENT_LENGTH
+}
+
+/// Create an HTTP error response for a gRPC status, with a JSON body in the
+/// same format as `google.rpc.Status`. If the status has a retry delay, then
+/// it is also sent in the `Retry-After` header.
+fn create_error_response(status: &Status) -> http::Response<BoxBody> {
+  let body = json!({
+    "code": status.code() as i32,
+    "message": status.message(),
+  });
+  let mut response =
+    create_json_response(get_http_status(status.code()), body.to_string());
+
+  let retry_delay = status
+    .get_details_retry_info()
+    .and_then(|retry_info| retry_info.retry_delay);
+  if let Some(retry_delay) = retry_delay {
+    let seconds = retry_delay.as_secs_f64().ceil() as u64;
+    response
+      .headers_mut()
+      .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+  }
+  response
+}
+
+/// Create an HTTP response with a JSON body.
+fn create_json_response(
+  status: StatusCode,
+  body: impl Into<Bytes>,
+) -> http::Response<BoxBody> {
+  let mut response = http::Response::new(full_body(body.into()));
+  *response.status_mut() = status;
+  response.headers_mut().insert(
+    header::CONTENT_TYPE,
+    HeaderValue::from_static("application/json"),
+  );
+  response
+}
+
+/// Map a gRPC status code to the closest HTTP status code, following the
+/// mapping used by `google.rpc.Code`.
+fn get_http_status(code: Code) -> StatusCode {
+  match code {
+    Code::Ok => StatusCode::OK,
+    Code::Cancelled => {
+      StatusCode::from_u16(499).unwrap_or(StatusCode::BAD_REQUEST)
+    }
+    Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => {
+      StatusCode::BAD_REQUEST
+    }
+    Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+    Code::NotFound => StatusCode::NOT_FOUND,
+    Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+    Code::PermissionDenied => StatusCode::FORBIDDEN,
+    Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+    Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+    Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+    Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+    Code::Unknown | Code::Internal | Code::DataLoss => {
+      StatusCode::INTERNAL_SERVER_ERROR
+    }
+  }
+}
+
+/// Encode a message as a single uncompressed gRPC frame.
+fn encode_grpc_frame(message: &DynamicMessage) -> Bytes {
+  let length = message.encoded_len();
+  let mut frame = BytesMut::with_capacity(5 + length);
+  frame.put_u8(0);
+  frame.put_u32(length as u32);
+  message
+    .encode(&mut frame)
+    .expect("The buffer has enough capacity");
+  frame.freeze()
+}
+
+/// Decode the message in the first gRPC frame of a response body.
+fn decode_grpc_frame(
+  descriptor: &MessageDescriptor,
+  mut body: Bytes,
+) -> Result<DynamicMessage, Status> {
+  if body.remaining() < 5 || body.get_u8() != 0 {
+    return Err(Status::internal("Invalid gRPC response"));
+  }
+  let length = body.get_u32() as usize;
+  if body.remaining() < length {
+    return Err(Status::internal("Invalid gRPC response"));
+  }
+
+  DynamicMessage::decode(descriptor.clone(), body.split_to(length))
+    .map_err(|e| Status::internal(format!("Failed to decode response: {}", e)))
+}
+
+/// Create a response or request body from bytes.
+fn full_body(bytes: Bytes) -> BoxBody {
+  tonic::body::boxed(Full::new(bytes))
+}
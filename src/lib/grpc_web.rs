@@ -0,0 +1,82 @@
+//! This module contains the configuration for gRPC-Web, which lets browsers
+//! call the services directly, without a proxy to translate the protocol.
+//!
+//! The translation itself is done by [`tonic_web::GrpcWebLayer`], which
+//! supports both the binary (`application/grpc-web`) and text
+//! (`application/grpc-web-text`) modes. Browsers will only call the server
+//! from another origin if it allows them to with CORS, so this module builds
+//! the CORS layer from the configured list of allowed origins. The same layer
+//! also covers the REST gateway in [`crate::gateway`].
+
+use crate::auth::API_KEY_METADATA_KEY;
+use crate::database::READ_CONSISTENCY_METADATA_KEY;
+use crate::telemetry::REQUEST_ID_METADATA_KEY;
+use std::time::Duration;
+use tonic::codegen::http::HeaderName;
+use tonic::codegen::http::HeaderValue;
+use tonic::codegen::http::Method;
+use tonic::Status;
+use tower_http::cors::AllowOrigin;
+use tower_http::cors::CorsLayer;
+
+/// The origin that allows any origin to call the server.
+const ANY_ORIGIN: &str = "*";
+
+/// How long browsers can cache the result of a preflight request.
+const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The request headers that browsers are allowed to send. These are the
+/// headers used by gRPC-Web clients, and the metadata that browsers can set
+/// themselves. The user and tenant metadata are not included, because they are
+/// set by the trusted proxy and not by the browser.
+const ALLOWED_HEADERS: [&str; 7] = [
+  "content-type",
+  "grpc-timeout",
+  "x-grpc-web",
+  "x-user-agent",
+  API_KEY_METADATA_KEY,
+  REQUEST_ID_METADATA_KEY,
+  READ_CONSISTENCY_METADATA_KEY,
+];
+
+/// The response headers that browsers are allowed to read, so that clients can
+/// read the status of a failed RPC and the request ID.
+const EXPOSED_HEADERS: [HeaderName; 4] = [
+  Status::GRPC_STATUS,
+  Status::GRPC_MESSAGE,
+  Status::GRPC_STATUS_DETAILS,
+  HeaderName::from_static(REQUEST_ID_METADATA_KEY),
+];
+
+/// Create the CORS layer for gRPC-Web and REST requests, which allows the given
+/// origins to call the server, e.g. `https://todos.example.com`. The origin `*`
+/// allows any origin. If no origins are given, then only same-origin requests
+/// are allowed.
+pub fn create_cors_layer(allowed_origins: &[HeaderValue]) -> CorsLayer {
+  let allow_origin =
+    if allowed_origins.iter().any(|origin| origin == ANY_ORIGIN) {
+      AllowOrigin::any()
+    } else {
+      AllowOrigin::list(allowed_origins.iter().cloned())
+    };
+
+  CorsLayer::new()
+    .allow_origin(allow_origin)
+    .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+    .allow_headers(ALLOWED_HEADERS.map(HeaderName::from_static))
+    .expose_headers(EXPOSED_HEADERS)
+    .max_age(MAX_AGE)
+}
+
+/// Parse a comma separated list of allowed origins, e.g.
+/// `https://todos.example.com,http://localhost:3000`.
+pub fn parse_allowed_origins(value: &str) -> anyhow::Result<Vec<HeaderValue>> {
+  let origins = value
+    .split(',')
+    .map(str::trim)
+    .filter(|origin| !origin.is_empty())
+    .map(HeaderValue::from_str)
+    .collect::<Result<_, _>>()?;
+
+  Ok(origins)
+}
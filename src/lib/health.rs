@@ -0,0 +1,62 @@
+//! This module contains the gRPC health service, which load balancers and
+//! orchestrators use to decide whether to send traffic to the server. See
+//! <https://github.com/grpc/grpc/blob/master/doc/health-checking.md>.
+//!
+//! The server starts in a degraded mode where every service is reported as
+//! `NOT_SERVING`, and switches to `SERVING` once the database is reachable.
+//! This lets the server start before the database in environments such as
+//! docker compose, without receiving traffic that it cannot handle.
+
+use crate::config::DatabaseConfig;
+use crate::database::wait_for_database;
+use crate::proto::v1::api_keys::api_key_service_server;
+use crate::proto::v1::labels::label_service_server;
+use crate::proto::v1::todo_lists::todo_list_service_server;
+use crate::proto::v1::todos::todo_service_server;
+use tonic_health::pb::health_server::Health;
+use tonic_health::pb::health_server::HealthServer;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+use tracing::info;
+
+/// The names of the services that report their health. The empty name is the
+/// health of the server as a whole.
+const SERVICE_NAMES: [&str; 5] = [
+  "",
+  todo_service_server::SERVICE_NAME,
+  api_key_service_server::SERVICE_NAME,
+  label_service_server::SERVICE_NAME,
+  todo_list_service_server::SERVICE_NAME,
+];
+
+/// Create the health service, with every service reported as `NOT_SERVING`.
+/// The returned reporter updates the status that the service reports.
+pub async fn create_health_service(
+) -> (HealthReporter, HealthServer<impl Health>) {
+  let (mut reporter, service) = tonic_health::server::health_reporter();
+  set_serving_status(&mut reporter, ServingStatus::NotServing).await;
+
+  (reporter, service)
+}
+
+/// Report the given status for every service.
+pub async fn set_serving_status(
+  reporter: &mut HealthReporter,
+  status: ServingStatus,
+) {
+  for name in SERVICE_NAMES {
+    reporter.set_service_status(name, status).await;
+  }
+}
+
+/// Wait until the database is reachable, and then report every service as
+/// `SERVING`. This runs until the database is reachable, so it should be
+/// spawned as a separate task.
+pub async fn serve_when_database_is_ready(
+  mut reporter: HealthReporter,
+  config: DatabaseConfig,
+) {
+  wait_for_database(&config).await;
+  set_serving_status(&mut reporter, ServingStatus::Serving).await;
+  info!("Server is serving.");
+}
@@ -0,0 +1,395 @@
+//! This module contains the metrics that the server collects, and an HTTP
+//! admin server that exports them in the Prometheus text format.
+//!
+//! The gRPC metrics are collected by the [`MetricsLayer`], which should wrap
+//! every service, and follow the naming used by the
+//! [go-grpc-prometheus](https://github.com/grpc-ecosystem/go-grpc-prometheus)
+//! library so that existing dashboards can be reused. The database pool
+//! metrics are read from the pool when the metrics are scraped, except for the
+//! acquire metrics, which are recorded by [`crate::database`]. The reminder
+//! metrics are recorded by [`crate::reminders`].
+
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use futures::future::BoxFuture;
+use http_body::Body;
+use http_body::Frame;
+use prometheus::Encoder;
+use prometheus::Histogram;
+use prometheus::HistogramOpts;
+use prometheus::HistogramVec;
+use prometheus::IntCounterVec;
+use prometheus::IntGauge;
+use prometheus::Opts;
+use prometheus::Registry;
+use prometheus::TextEncoder;
+use sqlx::PgPool;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::LazyLock;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::server::NamedService;
+use tonic::Code;
+use tower::Layer;
+use tracing::info;
+
+/// The metrics collected by the server, registered in their own registry.
+pub struct Metrics {
+  registry: Registry,
+  grpc_started: IntCounterVec,
+  grpc_handled: IntCounterVec,
+  grpc_handling_seconds: HistogramVec,
+  db_pool_size: IntGauge,
+  db_pool_idle: IntGauge,
+  db_pool_acquire_waiting: IntGauge,
+  db_pool_acquire_seconds: Histogram,
+  reminder_deliveries: IntCounterVec,
+}
+
+/// The metrics for this process.
+pub static METRICS: LazyLock<Metrics> =
+  LazyLock::new(|| Metrics::new().expect("Failed to register metrics"));
+
+impl Metrics {
+  fn new() -> prometheus::Result<Self> {
+    let registry = Registry::new();
+
+    let grpc_started = IntCounterVec::new(
+      Opts::new(
+        "grpc_server_started_total",
+        "Total number of RPCs started on the server.",
+      ),
+      &["grpc_service", "grpc_method"],
+    )?;
+    let grpc_handled = IntCounterVec::new(
+      Opts::new(
+        "grpc_server_handled_total",
+        "Total number of RPCs completed on the server, regardless of success \
+         or failure.",
+      ),
+      &["grpc_service", "grpc_method", "grpc_code"],
+    )?;
+    let grpc_handling_seconds = HistogramVec::new(
+      HistogramOpts::new(
+        "grpc_server_handling_seconds",
+        "Histogram of response latency of RPCs handled by the server.",
+      ),
+      &["grpc_service", "grpc_method"],
+    )?;
+    let db_pool_size = IntGauge::new(
+      "db_pool_connections",
+      "Number of connections currently open in the database pool.",
+    )?;
+    let db_pool_idle = IntGauge::new(
+      "db_pool_idle_connections",
+      "Number of idle connections in the database pool.",
+    )?;
+    let db_pool_acquire_waiting = IntGauge::new(
+      "db_pool_acquire_waiting",
+      "Number of tasks waiting to acquire a connection from the database pool.",
+    )?;
+    let db_pool_acquire_seconds = Histogram::with_opts(HistogramOpts::new(
+      "db_pool_acquire_seconds",
+      "Histogram of the time taken to acquire a connection from the database \
+       pool.",
+    ))?;
+
+    let reminder_deliveries = IntCounterVec::new(
+      Opts::new(
+        "reminder_deliveries_total",
+        "Total number of attempts to send a reminder, by whether the reminder \
+         was sent, will be retried or was abandoned.",
+      ),
+      &["notifier", "result"],
+    )?;
+
+    registry.register(Box::new(grpc_started.clone()))?;
+    registry.register(Box::new(grpc_handled.clone()))?;
+    registry.register(Box::new(grpc_handling_seconds.clone()))?;
+    registry.register(Box::new(db_pool_size.clone()))?;
+    registry.register(Box::new(db_pool_idle.clone()))?;
+    registry.register(Box::new(db_pool_acquire_waiting.clone()))?;
+    registry.register(Box::new(db_pool_acquire_seconds.clone()))?;
+    registry.register(Box::new(reminder_deliveries.clone()))?;
+
+    Ok(Self {
+      registry,
+      grpc_started,
+      grpc_handled,
+      grpc_handling_seconds,
+      db_pool_size,
+      db_pool_idle,
+      db_pool_acquire_waiting,
+      db_pool_acquire_seconds,
+      reminder_deliveries,
+    })
+  }
+
+  /// Record the start of an attempt to acquire a database connection. The
+  /// returned guard records the time taken when it is dropped, so it should be
+  /// held until the connection has been acquired.
+  pub fn start_db_acquire(&self) -> AcquireTimer {
+    self.db_pool_acquire_waiting.inc();
+    AcquireTimer {
+      started_at: Instant::now(),
+    }
+  }
+
+  /// Record an attempt to send a reminder with the given notifier. The result
+  /// is `sent`, `retry` or `abandoned`.
+  pub fn record_reminder_delivery(&self, notifier: &str, result: &str) {
+    self
+      .reminder_deliveries
+      .with_label_values(&[notifier, result])
+      .inc();
+  }
+
+  /// Render all of the metrics in the Prometheus text format, after updating
+  /// the database pool gauges from the given pool.
+  pub fn render(&self, pool: &PgPool) -> anyhow::Result<String> {
+    self.db_pool_size.set(pool.size().into());
+    self.db_pool_idle.set(pool.num_idle() as i64);
+
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+    Ok(String::from_utf8(buffer)?)
+  }
+}
+
+/// Records the time taken to acquire a database connection when dropped. See
+/// [`Metrics::start_db_acquire`].
+pub struct AcquireTimer {
+  started_at: Instant,
+}
+
+impl Drop for AcquireTimer {
+  fn drop(&mut self) {
+    METRICS.db_pool_acquire_waiting.dec();
+    METRICS
+      .db_pool_acquire_seconds
+      .observe(self.started_at.elapsed().as_secs_f64());
+  }
+}
+
+/// Serve the metrics on `/metrics` on the given address, in the Prometheus
+/// text format. This runs until the server fails, so it should be spawned as
+/// a separate task.
+pub async fn serve_admin(
+  address: SocketAddr,
+  pool: PgPool,
+) -> anyhow::Result<()> {
+  let app = Router::new()
+    .route("/metrics", get(get_metrics))
+    .with_state(pool);
+  let listener = TcpListener::bind(address).await?;
+
+  info!("Starting admin server on tcp socket: {address}...");
+  axum::serve(listener, app).await?;
+
+  Ok(())
+}
+
+/// Handler for the `/metrics` endpoint.
+async fn get_metrics(State(pool): State<PgPool>) -> impl IntoResponse {
+  match METRICS.render(&pool) {
+    Ok(body) => (
+      http::StatusCode::OK,
+      [(CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+      body,
+    ),
+    Err(e) => (
+      http::StatusCode::INTERNAL_SERVER_ERROR,
+      [(CONTENT_TYPE, "text/plain")],
+      e.to_string(),
+    ),
+  }
+}
+
+/// A tower layer that records the gRPC metrics for each request.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsLayer;
+
+impl MetricsLayer {
+  /// Create a new layer, which records to the global [`METRICS`].
+  pub fn new() -> Self {
+    Self
+  }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+  type Service = MetricsService<S>;
+
+  fn layer(&self, inner: S) -> Self::Service {
+    MetricsService { inner }
+  }
+}
+
+/// The service created by the [`MetricsLayer`].
+#[derive(Debug, Clone)]
+pub struct MetricsService<S> {
+  inner: S,
+}
+
+impl<S, B> tower::Service<http::Request<B>> for MetricsService<S>
+where
+  S: tower::Service<http::Request<B>, Response = http::Response<BoxBody>>,
+  S::Future: Send + 'static,
+{
+  type Response = S::Response;
+  type Error = S::Error;
+  type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, request: http::Request<B>) -> Self::Future {
+    let recorder = RpcRecorder::start(request.uri().path());
+    let future = self.inner.call(request);
+
+    Box::pin(async move {
+      let response = future.await?;
+
+      // Errors that are returned before any messages are sent have their
+      // status in the headers. Otherwise the status is sent in the trailers, so
+      // we record it when the body has been sent.
+      let status = get_grpc_code(response.headers());
+      let (parts, body) = response.into_parts();
+      let body = MetricsBody {
+        inner: body,
+        recorder: Some(recorder),
+        code: status,
+        ended: false,
+      };
+
+      Ok(http::Response::from_parts(parts, tonic::body::boxed(body)))
+    })
+  }
+}
+
+impl<S: NamedService> NamedService for MetricsService<S> {
+  const NAME: &'static str = S::NAME;
+}
+
+/// The labels and start time of a single RPC.
+struct RpcRecorder {
+  service: String,
+  method: String,
+  started_at: Instant,
+}
+
+impl RpcRecorder {
+  /// Record that an RPC has started for the given request path, which has the
+  /// format `/package.Service/Method`.
+  fn start(path: &str) -> Self {
+    let (service, method) = path
+      .trim_start_matches('/')
+      .split_once('/')
+      .unwrap_or(("unknown", "unknown"));
+
+    METRICS
+      .grpc_started
+      .with_label_values(&[service, method])
+      .inc();
+
+    Self {
+      service: service.to_string(),
+      method: method.to_string(),
+      started_at: Instant::now(),
+    }
+  }
+
+  /// Record that the RPC has finished with the given code.
+  fn finish(self, code: Code) {
+    let labels = [self.service.as_str(), self.method.as_str()];
+
+    METRICS
+      .grpc_handling_seconds
+      .with_label_values(&labels)
+      .observe(self.started_at.elapsed().as_secs_f64());
+    METRICS
+      .grpc_handled
+      .with_label_values(&[labels[0], labels[1], &get_code_label(code)])
+      .inc();
+  }
+}
+
+/// Get the label for a gRPC status code, using the same names as the Go gRPC
+/// library, e.g. `OK` and `NotFound`.
+fn get_code_label(code: Code) -> String {
+  match code {
+    Code::Ok => "OK".to_string(),
+    code => format!("{:?}", code),
+  }
+}
+
+/// A response body that records the metrics for an RPC when it is dropped,
+/// using the status from the trailers if there are any.
+struct MetricsBody {
+  inner: BoxBody,
+  recorder: Option<RpcRecorder>,
+  code: Option<Code>,
+  ended: bool,
+}
+
+impl Body for MetricsBody {
+  type Data = <BoxBody as Body>::Data;
+  type Error = <BoxBody as Body>::Error;
+
+  fn poll_frame(
+    mut self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+  ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+    let result = Pin::new(&mut self.inner).poll_frame(cx);
+
+    match &result {
+      Poll::Ready(Some(Ok(frame))) => {
+        if let Some(code) = frame.trailers_ref().and_then(get_grpc_code) {
+          self.code = Some(code);
+        }
+      }
+      Poll::Ready(None) => self.ended = true,
+      _ => {}
+    }
+
+    result
+  }
+
+  fn is_end_stream(&self) -> bool {
+    self.inner.is_end_stream()
+  }
+
+  fn size_hint(&self) -> http_body::SizeHint {
+    self.inner.size_hint()
+  }
+}
+
+impl Drop for MetricsBody {
+  fn drop(&mut self) {
+    if let Some(recorder) = self.recorder.take() {
+      // A body that is dropped before it ends means that the client went away.
+      let code = match (self.code, self.ended || self.inner.is_end_stream()) {
+        (Some(code), _) => code,
+        (None, true) => Code::Unknown,
+        (None, false) => Code::Cancelled,
+      };
+      recorder.finish(code);
+    }
+  }
+}
+
+/// Get the gRPC status code from response headers or trailers, if present.
+fn get_grpc_code(headers: &http::HeaderMap) -> Option<Code> {
+  headers
+    .get("grpc-status")
+    .map(|value| Code::from_bytes(value.as_bytes()))
+}
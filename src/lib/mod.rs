@@ -1,7 +1,24 @@
+// Tonic uses `Status` as the error type throughout its API, so we return it
+// from our own helpers as well, even though clippy considers it large.
+#![allow(clippy::result_large_err)]
+
 pub mod api_docs;
+pub mod auth;
+pub mod blobs;
 pub mod common;
+pub mod config;
 pub mod database;
+pub mod gateway;
+pub mod grpc_web;
+pub mod health;
+pub mod metrics;
+pub mod position;
 pub mod proto;
+pub mod rate_limit;
+pub mod recurrence;
+pub mod reminders;
+pub mod retry;
 pub mod server;
 pub mod services;
+pub mod telemetry;
 pub mod update_mask_handler;
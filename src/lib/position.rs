@@ -0,0 +1,234 @@
+//! This module generates the position keys that todos are ordered by. A key is
+//! a string that sorts between its neighbours, so moving a todo only changes
+//! that todo's key, rather than renumbering the todos around it.
+//!
+//! The keys are those of
+//! [fractional indexing](https://observablehq.com/@dgreenspan/implementing-fractional-indexing):
+//! base 62 strings that are compared byte by byte, made of an integer part,
+//! whose first character gives its length, followed by an optional fraction.
+//! Adding todos at either end increments or decrements the integer part, so
+//! the keys stay short, while moving a todo between two others extends the
+//! fraction. The database generates the keys of new todos with the same
+//! scheme, in the `todo_position_after` function.
+
+use anyhow::anyhow;
+
+/// The digits of the keys, in the order that they sort.
+const DIGITS: &[u8] =
+  b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// The key of the first todo in an empty list.
+const INTEGER_ZERO: &str = "a0";
+
+/// The smallest integer part, which cannot be decremented.
+const SMALLEST_INTEGER: &str = "A00000000000000000000000000";
+
+/// Generate a key that sorts after `before` and before `after`, where `None`
+/// is the start or end of the list. The keys must be valid, and `before` must
+/// sort before `after`.
+pub fn key_between(
+  before: Option<&str>,
+  after: Option<&str>,
+) -> anyhow::Result<String> {
+  if let Some(key) = before {
+    validate_key(key)?;
+  }
+  if let Some(key) = after {
+    validate_key(key)?;
+  }
+
+  match (before, after) {
+    (None, None) => Ok(INTEGER_ZERO.to_string()),
+    (Some(a), Some(b)) if a >= b => {
+      Err(anyhow!("Position {} does not sort before {}", a, b))
+    }
+    (None, Some(b)) => {
+      let (integer, fraction) = split_key(b)?;
+      if integer == SMALLEST_INTEGER {
+        return Ok(format!("{}{}", integer, midpoint("", Some(fraction))));
+      }
+      // The integer part on its own sorts before the key if it has a
+      // fraction.
+      if !fraction.is_empty() {
+        return Ok(integer.to_string());
+      }
+      decrement_integer(integer)
+        .ok_or_else(|| anyhow!("Cannot create a position before {}", b))
+    }
+    (Some(a), None) => {
+      let (integer, fraction) = split_key(a)?;
+      Ok(match increment_integer(integer) {
+        Some(next) => next,
+        None => format!("{}{}", integer, midpoint(fraction, None)),
+      })
+    }
+    (Some(a), Some(b)) => {
+      let (integer_a, fraction_a) = split_key(a)?;
+      let (integer_b, fraction_b) = split_key(b)?;
+      if integer_a == integer_b {
+        return Ok(format!(
+          "{}{}",
+          integer_a,
+          midpoint(fraction_a, Some(fraction_b))
+        ));
+      }
+      match increment_integer(integer_a) {
+        Some(next) if next.as_str() < b => Ok(next),
+        _ => Ok(format!("{}{}", integer_a, midpoint(fraction_a, None))),
+      }
+    }
+  }
+}
+
+/// Check that a key has a valid integer part, and a fraction that does not end
+/// in zero, so that there is always room for another key before it.
+fn validate_key(key: &str) -> anyhow::Result<()> {
+  let (integer, fraction) = split_key(key)?;
+  if integer == SMALLEST_INTEGER
+    || !key.bytes().all(|byte| DIGITS.contains(&byte))
+    || fraction.ends_with('0')
+  {
+    return Err(anyhow!("Invalid position {}", key));
+  }
+
+  Ok(())
+}
+
+/// Split a key into its integer part and its fraction.
+fn split_key(key: &str) -> anyhow::Result<(&str, &str)> {
+  let invalid = || anyhow!("Invalid position {}", key);
+  let length = key
+    .bytes()
+    .next()
+    .and_then(integer_length)
+    .ok_or_else(invalid)?;
+
+  match key.is_char_boundary(length) && key.len() >= length {
+    true => Ok(key.split_at(length)),
+    false => Err(invalid()),
+  }
+}
+
+/// Get the length of an integer part from its first character. Positive
+/// integers start with `a` to `z`, and negative ones with `Z` to `A`, where
+/// the length grows further from `a0`.
+fn integer_length(head: u8) -> Option<usize> {
+  match head {
+    b'a'..=b'z' => Some(usize::from(head - b'a') + 2),
+    b'A'..=b'Z' => Some(usize::from(b'Z' - head) + 2),
+    _ => None,
+  }
+}
+
+/// Get the integer part that follows the given one, or `None` if it is the
+/// largest.
+fn increment_integer(integer: &str) -> Option<String> {
+  let (head, digits) = integer.as_bytes().split_first()?;
+  let mut digits = digits.to_vec();
+
+  for digit in digits.iter_mut().rev() {
+    let index = digit_index(*digit);
+    if index + 1 < DIGITS.len() {
+      *digit = DIGITS[index + 1];
+      return Some(format!("{}{}", *head as char, to_str(&digits)));
+    }
+    *digit = DIGITS[0];
+  }
+
+  // Every digit carried, so the integer part gets longer, or shorter if it is
+  // negative.
+  let head = match head {
+    b'Z' => return Some(INTEGER_ZERO.to_string()),
+    b'z' => return None,
+    head => head + 1,
+  };
+  match head > b'a' {
+    true => digits.push(DIGITS[0]),
+    false => {
+      digits.pop();
+    }
+  }
+  Some(format!("{}{}", head as char, to_str(&digits)))
+}
+
+/// Get the integer part that precedes the given one, or `None` if it is the
+/// smallest.
+fn decrement_integer(integer: &str) -> Option<String> {
+  let (head, digits) = integer.as_bytes().split_first()?;
+  let mut digits = digits.to_vec();
+  let last = DIGITS[DIGITS.len() - 1];
+
+  for digit in digits.iter_mut().rev() {
+    let index = digit_index(*digit);
+    if index > 0 {
+      *digit = DIGITS[index - 1];
+      return Some(format!("{}{}", *head as char, to_str(&digits)));
+    }
+    *digit = last;
+  }
+
+  let head = match head {
+    b'a' => return Some(format!("Z{}", last as char)),
+    b'A' => return None,
+    head => head - 1,
+  };
+  match head < b'Z' {
+    true => digits.push(last),
+    false => {
+      digits.pop();
+    }
+  }
+  Some(format!("{}{}", head as char, to_str(&digits)))
+}
+
+/// Get a fraction that sorts between two fractions, where `None` is the end.
+/// Neither fraction ends in zero, and neither does the result.
+fn midpoint(a: &str, b: Option<&str>) -> String {
+  let a_digits = a.as_bytes();
+
+  if let Some(b) = b {
+    // Keep any common prefix, treating `a` as padded with zeros.
+    let b_digits = b.as_bytes();
+    let prefix = b_digits
+      .iter()
+      .enumerate()
+      .take_while(|(i, digit)| a_digits.get(*i).unwrap_or(&DIGITS[0]) == *digit)
+      .count();
+    if prefix > 0 {
+      let rest_a = a.get(prefix.min(a.len())..).unwrap_or("");
+      return format!(
+        "{}{}",
+        &b[..prefix],
+        midpoint(rest_a, Some(&b[prefix..]))
+      );
+    }
+  }
+
+  let digit_a = a_digits.first().map_or(0, |digit| digit_index(*digit));
+  let digit_b = b
+    .and_then(|b| b.as_bytes().first())
+    .map_or(DIGITS.len(), |digit| digit_index(*digit));
+
+  if digit_b - digit_a > 1 {
+    return (DIGITS[(digit_a + digit_b).div_ceil(2)] as char).to_string();
+  }
+
+  // The first digits are adjacent, so the first digit of `b` on its own sorts
+  // between them if `b` is longer, and otherwise we need another digit.
+  match b {
+    Some(b) if b.len() > 1 => b[..1].to_string(),
+    _ => format!(
+      "{}{}",
+      DIGITS[digit_a] as char,
+      midpoint(a.get(1..).unwrap_or(""), None)
+    ),
+  }
+}
+
+fn digit_index(digit: u8) -> usize {
+  DIGITS.iter().position(|d| *d == digit).unwrap_or(0)
+}
+
+fn to_str(digits: &[u8]) -> &str {
+  std::str::from_utf8(digits).unwrap_or_default()
+}
@@ -1,6 +1,18 @@
 //! This module contains the generated protobuf code for the service
 pub mod v1 {
 
+  pub mod api_keys {
+    tonic::include_proto!("example.v1.api_keys");
+  }
+
+  pub mod labels {
+    tonic::include_proto!("example.v1.labels");
+  }
+
+  pub mod todo_lists {
+    tonic::include_proto!("example.v1.todo_lists");
+  }
+
   pub mod todos {
     tonic::include_proto!("example.v1.todos");
   }
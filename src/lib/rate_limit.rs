@@ -0,0 +1,339 @@
+//! This module contains a tower layer that limits the rate at which each
+//! client can call each gRPC method, using a token bucket per client and
+//! method.
+//!
+//! There are two kinds of limit. Limits applied inside the authentication
+//! layer identify clients by the [`Principal`] that it attached, so that each
+//! user is limited separately. Limits applied outside of it identify clients by
+//! their peer address, so that clients flooding the server with requests that
+//! fail to authenticate are limited before their credentials are checked.
+//! Requests over the limit are rejected with a `RESOURCE_EXHAUSTED` status,
+//! which includes a `google.rpc.RetryInfo` detail telling the client how long
+//! to wait before retrying.
+
+use crate::auth::Principal;
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::server::NamedService;
+use tonic::transport::server::TcpConnectInfo;
+use tonic::Code;
+use tonic::Status;
+use tonic_types::ErrorDetails;
+use tonic_types::StatusExt;
+use tower::Layer;
+
+/// The number of buckets above which we discard buckets that have refilled,
+/// to stop the memory used by the limiter from growing without bound.
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// The name used for the default limit in the configuration string.
+const DEFAULT_METHOD_NAME: &str = "*";
+
+/// The size and refill rate of a token bucket. Each request takes one token
+/// from the bucket, so `capacity` is the maximum burst of requests and
+/// `refill_per_second` is the sustained rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BucketConfig {
+  pub capacity: f64,
+  pub refill_per_second: f64,
+}
+
+impl FromStr for BucketConfig {
+  type Err = anyhow::Error;
+
+  /// Parse a bucket configuration in the format `capacity:refill_per_second`,
+  /// for example `20:1`.
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    let (capacity, refill_per_second) = value.split_once(':').ok_or(
+      anyhow!("Expected capacity:refill_per_second, got {}", value),
+    )?;
+    let config = Self {
+      capacity: capacity.trim().parse()?,
+      refill_per_second: refill_per_second.trim().parse()?,
+    };
+
+    if config.capacity < 1.0 || config.refill_per_second <= 0.0 {
+      return Err(anyhow!(
+        "Capacity must be at least 1 and refill rate must be positive, got {}",
+        value
+      ));
+    }
+
+    Ok(config)
+  }
+}
+
+/// The rate limits for each gRPC method. Methods without their own limit use
+/// the default limit, and if there is no default limit then they are not
+/// limited at all.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimitConfig {
+  pub default: Option<BucketConfig>,
+  pub methods: HashMap<String, BucketConfig>,
+}
+
+impl RateLimitConfig {
+  /// Get the limit for the gRPC method with the given path, e.g.
+  /// `/example.v1.todos.TodoService/ListTodos`. Limits are configured by the
+  /// method name alone, e.g. `ListTodos`.
+  fn get(&self, path: &str) -> Option<BucketConfig> {
+    let method = path.rsplit('/').next().unwrap_or(path);
+    self.methods.get(method).copied().or(self.default)
+  }
+}
+
+impl FromStr for RateLimitConfig {
+  type Err = anyhow::Error;
+
+  /// Parse the rate limits from a comma separated list of
+  /// `method=capacity:refill_per_second` entries, where the method `*` sets the
+  /// default limit. For example, `*=100:10,ListTodos=20:1` allows bursts of 100
+  /// requests and 10 requests per second to every method, except `ListTodos`
+  /// which allows bursts of 20 requests and 1 request per second.
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    let mut config = Self::default();
+
+    for entry in value.split(',').filter(|entry| !entry.trim().is_empty()) {
+      let (method, bucket) = entry
+        .split_once('=')
+        .ok_or(anyhow!("Expected method=limit, got {}", entry))?;
+      let method = method.trim();
+      let bucket = bucket.parse()?;
+
+      if method == DEFAULT_METHOD_NAME {
+        config.default = Some(bucket);
+      } else {
+        config.methods.insert(method.to_string(), bucket);
+      }
+    }
+
+    Ok(config)
+  }
+}
+
+/// The state of a single token bucket.
+#[derive(Debug)]
+struct Bucket {
+  tokens: f64,
+  updated_at: Instant,
+}
+
+impl Bucket {
+  /// Add the tokens that have accumulated since the bucket was last updated.
+  fn refill(&mut self, config: &BucketConfig, now: Instant) {
+    let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+    self.tokens =
+      (self.tokens + elapsed * config.refill_per_second).min(config.capacity);
+    self.updated_at = now;
+  }
+
+  /// Whether the bucket has refilled completely, in which case it is
+  /// equivalent to a new bucket and can be discarded.
+  fn is_full(&self, config: &BucketConfig, now: Instant) -> bool {
+    let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+    self.tokens + elapsed * config.refill_per_second >= config.capacity
+  }
+}
+
+/// How a limiter identifies the client making a request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClientKey {
+  /// By the principal attached by the authentication layer, or by the peer
+  /// address if there is none.
+  Principal,
+  /// By the peer address alone.
+  PeerAddress,
+}
+
+/// The buckets of every client and method, and the number of buckets above
+/// which the next request discards the buckets that have refilled.
+#[derive(Debug)]
+struct Buckets {
+  buckets: HashMap<(String, String), (BucketConfig, Bucket)>,
+  sweep_above: usize,
+}
+
+impl Default for Buckets {
+  fn default() -> Self {
+    Self {
+      buckets: HashMap::new(),
+      sweep_above: MAX_IDLE_BUCKETS,
+    }
+  }
+}
+
+/// The buckets of every client and method, shared by all clones of the layer.
+#[derive(Debug)]
+struct RateLimiter {
+  config: RateLimitConfig,
+  client_key: ClientKey,
+  buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+  /// Take a token from the bucket for the given client and method. If the
+  /// bucket is empty, then return the time until the next token is available.
+  fn try_acquire(&self, client: &str, path: &str) -> Result<(), Duration> {
+    let Some(config) = self.config.get(path) else {
+      return Ok(());
+    };
+
+    let now = Instant::now();
+    let mut state = self.buckets.lock().unwrap();
+
+    // Sweeping is linear in the number of buckets, so if most of them are
+    // still in use, then we wait for the map to double before sweeping again.
+    if state.buckets.len() > state.sweep_above {
+      state
+        .buckets
+        .retain(|_, (config, bucket)| !bucket.is_full(config, now));
+      state.sweep_above = MAX_IDLE_BUCKETS.max(state.buckets.len() * 2);
+    }
+
+    let (_, bucket) = state
+      .buckets
+      .entry((client.to_string(), path.to_string()))
+      .or_insert_with(|| {
+        let bucket = Bucket {
+          tokens: config.capacity,
+          updated_at: now,
+        };
+        (config, bucket)
+      });
+
+    bucket.refill(&config, now);
+
+    if bucket.tokens >= 1.0 {
+      bucket.tokens -= 1.0;
+      return Ok(());
+    }
+
+    let wait = (1.0 - bucket.tokens) / config.refill_per_second;
+    Err(Duration::from_secs_f64(wait))
+  }
+}
+
+/// A tower layer that applies the configured rate limits to each request. A
+/// layer created with [`RateLimitLayer::new`] must be applied inside the
+/// authentication layer, so that requests can be limited per principal, and
+/// one created with [`RateLimitLayer::per_peer`] outside of it. For example:
+/// ```ignore
+/// ServiceBuilder::new()
+///   .layer(RateLimitLayer::per_peer(peer_config))
+///   .layer(AuthLayer::new(pool.clone()))
+///   .layer(RateLimitLayer::new(config))
+///   .service(TodoServiceServer::new(handler))
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+  limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitLayer {
+  /// Create a new layer with the given limits for each principal.
+  pub fn new(config: RateLimitConfig) -> Self {
+    Self::with_client_key(config, ClientKey::Principal)
+  }
+
+  /// Create a new layer with the given limits for each peer address.
+  pub fn per_peer(config: RateLimitConfig) -> Self {
+    Self::with_client_key(config, ClientKey::PeerAddress)
+  }
+
+  fn with_client_key(config: RateLimitConfig, client_key: ClientKey) -> Self {
+    Self {
+      limiter: Arc::new(RateLimiter {
+        config,
+        client_key,
+        buckets: Mutex::default(),
+      }),
+    }
+  }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+  type Service = RateLimitService<S>;
+
+  fn layer(&self, inner: S) -> Self::Service {
+    RateLimitService {
+      inner,
+      limiter: Arc::clone(&self.limiter),
+    }
+  }
+}
+
+/// The service created by the [`RateLimitLayer`].
+#[derive(Debug, Clone)]
+pub struct RateLimitService<S> {
+  inner: S,
+  limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> tower::Service<http::Request<B>> for RateLimitService<S>
+where
+  S: tower::Service<http::Request<B>, Response = http::Response<BoxBody>>,
+  S::Future: Send + 'static,
+{
+  type Response = S::Response;
+  type Error = S::Error;
+  type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, request: http::Request<B>) -> Self::Future {
+    let client = get_client_key(&request, self.limiter.client_key);
+    let path = request.uri().path();
+
+    match self.limiter.try_acquire(&client, path) {
+      Ok(()) => Box::pin(self.inner.call(request)),
+      Err(retry_delay) => {
+        let status = Status::with_error_details(
+          Code::ResourceExhausted,
+          format!("Rate limit exceeded for {}", path),
+          ErrorDetails::with_retry_info(Some(retry_delay)),
+        );
+        Box::pin(async move { Ok(status.into_http()) })
+      }
+    }
+  }
+}
+
+impl<S: NamedService> NamedService for RateLimitService<S> {
+  const NAME: &'static str = S::NAME;
+}
+
+/// Get the key that identifies the client making the request. Authenticated
+/// clients are identified by their principal, so that all of a client's
+/// connections share a limit, unless the limiter is keyed by peer address.
+/// Otherwise, clients are identified by their IP address.
+fn get_client_key<B>(
+  request: &http::Request<B>,
+  client_key: ClientKey,
+) -> String {
+  let principal = match client_key {
+    ClientKey::Principal => request.extensions().get::<Principal>(),
+    ClientKey::PeerAddress => None,
+  };
+  if let Some(principal) = principal {
+    return format!("principal:{}/{}", principal.tenant_id, principal.subject);
+  }
+
+  request
+    .extensions()
+    .get::<TcpConnectInfo>()
+    .and_then(|info| info.remote_addr())
+    .map(|address| format!("peer:{}", address.ip()))
+    .unwrap_or_else(|| "peer:unknown".to_string())
+}
@@ -0,0 +1,398 @@
+//! This module contains a parser and evaluator for the recurrence rules of
+//! recurring todos, which use the `RRULE` format of
+//! [RFC 5545](https://www.rfc-editor.org/rfc/rfc5545#section-3.3.10), for
+//! example `FREQ=WEEKLY;BYDAY=MO,TH` or `FREQ=MONTHLY;BYDAY=-1FR;COUNT=12`.
+//!
+//! The rule parts that are supported are `FREQ` (`DAILY`, `WEEKLY`, `MONTHLY`
+//! or `YEARLY`), `INTERVAL`, `COUNT`, `UNTIL`, `BYDAY`, `BYMONTHDAY`,
+//! `BYMONTH` and `WKST=MO`. Rules are evaluated in UTC, with every occurrence
+//! at the same time of day as the start of the series.
+
+use anyhow::anyhow;
+use std::str::FromStr;
+use time::Date;
+use time::Month;
+use time::OffsetDateTime;
+use time::PrimitiveDateTime;
+use time::Weekday;
+
+/// The number of periods, such as days or months, that are searched for the
+/// next occurrence before giving up. This stops rules that can never match,
+/// such as the 30th of February, from searching forever.
+const MAX_PERIODS: i64 = 100_000;
+
+/// How often a rule repeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+  Daily,
+  Weekly,
+  Monthly,
+  Yearly,
+}
+
+/// A parsed recurrence rule.
+///
+/// # Fields
+///
+/// * `frequency` - How often the rule repeats.
+/// * `interval` - The number of periods between each repeat, e.g. 2 for every
+///   other week.
+/// * `count` - The number of occurrences, including the first, if limited.
+/// * `until` - The time of the last possible occurrence, if limited.
+/// * `by_day` - The days of the week to repeat on, each with an optional
+///   ordinal within the month, e.g. `-1FR` for the last Friday.
+/// * `by_month_day` - The days of the month to repeat on, where negative days
+///   count back from the end of the month.
+/// * `by_month` - The months to repeat in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurrenceRule {
+  pub frequency: Frequency,
+  pub interval: u32,
+  pub count: Option<u32>,
+  pub until: Option<OffsetDateTime>,
+  pub by_day: Vec<(Option<i8>, Weekday)>,
+  pub by_month_day: Vec<i8>,
+  pub by_month: Vec<Month>,
+}
+
+impl FromStr for RecurrenceRule {
+  type Err = anyhow::Error;
+
+  /// Parse a rule, which may start with `RRULE:`. Parts that are not
+  /// supported are reported as errors, rather than ignored, so that a todo
+  /// never repeats differently to how its owner expects.
+  fn from_str(value: &str) -> anyhow::Result<Self> {
+    let value = value.trim();
+    let value = value.strip_prefix("RRULE:").unwrap_or(value);
+
+    let mut frequency = None;
+    let mut rule = RecurrenceRule {
+      frequency: Frequency::Daily,
+      interval: 1,
+      count: None,
+      until: None,
+      by_day: Vec::new(),
+      by_month_day: Vec::new(),
+      by_month: Vec::new(),
+    };
+    let mut names = Vec::new();
+
+    for part in value.split(';') {
+      let (name, value) = part
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected NAME=VALUE, got {:?}", part))?;
+      let name = name.to_ascii_uppercase();
+      let value = value.to_ascii_uppercase();
+      if names.contains(&name) {
+        return Err(anyhow!("{} is set more than once", name));
+      }
+
+      match name.as_str() {
+        "FREQ" => frequency = Some(parse_frequency(&value)?),
+        "INTERVAL" => rule.interval = parse_positive(&name, &value)?,
+        "COUNT" => rule.count = Some(parse_positive(&name, &value)?),
+        "UNTIL" => rule.until = Some(parse_until(&value)?),
+        "BYDAY" => rule.by_day = parse_list(&value, parse_weekday)?,
+        "BYMONTHDAY" => {
+          rule.by_month_day = parse_list(&value, parse_month_day)?
+        }
+        "BYMONTH" => rule.by_month = parse_list(&value, parse_month)?,
+        "WKST" if value == "MO" => (),
+        _ => return Err(anyhow!("{}={} is not supported", name, value)),
+      }
+      names.push(name);
+    }
+
+    rule.frequency = frequency.ok_or_else(|| anyhow!("FREQ is required"))?;
+    if rule.count.is_some() && rule.until.is_some() {
+      return Err(anyhow!("COUNT and UNTIL cannot both be set"));
+    }
+
+    // Ordinals only make sense for days within a month, and a yearly rule's
+    // days are only supported within the months that it lists.
+    let has_ordinals = rule.by_day.iter().any(|(ordinal, _)| ordinal.is_some());
+    match rule.frequency {
+      Frequency::Daily | Frequency::Weekly if has_ordinals => {
+        return Err(anyhow!("BYDAY ordinals need a MONTHLY or YEARLY FREQ"));
+      }
+      Frequency::Weekly if !rule.by_month_day.is_empty() => {
+        return Err(anyhow!("BYMONTHDAY is not supported with FREQ=WEEKLY"));
+      }
+      Frequency::Yearly
+        if !rule.by_day.is_empty() && rule.by_month.is_empty() =>
+      {
+        return Err(anyhow!("BYDAY needs BYMONTH with FREQ=YEARLY"));
+      }
+      _ => (),
+    }
+
+    Ok(rule)
+  }
+}
+
+impl RecurrenceRule {
+  /// Get the first occurrence of the series that starts at `start` that is
+  /// after `after`, or `None` if the series has ended by then. The start of
+  /// the series is always its first occurrence.
+  pub fn next_after(
+    &self,
+    start: OffsetDateTime,
+    after: OffsetDateTime,
+  ) -> Option<OffsetDateTime> {
+    let mut count = 0;
+
+    for period in 0..MAX_PERIODS {
+      for date in self.get_period_dates(start.date(), period)? {
+        let occurrence = start.replace_date(date);
+        if occurrence < start {
+          continue;
+        }
+        if self.until.is_some_and(|until| occurrence > until) {
+          return None;
+        }
+        count += 1;
+        if self.count.is_some_and(|limit| count > limit) {
+          return None;
+        }
+        if occurrence > after {
+          return Some(occurrence);
+        }
+      }
+    }
+
+    None
+  }
+
+  /// Get the dates of the occurrences in the given period of the series that
+  /// starts on `start`, in order. Returns `None` if the period is out of the
+  /// range of dates that can be represented.
+  fn get_period_dates(&self, start: Date, period: i64) -> Option<Vec<Date>> {
+    let periods = period * i64::from(self.interval);
+
+    let dates = match self.frequency {
+      Frequency::Daily => {
+        let date = start.checked_add(time::Duration::days(periods))?;
+        let matches_day = self.by_day.is_empty()
+          || self.by_day.iter().any(|(_, day)| *day == date.weekday());
+        let matches_month_day = self.by_month_day.is_empty()
+          || self.by_month_day.iter().any(|day| {
+            resolve_month_day(date.year(), date.month(), *day)
+              == Some(date.day())
+          });
+        match matches_day && matches_month_day {
+          true => vec![date],
+          false => vec![],
+        }
+      }
+      Frequency::Weekly => {
+        let monday = start
+          .checked_sub(time::Duration::days(
+            start.weekday().number_days_from_monday().into(),
+          ))?
+          .checked_add(time::Duration::weeks(periods))?;
+        let mut days = match self.by_day.is_empty() {
+          true => vec![start.weekday()],
+          false => self.by_day.iter().map(|(_, day)| *day).collect(),
+        };
+        days.sort_by_key(|day| day.number_days_from_monday());
+        days.dedup();
+        days
+          .into_iter()
+          .map(|day| {
+            monday.checked_add(time::Duration::days(
+              day.number_days_from_monday().into(),
+            ))
+          })
+          .collect::<Option<Vec<_>>>()?
+      }
+      Frequency::Monthly => {
+        let months = i64::from(start.year()) * 12
+          + i64::from(u8::from(start.month()) - 1)
+          + periods;
+        let year = i32::try_from(months.div_euclid(12)).ok()?;
+        let month = Month::try_from(months.rem_euclid(12) as u8 + 1).ok()?;
+        self.get_month_dates(year, month, start.day())
+      }
+      Frequency::Yearly => {
+        let year = i32::try_from(i64::from(start.year()) + periods).ok()?;
+        let months = match self.by_month.is_empty() {
+          true => vec![start.month()],
+          false => self.by_month.clone(),
+        };
+        let mut dates = months
+          .into_iter()
+          .flat_map(|month| self.get_month_dates(year, month, start.day()))
+          .collect::<Vec<_>>();
+        dates.sort();
+        dates
+      }
+    };
+
+    Some(
+      dates
+        .into_iter()
+        .filter(|date| {
+          self.by_month.is_empty() || self.by_month.contains(&date.month())
+        })
+        .collect(),
+    )
+  }
+
+  /// Get the dates in the given month that match the `BYMONTHDAY` and `BYDAY`
+  /// parts, in order. If neither is set, then the date is the same day of the
+  /// month as the start of the series, which is skipped in months that are too
+  /// short.
+  fn get_month_dates(&self, year: i32, month: Month, day: u8) -> Vec<Date> {
+    let length = month.length(year);
+    let month_days = (1..=length)
+      .filter_map(|day| Date::from_calendar_date(year, month, day).ok())
+      .collect::<Vec<_>>();
+
+    month_days
+      .iter()
+      .enumerate()
+      .filter(|(index, date)| {
+        let matches_month_day = match self.by_month_day.is_empty() {
+          true => !self.by_day.is_empty() || date.day() == day,
+          false => self.by_month_day.iter().any(|day| {
+            resolve_month_day(year, month, *day) == Some(date.day())
+          }),
+        };
+        let matches_day = self.by_day.is_empty()
+          || self.by_day.iter().any(|(ordinal, weekday)| {
+            // The nth weekday of the month is in the nth week from the start
+            // of the month, or from the end if the ordinal is negative.
+            *weekday == date.weekday()
+              && match ordinal {
+                None => true,
+                Some(ordinal) if *ordinal > 0 => {
+                  index / 7 == (*ordinal - 1) as usize
+                }
+                Some(ordinal) => {
+                  (usize::from(length) - 1 - index) / 7
+                    == (-*ordinal - 1) as usize
+                }
+              }
+          });
+        matches_month_day && matches_day
+      })
+      .map(|(_, date)| *date)
+      .collect()
+  }
+}
+
+/// Get the day of the month that a `BYMONTHDAY` value refers to, if the month
+/// has that day.
+fn resolve_month_day(year: i32, month: Month, day: i8) -> Option<u8> {
+  let length = i16::from(month.length(year));
+  let day = match day {
+    day if day > 0 => i16::from(day),
+    day => length + i16::from(day) + 1,
+  };
+  (1..=length).contains(&day).then_some(day as u8)
+}
+
+fn parse_frequency(value: &str) -> anyhow::Result<Frequency> {
+  match value {
+    "DAILY" => Ok(Frequency::Daily),
+    "WEEKLY" => Ok(Frequency::Weekly),
+    "MONTHLY" => Ok(Frequency::Monthly),
+    "YEARLY" => Ok(Frequency::Yearly),
+    _ => Err(anyhow!("FREQ={} is not supported", value)),
+  }
+}
+
+fn parse_positive(name: &str, value: &str) -> anyhow::Result<u32> {
+  match value.parse::<u32>() {
+    Ok(value) if value > 0 => Ok(value),
+    _ => Err(anyhow!(
+      "{} must be a positive integer, got {}",
+      name,
+      value
+    )),
+  }
+}
+
+/// Parse an `UNTIL` value, which is either a UTC date and time such as
+/// `20261231T235959Z`, or a date such as `20261231`, which includes the whole
+/// day.
+fn parse_until(value: &str) -> anyhow::Result<OffsetDateTime> {
+  let invalid =
+    || anyhow!("UNTIL must be a UTC date or date-time, got {}", value);
+  let number = |range: std::ops::Range<usize>| {
+    value
+      .get(range)
+      .and_then(|digits| digits.parse::<u32>().ok())
+      .ok_or_else(invalid)
+  };
+
+  let month = Month::try_from(number(4..6)? as u8).map_err(|_| invalid())?;
+  let date =
+    Date::from_calendar_date(number(0..4)? as i32, month, number(6..8)? as u8)
+      .map_err(|_| invalid())?;
+  let time = match value.len() {
+    8 => time::Time::from_hms(23, 59, 59),
+    16 if value.as_bytes()[8] == b'T' && value.ends_with('Z') => {
+      time::Time::from_hms(
+        number(9..11)? as u8,
+        number(11..13)? as u8,
+        number(13..15)? as u8,
+      )
+    }
+    _ => return Err(invalid()),
+  }
+  .map_err(|_| invalid())?;
+
+  Ok(PrimitiveDateTime::new(date, time).assume_utc())
+}
+
+fn parse_list<T>(
+  value: &str,
+  parse: fn(&str) -> anyhow::Result<T>,
+) -> anyhow::Result<Vec<T>> {
+  value.split(',').map(parse).collect()
+}
+
+/// Parse a `BYDAY` value, such as `MO`, `2TU` or `-1FR`.
+fn parse_weekday(value: &str) -> anyhow::Result<(Option<i8>, Weekday)> {
+  let invalid = || anyhow!("Invalid BYDAY value {}", value);
+  let split = value.len().checked_sub(2).ok_or_else(invalid)?;
+  let (ordinal, day) = value.split_at_checked(split).ok_or_else(invalid)?;
+
+  let ordinal = match ordinal {
+    "" => None,
+    ordinal => match ordinal.parse::<i8>() {
+      Ok(ordinal) if ordinal != 0 && (-5..=5).contains(&ordinal) => {
+        Some(ordinal)
+      }
+      _ => return Err(invalid()),
+    },
+  };
+  let day = match day {
+    "MO" => Weekday::Monday,
+    "TU" => Weekday::Tuesday,
+    "WE" => Weekday::Wednesday,
+    "TH" => Weekday::Thursday,
+    "FR" => Weekday::Friday,
+    "SA" => Weekday::Saturday,
+    "SU" => Weekday::Sunday,
+    _ => return Err(invalid()),
+  };
+
+  Ok((ordinal, day))
+}
+
+fn parse_month_day(value: &str) -> anyhow::Result<i8> {
+  match value.parse::<i8>() {
+    Ok(day) if day != 0 && (-31..=31).contains(&day) => Ok(day),
+    _ => Err(anyhow!("Invalid BYMONTHDAY value {}", value)),
+  }
+}
+
+fn parse_month(value: &str) -> anyhow::Result<Month> {
+  value
+    .parse::<u8>()
+    .ok()
+    .and_then(|month| Month::try_from(month).ok())
+    .ok_or_else(|| anyhow!("Invalid BYMONTH value {}", value))
+}
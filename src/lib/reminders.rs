@@ -0,0 +1,293 @@
+//! This module contains the reminder scheduler, which runs in the background of
+//! the server and sends each todo's reminder once its reminder time passes.
+//!
+//! The scheduler polls Postgres for todos whose `remind_at` time has passed,
+//! and claims them with `FOR UPDATE SKIP LOCKED`, so that several replicas of
+//! the server can run the scheduler at once without sending a reminder twice.
+//! Claiming a reminder moves its `remind_at` forward by the lease, which hides
+//! it from the other schedulers while it is sent. Once it has been sent, the
+//! attempt is recorded in `reminder_deliveries`, and `remind_at` is cleared, or
+//! moved forward again if the reminder should be retried.
+//!
+//! Reminders are sent by a [`Notifier`], which is chosen by the
+//! `reminders.notifier` configuration key. See [`notifiers`].
+
+pub mod notifiers;
+mod smtp;
+
+use crate::config::NotifierConfig;
+use crate::config::RemindersConfig;
+use crate::database::begin_scheduler_transaction;
+use crate::metrics::METRICS;
+use futures::future::join_all;
+use notifiers::EmailNotifier;
+use notifiers::LogNotifier;
+use notifiers::WebhookNotifier;
+use sqlx::query;
+use sqlx::query_as;
+use sqlx::types::time::OffsetDateTime;
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::info;
+use tracing::instrument;
+use tracing::warn;
+
+/// A reminder that is due to be sent.
+///
+/// # Fields
+///
+/// * `todo_id` - The ID of the todo to remind the owner about.
+/// * `tenant_id` - The ID of the tenant that the todo belongs to.
+/// * `owner_id` - The ID of the user to remind.
+/// * `title` - The title of the todo.
+/// * `description` - The description of the todo.
+/// * `due_time` - The time the todo should be done by, if it has one.
+/// * `reminder_time` - The time the owner asked to be reminded at.
+/// * `attempt` - The number of this attempt to send the reminder, starting at
+///   1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reminder {
+  pub todo_id: String,
+  pub tenant_id: String,
+  pub owner_id: String,
+  pub title: String,
+  pub description: String,
+  pub due_time: Option<OffsetDateTime>,
+  pub reminder_time: OffsetDateTime,
+  pub attempt: i64,
+}
+
+/// Something that can send reminders to the owners of todos.
+#[async_trait::async_trait]
+pub trait Notifier: Send + Sync {
+  /// The name of the notifier, which is recorded with each delivery attempt.
+  fn name(&self) -> &'static str;
+
+  /// Send the reminder. If this fails, then the reminder is sent again later,
+  /// so notifiers do not need to retry themselves.
+  async fn notify(&self, reminder: &Reminder) -> anyhow::Result<()>;
+}
+
+/// Create the notifier that the configuration asks for, or `None` if
+/// reminders should not be sent.
+pub fn create_notifier(
+  config: &RemindersConfig,
+) -> anyhow::Result<Option<Arc<dyn Notifier>>> {
+  let notifier: Arc<dyn Notifier> = match &config.notifier {
+    NotifierConfig::None => return Ok(None),
+    NotifierConfig::Log => Arc::new(LogNotifier),
+    NotifierConfig::Webhook { url } => {
+      Arc::new(WebhookNotifier::new(url, config.timeout)?)
+    }
+    NotifierConfig::Email {
+      smtp_host,
+      smtp_port,
+      from,
+      domain,
+    } => Arc::new(EmailNotifier::new(
+      smtp_host,
+      *smtp_port,
+      from,
+      domain.as_deref(),
+    )),
+  };
+
+  Ok(Some(notifier))
+}
+
+/// Run the scheduler until the process exits, sending reminders with the given
+/// notifier. The scheduler keeps running if the database is not reachable, so
+/// it should be spawned as a separate task when the server starts.
+pub async fn run_reminder_scheduler(
+  pool: PgPool,
+  config: RemindersConfig,
+  notifier: Arc<dyn Notifier>,
+) {
+  info!(notifier = notifier.name(), "Starting reminder scheduler...");
+
+  loop {
+    // If the batch was full, then there may be more reminders waiting, so we
+    // look again straight away.
+    match send_due_reminders(&pool, &config, notifier.as_ref()).await {
+      Ok(count) if count >= config.batch_size as usize => continue,
+      Ok(_) => (),
+      Err(e) => warn!(error = %e, "Failed to send due reminders"),
+    }
+    tokio::time::sleep(config.poll_interval).await;
+  }
+}
+
+/// Claim up to `batch_size` due reminders and send them, returning the number
+/// of reminders that were claimed.
+#[instrument(skip_all)]
+pub async fn send_due_reminders(
+  pool: &PgPool,
+  config: &RemindersConfig,
+  notifier: &dyn Notifier,
+) -> anyhow::Result<usize> {
+  let reminders = claim_due_reminders(pool, config).await?;
+
+  let results = join_all(reminders.iter().map(|reminder| async move {
+    let result =
+      match tokio::time::timeout(config.timeout, notifier.notify(reminder))
+        .await
+      {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("Timed out after {:?}", config.timeout)),
+      };
+    record_attempt(pool, config, notifier.name(), reminder, result).await
+  }))
+  .await;
+
+  for result in results {
+    if let Err(e) = result {
+      warn!(error = %e, "Failed to record reminder delivery");
+    }
+  }
+
+  Ok(reminders.len())
+}
+
+/// Claim the due reminders, by moving their `remind_at` forward by the lease
+/// so that no other scheduler claims them while they are sent. Reminders that
+/// another scheduler is claiming at the same time are skipped, rather than
+/// waited for.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn claim_due_reminders(
+  pool: &PgPool,
+  config: &RemindersConfig,
+) -> anyhow::Result<Vec<Reminder>> {
+  let mut transaction = begin_scheduler_transaction(pool).await?;
+
+  let reminders = query_as!(
+    Reminder,
+    r#"
+    with due as (
+      select tenant_id, owner_id, todo_id
+      from todos
+      where remind_at <= now()
+        and reminder_time is not null
+        and not completed
+      order by remind_at
+      limit $1
+      for update skip locked
+    )
+    update todos
+    set remind_at = now() + make_interval(secs => $2)
+    from due
+    where todos.tenant_id = due.tenant_id
+      and todos.owner_id = due.owner_id
+      and todos.todo_id = due.todo_id
+    returning todos.todo_id,
+              todos.tenant_id,
+              todos.owner_id,
+              todos.title,
+              todos.description,
+              todos.due_time,
+              todos.reminder_time as "reminder_time!",
+              (
+                select count(*)
+                from reminder_deliveries
+                where reminder_deliveries.tenant_id = todos.tenant_id
+                  and reminder_deliveries.owner_id = todos.owner_id
+                  and reminder_deliveries.todo_id = todos.todo_id
+                  and reminder_deliveries.reminder_time = todos.reminder_time
+              ) + 1 as "attempt!"
+    "#,
+    i64::from(config.batch_size),
+    config.lease.as_secs_f64()
+  )
+  .fetch_all(&mut *transaction)
+  .await?;
+
+  transaction.commit().await?;
+
+  Ok(reminders)
+}
+
+/// Record an attempt to send a reminder, and schedule the next attempt if it
+/// failed and has attempts left. Otherwise, the reminder is not sent again.
+///
+/// If the owner changed the reminder time while it was being sent, then the
+/// new reminder time has already been scheduled, so it is left alone.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn record_attempt(
+  pool: &PgPool,
+  config: &RemindersConfig,
+  notifier: &str,
+  reminder: &Reminder,
+  result: anyhow::Result<()>,
+) -> anyhow::Result<()> {
+  let error = result.err().map(|e| e.to_string());
+  let retry = error.is_some() && reminder.attempt < config.max_attempts.into();
+  let outcome = match (&error, retry) {
+    (None, _) => "sent",
+    (Some(_), true) => "retry",
+    (Some(_), false) => "abandoned",
+  };
+
+  if let Some(error) = &error {
+    warn!(
+      todo_id = reminder.todo_id,
+      attempt = reminder.attempt,
+      outcome,
+      error,
+      "Failed to send reminder"
+    );
+  }
+
+  // The wait doubles after each failed attempt.
+  let backoff = config
+    .retry_backoff
+    .saturating_mul(1 << (reminder.attempt - 1).clamp(0, 16));
+  let remind_at = retry.then(|| OffsetDateTime::now_utc() + backoff);
+
+  let mut transaction = begin_scheduler_transaction(pool).await?;
+
+  query!(
+    r#"
+    insert into reminder_deliveries (
+      tenant_id,
+      owner_id,
+      todo_id,
+      reminder_time,
+      attempt,
+      notifier,
+      error
+    )
+    values ($1, $2, $3, $4, $5, $6, $7)
+    "#,
+    reminder.tenant_id,
+    reminder.owner_id,
+    reminder.todo_id,
+    reminder.reminder_time,
+    reminder.attempt as i32,
+    notifier,
+    error
+  )
+  .execute(&mut *transaction)
+  .await?;
+
+  query!(
+    r#"
+    update todos
+    set remind_at = $5
+    where tenant_id = $1
+      and owner_id = $2
+      and todo_id = $3
+      and reminder_time = $4
+    "#,
+    reminder.tenant_id,
+    reminder.owner_id,
+    reminder.todo_id,
+    reminder.reminder_time,
+    remind_at
+  )
+  .execute(&mut *transaction)
+  .await?;
+
+  transaction.commit().await?;
+  METRICS.record_reminder_delivery(notifier, outcome);
+
+  Ok(())
+}
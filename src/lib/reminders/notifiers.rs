@@ -0,0 +1,169 @@
+//! The notifiers that can send reminders. Each one is chosen by a value of the
+//! `reminders.notifier` configuration key:
+//!
+//! - `log`: [`LogNotifier`] writes each reminder to the log.
+//! - `webhook`: [`WebhookNotifier`] posts each reminder to an HTTP endpoint.
+//! - `email`: [`EmailNotifier`] emails each reminder to its owner.
+
+use crate::common::sql_datetime_to_proto_timestamp;
+use crate::reminders::smtp::send_mail;
+use crate::reminders::smtp::Mail;
+use crate::reminders::Notifier;
+use crate::reminders::Reminder;
+use serde_json::json;
+use std::time::Duration;
+use tonic::codegen::http::header::CONTENT_TYPE;
+use tracing::info;
+
+/// The header that identifies a reminder to a webhook, so that the receiver
+/// can ignore a reminder that was sent again after its response was lost.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Writes each reminder to the log, which is useful for development.
+#[derive(Debug, Clone, Default)]
+pub struct LogNotifier;
+
+#[async_trait::async_trait]
+impl Notifier for LogNotifier {
+  fn name(&self) -> &'static str {
+    "log"
+  }
+
+  async fn notify(&self, reminder: &Reminder) -> anyhow::Result<()> {
+    info!(
+      todo_id = reminder.todo_id,
+      tenant_id = reminder.tenant_id,
+      owner_id = reminder.owner_id,
+      title = reminder.title,
+      "Reminder"
+    );
+
+    Ok(())
+  }
+}
+
+/// Posts each reminder as a JSON object to a URL, such as an endpoint of
+/// another local service that sends push notifications. Any response other
+/// than a `2xx` status is treated as a failure.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+  client: reqwest::Client,
+  url: String,
+}
+
+impl WebhookNotifier {
+  /// Create a notifier that posts to the given URL, giving up on a request
+  /// after the given timeout.
+  pub fn new(url: &str, timeout: Duration) -> anyhow::Result<Self> {
+    let client = reqwest::Client::builder().timeout(timeout).build()?;
+
+    Ok(Self {
+      client,
+      url: url.to_string(),
+    })
+  }
+}
+
+#[async_trait::async_trait]
+impl Notifier for WebhookNotifier {
+  fn name(&self) -> &'static str {
+    "webhook"
+  }
+
+  async fn notify(&self, reminder: &Reminder) -> anyhow::Result<()> {
+    let reminder_time = sql_datetime_to_proto_timestamp(reminder.reminder_time);
+    let body = json!({
+      "todoId": reminder.todo_id,
+      "tenantId": reminder.tenant_id,
+      "ownerId": reminder.owner_id,
+      "title": reminder.title,
+      "description": reminder.description,
+      "dueTime": reminder
+        .due_time
+        .map(|time| sql_datetime_to_proto_timestamp(time).to_string()),
+      "reminderTime": reminder_time.to_string(),
+      "attempt": reminder.attempt,
+    });
+
+    self
+      .client
+      .post(&self.url)
+      .header(CONTENT_TYPE, "application/json")
+      .header(
+        IDEMPOTENCY_KEY_HEADER,
+        format!(
+          "{}/{}/{}/{}",
+          reminder.tenant_id,
+          reminder.owner_id,
+          reminder.todo_id,
+          reminder_time
+        ),
+      )
+      .body(body.to_string())
+      .send()
+      .await?
+      .error_for_status()?;
+
+    Ok(())
+  }
+}
+
+/// Emails each reminder to its owner, through an SMTP relay that accepts mail
+/// without authentication or TLS, such as a local Postfix. The address of each
+/// owner is their ID, followed by `@domain` if a domain is set.
+#[derive(Debug, Clone)]
+pub struct EmailNotifier {
+  smtp_host: String,
+  smtp_port: u16,
+  from: String,
+  domain: Option<String>,
+}
+
+impl EmailNotifier {
+  pub fn new(
+    smtp_host: &str,
+    smtp_port: u16,
+    from: &str,
+    domain: Option<&str>,
+  ) -> Self {
+    Self {
+      smtp_host: smtp_host.to_string(),
+      smtp_port,
+      from: from.to_string(),
+      domain: domain.map(str::to_string),
+    }
+  }
+}
+
+#[async_trait::async_trait]
+impl Notifier for EmailNotifier {
+  fn name(&self) -> &'static str {
+    "email"
+  }
+
+  async fn notify(&self, reminder: &Reminder) -> anyhow::Result<()> {
+    let to = match &self.domain {
+      Some(domain) => format!("{}@{}", reminder.owner_id, domain),
+      None => reminder.owner_id.clone(),
+    };
+
+    let mut body = format!("Reminder: {}\n", reminder.title);
+    if let Some(due_time) = reminder.due_time {
+      body.push_str(&format!(
+        "Due: {}\n",
+        sql_datetime_to_proto_timestamp(due_time)
+      ));
+    }
+    if !reminder.description.is_empty() {
+      body.push_str(&format!("\n{}\n", reminder.description));
+    }
+
+    let mail = Mail {
+      from: &self.from,
+      to: &to,
+      subject: &format!("Reminder: {}", reminder.title),
+      body: &body,
+    };
+    send_mail(&self.smtp_host, self.smtp_port, &mail).await
+  }
+}
@@ -0,0 +1,149 @@
+//! A minimal SMTP client, which sends plain text mail to a relay that accepts
+//! mail without authentication or TLS. See
+//! <https://www.rfc-editor.org/rfc/rfc5321> for the protocol.
+
+use anyhow::anyhow;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+
+/// A plain text mail with a single recipient.
+pub struct Mail<'a> {
+  pub from: &'a str,
+  pub to: &'a str,
+  pub subject: &'a str,
+  pub body: &'a str,
+}
+
+/// An open connection to an SMTP relay.
+struct Connection {
+  reader: BufReader<OwnedReadHalf>,
+  writer: OwnedWriteHalf,
+}
+
+impl Connection {
+  /// Read a reply, which may span several lines, and check that it has the
+  /// expected code.
+  async fn expect(&mut self, code: u16) -> anyhow::Result<()> {
+    let mut reply = String::new();
+
+    loop {
+      let mut line = String::new();
+      if self.reader.read_line(&mut line).await? == 0 {
+        return Err(anyhow!("SMTP relay closed the connection"));
+      }
+      reply.push_str(&line);
+
+      // The last line of a reply has a space after the code, rather than a
+      // hyphen.
+      if line.as_bytes().get(3) != Some(&b'-') {
+        break;
+      }
+    }
+
+    match reply.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+      Some(reply_code) if reply_code == code => Ok(()),
+      _ => Err(anyhow!("Unexpected SMTP reply: {}", reply.trim_end())),
+    }
+  }
+
+  /// Send a command, and check that the reply has the expected code.
+  async fn command(&mut self, command: &str, code: u16) -> anyhow::Result<()> {
+    self
+      .writer
+      .write_all(format!("{}\r\n", command).as_bytes())
+      .await?;
+    self.expect(code).await
+  }
+}
+
+/// Send the mail through the relay at the given host and port.
+pub async fn send_mail(
+  host: &str,
+  port: u16,
+  mail: &Mail<'_>,
+) -> anyhow::Result<()> {
+  let from = check_address(mail.from)?;
+  let to = check_address(mail.to)?;
+
+  let (reader, writer) = TcpStream::connect((host, port)).await?.into_split();
+  let mut connection = Connection {
+    reader: BufReader::new(reader),
+    writer,
+  };
+
+  connection.expect(220).await?;
+  connection.command("EHLO localhost", 250).await?;
+  connection
+    .command(&format!("MAIL FROM:<{}>", from), 250)
+    .await?;
+  connection
+    .command(&format!("RCPT TO:<{}>", to), 250)
+    .await?;
+  connection.command("DATA", 354).await?;
+  connection
+    .command(&format!("{}\r\n.", format_message(mail)), 250)
+    .await?;
+  connection.command("QUIT", 221).await?;
+
+  Ok(())
+}
+
+/// Check that an address cannot change the meaning of a command or header.
+fn check_address(address: &str) -> anyhow::Result<&str> {
+  if address.is_empty()
+    || address.contains(|c: char| c.is_control() || "<> ".contains(c))
+  {
+    return Err(anyhow!("Invalid email address {:?}", address));
+  }
+
+  Ok(address)
+}
+
+/// Format the headers and body of the mail, with CRLF line endings. Lines of
+/// the body that start with `.` are escaped with another `.`, so that they do
+/// not end the data.
+fn format_message(mail: &Mail<'_>) -> String {
+  let mut message = format!(
+    "From: <{}>\r\nTo: <{}>\r\nSubject: {}\r\nMIME-Version: 1.0\r\n\
+     Content-Type: text/plain; charset=utf-8\r\n\
+     Content-Transfer-Encoding: 8bit\r\n\r\n",
+    mail.from,
+    mail.to,
+    encode_header(mail.subject)
+  );
+
+  for line in mail.body.lines() {
+    if line.starts_with('.') {
+      message.push('.');
+    }
+    message.push_str(line);
+    message.push_str("\r\n");
+  }
+
+  // The terminating `.` is sent on the line after the body.
+  message.truncate(message.len() - 2);
+  message
+}
+
+/// Encode a header value so that it is a single line of ASCII, using the
+/// RFC 2047 `Q` encoding if it contains anything else.
+fn encode_header(value: &str) -> String {
+  if value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+    return value.to_string();
+  }
+
+  let encoded = value
+    .bytes()
+    .map(|byte| match byte {
+      b' ' => "_".to_string(),
+      b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => (byte as char).to_string(),
+      _ => format!("={:02X}", byte),
+    })
+    .collect::<String>();
+
+  format!("=?utf-8?Q?{}?=", encoded)
+}
@@ -0,0 +1,125 @@
+//! This module retries database operations that fail with transient errors.
+//! Serialization failures and deadlocks roll back the transaction, and a
+//! dropped connection is replaced by the pool, so running the operation again
+//! will usually succeed.
+//!
+//! Operations are only safe to retry if running them twice has the same
+//! effect as running them once. If the connection drops while a transaction
+//! commits, then we cannot tell whether the commit succeeded, so operations
+//! that are not idempotent should use [`RetryPolicy::never`].
+
+use crate::config::DatabaseConfig;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::time::Duration;
+use tracing::warn;
+
+/// The SQLSTATE of a transaction that could not be serialized with concurrent
+/// transactions.
+const SERIALIZATION_FAILURE: &str = "40001";
+
+/// The SQLSTATE of a transaction that was chosen as the victim of a deadlock.
+const DEADLOCK_DETECTED: &str = "40P01";
+
+/// The SQLSTATE of a connection that was terminated by an administrator, for
+/// example when the database fails over.
+const ADMIN_SHUTDOWN: &str = "57P01";
+
+/// How a database operation is retried when it fails with a transient error.
+/// The wait between attempts starts at `backoff` and doubles after each
+/// attempt, up to `max_backoff`, and is jittered so that requests that failed
+/// together do not retry together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+  /// The number of times the operation is attempted, including the first.
+  pub attempts: u32,
+  /// How long to wait before the first retry.
+  pub backoff: Duration,
+  /// The longest wait between attempts.
+  pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      attempts: 3,
+      backoff: Duration::from_millis(50),
+      max_backoff: Duration::from_secs(1),
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// Get the policy from the `database.retry_*` configuration keys.
+  pub fn from_config(config: &DatabaseConfig) -> Self {
+    Self {
+      attempts: config.retry_attempts,
+      backoff: config.retry_backoff,
+      max_backoff: config.retry_max_backoff,
+    }
+  }
+
+  /// A policy that attempts the operation once, for operations that are not
+  /// safe to retry.
+  pub fn never() -> Self {
+    Self {
+      attempts: 1,
+      ..Self::default()
+    }
+  }
+
+  /// Run the operation, running it again if it fails with a transient error
+  /// until it has been attempted `attempts` times. The error of the last
+  /// attempt is returned if every attempt fails.
+  pub async fn run<T, F, U>(&self, mut operation: F) -> anyhow::Result<T>
+  where
+    F: FnMut() -> U,
+    U: Future<Output = anyhow::Result<T>>,
+  {
+    let mut backoff = self.backoff;
+    let mut attempt = 1;
+
+    loop {
+      match operation().await {
+        Err(e) if attempt < self.attempts && is_transient(&e) => {
+          let delay = jitter(backoff);
+          warn!(
+            error = %e,
+            attempt,
+            retry_in_ms = delay.as_millis() as u64,
+            "Retrying after a transient database error"
+          );
+          tokio::time::sleep(delay).await;
+          backoff = (backoff * 2).min(self.max_backoff);
+          attempt += 1;
+        }
+        result => return result,
+      }
+    }
+  }
+}
+
+/// Whether the error is a transient database error, which may not happen if
+/// the operation is attempted again. These are serialization failures,
+/// deadlocks, and connections that were dropped.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+  match error.downcast_ref::<sqlx::Error>() {
+    Some(sqlx::Error::Database(e)) => matches!(
+      e.code().as_deref(),
+      Some(SERIALIZATION_FAILURE | DEADLOCK_DETECTED | ADMIN_SHUTDOWN)
+    ),
+    Some(sqlx::Error::Io(e)) => matches!(
+      e.kind(),
+      ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::BrokenPipe
+        | ErrorKind::UnexpectedEof
+    ),
+    _ => false,
+  }
+}
+
+/// Pick a random delay between half of the backoff and the whole backoff.
+fn jitter(backoff: Duration) -> Duration {
+  backoff.mul_f64(0.5 + fastrand::f64() / 2.0)
+}
@@ -1,10 +1,10 @@
-use log::info;
-use std::net::SocketAddr;
+use tracing::info;
 use tonic_reflection::server::{ServerReflection, ServerReflectionServer};
 use tokio_stream::wrappers::UnixListenerStream;
 use std::path::Path;
 use tokio::net::UnixListener;
-use crate::common::require_environment_variable;
+use crate::config::ServerConfig;
+use tonic::transport::Server;
 use crate::proto;
 
 /// This function creates a gRPC reflection server that allows clients to
@@ -39,20 +39,28 @@ pub fn set_sigint_handler_uds(host: String) {
 /// at the specified path, to ensure that the server starts cleanly.
 pub fn get_server_uds_stream(host: &String) -> anyhow::Result<UnixListenerStream> {
   let path = Path::new(&host);
-  let _ = std::fs::remove_file(&*path);
-  let uds = UnixListener::bind(&*path)?;
+  let _ = std::fs::remove_file(path);
+  let uds = UnixListener::bind(path)?;
   let uds_stream = UnixListenerStream::new(uds);
 
   Ok(uds_stream)
 }
 
-/// Get the address that the gRPC server should listen on. This address is
-/// determined by the SERVER_HOST and SERVER_PORT environment variables. If
-/// these variables are not set, then the server will fail to start.
-pub fn get_server_address_tcp() -> anyhow::Result<SocketAddr> {
-  let host = require_environment_variable("SERVER_HOST")?;
-  let port = require_environment_variable("SERVER_PORT")?;
-  let result = format!("{}:{}", host, port).parse()?;
+/// Create a tonic server builder from the configuration, which applies the
+/// request timeout and TLS settings. Pass it to
+/// [`crate::services::build_server`] to add the services.
+///
+/// With TLS the server only negotiates HTTP/2, so gRPC-Web and REST clients
+/// must also use HTTP/2.
+pub fn create_server_builder(config: &ServerConfig) -> anyhow::Result<Server> {
+  let mut server = Server::builder();
+
+  if let Some(timeout) = config.request_timeout {
+    server = server.timeout(timeout);
+  }
+  if let Some(tls) = &config.tls {
+    server = server.tls_config(tls.load()?)?;
+  }
 
-  Ok(result)
-}
\ No newline at end of file
+  Ok(server)
+}
@@ -1,16 +1,140 @@
 //! This module exposes a function that builds all the service implementations
 //! and adds them to a new tonic server.
+use crate::blobs::BlobStore;
+use crate::config::AttachmentsConfig;
+use crate::config::Config;
+use crate::config::TodosConfig;
+use crate::database::create_lazy_read_pool;
+use crate::gateway;
+use crate::gateway::GatewayLayer;
+use crate::grpc_web::create_cors_layer;
+use crate::metrics::MetricsLayer;
+use crate::rate_limit::RateLimitConfig;
+use crate::retry::RetryPolicy;
+use crate::services::api_keys::ApiKeyServiceHandler;
+use crate::services::labels::LabelServiceHandler;
+use crate::services::todo_lists::TodoListServiceHandler;
 use crate::services::todos::TodoServiceHandler;
+use crate::telemetry::TraceLayer;
 use sqlx::PgPool;
+use std::sync::Arc;
+use tonic::codegen::http::HeaderValue;
 use tonic::transport::server::Router;
 pub use tonic::transport::Server;
+use tonic_web::GrpcWebLayer;
+use tower::layer::util::Identity;
+use tower::layer::util::Stack;
+use tower_http::cors::CorsLayer;
 
+pub mod api_keys;
+pub mod labels;
+pub mod todo_lists;
 pub mod todos;
 
-/// Add all the services to a new tonic server. This will need to be updated 
-/// when new services are added.
-pub fn build_server(pool: &PgPool) -> Router {
-  let mut server = Server::builder();
+/// Options that control the behaviour of the services, shared by all of the
+/// service handlers.
+#[derive(Debug, Clone)]
+pub struct ServiceOptions {
+  /// The rate limits applied to each client, see [`RateLimitConfig`].
+  pub rate_limits: RateLimitConfig,
+  /// The rate limits applied to each peer address before requests are
+  /// authenticated, see [`crate::rate_limit::RateLimitLayer::per_peer`].
+  pub peer_rate_limits: RateLimitConfig,
+  /// The maximum number of todos that each user can own, or `None` if there is
+  /// no limit.
+  pub max_todos_per_owner: Option<i64>,
+  /// How subtasks are nested and completed, see [`TodosConfig`].
+  pub todos: TodosConfig,
+  /// The origins that browsers can call the services from with gRPC-Web, see
+  /// [`create_cors_layer`].
+  pub cors_allowed_origins: Vec<HeaderValue>,
+  /// The largest body in bytes that the REST/JSON gateway reads, see
+  /// [`crate::gateway`].
+  pub gateway_max_body_size: usize,
+  /// The pool of the read replica that read-only RPCs use, or `None` if they
+  /// use the primary. See [`crate::database::ReadConsistency`].
+  pub read_pool: Option<PgPool>,
+  /// How database operations are retried after transient errors, see
+  /// [`crate::retry`].
+  pub retry_policy: RetryPolicy,
+  /// The size limits of attachments, see [`AttachmentsConfig`].
+  pub attachments: AttachmentsConfig,
+  /// Where the content of attachments is kept, or `None` if attachments are
+  /// disabled. See [`crate::blobs`].
+  pub blob_store: Option<Arc<dyn BlobStore>>,
+}
+
+impl ServiceOptions {
+  /// Get the options from the `[limits]`, `[todos]`, `[server]`, `[database]`
+  /// and `[attachments]` sections of the configuration, and create the read
+  /// replica's pool if one is configured. The pool connects lazily, so this
+  /// does not wait for the replica. The blob store is not created here, see
+  /// [`crate::blobs::create_blob_store`].
+  pub fn from_config(config: &Config) -> Self {
+    Self {
+      rate_limits: config.limits.rate_limits.clone(),
+      peer_rate_limits: config.limits.peer_rate_limits.clone(),
+      max_todos_per_owner: config.limits.max_todos_per_owner,
+      todos: config.todos,
+      cors_allowed_origins: config.server.cors_allowed_origins.clone(),
+      gateway_max_body_size: config.server.gateway_max_body_size,
+      read_pool: create_lazy_read_pool(&config.database),
+      retry_policy: RetryPolicy::from_config(&config.database),
+      attachments: config.attachments.clone(),
+      blob_store: None,
+    }
+  }
+}
+
+impl Default for ServiceOptions {
+  fn default() -> Self {
+    Self {
+      rate_limits: RateLimitConfig::default(),
+      peer_rate_limits: RateLimitConfig::default(),
+      max_todos_per_owner: None,
+      todos: TodosConfig::default(),
+      cors_allowed_origins: Vec::new(),
+      gateway_max_body_size: gateway::DEFAULT_MAX_BODY_SIZE,
+      read_pool: None,
+      retry_policy: RetryPolicy::default(),
+      attachments: AttachmentsConfig::default(),
+      blob_store: None,
+    }
+  }
+}
+
+/// The layers that are applied to every service in the server.
+pub type ServerLayers = Stack<
+  MetricsLayer,
+  Stack<
+    TraceLayer,
+    Stack<GrpcWebLayer, Stack<GatewayLayer, Stack<CorsLayer, Identity>>>,
+  >,
+>;
+
+/// Add all the services to the given tonic server, which can be created with
+/// [`crate::server::create_server_builder`] or `Server::builder()`. This will
+/// need to be updated when new services are added. The server accepts HTTP/1.1
+/// as well as HTTP/2, so that browsers can call the services with gRPC-Web and
+/// partners can call them with REST/JSON. Both are translated to gRPC before
+/// any of the other layers see the request. Every request is then run in a span
+/// and recorded in the metrics.
+pub fn build_server(
+  server: Server,
+  pool: &PgPool,
+  options: &ServiceOptions,
+) -> Router<ServerLayers> {
+  let mut server = server
+    .accept_http1(true)
+    .layer(create_cors_layer(&options.cors_allowed_origins))
+    .layer(GatewayLayer::new(options.gateway_max_body_size))
+    .layer(GrpcWebLayer::new())
+    .layer(TraceLayer::new())
+    .layer(MetricsLayer::new());
 
-  server.add_service(TodoServiceHandler::create_server(pool.clone()))
+  server
+    .add_service(TodoServiceHandler::create_server(pool.clone(), options))
+    .add_service(ApiKeyServiceHandler::create_server(pool.clone(), options))
+    .add_service(LabelServiceHandler::create_server(pool.clone(), options))
+    .add_service(TodoListServiceHandler::create_server(pool.clone(), options))
 }
@@ -0,0 +1,112 @@
+//!
+//! # API Keys Service
+//!
+//! This module contains the implementation for the API keys service.
+//!
+mod common;
+mod create;
+mod list;
+mod revoke;
+mod rotate;
+
+use crate::auth::get_principal;
+use crate::auth::AuthLayer;
+use crate::auth::AuthService;
+use crate::common::error_to_status;
+use crate::proto::v1::api_keys::api_key_service_server::ApiKeyService;
+use crate::proto::v1::api_keys::api_key_service_server::ApiKeyServiceServer;
+use crate::proto::v1::api_keys::*;
+use crate::rate_limit::RateLimitLayer;
+use crate::rate_limit::RateLimitService;
+use crate::services::ServiceOptions;
+use sqlx::PgPool;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tower::ServiceBuilder;
+
+pub use common::ApiKeyRow;
+pub use create::create_api_key;
+pub use list::list_api_keys;
+pub use revoke::revoke_api_key;
+pub use rotate::rotate_api_key;
+
+/// Service handler struct definition that takes a database pool.
+#[derive(Debug)]
+pub struct ApiKeyServiceHandler {
+  pool: PgPool,
+}
+
+impl ApiKeyServiceHandler {
+  /// Create the server instance with this handler, wrapped in the
+  /// authentication and rate limiting layers.
+  pub fn create_server(
+    pool: PgPool,
+    options: &ServiceOptions,
+  ) -> RateLimitService<AuthService<RateLimitService<ApiKeyServiceServer<Self>>>>
+  {
+    ServiceBuilder::new()
+      .layer(RateLimitLayer::per_peer(options.peer_rate_limits.clone()))
+      .layer(AuthLayer::new(pool.clone()))
+      .layer(RateLimitLayer::new(options.rate_limits.clone()))
+      .service(ApiKeyServiceServer::new(Self { pool }))
+  }
+}
+
+/// This is the implementation of our gRPC service. Each function maps to a
+/// method in our protobuf definition, and delegates to a function in a
+/// separate module.
+#[tonic::async_trait]
+impl ApiKeyService for ApiKeyServiceHandler {
+  async fn create_api_key(
+    &self,
+    request: Request<CreateApiKeyRequest>,
+  ) -> Result<Response<CreateApiKeyResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let response =
+      create_api_key(self.pool.clone(), principal, request.into_inner())
+        .await
+        .map_err(|e| error_to_status("Failed to create API key", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn list_api_keys(
+    &self,
+    request: Request<ListApiKeysRequest>,
+  ) -> Result<Response<ListApiKeysResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let response =
+      list_api_keys(self.pool.clone(), principal, request.into_inner())
+        .await
+        .map_err(|e| error_to_status("Failed to list API keys", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn revoke_api_key(
+    &self,
+    request: Request<RevokeApiKeyRequest>,
+  ) -> Result<Response<RevokeApiKeyResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let response =
+      revoke_api_key(self.pool.clone(), principal, request.into_inner())
+        .await
+        .map_err(|e| error_to_status("Failed to revoke API key", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn rotate_api_key(
+    &self,
+    request: Request<RotateApiKeyRequest>,
+  ) -> Result<Response<RotateApiKeyResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let response =
+      rotate_api_key(self.pool.clone(), principal, request.into_inner())
+        .await
+        .map_err(|e| error_to_status("Failed to rotate API key", e))?;
+
+    Ok(Response::new(response))
+  }
+}
@@ -0,0 +1,91 @@
+//! Common types and functions for the API keys service.
+
+use crate::auth::Scope;
+use crate::common::sql_datetime_to_proto_timestamp;
+use crate::proto;
+use tonic::Status;
+use uuid::Uuid;
+
+/// The prefix of every API key, which makes keys easy to recognise, for
+/// example by secret scanners.
+const API_KEY_PREFIX: &str = "tdk_";
+
+/// The number of characters of each key that are stored in plain text, to
+/// help users identify their keys.
+const API_KEY_VISIBLE_LENGTH: usize = 12;
+
+/// Represents a row in the `api_keys` table.
+///
+/// # Fields
+///
+/// * `api_key_id` - The unique identifier of the API key.
+/// * `tenant_id` - The ID of the tenant that the API key belongs to.
+/// * `owner_id` - The ID of the user that the API key acts on behalf of.
+/// * `display_name` - A human readable name for the API key.
+/// * `key_prefix` - The first few characters of the key.
+/// * `key_hash` - The hash of the key, see [`crate::auth::hash_api_key`].
+/// * `scopes` - The scopes granted to the API key.
+/// * `created_at` - The timestamp when the API key was created.
+/// * `updated_at` - The timestamp when the API key was last updated.
+/// * `revoked_at` - The timestamp when the API key was revoked, if it has been.
+pub struct ApiKeyRow {
+  pub api_key_id: String,
+  pub tenant_id: String,
+  pub owner_id: String,
+  pub display_name: String,
+  pub key_prefix: String,
+  pub key_hash: String,
+  pub scopes: Vec<String>,
+  pub created_at: sqlx::types::time::OffsetDateTime,
+  pub updated_at: sqlx::types::time::OffsetDateTime,
+  pub revoked_at: Option<sqlx::types::time::OffsetDateTime>,
+}
+
+/// Converts an `ApiKeyRow` to a `proto::v1::api_keys::ApiKey`. The hash of the
+/// key is never returned to callers.
+impl From<ApiKeyRow> for proto::v1::api_keys::ApiKey {
+  fn from(row: ApiKeyRow) -> Self {
+    proto::v1::api_keys::ApiKey {
+      api_key_id: row.api_key_id,
+      display_name: row.display_name,
+      scopes: row.scopes,
+      key_prefix: row.key_prefix,
+      created_at: Some(sql_datetime_to_proto_timestamp(row.created_at)),
+      updated_at: Some(sql_datetime_to_proto_timestamp(row.updated_at)),
+      revoked_at: row.revoked_at.map(sql_datetime_to_proto_timestamp),
+    }
+  }
+}
+
+/// Generate a new random API key. The key contains 244 random bits from two
+/// version 4 UUIDs.
+pub fn generate_api_key() -> String {
+  format!(
+    "{}{}{}",
+    API_KEY_PREFIX,
+    Uuid::new_v4().simple(),
+    Uuid::new_v4().simple()
+  )
+}
+
+/// Get the part of a key that is stored in plain text.
+pub fn get_api_key_prefix(key: &str) -> String {
+  key.chars().take(API_KEY_VISIBLE_LENGTH).collect()
+}
+
+/// Validate the scopes requested for an API key, returning them in their
+/// canonical form. At least one scope is required.
+pub fn validate_scopes(scopes: &[String]) -> Result<Vec<String>, Status> {
+  if scopes.is_empty() {
+    return Err(Status::invalid_argument("At least one scope is required"));
+  }
+
+  let mut scopes = scopes
+    .iter()
+    .map(|scope| scope.parse::<Scope>().map(|s| s.to_string()))
+    .collect::<Result<Vec<_>, _>>()?;
+  scopes.sort();
+  scopes.dedup();
+
+  Ok(scopes)
+}
@@ -0,0 +1,67 @@
+//! # Create API Key
+//!
+//! This module contains the implementation for creating a new API key.
+use crate::auth::hash_api_key;
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::api_keys::common::generate_api_key;
+use crate::services::api_keys::common::get_api_key_prefix;
+use crate::services::api_keys::common::validate_scopes;
+use crate::services::api_keys::common::ApiKeyRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Create a new API key in the database.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who the key will act on behalf of.
+/// * `request` - The request containing the API key to create.
+///
+/// # Returns
+///
+/// A `CreateApiKeyResponse` containing the created API key and the key itself.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn create_api_key(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::api_keys::CreateApiKeyRequest,
+) -> anyhow::Result<proto::v1::api_keys::CreateApiKeyResponse> {
+  let params = request
+    .api_key
+    .ok_or(Status::invalid_argument("API key not provided"))?;
+  let scopes = validate_scopes(&params.scopes)?;
+  let key = generate_api_key();
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  // Only the hash of the key is stored, so this is the only time that the key
+  // can be returned to the caller.
+  let row = query_as!(
+    ApiKeyRow,
+    r#"
+    insert into api_keys (owner_id, display_name, key_prefix, key_hash, scopes)
+    values ($1, $2, $3, $4, $5)
+    returning *
+    "#,
+    principal.subject,
+    params.display_name,
+    get_api_key_prefix(&key),
+    hash_api_key(&key),
+    &scopes
+  )
+  .fetch_one(&mut *transaction)
+  .await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::api_keys::CreateApiKeyResponse {
+    api_key: Some(row.into()),
+    key,
+  })
+}
@@ -0,0 +1,51 @@
+//! # List API Keys
+//!
+//! This module contains the implementation for listing API keys.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::api_keys::common::ApiKeyRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tracing::instrument;
+
+/// List all API keys that act on behalf of the caller, including revoked keys.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, whose API keys will be listed.
+/// * `request` - The request, which is currently empty.
+///
+/// # Returns
+///
+/// A `ListApiKeysResponse` containing the list of API keys.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn list_api_keys(
+  pool: PgPool,
+  principal: Principal,
+  _request: proto::v1::api_keys::ListApiKeysRequest,
+) -> anyhow::Result<proto::v1::api_keys::ListApiKeysResponse> {
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let api_keys = query_as!(
+    ApiKeyRow,
+    r#"
+    select *
+    from api_keys
+    where owner_id = $1
+    order by created_at desc
+    "#,
+    principal.subject
+  )
+  .fetch_all(&mut *transaction)
+  .await?
+  .into_iter()
+  .map(|r| r.into())
+  .collect();
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::api_keys::ListApiKeysResponse { api_keys })
+}
@@ -0,0 +1,58 @@
+//! # Revoke API Key
+//!
+//! This module contains the implementation for revoking an API key.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::api_keys::common::ApiKeyRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Revoke an API key, so that it can no longer be used. Revoking a key that has
+/// already been revoked has no effect, and keeps the original revocation time.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who the key must act on behalf of.
+/// * `request` - The request containing the ID of the API key to revoke.
+///
+/// # Returns
+///
+/// A `RevokeApiKeyResponse` containing the revoked API key.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn revoke_api_key(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::api_keys::RevokeApiKeyRequest,
+) -> anyhow::Result<proto::v1::api_keys::RevokeApiKeyResponse> {
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row = query_as!(
+    ApiKeyRow,
+    r#"
+    update api_keys
+    set revoked_at = coalesce(revoked_at, now())
+    where api_key_id = $1
+      and owner_id = $2
+    returning *
+    "#,
+    request.api_key_id,
+    principal.subject
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "API key with id {} not found",
+    request.api_key_id
+  )))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::api_keys::RevokeApiKeyResponse {
+    api_key: Some(row.into()),
+  })
+}
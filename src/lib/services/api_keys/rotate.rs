@@ -0,0 +1,68 @@
+//! # Rotate API Key
+//!
+//! This module contains the implementation for rotating an API key.
+use crate::auth::hash_api_key;
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::api_keys::common::generate_api_key;
+use crate::services::api_keys::common::get_api_key_prefix;
+use crate::services::api_keys::common::ApiKeyRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Replace the key of an API key with a new one. The previous key stops working
+/// as soon as the transaction commits. Revoked keys cannot be rotated.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who the key must act on behalf of.
+/// * `request` - The request containing the ID of the API key to rotate.
+///
+/// # Returns
+///
+/// A `RotateApiKeyResponse` containing the API key and the new key itself.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn rotate_api_key(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::api_keys::RotateApiKeyRequest,
+) -> anyhow::Result<proto::v1::api_keys::RotateApiKeyResponse> {
+  let key = generate_api_key();
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row = query_as!(
+    ApiKeyRow,
+    r#"
+    update api_keys
+    set key_prefix = $1,
+        key_hash = $2
+    where api_key_id = $3
+      and owner_id = $4
+      and revoked_at is null
+    returning *
+    "#,
+    get_api_key_prefix(&key),
+    hash_api_key(&key),
+    request.api_key_id,
+    principal.subject
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Active API key with id {} not found",
+    request.api_key_id
+  )))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::api_keys::RotateApiKeyResponse {
+    api_key: Some(row.into()),
+    key,
+  })
+}
@@ -0,0 +1,169 @@
+//!
+//! # Labels Service
+//!
+//! This module contains the implementation for the labels service.
+//!
+mod common;
+mod create;
+mod delete;
+mod get;
+mod list;
+mod rename;
+
+use crate::auth::get_principal;
+use crate::auth::AuthLayer;
+use crate::auth::AuthService;
+use crate::common::error_to_status;
+use crate::database::ReadConsistency;
+use crate::proto::v1::labels::label_service_server::LabelService;
+use crate::proto::v1::labels::label_service_server::LabelServiceServer;
+use crate::proto::v1::labels::*;
+use crate::rate_limit::RateLimitLayer;
+use crate::rate_limit::RateLimitService;
+use crate::retry::RetryPolicy;
+use crate::services::ServiceOptions;
+use sqlx::PgPool;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tower::ServiceBuilder;
+
+pub use common::validate_label_name;
+pub use common::LabelRow;
+pub use create::create_label;
+pub use delete::delete_label;
+pub use get::get_label;
+pub use list::list_labels;
+pub use rename::rename_label;
+
+/// Service handler struct definition that takes a database pool, and
+/// optionally a read replica's pool. Every database operation is retried after
+/// transient errors, see [`crate::retry`].
+#[derive(Debug)]
+pub struct LabelServiceHandler {
+  pool: PgPool,
+  read_pool: Option<PgPool>,
+  retry_policy: RetryPolicy,
+}
+
+impl LabelServiceHandler {
+  /// Create the server instance with this handler, wrapped in the
+  /// authentication and rate limiting layers.
+  pub fn create_server(
+    pool: PgPool,
+    options: &ServiceOptions,
+  ) -> RateLimitService<AuthService<RateLimitService<LabelServiceServer<Self>>>>
+  {
+    let handler = Self {
+      pool: pool.clone(),
+      read_pool: options.read_pool.clone(),
+      retry_policy: options.retry_policy.clone(),
+    };
+
+    ServiceBuilder::new()
+      .layer(RateLimitLayer::per_peer(options.peer_rate_limits.clone()))
+      .layer(AuthLayer::new(pool))
+      .layer(RateLimitLayer::new(options.rate_limits.clone()))
+      .service(LabelServiceServer::new(handler))
+  }
+
+  /// Get the pool that a read-only request should use, in the same way as the
+  /// todos service.
+  fn get_read_pool<T>(&self, request: &Request<T>) -> Result<PgPool, Status> {
+    let consistency = ReadConsistency::from_metadata(request.metadata())?;
+
+    match (&self.read_pool, consistency) {
+      (Some(read_pool), ReadConsistency::Eventual) => Ok(read_pool.clone()),
+      _ => Ok(self.pool.clone()),
+    }
+  }
+}
+
+/// This is the implementation of our gRPC service. Each function maps to a
+/// method in our protobuf definition, and delegates to a function in a
+/// separate module.
+#[tonic::async_trait]
+impl LabelService for LabelServiceHandler {
+  async fn list_labels(
+    &self,
+    request: Request<ListLabelsRequest>,
+  ) -> Result<Response<ListLabelsResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| list_labels(pool.clone(), principal.clone(), request))
+      .await
+      .map_err(|e| error_to_status("Failed to list labels", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn get_label(
+    &self,
+    request: Request<GetLabelRequest>,
+  ) -> Result<Response<GetLabelResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| get_label(pool.clone(), principal.clone(), request.clone()))
+      .await
+      .map_err(|e| error_to_status("Failed to get label", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn create_label(
+    &self,
+    request: Request<CreateLabelRequest>,
+  ) -> Result<Response<CreateLabelResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| {
+        create_label(self.pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to create label", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn rename_label(
+    &self,
+    request: Request<RenameLabelRequest>,
+  ) -> Result<Response<RenameLabelResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| {
+        rename_label(self.pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to rename label", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn delete_label(
+    &self,
+    request: Request<DeleteLabelRequest>,
+  ) -> Result<Response<DeleteLabelResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| {
+        delete_label(self.pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to delete label", e))?;
+
+    Ok(Response::new(response))
+  }
+}
@@ -0,0 +1,56 @@
+//! Common types and functions for the labels service.
+
+use crate::common::sql_datetime_to_proto_timestamp;
+use crate::proto;
+use tonic::Status;
+
+/// The maximum number of characters in the name of a label.
+const MAX_LABEL_NAME_LENGTH: usize = 64;
+
+/// Represents a row in the `labels` table.
+///
+/// # Fields
+///
+/// * `tenant_id` - The ID of the tenant that the label belongs to.
+/// * `owner_id` - The ID of the user that owns the label.
+/// * `name` - The name of the label, which is unique for its owner.
+/// * `created_at` - The timestamp when the label was created.
+/// * `updated_at` - The timestamp when the label was last updated.
+pub struct LabelRow {
+  pub tenant_id: String,
+  pub owner_id: String,
+  pub name: String,
+  pub created_at: sqlx::types::time::OffsetDateTime,
+  pub updated_at: sqlx::types::time::OffsetDateTime,
+}
+
+/// Converts a `LabelRow` to a `proto::v1::labels::Label`.
+impl From<LabelRow> for proto::v1::labels::Label {
+  fn from(row: LabelRow) -> Self {
+    proto::v1::labels::Label {
+      name: row.name,
+      created_at: Some(sql_datetime_to_proto_timestamp(row.created_at)),
+      updated_at: Some(sql_datetime_to_proto_timestamp(row.updated_at)),
+    }
+  }
+}
+
+/// Check that a label name is between 1 and 64 characters long, does not start
+/// or end with whitespace and does not contain control characters. If it is
+/// not, then an `INVALID_ARGUMENT` status is returned.
+pub fn validate_label_name(name: &str) -> Result<(), Status> {
+  let length = name.chars().count();
+  if length == 0
+    || length > MAX_LABEL_NAME_LENGTH
+    || name.trim() != name
+    || name.chars().any(char::is_control)
+  {
+    return Err(Status::invalid_argument(format!(
+      "Invalid label name {:?}, which must be 1 to {} characters without \
+       surrounding whitespace",
+      name, MAX_LABEL_NAME_LENGTH
+    )));
+  }
+
+  Ok(())
+}
@@ -0,0 +1,63 @@
+//! # Create Label
+//!
+//! This module contains the implementation for creating a new label.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::labels::common::validate_label_name;
+use crate::services::labels::common::LabelRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Create a new label. If the caller already has a label with the same name,
+/// then an `ALREADY_EXISTS` error is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who will own the new label.
+/// * `request` - The request containing the label to create.
+///
+/// # Returns
+///
+/// A `CreateLabelResponse` containing the created label.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn create_label(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::labels::CreateLabelRequest,
+) -> anyhow::Result<proto::v1::labels::CreateLabelResponse> {
+  let label = request
+    .label
+    .ok_or(Status::invalid_argument("Label not provided"))?;
+  validate_label_name(&label.name)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row = query_as!(
+    LabelRow,
+    r#"
+    insert into labels (owner_id, name)
+    values ($1, $2)
+    on conflict do nothing
+    returning *
+    "#,
+    principal.subject,
+    label.name
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::already_exists(format!(
+    "Label {} already exists",
+    label.name
+  )))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::labels::CreateLabelResponse {
+    label: Some(row.into()),
+  })
+}
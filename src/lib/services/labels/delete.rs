@@ -0,0 +1,53 @@
+//! # Delete Label
+//!
+//! This module contains the implementation for deleting a label.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use sqlx::query;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Delete a label. The database removes the label from every todo that has it,
+/// but the todos themselves are kept.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the label.
+/// * `request` - The request containing the name of the label to delete.
+///
+/// # Returns
+///
+/// A `DeleteLabelResponse` indicating the label was deleted.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn delete_label(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::labels::DeleteLabelRequest,
+) -> anyhow::Result<proto::v1::labels::DeleteLabelResponse> {
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  query!(
+    r#"
+    delete from labels
+    where owner_id = $1
+      and name = $2
+    returning 1 as deleted
+    "#,
+    principal.subject,
+    request.name
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Label {} not found",
+    request.name
+  )))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::labels::DeleteLabelResponse {})
+}
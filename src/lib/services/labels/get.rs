@@ -0,0 +1,57 @@
+//! # Get Label
+//!
+//! This module contains the implementation for getting a label by its name.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::labels::common::LabelRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Get a label by its name. If the caller does not have a label with the name,
+/// then a `NOT_FOUND` error is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the label.
+/// * `request` - The request containing the name of the label to retrieve.
+///
+/// # Returns
+///
+/// A `GetLabelResponse` containing the label.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_label(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::labels::GetLabelRequest,
+) -> anyhow::Result<proto::v1::labels::GetLabelResponse> {
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row = query_as!(
+    LabelRow,
+    r#"
+    select *
+    from labels
+    where owner_id = $1
+      and name = $2
+    "#,
+    principal.subject,
+    request.name
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Label {} not found",
+    request.name
+  )))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::labels::GetLabelResponse {
+    label: Some(row.into()),
+  })
+}
@@ -0,0 +1,51 @@
+//! # List Labels
+//!
+//! This module contains the implementation for listing labels.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::labels::common::LabelRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tracing::instrument;
+
+/// List all labels owned by the caller, ordered by name.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, whose labels will be listed.
+/// * `request` - The request, which is currently empty.
+///
+/// # Returns
+///
+/// A `ListLabelsResponse` containing the list of labels.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn list_labels(
+  pool: PgPool,
+  principal: Principal,
+  _request: proto::v1::labels::ListLabelsRequest,
+) -> anyhow::Result<proto::v1::labels::ListLabelsResponse> {
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let labels = query_as!(
+    LabelRow,
+    r#"
+    select *
+    from labels
+    where owner_id = $1
+    order by name
+    "#,
+    principal.subject
+  )
+  .fetch_all(&mut *transaction)
+  .await?
+  .into_iter()
+  .map(|r| r.into())
+  .collect();
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::labels::ListLabelsResponse { labels })
+}
@@ -0,0 +1,71 @@
+//! # Rename Label
+//!
+//! This module contains the implementation for renaming a label.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::labels::common::validate_label_name;
+use crate::services::labels::common::LabelRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Rename a label. The todos that have the label refer to it by name, and the
+/// database cascades the new name to them, so they keep the label. If the
+/// caller already has a label with the new name, then an `ALREADY_EXISTS`
+/// error is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the label.
+/// * `request` - The request containing the current and new names.
+///
+/// # Returns
+///
+/// A `RenameLabelResponse` containing the renamed label.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn rename_label(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::labels::RenameLabelRequest,
+) -> anyhow::Result<proto::v1::labels::RenameLabelResponse> {
+  validate_label_name(&request.new_name)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row = query_as!(
+    LabelRow,
+    r#"
+    update labels
+    set name = $3
+    where owner_id = $1
+      and name = $2
+    returning *
+    "#,
+    principal.subject,
+    request.name,
+    request.new_name
+  )
+  .fetch_optional(&mut *transaction)
+  .await
+  .map_err(|e| match e.as_database_error() {
+    Some(error) if error.is_unique_violation() => Status::already_exists(
+      format!("Label {} already exists", request.new_name),
+    )
+    .into(),
+    _ => anyhow::Error::from(e),
+  })?
+  .ok_or(Status::not_found(format!(
+    "Label {} not found",
+    request.name
+  )))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::labels::RenameLabelResponse {
+    label: Some(row.into()),
+  })
+}
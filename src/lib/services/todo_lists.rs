@@ -0,0 +1,173 @@
+//!
+//! # Todo Lists Service
+//!
+//! This module contains the implementation for the todo lists service, which
+//! manages the lists that todos belong to.
+//!
+mod common;
+mod create;
+mod delete;
+mod get;
+mod list;
+mod update;
+
+use crate::auth::get_principal;
+use crate::auth::AuthLayer;
+use crate::auth::AuthService;
+use crate::common::error_to_status;
+use crate::database::ReadConsistency;
+use crate::proto::v1::todo_lists::todo_list_service_server::TodoListService;
+use crate::proto::v1::todo_lists::todo_list_service_server::TodoListServiceServer;
+use crate::proto::v1::todo_lists::*;
+use crate::rate_limit::RateLimitLayer;
+use crate::rate_limit::RateLimitService;
+use crate::retry::RetryPolicy;
+use crate::services::ServiceOptions;
+use sqlx::PgPool;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tower::ServiceBuilder;
+
+pub use common::get_list_name;
+pub use common::parse_list_name;
+pub use common::TodoListRow;
+pub use common::DEFAULT_LIST_ID;
+pub use create::create_todo_list;
+pub use delete::delete_todo_list;
+pub use get::get_todo_list;
+pub use list::list_todo_lists;
+pub use update::update_todo_list;
+
+/// Service handler struct definition that takes a database pool, and
+/// optionally a read replica's pool. Every database operation is retried after
+/// transient errors, see [`crate::retry`].
+#[derive(Debug)]
+pub struct TodoListServiceHandler {
+  pool: PgPool,
+  read_pool: Option<PgPool>,
+  retry_policy: RetryPolicy,
+}
+
+impl TodoListServiceHandler {
+  /// Create the server instance with this handler, wrapped in the
+  /// authentication and rate limiting layers.
+  pub fn create_server(
+    pool: PgPool,
+    options: &ServiceOptions,
+  ) -> RateLimitService<
+    AuthService<RateLimitService<TodoListServiceServer<Self>>>,
+  > {
+    let handler = Self {
+      pool: pool.clone(),
+      read_pool: options.read_pool.clone(),
+      retry_policy: options.retry_policy.clone(),
+    };
+
+    ServiceBuilder::new()
+      .layer(RateLimitLayer::per_peer(options.peer_rate_limits.clone()))
+      .layer(AuthLayer::new(pool))
+      .layer(RateLimitLayer::new(options.rate_limits.clone()))
+      .service(TodoListServiceServer::new(handler))
+  }
+
+  /// Get the pool that a read-only request should use, in the same way as the
+  /// todos service.
+  fn get_read_pool<T>(&self, request: &Request<T>) -> Result<PgPool, Status> {
+    let consistency = ReadConsistency::from_metadata(request.metadata())?;
+
+    match (&self.read_pool, consistency) {
+      (Some(read_pool), ReadConsistency::Eventual) => Ok(read_pool.clone()),
+      _ => Ok(self.pool.clone()),
+    }
+  }
+}
+
+/// This is the implementation of our gRPC service. Each function maps to a
+/// method in our protobuf definition, and delegates to a function in a
+/// separate module.
+#[tonic::async_trait]
+impl TodoListService for TodoListServiceHandler {
+  async fn list_todo_lists(
+    &self,
+    request: Request<ListTodoListsRequest>,
+  ) -> Result<Response<ListTodoListsResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| list_todo_lists(pool.clone(), principal.clone(), request))
+      .await
+      .map_err(|e| error_to_status("Failed to list todo lists", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn get_todo_list(
+    &self,
+    request: Request<GetTodoListRequest>,
+  ) -> Result<Response<GetTodoListResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| get_todo_list(pool.clone(), principal.clone(), request.clone()))
+      .await
+      .map_err(|e| error_to_status("Failed to get todo list", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn create_todo_list(
+    &self,
+    request: Request<CreateTodoListRequest>,
+  ) -> Result<Response<CreateTodoListResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| {
+        create_todo_list(self.pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to create todo list", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn update_todo_list(
+    &self,
+    request: Request<UpdateTodoListRequest>,
+  ) -> Result<Response<UpdateTodoListResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| {
+        update_todo_list(self.pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to update todo list", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn delete_todo_list(
+    &self,
+    request: Request<DeleteTodoListRequest>,
+  ) -> Result<Response<DeleteTodoListResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| {
+        delete_todo_list(self.pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to delete todo list", e))?;
+
+    Ok(Response::new(response))
+  }
+}
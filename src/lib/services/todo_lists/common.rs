@@ -0,0 +1,100 @@
+//! Common types and functions for the todo lists service.
+
+use crate::common::sql_datetime_to_proto_timestamp;
+use crate::proto;
+use tonic::Status;
+
+/// The ID of the list that todos are added to when they are created without
+/// a parent. The database creates it with its owner's first such todo.
+pub const DEFAULT_LIST_ID: &str = "inbox";
+
+/// The maximum number of characters in the ID of a list.
+const MAX_LIST_ID_LENGTH: usize = 63;
+
+/// Represents a row in the `todo_lists` table.
+///
+/// # Fields
+///
+/// * `tenant_id` - The ID of the tenant that the list belongs to.
+/// * `owner_id` - The ID of the user that owns the list.
+/// * `list_id` - The ID of the list, which is unique for its owner.
+/// * `display_name` - The name of the list that is shown to the user.
+/// * `description` - The description of the list.
+/// * `archived` - Whether the list is archived.
+/// * `created_at` - The timestamp when the list was created.
+/// * `updated_at` - The timestamp when the list was last updated.
+pub struct TodoListRow {
+  pub tenant_id: String,
+  pub owner_id: String,
+  pub list_id: String,
+  pub display_name: String,
+  pub description: String,
+  pub archived: bool,
+  pub created_at: sqlx::types::time::OffsetDateTime,
+  pub updated_at: sqlx::types::time::OffsetDateTime,
+}
+
+/// Converts a `TodoListRow` to a `proto::v1::todo_lists::TodoList`.
+impl From<TodoListRow> for proto::v1::todo_lists::TodoList {
+  fn from(row: TodoListRow) -> Self {
+    proto::v1::todo_lists::TodoList {
+      name: get_list_name(&row.list_id),
+      display_name: row.display_name,
+      description: row.description,
+      archived: row.archived,
+      created_at: Some(sql_datetime_to_proto_timestamp(row.created_at)),
+      updated_at: Some(sql_datetime_to_proto_timestamp(row.updated_at)),
+    }
+  }
+}
+
+/// Get the resource name of a list, `lists/{list_id}`.
+pub fn get_list_name(list_id: &str) -> String {
+  format!("lists/{}", list_id)
+}
+
+/// Get the ID of a list from its resource name, `lists/{list_id}`. If the name
+/// is not in that format, then an `INVALID_ARGUMENT` status is returned.
+pub fn parse_list_name(name: &str) -> Result<&str, Status> {
+  match name.strip_prefix("lists/") {
+    Some(list_id) if !list_id.is_empty() && !list_id.contains('/') => {
+      Ok(list_id)
+    }
+    _ => Err(Status::invalid_argument(format!(
+      "Invalid list name {:?}, expected lists/{{list_id}}",
+      name
+    ))),
+  }
+}
+
+/// Check that a list ID chosen by the caller is between 1 and 63 characters
+/// long, starts with a lowercase letter, and only contains lowercase letters,
+/// digits and hyphens, which cannot be last. See https://google.aip.dev/122.
+/// If it is not, then an `INVALID_ARGUMENT` status is returned.
+pub fn validate_list_id(list_id: &str) -> Result<(), Status> {
+  let is_valid = list_id.len() <= MAX_LIST_ID_LENGTH
+    && list_id.starts_with(|c: char| c.is_ascii_lowercase())
+    && !list_id.ends_with('-')
+    && list_id
+      .chars()
+      .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+  if !is_valid {
+    return Err(Status::invalid_argument(format!(
+      "Invalid list ID {:?}, which must be 1 to {} lowercase letters, digits \
+       or hyphens, starting with a letter",
+      list_id, MAX_LIST_ID_LENGTH
+    )));
+  }
+
+  Ok(())
+}
+
+/// Check that a display name is not empty. If it is, then an
+/// `INVALID_ARGUMENT` status is returned.
+pub fn validate_display_name(display_name: &str) -> Result<(), Status> {
+  if display_name.trim().is_empty() {
+    return Err(Status::invalid_argument("display_name cannot be empty"));
+  }
+
+  Ok(())
+}
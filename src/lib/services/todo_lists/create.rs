@@ -0,0 +1,77 @@
+//! # Create Todo List
+//!
+//! This module contains the implementation for creating a new todo list.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todo_lists::common::get_list_name;
+use crate::services::todo_lists::common::validate_display_name;
+use crate::services::todo_lists::common::validate_list_id;
+use crate::services::todo_lists::common::TodoListRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Create a new todo list, with the ID from the request or a generated one. If
+/// the caller already has a list with the same ID, then an `ALREADY_EXISTS`
+/// error is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who will own the new list.
+/// * `request` - The request containing the list to create.
+///
+/// # Returns
+///
+/// A `CreateTodoListResponse` containing the created todo list.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn create_todo_list(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todo_lists::CreateTodoListRequest,
+) -> anyhow::Result<proto::v1::todo_lists::CreateTodoListResponse> {
+  let todo_list = request
+    .todo_list
+    .ok_or(Status::invalid_argument("Todo list not provided"))?;
+  validate_display_name(&todo_list.display_name)?;
+  let list_id = match request.todo_list_id.is_empty() {
+    true => Uuid::new_v4().to_string(),
+    false => {
+      validate_list_id(&request.todo_list_id)?;
+      request.todo_list_id
+    }
+  };
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row = query_as!(
+    TodoListRow,
+    r#"
+    insert into todo_lists (owner_id, list_id, display_name, description, archived)
+    values ($1, $2, $3, $4, $5)
+    on conflict do nothing
+    returning *
+    "#,
+    principal.subject,
+    list_id,
+    todo_list.display_name,
+    todo_list.description,
+    todo_list.archived
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::already_exists(format!(
+    "{} already exists",
+    get_list_name(&list_id)
+  )))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todo_lists::CreateTodoListResponse {
+    todo_list: Some(row.into()),
+  })
+}
@@ -0,0 +1,78 @@
+//! # Delete Todo List
+//!
+//! This module contains the implementation for deleting a todo list.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todo_lists::common::parse_list_name;
+use sqlx::query;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Delete a todo list. The database refuses to delete a list that still has
+/// todos, so a `FAILED_PRECONDITION` error is returned unless the request
+/// forces the deletion, in which case the list's todos are deleted first.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the list.
+/// * `request` - The request containing the name of the list to delete.
+///
+/// # Returns
+///
+/// A `DeleteTodoListResponse` indicating the list was deleted.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn delete_todo_list(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todo_lists::DeleteTodoListRequest,
+) -> anyhow::Result<proto::v1::todo_lists::DeleteTodoListResponse> {
+  let list_id = parse_list_name(&request.name)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  if request.force {
+    query!(
+      r#"
+      delete from todos
+      where owner_id = $1
+        and list_id = $2
+      "#,
+      principal.subject,
+      list_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+  }
+
+  query!(
+    r#"
+    delete from todo_lists
+    where owner_id = $1
+      and list_id = $2
+    returning 1 as deleted
+    "#,
+    principal.subject,
+    list_id
+  )
+  .fetch_optional(&mut *transaction)
+  .await
+  .map_err(|e| match e.as_database_error() {
+    Some(error) if error.is_foreign_key_violation() => {
+      Status::failed_precondition(format!(
+        "{} still has todos, which must be deleted first or with force",
+        request.name
+      ))
+      .into()
+    }
+    _ => anyhow::Error::from(e),
+  })?
+  .ok_or(Status::not_found(format!("{} not found", request.name)))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todo_lists::DeleteTodoListResponse {})
+}
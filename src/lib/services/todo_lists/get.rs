@@ -0,0 +1,58 @@
+//! # Get Todo List
+//!
+//! This module contains the implementation for getting a todo list by its
+//! name.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todo_lists::common::parse_list_name;
+use crate::services::todo_lists::common::TodoListRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Get a todo list by its name. If the caller does not have a list with the
+/// name, then a `NOT_FOUND` error is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the list.
+/// * `request` - The request containing the name of the list to retrieve.
+///
+/// # Returns
+///
+/// A `GetTodoListResponse` containing the todo list.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_todo_list(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todo_lists::GetTodoListRequest,
+) -> anyhow::Result<proto::v1::todo_lists::GetTodoListResponse> {
+  let list_id = parse_list_name(&request.name)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row = query_as!(
+    TodoListRow,
+    r#"
+    select *
+    from todo_lists
+    where owner_id = $1
+      and list_id = $2
+    "#,
+    principal.subject,
+    list_id
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!("{} not found", request.name)))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todo_lists::GetTodoListResponse {
+    todo_list: Some(row.into()),
+  })
+}
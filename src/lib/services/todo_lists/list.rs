@@ -0,0 +1,54 @@
+//! # List Todo Lists
+//!
+//! This module contains the implementation for listing todo lists.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todo_lists::common::TodoListRow;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tracing::instrument;
+
+/// List the todo lists owned by the caller, ordered by when they were created.
+/// Archived lists are only listed if the request asks for them.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, whose lists will be listed.
+/// * `request` - The request, which says whether to list archived lists.
+///
+/// # Returns
+///
+/// A `ListTodoListsResponse` containing the list of todo lists.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn list_todo_lists(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todo_lists::ListTodoListsRequest,
+) -> anyhow::Result<proto::v1::todo_lists::ListTodoListsResponse> {
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let todo_lists = query_as!(
+    TodoListRow,
+    r#"
+    select *
+    from todo_lists
+    where owner_id = $1
+      and ($2 or not archived)
+    order by created_at, list_id
+    "#,
+    principal.subject,
+    request.show_archived
+  )
+  .fetch_all(&mut *transaction)
+  .await?
+  .into_iter()
+  .map(|r| r.into())
+  .collect();
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todo_lists::ListTodoListsResponse { todo_lists })
+}
@@ -0,0 +1,82 @@
+//! # Update Todo List
+//!
+//! This module contains the implementation for updating a todo list.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todo_lists::common::parse_list_name;
+use crate::services::todo_lists::common::validate_display_name;
+use crate::services::todo_lists::common::TodoListRow;
+use crate::update_mask_handler::UpdateMaskHandler;
+use sqlx::query_as;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Update the fields of a todo list that are in the request's update mask. If
+/// the caller does not have the list, then a `NOT_FOUND` error is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the list.
+/// * `request` - The request containing the list to update and the mask.
+///
+/// # Returns
+///
+/// A `UpdateTodoListResponse` containing the updated todo list.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn update_todo_list(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todo_lists::UpdateTodoListRequest,
+) -> anyhow::Result<proto::v1::todo_lists::UpdateTodoListResponse> {
+  let params = request
+    .todo_list
+    .ok_or(Status::invalid_argument("Todo list not provided"))?;
+  let list_id = parse_list_name(&params.name)?;
+
+  // As with todos, we require an update mask so that the fields to update are
+  // always explicit.
+  let update_mask_paths = request
+    .update_mask
+    .ok_or(Status::invalid_argument("Update mask not provided"))?
+    .paths;
+  let update_mask_handler = UpdateMaskHandler::new(&params, update_mask_paths);
+
+  let display_name =
+    update_mask_handler.get_param("display_name", |p| &p.display_name);
+  if let Some(display_name) = &display_name {
+    validate_display_name(display_name)?;
+  }
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row = query_as!(
+    TodoListRow,
+    r#"
+    update todo_lists
+    set display_name = coalesce($3, todo_lists.display_name),
+        description = coalesce($4, todo_lists.description),
+        archived = coalesce($5, todo_lists.archived)
+    where owner_id = $1
+      and list_id = $2
+    returning *
+    "#,
+    principal.subject,
+    list_id,
+    display_name,
+    update_mask_handler.get_param("description", |p| &p.description),
+    update_mask_handler.get_param("archived", |p| &p.archived)
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!("{} not found", params.name)))?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todo_lists::UpdateTodoListResponse {
+    todo_list: Some(row.into()),
+  })
+}
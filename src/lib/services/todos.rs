@@ -3,40 +3,142 @@
 //!
 //! This module contains the implementation for the todos service.
 //!
+mod attachments;
+mod comments;
 mod common;
+mod complete;
 mod create;
 mod delete;
 mod get;
+mod history;
+mod labels;
 mod list;
+mod move_todo;
+mod revisions;
+mod search;
+mod series;
+mod subtasks;
 mod update;
 
+use crate::auth::get_principal;
+use crate::auth::AuthLayer;
+use crate::auth::AuthService;
+use crate::blobs::BlobStore;
+use crate::common::error_to_status;
+use crate::config::AttachmentsConfig;
+use crate::config::TodosConfig;
+use crate::database::ReadConsistency;
 use crate::proto::v1::todos::todo_service_server::TodoService;
 use crate::proto::v1::todos::todo_service_server::TodoServiceServer;
 use crate::proto::v1::todos::*;
+use crate::rate_limit::RateLimitLayer;
+use crate::rate_limit::RateLimitService;
+use crate::retry::RetryPolicy;
+use crate::services::todos::attachments::delete_attachment;
+use crate::services::todos::attachments::download_attachment;
+use crate::services::todos::attachments::get_attachment;
+use crate::services::todos::attachments::list_attachments;
+use crate::services::todos::attachments::upload_attachment;
+use crate::services::todos::attachments::DownloadStream;
+use crate::services::todos::comments::create_comment;
+use crate::services::todos::comments::delete_comment;
+use crate::services::todos::comments::get_comment;
+use crate::services::todos::comments::list_comment_edits;
+use crate::services::todos::comments::list_comments;
+use crate::services::todos::comments::update_comment;
+use crate::services::todos::common::parse_todo_reference;
+use crate::services::todos::complete::complete_todo;
+use crate::services::todos::complete::reopen_todo;
 use crate::services::todos::create::create_todo;
 use crate::services::todos::delete::delete;
 use crate::services::todos::get::get_todo;
-use crate::services::todos::list::list_todos;
+use crate::services::todos::move_todo::move_todo;
+use crate::services::todos::revisions::get_todo_revision;
+use crate::services::todos::revisions::list_todo_revisions;
+use crate::services::todos::revisions::restore_todo_revision;
+use crate::services::todos::search::search_todos;
 use crate::services::todos::update::update_todo;
+use crate::services::ServiceOptions;
+use crate::telemetry::record_todo_id;
 use sqlx::PgPool;
+use std::sync::Arc;
 use tonic::Request;
 use tonic::Response;
 use tonic::Status;
+use tonic::Streaming;
+use tower::ServiceBuilder;
 
 pub use common::TodoRow;
+pub use history::list_todo_history;
+pub use list::list_todos;
+pub use search::parse_search_language;
+pub use subtasks::SubtaskCompletion;
 
-/// Service handler struct definition that takes a database pool. Any other
-/// required dependencies should be added here.
+/// Service handler struct definition that takes a database pool, and
+/// optionally a read replica's pool. Any other required dependencies should be
+/// added here.
+///
+/// Every database operation is retried after transient errors, except for
+/// creates that do not carry a request ID, and uploads, whose content is
+/// streamed and cannot be sent again. See [`crate::retry`].
 #[derive(Debug)]
 pub struct TodoServiceHandler {
   pool: PgPool,
+  read_pool: Option<PgPool>,
+  max_todos_per_owner: Option<i64>,
+  config: TodosConfig,
+  retry_policy: RetryPolicy,
+  attachments: AttachmentsConfig,
+  blob_store: Option<Arc<dyn BlobStore>>,
 }
 
 impl TodoServiceHandler {
   /// Create the server instance with this handler so the application level
-  /// server code can be kept clean.
-  pub fn create_server(pool: PgPool) -> TodoServiceServer<Self> {
-    TodoServiceServer::new(Self { pool })
+  /// server code can be kept clean. The server is wrapped in the
+  /// authentication layer, because every todo is owned by a user, and then in
+  /// the rate limiting layer, so that each user is limited separately. Each
+  /// peer address is also limited before it is authenticated, so that failed
+  /// authentication attempts cannot flood the database.
+  pub fn create_server(
+    pool: PgPool,
+    options: &ServiceOptions,
+  ) -> RateLimitService<AuthService<RateLimitService<TodoServiceServer<Self>>>>
+  {
+    let handler = Self {
+      pool: pool.clone(),
+      read_pool: options.read_pool.clone(),
+      max_todos_per_owner: options.max_todos_per_owner,
+      config: options.todos,
+      retry_policy: options.retry_policy.clone(),
+      attachments: options.attachments.clone(),
+      blob_store: options.blob_store.clone(),
+    };
+
+    ServiceBuilder::new()
+      .layer(RateLimitLayer::per_peer(options.peer_rate_limits.clone()))
+      .layer(AuthLayer::new(pool))
+      .layer(RateLimitLayer::new(options.rate_limits.clone()))
+      .service(TodoServiceServer::new(handler))
+  }
+
+  /// Get the pool that a read-only request should use. Requests read from the
+  /// read replica if there is one, unless they asked for strong consistency
+  /// because they need to see their own writes.
+  fn get_read_pool<T>(&self, request: &Request<T>) -> Result<PgPool, Status> {
+    let consistency = ReadConsistency::from_metadata(request.metadata())?;
+
+    match (&self.read_pool, consistency) {
+      (Some(read_pool), ReadConsistency::Eventual) => Ok(read_pool.clone()),
+      _ => Ok(self.pool.clone()),
+    }
+  }
+
+  /// Get the store that the content of attachments is kept in, or an
+  /// `UNIMPLEMENTED` status if attachments are disabled.
+  fn get_blob_store(&self) -> Result<Arc<dyn BlobStore>, Status> {
+    self.blob_store.clone().ok_or(Status::unimplemented(
+      "Attachments are not enabled on this server",
+    ))
   }
 }
 
@@ -45,32 +147,67 @@ impl TodoServiceHandler {
 /// specific to the service and how it interacts with the database.
 #[tonic::async_trait]
 impl TodoService for TodoServiceHandler {
+  type DownloadAttachmentStream = DownloadStream;
+
   async fn list_todos(
     &self,
     request: Request<ListTodosRequest>,
   ) -> Result<Response<ListTodosResponse>, Status> {
+    // Every todo is scoped to the caller that was identified by the
+    // authentication layer.
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+
     // Delegate the request handling to a function in a separate module, so that
-    // we can keep this file clean.
-    let response = list_todos(self.pool.clone(), request.into_inner())
+    // we can keep this file clean. Listing has no side effects, so it can be
+    // retried if it fails with a transient error.
+    let response = self
+      .retry_policy
+      .run(|| list_todos(pool.clone(), principal.clone(), request.clone()))
       .await
-      .map_err(|e| {
-        // Map any errors to a gRPC status message.
-        Status::internal(format!("Failed to list todos: \n{}", e.to_string()))
-      })?;
+      // Map any errors to a gRPC status message.
+      .map_err(|e| error_to_status("Failed to list todos", e))?;
 
     // Wrap the protobuf response in tonic's Response type.
     Ok(Response::new(response))
   }
 
+  async fn search_todos(
+    &self,
+    request: Request<SearchTodosRequest>,
+  ) -> Result<Response<SearchTodosResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    let response = self
+      .retry_policy
+      .run(|| {
+        search_todos(
+          pool.clone(),
+          principal.clone(),
+          request.clone(),
+        )
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to search todos", e))?;
+
+    Ok(Response::new(response))
+  }
+
   async fn get_todo(
     &self,
     request: Request<GetTodoRequest>,
   ) -> Result<Response<GetTodoResponse>, Status> {
-    let response = get_todo(self.pool.clone(), request.into_inner())
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| get_todo(pool.clone(), principal.clone(), request.clone()))
       .await
-      .map_err(|e| {
-        Status::internal(format!("Failed to get todo: \n{}", e.to_string()))
-      })?;
+      .map_err(|e| error_to_status("Failed to get todo", e))?;
 
     Ok(Response::new(response))
   }
@@ -79,11 +216,33 @@ impl TodoService for TodoServiceHandler {
     &self,
     request: Request<CreateTodoRequest>,
   ) -> Result<Response<CreateTodoResponse>, Status> {
-    let response = create_todo(self.pool.clone(), request.into_inner())
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+
+    // A create that failed when its connection dropped may have committed, so
+    // running it again could create the todo twice. We only retry creates with
+    // a request ID, which lets a retry find the todo that was created.
+    let retry_policy = if request.request_id.is_empty() {
+      RetryPolicy::never()
+    } else {
+      self.retry_policy.clone()
+    };
+    let response = retry_policy
+      .run(|| {
+        create_todo(
+          self.pool.clone(),
+          principal.clone(),
+          self.max_todos_per_owner,
+          self.config,
+          request.clone(),
+        )
+      })
       .await
-      .map_err(|e| {
-      Status::internal(format!("Failed to create todo: \n{}", e.to_string()))
-    })?;
+      .map_err(|e| error_to_status("Failed to create todo", e))?;
+
+    if let Some(todo) = &response.todo {
+      record_todo_id(&todo.todo_id);
+    }
 
     Ok(Response::new(response))
   }
@@ -92,11 +251,326 @@ impl TodoService for TodoServiceHandler {
     &self,
     request: Request<UpdateTodoRequest>,
   ) -> Result<Response<UpdateTodoResponse>, Status> {
-    let response = update_todo(self.pool.clone(), request.into_inner())
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    if let Some(todo) = &request.todo {
+      record_todo_id(&parse_todo_reference(&todo.todo_id, &todo.name)?.0);
+    }
+    let response = self
+      .retry_policy
+      .run(|| {
+        update_todo(
+          self.pool.clone(),
+          principal.clone(),
+          self.config,
+          request.clone(),
+        )
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to update todo", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn list_todo_history(
+    &self,
+    request: Request<ListTodoHistoryRequest>,
+  ) -> Result<Response<ListTodoHistoryResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        list_todo_history(pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to list todo history", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn list_todo_revisions(
+    &self,
+    request: Request<ListTodoRevisionsRequest>,
+  ) -> Result<Response<ListTodoRevisionsResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        list_todo_revisions(pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to list todo revisions", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn get_todo_revision(
+    &self,
+    request: Request<GetTodoRevisionRequest>,
+  ) -> Result<Response<GetTodoRevisionResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        get_todo_revision(pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to get todo revision", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn restore_todo_revision(
+    &self,
+    request: Request<RestoreTodoRevisionRequest>,
+  ) -> Result<Response<RestoreTodoRevisionResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+
+    // Restoring the same revision again changes nothing, so the request can be
+    // retried.
+    let response = self
+      .retry_policy
+      .run(|| {
+        restore_todo_revision(
+          self.pool.clone(),
+          principal.clone(),
+          self.config,
+          request.clone(),
+        )
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to restore todo revision", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn list_comments(
+    &self,
+    request: Request<ListCommentsRequest>,
+  ) -> Result<Response<ListCommentsResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| list_comments(pool.clone(), principal.clone(), request.clone()))
+      .await
+      .map_err(|e| error_to_status("Failed to list comments", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn get_comment(
+    &self,
+    request: Request<GetCommentRequest>,
+  ) -> Result<Response<GetCommentResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| get_comment(pool.clone(), principal.clone(), request.clone()))
+      .await
+      .map_err(|e| error_to_status("Failed to get comment", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn create_comment(
+    &self,
+    request: Request<CreateCommentRequest>,
+  ) -> Result<Response<CreateCommentResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+
+    // As with todos created without a request ID, a create that failed when
+    // its connection dropped may have committed, so it is not retried.
+    let response = RetryPolicy::never()
+      .run(|| {
+        create_comment(self.pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to create comment", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn update_comment(
+    &self,
+    request: Request<UpdateCommentRequest>,
+  ) -> Result<Response<UpdateCommentResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        update_comment(self.pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to update comment", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn delete_comment(
+    &self,
+    request: Request<DeleteCommentRequest>,
+  ) -> Result<Response<DeleteCommentResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        delete_comment(self.pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to delete comment", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn list_comment_edits(
+    &self,
+    request: Request<ListCommentEditsRequest>,
+  ) -> Result<Response<ListCommentEditsResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        list_comment_edits(pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to list comment edits", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn list_attachments(
+    &self,
+    request: Request<ListAttachmentsRequest>,
+  ) -> Result<Response<ListAttachmentsResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        list_attachments(pool.clone(), principal.clone(), request.clone())
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to list attachments", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn get_attachment(
+    &self,
+    request: Request<GetAttachmentRequest>,
+  ) -> Result<Response<GetAttachmentResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| get_attachment(pool.clone(), principal.clone(), request.clone()))
+      .await
+      .map_err(|e| error_to_status("Failed to get attachment", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn upload_attachment(
+    &self,
+    request: Request<Streaming<UploadAttachmentRequest>>,
+  ) -> Result<Response<UploadAttachmentResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let store = self.get_blob_store()?;
+    let mut requests = request.into_inner();
+
+    // The first message names the todo and describes the file.
+    let first = requests
+      .message()
+      .await?
+      .ok_or(Status::invalid_argument("Attachment not provided"))?;
+    record_todo_id(&parse_todo_reference(&first.todo_id, &first.name)?.0);
+
+    // The rest of the content has not been received yet, and can only be
+    // received once, so the upload is not retried.
+    let response = upload_attachment(
+      self.pool.clone(),
+      principal,
+      store,
+      &self.attachments,
+      first,
+      requests,
+    )
+    .await
+    .map_err(|e| error_to_status("Failed to upload attachment", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn download_attachment(
+    &self,
+    request: Request<DownloadAttachmentRequest>,
+  ) -> Result<Response<Self::DownloadAttachmentStream>, Status> {
+    let principal = get_principal(&request)?;
+    let store = self.get_blob_store()?;
+    let pool = self.get_read_pool(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        download_attachment(
+          pool.clone(),
+          principal.clone(),
+          store.clone(),
+          self.attachments.chunk_size,
+          request.clone(),
+        )
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to download attachment", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn delete_attachment(
+    &self,
+    request: Request<DeleteAttachmentRequest>,
+  ) -> Result<Response<DeleteAttachmentResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        delete_attachment(self.pool.clone(), principal.clone(), request.clone())
+      })
       .await
-      .map_err(|e| {
-      Status::internal(format!("Failed to update todo: \n{}", e.to_string()))
-    })?;
+      .map_err(|e| error_to_status("Failed to delete attachment", e))?;
 
     Ok(Response::new(response))
   }
@@ -105,11 +579,79 @@ impl TodoService for TodoServiceHandler {
     &self,
     request: Request<DeleteTodoRequest>,
   ) -> Result<Response<DeleteTodoResponse>, Status> {
-    let response = delete(self.pool.clone(), request.into_inner())
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| delete(self.pool.clone(), principal.clone(), request.clone()))
+      .await
+      .map_err(|e| error_to_status("Failed to delete todo", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn move_todo(
+    &self,
+    request: Request<MoveTodoRequest>,
+  ) -> Result<Response<MoveTodoResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| move_todo(self.pool.clone(), principal.clone(), request.clone()))
+      .await
+      .map_err(|e| error_to_status("Failed to move todo", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn complete_todo(
+    &self,
+    request: Request<CompleteTodoRequest>,
+  ) -> Result<Response<CompleteTodoResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+
+    // Completing a todo that is already completed changes nothing, so the
+    // request can be retried.
+    let response = self
+      .retry_policy
+      .run(|| {
+        complete_todo(
+          self.pool.clone(),
+          principal.clone(),
+          self.config,
+          request.clone(),
+        )
+      })
+      .await
+      .map_err(|e| error_to_status("Failed to complete todo", e))?;
+
+    Ok(Response::new(response))
+  }
+
+  async fn reopen_todo(
+    &self,
+    request: Request<ReopenTodoRequest>,
+  ) -> Result<Response<ReopenTodoResponse>, Status> {
+    let principal = get_principal(&request)?;
+    let request = request.into_inner();
+    record_todo_id(&parse_todo_reference(&request.todo_id, &request.name)?.0);
+    let response = self
+      .retry_policy
+      .run(|| {
+        reopen_todo(
+          self.pool.clone(),
+          principal.clone(),
+          self.config,
+          request.clone(),
+        )
+      })
       .await
-      .map_err(|e| {
-        Status::internal(format!("Failed to delete todo: \n{}", e.to_string()))
-      })?;
+      .map_err(|e| error_to_status("Failed to reopen todo", e))?;
 
     Ok(Response::new(response))
   }
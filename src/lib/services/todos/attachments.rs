@@ -0,0 +1,726 @@
+//! # Todo Attachments
+//!
+//! This module contains the implementation for the files attached to todos,
+//! which are named
+//! `lists/{list_id}/todos/{todo_id}/attachments/{attachment_id}`. The metadata
+//! of each attachment is kept in the `todo_attachments` table, and its content
+//! is kept in the server's blob store. See [`crate::blobs`].
+//!
+//! Content is uploaded and downloaded in chunks, and its SHA-256 hash is
+//! computed as it is uploaded. The hash is checked again as the content is
+//! downloaded, so that a blob that was changed or damaged in the store is not
+//! returned as if it were the file that was uploaded.
+use crate::auth::Principal;
+use crate::blobs::get_blob_key;
+use crate::blobs::BlobStore;
+use crate::common::error_to_status;
+use crate::common::sql_datetime_to_proto_timestamp;
+use crate::config::AttachmentsConfig;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todos::common::check_todo;
+use crate::services::todos::common::get_todo_name;
+use crate::services::todos::common::parse_todo_reference;
+use bytes::Bytes;
+use futures::future;
+use futures::stream;
+use futures::stream::BoxStream;
+use futures::Stream;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use sha2::Digest;
+use sha2::Sha256;
+use sqlx::query;
+use sqlx::query_as;
+use sqlx::PgPool;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use std::mem;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tonic::Status;
+use tracing::instrument;
+use tracing::warn;
+use uuid::Uuid;
+
+/// The content type of attachments that were uploaded without one.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The longest filename that an attachment can have, in characters.
+const MAX_FILENAME_LENGTH: usize = 255;
+
+/// The stream of messages that an attachment is downloaded in.
+pub type DownloadStream = BoxStream<
+  'static,
+  Result<proto::v1::todos::DownloadAttachmentResponse, Status>,
+>;
+
+/// Represents a row in the `todo_attachments` table.
+///
+/// # Fields
+///
+/// * `attachment_id` - The ID of the attachment, which is also the key of its
+///   content in the blob store.
+/// * `todo_id` - The ID of the todo that the file is attached to.
+/// * `list_id` - The ID of the list that the todo is in, which is read from
+///   the `todos` table by the same query.
+/// * `uploader_id` - The ID of the user that uploaded the file.
+/// * `filename` - The name of the file.
+/// * `content_type` - The media type of the file.
+/// * `size_bytes` - The size of the file in bytes.
+/// * `sha256` - The SHA-256 hash of the content of the file, in lowercase hex.
+/// * `created_at` - The timestamp when the file was attached.
+struct AttachmentRow {
+  attachment_id: Uuid,
+  todo_id: String,
+  list_id: String,
+  uploader_id: String,
+  filename: String,
+  content_type: String,
+  size_bytes: i64,
+  sha256: String,
+  created_at: sqlx::types::time::OffsetDateTime,
+}
+
+impl From<AttachmentRow> for proto::v1::todos::Attachment {
+  fn from(row: AttachmentRow) -> Self {
+    proto::v1::todos::Attachment {
+      name: format!(
+        "{}/attachments/{}",
+        get_todo_name(&row.list_id, &row.todo_id),
+        row.attachment_id
+      ),
+      attachment_id: row.attachment_id.to_string(),
+      todo_id: row.todo_id,
+      uploader_id: row.uploader_id,
+      filename: row.filename,
+      content_type: row.content_type,
+      size_bytes: row.size_bytes,
+      sha256: row.sha256,
+      created_at: Some(sql_datetime_to_proto_timestamp(row.created_at)),
+    }
+  }
+}
+
+/// The progress of an upload, which is updated as its content is streamed to
+/// the blob store.
+#[derive(Default)]
+struct UploadProgress {
+  hasher: Sha256,
+  size: u64,
+  /// The status that the upload failed with, if the caller's stream failed
+  /// or sent too much content. The store only sees the stream fail, so this
+  /// is kept to return to the caller instead of the store's error.
+  error: Option<Status>,
+}
+
+impl UploadProgress {
+  /// Add the next chunk of the content, failing if the caller's stream failed
+  /// or if the content is now larger than its declared size.
+  fn add(
+    &mut self,
+    chunk: Result<Vec<u8>, Status>,
+    size: u64,
+  ) -> anyhow::Result<Bytes> {
+    let chunk = chunk.map_err(|status| self.fail(status))?;
+
+    self.size += chunk.len() as u64;
+    if self.size > size {
+      return Err(self.fail(Status::invalid_argument(format!(
+        "Attachment content is larger than its size of {} bytes",
+        size
+      ))));
+    }
+    self.hasher.update(&chunk);
+
+    Ok(Bytes::from(chunk))
+  }
+
+  fn fail(&mut self, status: Status) -> anyhow::Error {
+    self.error = Some(status.clone());
+    status.into()
+  }
+}
+
+/// List the files attached to a todo owned by the caller, oldest first. If the
+/// caller does not have the todo, then a `NOT_FOUND` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo.
+///
+/// # Returns
+///
+/// A `ListAttachmentsResponse` containing the attachments of the todo.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn list_attachments(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::ListAttachmentsRequest,
+) -> anyhow::Result<proto::v1::todos::ListAttachmentsResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  let rows =
+    get_attachments(&mut transaction, &principal, &todo_id, None).await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::ListAttachmentsResponse {
+    attachments: rows.into_iter().map(|row| row.into()).collect(),
+  })
+}
+
+/// Get the metadata of a file attached to a todo owned by the caller. If the
+/// todo does not have an attachment with the given ID, then a `NOT_FOUND`
+/// status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo and the ID of the attachment.
+///
+/// # Returns
+///
+/// A `GetAttachmentResponse` containing the attachment.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_attachment(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::GetAttachmentRequest,
+) -> anyhow::Result<proto::v1::todos::GetAttachmentResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+  let attachment_id = parse_attachment_id(&request.attachment_id)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  let row = get_single_attachment(
+    &mut transaction,
+    &principal,
+    &todo_id,
+    attachment_id,
+  )
+  .await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::GetAttachmentResponse {
+    attachment: Some(row.into()),
+  })
+}
+
+/// Attach a file to a todo owned by the caller, with the caller as its
+/// uploader. The content is streamed to the blob store as it arrives, and its
+/// size and hash are checked once it has all been written, before the
+/// attachment is recorded. If the upload fails, then its content is removed
+/// from the store.
+///
+/// If the attachment is missing, has no filename, or is larger than the server
+/// allows, or if its content does not match its size or hash, then an
+/// `INVALID_ARGUMENT` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `store` - The blob store to write the content to.
+/// * `config` - The size limits of attachments.
+/// * `first` - The first message of the upload, containing the todo and the
+///   attachment to create.
+/// * `requests` - The rest of the messages of the upload.
+///
+/// # Returns
+///
+/// An `UploadAttachmentResponse` containing the created attachment.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn upload_attachment<S>(
+  pool: PgPool,
+  principal: Principal,
+  store: Arc<dyn BlobStore>,
+  config: &AttachmentsConfig,
+  first: proto::v1::todos::UploadAttachmentRequest,
+  requests: S,
+) -> anyhow::Result<proto::v1::todos::UploadAttachmentResponse>
+where
+  S: Stream<Item = Result<proto::v1::todos::UploadAttachmentRequest, Status>>
+    + Send
+    + 'static,
+{
+  let (todo_id, list_id) = parse_todo_reference(&first.todo_id, &first.name)?;
+  let attachment = first
+    .attachment
+    .ok_or(Status::invalid_argument("Attachment not provided"))?;
+  validate_filename(&attachment.filename)?;
+  let size = validate_size(attachment.size_bytes, config.max_size)?;
+  let content_type = match attachment.content_type.as_str() {
+    "" => DEFAULT_CONTENT_TYPE.to_string(),
+    content_type => validate_content_type(content_type)?,
+  };
+  let expected_sha256 = validate_sha256(&attachment.sha256)?;
+
+  // The todo is checked before the content is streamed, so that a caller
+  // cannot fill the store with files that could never be attached.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  transaction.commit().await?;
+
+  let attachment_id = Uuid::new_v4();
+  let key = get_blob_key(attachment_id);
+  let progress = Arc::new(Mutex::new(UploadProgress::default()));
+  let content = stream::once(future::ready(Ok(first.chunk)))
+    .chain(requests.map_ok(|request| request.chunk))
+    .map({
+      let progress = progress.clone();
+      move |chunk| progress.lock().unwrap().add(chunk, size)
+    })
+    .boxed();
+  let result = store.put(&key, size, content).await;
+  let progress = mem::take(&mut *progress.lock().unwrap());
+
+  if let Some(status) = progress.error {
+    return Err(status.into());
+  }
+  if progress.size != size {
+    // Stores fail when the content is shorter than its size, but the blob is
+    // removed in case one did not.
+    if result.is_ok() {
+      discard_blob(store.as_ref(), &key).await;
+    }
+    return Err(
+      Status::invalid_argument(format!(
+        "Attachment content is {} bytes, but its size is {} bytes",
+        progress.size, size
+      ))
+      .into(),
+    );
+  }
+  result?;
+
+  let sha256 = format!("{:x}", progress.hasher.finalize());
+  if expected_sha256.is_some_and(|expected| expected != sha256) {
+    discard_blob(store.as_ref(), &key).await;
+    return Err(
+      Status::invalid_argument(format!(
+        "Attachment content has SHA-256 hash {}, which does not match",
+        sha256
+      ))
+      .into(),
+    );
+  }
+
+  let attachment = proto::v1::todos::Attachment {
+    filename: attachment.filename,
+    content_type,
+    size_bytes: attachment.size_bytes,
+    sha256,
+    ..Default::default()
+  };
+  let result = insert_attachment(
+    &pool,
+    &principal,
+    &todo_id,
+    list_id.as_deref(),
+    attachment_id,
+    attachment,
+  )
+  .await;
+  if result.is_err() {
+    discard_blob(store.as_ref(), &key).await;
+  }
+
+  Ok(proto::v1::todos::UploadAttachmentResponse {
+    attachment: Some(result?.into()),
+  })
+}
+
+/// Download a file attached to a todo owned by the caller. The first message
+/// contains the attachment's metadata, and the rest contain its content in
+/// chunks of at most `chunk_size` bytes. If the todo does not have an
+/// attachment with the given ID, then a `NOT_FOUND` status is returned. If
+/// the content in the store does not match the attachment's hash, then the
+/// stream ends with a `DATA_LOSS` status.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `store` - The blob store to read the content from.
+/// * `chunk_size` - The largest chunk to send the content in.
+/// * `request` - The request containing the todo and the ID of the attachment.
+///
+/// # Returns
+///
+/// A stream of `DownloadAttachmentResponse` messages.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn download_attachment(
+  pool: PgPool,
+  principal: Principal,
+  store: Arc<dyn BlobStore>,
+  chunk_size: usize,
+  request: proto::v1::todos::DownloadAttachmentRequest,
+) -> anyhow::Result<DownloadStream> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+  let attachment_id = parse_attachment_id(&request.attachment_id)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  let row = get_single_attachment(
+    &mut transaction,
+    &principal,
+    &todo_id,
+    attachment_id,
+  )
+  .await?;
+
+  transaction.commit().await?;
+
+  let content = store.get(&get_blob_key(attachment_id)).await?;
+  let expected_sha256 = row.sha256.clone();
+  let metadata = proto::v1::todos::DownloadAttachmentResponse {
+    attachment: Some(row.into()),
+    chunk: Vec::new(),
+  };
+
+  // The content is split into chunks no larger than the chunk size, whatever
+  // size the store reads it in.
+  let chunks = content
+    .map_ok(move |mut chunk| {
+      let mut chunks = Vec::new();
+      while chunk.len() > chunk_size {
+        chunks.push(chunk.split_to(chunk_size));
+      }
+      if !chunk.is_empty() {
+        chunks.push(chunk);
+      }
+      stream::iter(chunks.into_iter().map(Ok))
+    })
+    .try_flatten()
+    .boxed();
+
+  // The content is hashed as it is sent, and the hash is checked after the
+  // last chunk. By then the caller has received the content, but the status
+  // tells them not to trust it.
+  let responses =
+    stream::unfold(Some((chunks, Sha256::default())), move |state| {
+      let expected_sha256 = expected_sha256.clone();
+      async move {
+        let (mut chunks, mut hasher) = state?;
+        match chunks.next().await {
+          Some(Ok(chunk)) => {
+            hasher.update(&chunk);
+            let response = proto::v1::todos::DownloadAttachmentResponse {
+              attachment: None,
+              chunk: chunk.to_vec(),
+            };
+            Some((Ok(response), Some((chunks, hasher))))
+          }
+          Some(Err(e)) => Some((
+            Err(error_to_status("Failed to read attachment content", e)),
+            None,
+          )),
+          None => {
+            let sha256 = format!("{:x}", hasher.finalize());
+            if sha256 == expected_sha256 {
+              return None;
+            }
+            warn!(
+              attachment_id = %attachment_id,
+              sha256 = sha256,
+              expected_sha256 = expected_sha256,
+              "Attachment content does not match its hash"
+            );
+            Some((
+              Err(Status::data_loss(format!(
+                "Attachment {} is damaged, its content does not match its \
+                 SHA-256 hash",
+                attachment_id
+              ))),
+              None,
+            ))
+          }
+        }
+      }
+    });
+
+  Ok(
+    stream::once(future::ready(Ok(metadata)))
+      .chain(responses)
+      .boxed(),
+  )
+}
+
+/// Delete a file attached to a todo owned by the caller. The database queues
+/// its content to be removed from the blob store by the sweeper. If the todo
+/// does not have an attachment with the given ID, then a `NOT_FOUND` status is
+/// returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo and the ID of the attachment.
+///
+/// # Returns
+///
+/// An empty `DeleteAttachmentResponse`.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn delete_attachment(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::DeleteAttachmentRequest,
+) -> anyhow::Result<proto::v1::todos::DeleteAttachmentResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+  let attachment_id = parse_attachment_id(&request.attachment_id)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  let result = query!(
+    r#"
+    delete from todo_attachments
+    where todo_id = $1
+      and owner_id = $2
+      and attachment_id = $3
+    "#,
+    todo_id,
+    principal.subject,
+    attachment_id
+  )
+  .execute(&mut *transaction)
+  .await?;
+  if result.rows_affected() == 0 {
+    return Err(attachment_not_found(&todo_id, attachment_id).into());
+  }
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::DeleteAttachmentResponse {})
+}
+
+/// Record an attachment whose content has been written to the blob store. The
+/// todo is checked again, as it may have been deleted or moved to another
+/// list while the content was uploaded.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn insert_attachment(
+  pool: &PgPool,
+  principal: &Principal,
+  todo_id: &str,
+  list_id: Option<&str>,
+  attachment_id: Uuid,
+  attachment: proto::v1::todos::Attachment,
+) -> anyhow::Result<AttachmentRow> {
+  let mut transaction =
+    begin_tenant_transaction(pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, principal, todo_id, list_id).await?;
+  query!(
+    r#"
+    insert into todo_attachments (
+      attachment_id,
+      todo_id,
+      owner_id,
+      uploader_id,
+      filename,
+      content_type,
+      size_bytes,
+      sha256
+    )
+    values ($1, $2, $3, $3, $4, $5, $6, $7)
+    "#,
+    attachment_id,
+    todo_id,
+    principal.subject,
+    attachment.filename,
+    attachment.content_type,
+    attachment.size_bytes,
+    attachment.sha256
+  )
+  .execute(&mut *transaction)
+  .await?;
+  let row =
+    get_single_attachment(&mut transaction, principal, todo_id, attachment_id)
+      .await?;
+
+  transaction.commit().await?;
+
+  Ok(row)
+}
+
+/// Get an attachment of a todo, or a `NOT_FOUND` status if there is no such
+/// attachment.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn get_single_attachment(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  attachment_id: Uuid,
+) -> anyhow::Result<AttachmentRow> {
+  let row =
+    get_attachments(transaction, principal, todo_id, Some(attachment_id))
+      .await?
+      .pop()
+      .ok_or(attachment_not_found(todo_id, attachment_id))?;
+
+  Ok(row)
+}
+
+/// Get the attachments of a todo, oldest first, or only the one with the given
+/// ID.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn get_attachments(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  attachment_id: Option<Uuid>,
+) -> anyhow::Result<Vec<AttachmentRow>> {
+  let rows = query_as!(
+    AttachmentRow,
+    r#"
+    select todo_attachments.attachment_id,
+           todo_attachments.todo_id,
+           todos.list_id,
+           todo_attachments.uploader_id,
+           todo_attachments.filename,
+           todo_attachments.content_type,
+           todo_attachments.size_bytes,
+           todo_attachments.sha256,
+           todo_attachments.created_at
+    from todo_attachments
+    join todos on todos.owner_id = todo_attachments.owner_id
+              and todos.todo_id = todo_attachments.todo_id
+    where todo_attachments.todo_id = $1
+      and todo_attachments.owner_id = $2
+      and ($3::uuid is null or todo_attachments.attachment_id = $3)
+    order by todo_attachments.created_at, todo_attachments.attachment_id
+    "#,
+    todo_id,
+    principal.subject,
+    attachment_id
+  )
+  .fetch_all(&mut **transaction)
+  .await?;
+
+  Ok(rows)
+}
+
+/// Remove the content of an upload that failed from the blob store. If that
+/// fails too, then the blob is left in the store, and the failure is logged.
+async fn discard_blob(store: &dyn BlobStore, key: &str) {
+  if let Err(e) = store.delete(key).await {
+    warn!(key = key, error = %e, "Failed to discard blob of failed upload");
+  }
+}
+
+/// Parse the ID of an attachment, which is a UUID. If it is not, then an
+/// `INVALID_ARGUMENT` status is returned.
+fn parse_attachment_id(attachment_id: &str) -> Result<Uuid, Status> {
+  Uuid::parse_str(attachment_id).map_err(|_| {
+    Status::invalid_argument(format!(
+      "Attachment ID must be a UUID, got {}",
+      attachment_id
+    ))
+  })
+}
+
+/// Check that the filename of an attachment is not empty or too long. If it
+/// is, then an `INVALID_ARGUMENT` status is returned.
+fn validate_filename(filename: &str) -> Result<(), Status> {
+  if filename.trim().is_empty() {
+    return Err(Status::invalid_argument(
+      "Attachment filename cannot be empty",
+    ));
+  }
+  if filename.chars().count() > MAX_FILENAME_LENGTH {
+    return Err(Status::invalid_argument(format!(
+      "Attachment filename can be at most {} characters",
+      MAX_FILENAME_LENGTH
+    )));
+  }
+
+  Ok(())
+}
+
+/// Check the declared size of an attachment against the largest size that the
+/// server allows. If it is negative or too large, then an `INVALID_ARGUMENT`
+/// status is returned.
+fn validate_size(size_bytes: i64, max_size: u64) -> Result<u64, Status> {
+  match u64::try_from(size_bytes) {
+    Ok(size) if size <= max_size => Ok(size),
+    Ok(_) => Err(Status::invalid_argument(format!(
+      "Attachments can be at most {} bytes",
+      max_size
+    ))),
+    Err(_) => Err(Status::invalid_argument(
+      "Attachment size cannot be negative",
+    )),
+  }
+}
+
+/// Check that the content type of an attachment looks like a media type, such
+/// as `image/png`. If it does not, then an `INVALID_ARGUMENT` status is
+/// returned.
+fn validate_content_type(content_type: &str) -> Result<String, Status> {
+  match content_type.split_once('/') {
+    Some((kind, subtype))
+      if !kind.is_empty()
+        && !subtype.is_empty()
+        && content_type
+          .chars()
+          .all(|c| c.is_ascii_graphic() || c == ' ') =>
+    {
+      Ok(content_type.to_string())
+    }
+    _ => Err(Status::invalid_argument(format!(
+      "Attachment content type must be a media type such as image/png, got {}",
+      content_type
+    ))),
+  }
+}
+
+/// Parse the SHA-256 hash that the caller expects the content of an upload to
+/// have, if they gave one. If it is not 64 hex digits, then an
+/// `INVALID_ARGUMENT` status is returned.
+fn validate_sha256(sha256: &str) -> Result<Option<String>, Status> {
+  if sha256.is_empty() {
+    return Ok(None);
+  }
+  if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Err(Status::invalid_argument(format!(
+      "Attachment SHA-256 hash must be 64 hex digits, got {}",
+      sha256
+    )));
+  }
+
+  Ok(Some(sha256.to_ascii_lowercase()))
+}
+
+/// The `NOT_FOUND` status for an attachment that a todo does not have.
+fn attachment_not_found(todo_id: &str, attachment_id: Uuid) -> Status {
+  Status::not_found(format!(
+    "Attachment {} of todo with id {} not found",
+    attachment_id, todo_id
+  ))
+}
@@ -0,0 +1,478 @@
+//! # Todo Comments
+//!
+//! This module contains the implementation for the comments on todos, which
+//! are named `lists/{list_id}/todos/{todo_id}/comments/{comment_id}`. Comments
+//! are kept in the `todo_comments` table, and are deleted with their todo.
+//! When the body of a comment is changed, the database records the body that
+//! was replaced in the `todo_comment_edits` table.
+use crate::auth::Principal;
+use crate::common::sql_datetime_to_proto_timestamp;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todos::common::check_todo;
+use crate::services::todos::common::get_next_page_token;
+use crate::services::todos::common::get_todo_name;
+use crate::services::todos::common::parse_page;
+use crate::services::todos::common::parse_todo_reference;
+use sqlx::query;
+use sqlx::query_as;
+use sqlx::PgPool;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::Status;
+use tracing::instrument;
+
+/// Represents a row in the `todo_comments` table.
+///
+/// # Fields
+///
+/// * `comment_id` - The ID of the comment.
+/// * `todo_id` - The ID of the todo that the comment is on.
+/// * `list_id` - The ID of the list that the todo is in, which is read from
+///   the `todos` table by the same query.
+/// * `author_id` - The ID of the user that wrote the comment.
+/// * `body` - The text of the comment.
+/// * `created_at` - The timestamp when the comment was created.
+/// * `updated_at` - The timestamp when the comment was last updated.
+/// * `edit_count` - The number of times the body has been changed, which is
+///   counted from the `todo_comment_edits` table by the same query.
+struct CommentRow {
+  comment_id: i64,
+  todo_id: String,
+  list_id: String,
+  author_id: String,
+  body: String,
+  created_at: sqlx::types::time::OffsetDateTime,
+  updated_at: sqlx::types::time::OffsetDateTime,
+  edit_count: i32,
+}
+
+impl From<CommentRow> for proto::v1::todos::Comment {
+  fn from(row: CommentRow) -> Self {
+    proto::v1::todos::Comment {
+      name: format!(
+        "{}/comments/{}",
+        get_todo_name(&row.list_id, &row.todo_id),
+        row.comment_id
+      ),
+      comment_id: row.comment_id,
+      todo_id: row.todo_id,
+      author_id: row.author_id,
+      body: row.body,
+      created_at: Some(sql_datetime_to_proto_timestamp(row.created_at)),
+      updated_at: Some(sql_datetime_to_proto_timestamp(row.updated_at)),
+      edit_count: row.edit_count,
+    }
+  }
+}
+
+/// Represents a row in the `todo_comment_edits` table.
+///
+/// # Fields
+///
+/// * `edit_id` - The ID of the edit.
+/// * `body` - The body of the comment before the edit.
+/// * `created_at` - The time that the body was written.
+/// * `edit_time` - The time that the body was replaced.
+struct CommentEditRow {
+  edit_id: i64,
+  body: String,
+  created_at: sqlx::types::time::OffsetDateTime,
+  edit_time: sqlx::types::time::OffsetDateTime,
+}
+
+impl From<CommentEditRow> for proto::v1::todos::CommentEdit {
+  fn from(row: CommentEditRow) -> Self {
+    proto::v1::todos::CommentEdit {
+      edit_id: row.edit_id,
+      body: row.body,
+      created_at: Some(sql_datetime_to_proto_timestamp(row.created_at)),
+      edit_time: Some(sql_datetime_to_proto_timestamp(row.edit_time)),
+    }
+  }
+}
+
+/// List the comments on a todo owned by the caller, oldest first. If the
+/// caller does not have the todo, then a `NOT_FOUND` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo, and the page to list.
+///
+/// # Returns
+///
+/// A `ListCommentsResponse` containing a page of comments, and the token of
+/// the next page if there is one.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn list_comments(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::ListCommentsRequest,
+) -> anyhow::Result<proto::v1::todos::ListCommentsResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+  let (page_size, after_comment_id) =
+    parse_page(request.page_size, &request.page_token)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+
+  // One more comment than the page size is fetched, to find out whether there
+  // is another page.
+  let mut rows = get_comments(&mut transaction, &principal, &todo_id,
+    None,
+    after_comment_id,
+    page_size + 1,
+  )
+  .await?;
+
+  transaction.commit().await?;
+
+  let next_page_token =
+    get_next_page_token(&mut rows, page_size, |row| row.comment_id);
+
+  Ok(proto::v1::todos::ListCommentsResponse {
+    comments: rows.into_iter().map(|row| row.into()).collect(),
+    next_page_token,
+  })
+}
+
+/// Get a comment on a todo owned by the caller. If the todo does not have a
+/// comment with the given ID, then a `NOT_FOUND` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo and the ID of the comment.
+///
+/// # Returns
+///
+/// A `GetCommentResponse` containing the comment.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_comment(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::GetCommentRequest,
+) -> anyhow::Result<proto::v1::todos::GetCommentResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  let row =
+    get_single_comment(&mut transaction, &principal, &todo_id, request.comment_id).await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::GetCommentResponse {
+    comment: Some(row.into()),
+  })
+}
+
+/// Add a comment to a todo owned by the caller, with the caller as its author.
+/// If the body of the comment is empty, then an `INVALID_ARGUMENT` status is
+/// returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo and the comment to create.
+///
+/// # Returns
+///
+/// A `CreateCommentResponse` containing the created comment.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn create_comment(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::CreateCommentRequest,
+) -> anyhow::Result<proto::v1::todos::CreateCommentResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+  let comment = request
+    .comment
+    .ok_or(Status::invalid_argument("Comment not provided"))?;
+  validate_body(&comment.body)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  let comment_id = query!(
+    r#"
+    insert into todo_comments (todo_id, owner_id, author_id, body)
+    values ($1, $2, $2, $3)
+    returning comment_id
+    "#,
+    todo_id,
+    principal.subject,
+    comment.body
+  )
+  .fetch_one(&mut *transaction)
+  .await?
+  .comment_id;
+  let row = get_single_comment(&mut transaction, &principal, &todo_id, comment_id).await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::CreateCommentResponse {
+    comment: Some(row.into()),
+  })
+}
+
+/// Change the body of a comment on a todo owned by the caller. The database
+/// records the body that was replaced as an edit of the comment, unless the
+/// body is unchanged. If the todo does not have a comment with the given ID,
+/// then a `NOT_FOUND` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the comment and its new body.
+///
+/// # Returns
+///
+/// An `UpdateCommentResponse` containing the updated comment.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn update_comment(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::UpdateCommentRequest,
+) -> anyhow::Result<proto::v1::todos::UpdateCommentResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+  let comment = request
+    .comment
+    .ok_or(Status::invalid_argument("Comment not provided"))?;
+  validate_body(&comment.body)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  // A body that is unchanged is not written, so that it is not recorded as
+  // an edit and does not change the time the comment was updated.
+  query!(
+    r#"
+    update todo_comments
+    set body = $3
+    where todo_id = $1
+      and owner_id = $4
+      and comment_id = $2
+      and body <> $3
+    "#,
+    todo_id,
+    request.comment_id,
+    comment.body,
+    principal.subject
+  )
+  .execute(&mut *transaction)
+  .await?;
+  let row =
+    get_single_comment(&mut transaction, &principal, &todo_id, request.comment_id).await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::UpdateCommentResponse {
+    comment: Some(row.into()),
+  })
+}
+
+/// Delete a comment on a todo owned by the caller, along with its edits. If
+/// the todo does not have a comment with the given ID, then a `NOT_FOUND`
+/// status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo and the ID of the comment.
+///
+/// # Returns
+///
+/// An empty `DeleteCommentResponse`.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn delete_comment(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::DeleteCommentRequest,
+) -> anyhow::Result<proto::v1::todos::DeleteCommentResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  let result = query!(
+    r#"
+    delete from todo_comments
+    where todo_id = $1
+      and owner_id = $3
+      and comment_id = $2
+    "#,
+    todo_id,
+    request.comment_id,
+    principal.subject
+  )
+  .execute(&mut *transaction)
+  .await?;
+  if result.rows_affected() == 0 {
+    return Err(comment_not_found(&todo_id, request.comment_id).into());
+  }
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::DeleteCommentResponse {})
+}
+
+/// List the earlier versions of the body of a comment on a todo owned by the
+/// caller, most recent first. If the todo does not have a comment with the
+/// given ID, then a `NOT_FOUND` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo and the ID of the comment.
+///
+/// # Returns
+///
+/// A `ListCommentEditsResponse` containing the edits of the comment.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn list_comment_edits(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::ListCommentEditsRequest,
+) -> anyhow::Result<proto::v1::todos::ListCommentEditsResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  check_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+    .await?;
+  get_single_comment(&mut transaction, &principal, &todo_id, request.comment_id).await?;
+  let rows = query_as!(
+    CommentEditRow,
+    r#"
+    select edit_id,
+           body,
+           created_at,
+           edit_time
+    from todo_comment_edits
+    where comment_id = $1
+    order by edit_id desc
+    "#,
+    request.comment_id
+  )
+  .fetch_all(&mut *transaction)
+  .await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::ListCommentEditsResponse {
+    edits: rows.into_iter().map(|row| row.into()).collect(),
+  })
+}
+
+/// Get a comment on a todo, or a `NOT_FOUND` status if there is no such
+/// comment.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn get_single_comment(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  comment_id: i64,
+) -> anyhow::Result<CommentRow> {
+  let row =
+    get_comments(transaction, principal, todo_id, Some(comment_id), None, 1)
+      .await?
+    .pop()
+    .ok_or(comment_not_found(todo_id, comment_id))?;
+
+  Ok(row)
+}
+
+/// Get the comments on a todo, oldest first. The comments can be limited to
+/// the one with the given ID, or to those after the given ID.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn get_comments(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  comment_id: Option<i64>,
+  after_comment_id: Option<i64>,
+  limit: i64,
+) -> anyhow::Result<Vec<CommentRow>> {
+  let rows = query_as!(
+    CommentRow,
+    r#"
+    select todo_comments.comment_id,
+           todo_comments.todo_id,
+           todos.list_id,
+           todo_comments.author_id,
+           todo_comments.body,
+           todo_comments.created_at,
+           todo_comments.updated_at,
+           (
+             select count(*)::integer
+             from todo_comment_edits
+             where todo_comment_edits.comment_id = todo_comments.comment_id
+           ) as "edit_count!"
+    from todo_comments
+    join todos on todos.owner_id = todo_comments.owner_id
+      and todos.todo_id = todo_comments.todo_id
+    where todo_comments.todo_id = $1
+      and todo_comments.owner_id = $5
+      and ($2::bigint is null or todo_comments.comment_id = $2)
+      and ($3::bigint is null or todo_comments.comment_id > $3)
+    order by todo_comments.comment_id
+    limit $4
+    "#,
+    todo_id,
+    comment_id,
+    after_comment_id,
+    limit,
+    principal.subject
+  )
+  .fetch_all(&mut **transaction)
+  .await?;
+
+  Ok(rows)
+}
+
+/// Check that the body of a comment is not empty. If it is, then an
+/// `INVALID_ARGUMENT` status is returned.
+fn validate_body(body: &str) -> Result<(), Status> {
+  if body.trim().is_empty() {
+    return Err(Status::invalid_argument("Comment body cannot be empty"));
+  }
+
+  Ok(())
+}
+
+/// The `NOT_FOUND` status for a comment that a todo does not have.
+fn comment_not_found(todo_id: &str, comment_id: i64) -> Status {
+  Status::not_found(format!(
+    "Comment {} on todo with id {} not found",
+    comment_id, todo_id
+  ))
+}
@@ -1,9 +1,26 @@
 //! Common types and functions for the todos service.
 
+use crate::auth::Principal;
 use crate::common::sql_datetime_to_proto_timestamp;
 use crate::proto;
+use crate::proto::v1::todos::Priority;
+use crate::services::todo_lists::get_list_name;
+use sqlx::query;
+use sqlx::query_as;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::Status;
+use tracing::instrument;
 
-/// Represents a row in the `todos` table.
+/// The number of items in a page if the request does not set a page size.
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
+/// The largest number of items in a page. Larger page sizes are reduced to
+/// this, see https://google.aip.dev/158.
+const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Represents a row in the `todos_view` view, which every query that returns
+/// todos selects from.
 ///
 /// # Fields
 ///
@@ -13,6 +30,26 @@ use crate::proto;
 /// * `completed` - Whether the todo is completed.
 /// * `created_at` - The timestamp when the todo was created.
 /// * `updated_at` - The timestamp when the todo was last updated.
+/// * `owner_id` - The ID of the user that owns the todo.
+/// * `due_time` - The time the todo should be done by, if it has one.
+/// * `reminder_time` - The time the owner wants to be reminded, if they do.
+/// * `recurrence_rule` - The RFC 5545 rule that the todo repeats by, or empty
+///   if it does not repeat.
+/// * `series_id` - The ID of the series that the todo is an occurrence of, if
+///   it repeats.
+/// * `priority` - How urgent the todo is, as a `proto::v1::todos::Priority`.
+/// * `position` - The key that orders the todo within its owner's todos.
+/// * `list_id` - The ID of the list that the todo is in.
+/// * `parent_todo_id` - The ID of the todo that this is a subtask of, if it is
+///   one.
+/// * `complete_time` - The time the todo was completed, if it is completed.
+/// * `labels` - The names of the todo's labels in alphabetical order, which
+///   the view aggregates from the `todo_labels` table.
+/// * `comment_count` - The number of comments on the todo, which the view
+///   counts from the `todo_comments` table.
+/// * `tenant_id` - The ID of the tenant that the todo belongs to. This is not
+///   exposed through the API, as callers can only ever see their own tenant.
+#[derive(sqlx::FromRow)]
 pub struct TodoRow {
   pub todo_id: String,
   pub title: String,
@@ -20,13 +57,26 @@ pub struct TodoRow {
   pub completed: bool,
   pub created_at: sqlx::types::time::OffsetDateTime,
   pub updated_at: sqlx::types::time::OffsetDateTime,
+  pub owner_id: String,
+  pub tenant_id: String,
+  pub due_time: Option<sqlx::types::time::OffsetDateTime>,
+  pub reminder_time: Option<sqlx::types::time::OffsetDateTime>,
+  pub recurrence_rule: String,
+  pub series_id: Option<String>,
+  pub priority: i16,
+  pub position: String,
+  pub list_id: String,
+  pub parent_todo_id: Option<String>,
+  pub complete_time: Option<sqlx::types::time::OffsetDateTime>,
+  pub labels: Vec<String>,
+  pub comment_count: i32,
 }
 
 /// Converts a `TodoRow` to a `proto::v1::todos::Todo`.
 ///
 /// # Arguments
 ///
-/// * `self` - The `TodoRow` to convert.
+/// * `row` - The `TodoRow` to convert.
 ///
 /// # Returns
 ///
@@ -34,15 +84,223 @@ pub struct TodoRow {
 ///
 /// # Examples
 ///
-impl Into<proto::v1::todos::Todo> for TodoRow {
-  fn into(self) -> proto::v1::todos::Todo {
+impl From<TodoRow> for proto::v1::todos::Todo {
+  fn from(row: TodoRow) -> Self {
+    let todo_id = row.todo_id;
     proto::v1::todos::Todo {
-      todo_id: self.todo_id,
-      title: self.title,
-      description: self.description,
-      completed: self.completed,
-      created_at: Some(sql_datetime_to_proto_timestamp(self.created_at)),
-      updated_at: Some(sql_datetime_to_proto_timestamp(self.updated_at)),
+      title: row.title,
+      description: row.description,
+      completed: row.completed,
+      created_at: Some(sql_datetime_to_proto_timestamp(row.created_at)),
+      updated_at: Some(sql_datetime_to_proto_timestamp(row.updated_at)),
+      owner_id: row.owner_id,
+      due_time: row.due_time.map(sql_datetime_to_proto_timestamp),
+      reminder_time: row.reminder_time.map(sql_datetime_to_proto_timestamp),
+      recurrence_rule: row.recurrence_rule,
+      series_id: row.series_id.unwrap_or_default(),
+      priority: row.priority.into(),
+      position: row.position,
+      labels: row.labels,
+      comment_count: row.comment_count,
+      parent_todo_id: row.parent_todo_id.unwrap_or_default(),
+      complete_time: row.complete_time.map(sql_datetime_to_proto_timestamp),
+      name: get_todo_name(&row.list_id, &todo_id),
+      todo_id,
     }
   }
 }
+
+/// Get the resource name of a todo, `lists/{list_id}/todos/{todo_id}`.
+pub fn get_todo_name(list_id: &str, todo_id: &str) -> String {
+  format!("{}/todos/{}", get_list_name(list_id), todo_id)
+}
+
+/// Get the todo that a request refers to, either by its ID or by its resource
+/// name, `lists/{list_id}/todos/{todo_id}`. Returns the ID of the todo, and the
+/// ID of the list that it must be in if it was referred to by name. If the name
+/// is not in that format, or the request has both and they differ, then an
+/// `INVALID_ARGUMENT` status is returned.
+pub fn parse_todo_reference(
+  todo_id: &str,
+  name: &str,
+) -> Result<(String, Option<String>), Status> {
+  if name.is_empty() {
+    return Ok((todo_id.to_string(), None));
+  }
+
+  let invalid = || {
+    Status::invalid_argument(format!(
+      "Invalid todo name {:?}, expected lists/{{list_id}}/todos/{{todo_id}}",
+      name
+    ))
+  };
+  let ["lists", list_id, "todos", id] = name.split('/').collect::<Vec<_>>()[..]
+  else {
+    return Err(invalid());
+  };
+  if list_id.is_empty() || id.is_empty() {
+    return Err(invalid());
+  }
+  if !todo_id.is_empty() && todo_id != id {
+    return Err(Status::invalid_argument(format!(
+      "todo_id {} does not match the name {}",
+      todo_id, name
+    )));
+  }
+
+  Ok((id.to_string(), Some(list_id.to_string())))
+}
+
+/// Convert the priority of a todo in a request to the value stored in the
+/// database. If the priority is not a value of the enum, then an
+/// `INVALID_ARGUMENT` status is returned.
+pub fn parse_priority(priority: i32) -> Result<i16, Status> {
+  Priority::try_from(priority)
+    .map(|priority| priority as i16)
+    .map_err(|_| Status::invalid_argument("priority is not a valid Priority"))
+}
+
+/// Lock the caller's todos until the transaction ends, so that concurrent
+/// requests that count the todos or generate their positions see each other's
+/// changes. The database takes the same lock when it generates the position of
+/// a new todo.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn lock_owner_todos(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+) -> anyhow::Result<()> {
+  query!(
+    "select pg_advisory_xact_lock(hashtextextended($1, 0))",
+    format!("todos:{}/{}", principal.tenant_id, principal.subject)
+  )
+  .execute(&mut **transaction)
+  .await?;
+
+  Ok(())
+}
+
+/// Check that the caller owns a todo with the given ID, in the given list if
+/// there is one. If they do not, then a `NOT_FOUND` status is returned.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn check_todo(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  list_id: Option<&str>,
+) -> anyhow::Result<()> {
+  query!(
+    r#"
+    select todo_id
+    from todos
+    where todo_id = $1
+      and owner_id = $2
+      and ($3::text is null or list_id = $3)
+    "#,
+    todo_id,
+    principal.subject,
+    list_id
+  )
+  .fetch_optional(&mut **transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Todo with id {} not found",
+    todo_id
+  )))?;
+
+  Ok(())
+}
+
+/// Get the caller's todo with the given ID from the `todos_view`, such as
+/// after it has been changed. The caller must already have checked that they
+/// have the todo.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_todo_row(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+) -> anyhow::Result<TodoRow> {
+  let row = query_as(
+    r#"
+    select *
+    from todos_view
+    where todo_id = $1
+      and owner_id = $2
+    "#,
+  )
+  .bind(todo_id)
+  .bind(&principal.subject)
+  .fetch_one(&mut **transaction)
+  .await?;
+
+  Ok(row)
+}
+
+/// Find the todo with the given ID in the `todos_view`, if the caller owns it
+/// and it is in the given list if there is one.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn find_todo_row(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  list_id: Option<&str>,
+) -> anyhow::Result<Option<TodoRow>> {
+  let row = query_as(
+    r#"
+    select *
+    from todos_view
+    where todo_id = $1
+      and owner_id = $2
+      and ($3::text is null or list_id = $3)
+    "#,
+  )
+  .bind(todo_id)
+  .bind(&principal.subject)
+  .bind(list_id)
+  .fetch_optional(&mut **transaction)
+  .await?;
+
+  Ok(row)
+}
+
+/// Parse the page size and token of a request that lists a page of items. The
+/// page token is a number that says where the page starts, such as the ID of
+/// the last event of the previous page.
+pub fn parse_page(
+  page_size: i32,
+  page_token: &str,
+) -> Result<(i64, Option<i64>), Status> {
+  let page_size = match i64::from(page_size) {
+    page_size if page_size < 0 => {
+      return Err(Status::invalid_argument("page_size cannot be negative"))
+    }
+    0 => DEFAULT_PAGE_SIZE,
+    page_size => page_size.min(MAX_PAGE_SIZE),
+  };
+  let page_start = match page_token {
+    "" => None,
+    token => Some(
+      token
+        .parse::<i64>()
+        .map_err(|_| Status::invalid_argument("Invalid page_token"))?,
+    ),
+  };
+
+  Ok((page_size, page_start))
+}
+
+/// Get the token of the page after the given rows, which were fetched with
+/// one more row than the page size to find out whether there is another page.
+/// The extra row is removed, and the token is made from the last row that is
+/// kept.
+pub fn get_next_page_token<T>(
+  rows: &mut Vec<T>,
+  page_size: i64,
+  page_start: impl Fn(&T) -> i64,
+) -> String {
+  if rows.len() as i64 <= page_size {
+    return String::new();
+  }
+
+  rows.truncate(page_size as usize);
+  rows.last().map(page_start).unwrap_or_default().to_string()
+}
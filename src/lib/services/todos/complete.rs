@@ -0,0 +1,219 @@
+//! # Complete and Reopen Todos
+//!
+//! This module contains the implementation of the `CompleteTodo` and
+//! `ReopenTodo` custom methods, see https://google.aip.dev/136. The database
+//! sets the todo's `complete_time` whenever it is completed or reopened.
+use crate::auth::Principal;
+use crate::config::TodosConfig;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todos::common::get_todo_row;
+use crate::services::todos::common::parse_todo_reference;
+use crate::services::todos::common::TodoRow;
+use crate::services::todos::history::set_actor;
+use crate::services::todos::series::create_next_occurrence;
+use crate::services::todos::subtasks::check_subtasks_completed;
+use crate::services::todos::subtasks::complete_subtasks;
+use crate::services::todos::subtasks::reopen_parent_todos;
+use crate::services::todos::subtasks::SubtaskCompletion;
+use sqlx::query;
+use sqlx::PgPool;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::Status;
+use tracing::info;
+use tracing::instrument;
+
+/// Complete a todo. If the todo is already completed, then it is returned
+/// unchanged, so that the request can safely be retried. Otherwise, completing
+/// the todo creates the next occurrence of a recurring todo, and may require or
+/// complete its subtasks, depending on the configuration.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `config` - How subtasks are completed.
+/// * `request` - The request containing the ID or name of the todo.
+///
+/// # Returns
+///
+/// A `CompleteTodoResponse` containing the completed todo, and the next
+/// occurrence if one was created.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn complete_todo(
+  pool: PgPool,
+  principal: Principal,
+  config: TodosConfig,
+  request: proto::v1::todos::CompleteTodoRequest,
+) -> anyhow::Result<proto::v1::todos::CompleteTodoResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+  set_actor(&mut transaction, &principal).await?;
+
+  let todo =
+    lock_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+      .await?;
+  if todo.completed {
+    transaction.commit().await?;
+    return Ok(proto::v1::todos::CompleteTodoResponse {
+      todo: Some(todo.into()),
+      next_occurrence: None,
+    });
+  }
+
+  if config.subtask_completion == SubtaskCompletion::RequireSubtasks {
+    check_subtasks_completed(&mut transaction, &principal, &todo_id).await?;
+  }
+  let todo = set_completed(&mut transaction, &principal, &todo_id, true).await?;
+  if config.subtask_completion == SubtaskCompletion::CompleteSubtasks {
+    complete_subtasks(&mut transaction, &principal, &todo_id).await?;
+  }
+  let next_occurrence =
+    create_next_occurrence(&mut transaction, &principal, &todo_id).await?;
+
+  transaction.commit().await?;
+
+  info!(
+    target: "audit",
+    tenant_id = principal.tenant_id,
+    actor = principal.subject,
+    todo_id,
+    action = "complete",
+    "Completed todo"
+  );
+
+  Ok(proto::v1::todos::CompleteTodoResponse {
+    todo: Some(todo.into()),
+    next_occurrence: next_occurrence.map(|row| row.into()),
+  })
+}
+
+/// Reopen a completed todo. If the todo is not completed, then it is returned
+/// unchanged, so that the request can safely be retried. Reopening a subtask
+/// also reopens the todos above it if they require their subtasks to be
+/// completed.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `config` - How subtasks are completed.
+/// * `request` - The request containing the ID or name of the todo.
+///
+/// # Returns
+///
+/// A `ReopenTodoResponse` containing the reopened todo.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn reopen_todo(
+  pool: PgPool,
+  principal: Principal,
+  config: TodosConfig,
+  request: proto::v1::todos::ReopenTodoRequest,
+) -> anyhow::Result<proto::v1::todos::ReopenTodoResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+  set_actor(&mut transaction, &principal).await?;
+
+  let todo =
+    lock_todo(&mut transaction, &principal, &todo_id, list_id.as_deref())
+      .await?;
+  if !todo.completed {
+    transaction.commit().await?;
+    return Ok(proto::v1::todos::ReopenTodoResponse {
+      todo: Some(todo.into()),
+    });
+  }
+
+  let todo = set_completed(&mut transaction, &principal, &todo_id, false).await?;
+  if config.subtask_completion == SubtaskCompletion::RequireSubtasks
+    && todo.parent_todo_id.is_some()
+  {
+    reopen_parent_todos(&mut transaction, &principal, &todo_id).await?;
+  }
+
+  transaction.commit().await?;
+
+  info!(
+    target: "audit",
+    tenant_id = principal.tenant_id,
+    actor = principal.subject,
+    todo_id,
+    action = "reopen",
+    "Reopened todo"
+  );
+
+  Ok(proto::v1::todos::ReopenTodoResponse {
+    todo: Some(todo.into()),
+  })
+}
+
+/// Get a todo of the caller and lock it until the transaction ends, so that
+/// only one request can complete or reopen it at once. If the todo is not
+/// found, or is not in the list that its name says, then a `NOT_FOUND` status
+/// is returned.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn lock_todo(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  list_id: Option<&str>,
+) -> anyhow::Result<TodoRow> {
+  query!(
+    r#"
+    select todo_id
+    from todos
+    where todo_id = $1
+      and owner_id = $2
+      and ($3::text is null or list_id = $3)
+    for update
+    "#,
+    todo_id,
+    principal.subject,
+    list_id
+  )
+  .fetch_optional(&mut **transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Todo with id {} not found",
+    todo_id
+  )))?;
+
+  get_todo_row(transaction, principal, todo_id).await
+}
+
+/// Set whether the todo with the given ID is completed, which the caller must
+/// already have locked, and return the updated todo.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn set_completed(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  completed: bool,
+) -> anyhow::Result<TodoRow> {
+  query!(
+    r#"
+    update todos
+    set completed = $2
+    where todo_id = $1
+      and owner_id = $3
+    "#,
+    todo_id,
+    completed,
+    principal.subject
+  )
+  .execute(&mut **transaction)
+  .await?;
+
+  get_todo_row(transaction, principal, todo_id).await
+}
@@ -1,44 +1,208 @@
 //! # Create Todo
 //!
 //! This module contains the implementation for creating a new todo.
+use crate::auth::Principal;
+use crate::common::proto_timestamp_to_sql_datetime;
+use crate::config::TodosConfig;
+use crate::database::begin_tenant_transaction;
 use crate::proto;
+use crate::services::todo_lists::get_list_name;
+use crate::services::todo_lists::parse_list_name;
+use crate::services::todo_lists::DEFAULT_LIST_ID;
+use crate::services::todos::common::get_todo_row;
+use crate::services::todos::common::lock_owner_todos;
+use crate::services::todos::common::parse_priority;
 use crate::services::todos::common::TodoRow;
-use anyhow::anyhow;
-use sqlx::query_as;
+use crate::services::todos::history::set_actor;
+use crate::services::todos::labels::parse_labels;
+use crate::services::todos::labels::set_todo_labels;
+use crate::services::todos::series::check_recurring_todo;
+use crate::services::todos::series::parse_recurrence_rule;
+use crate::services::todos::series::start_series;
+use crate::services::todos::subtasks::check_parent_todo;
+use crate::services::todos::subtasks::check_subtask;
+use crate::services::todos::subtasks::reopen_parent_todos;
+use crate::services::todos::subtasks::SubtaskCompletion;
+use sqlx::query;
 use sqlx::PgPool;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::Code;
+use tonic::Status;
+use tonic_types::ErrorDetails;
+use tonic_types::StatusExt;
+use tracing::instrument;
 
-
-/// Create a new todo in the database.
+/// Create a new todo in the database. If the request has a request ID, and the
+/// caller has already created a todo with that request ID, then that todo is
+/// returned instead, so that the request can be retried safely. A todo with a
+/// recurrence rule is created as the first occurrence of a new series. The
+/// todo is created in the list that is the request's parent, or in the
+/// caller's inbox if it has none. A subtask is created in its parent todo's
+/// list. If the caller already has a todo with the same ID, then an
+/// `ALREADY_EXISTS` status is returned. Todos of other users and tenants may
+/// use the same IDs.
 ///
 /// # Arguments
 ///
 /// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who will own the new todo.
+/// * `max_todos_per_owner` - The maximum number of todos the caller can own, or
+///   `None` if there is no limit.
+/// * `config` - How subtasks are nested and completed, and the language that
+///   the todo is indexed in for search.
 /// * `request` - The request containing the todo to create.
 ///
 /// # Returns
 ///
 /// A `CreateTodoResponse` containing the created todo.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn create_todo(
   pool: PgPool,
+  principal: Principal,
+  max_todos_per_owner: Option<i64>,
+  config: TodosConfig,
   request: proto::v1::todos::CreateTodoRequest,
 ) -> anyhow::Result<proto::v1::todos::CreateTodoResponse> {
-  let params = request.todo.ok_or(anyhow!("Todo not provided"))?;
+  let params = request
+    .todo
+    .ok_or(Status::invalid_argument("Todo not provided"))?;
+  let due_time = params
+    .due_time
+    .as_ref()
+    .map(|time| proto_timestamp_to_sql_datetime("due_time", time))
+    .transpose()?;
+  let reminder_time = params
+    .reminder_time
+    .as_ref()
+    .map(|time| proto_timestamp_to_sql_datetime("reminder_time", time))
+    .transpose()?;
+  parse_recurrence_rule(&params.recurrence_rule)?;
+  let priority = parse_priority(params.priority)?;
+  let labels = parse_labels(&params.labels)?;
+  let list_id = match request.parent.as_str() {
+    "" => None,
+    parent => Some(parse_list_name(parent)?.to_string()),
+  };
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+  set_actor(&mut transaction, &principal).await?;
+
+  if !request.request_id.is_empty() {
+    let existing =
+      find_created_todo(&mut transaction, &principal, &request.request_id)
+        .await?;
+    if let Some(row) = existing {
+      transaction.commit().await?;
+      return Ok(proto::v1::todos::CreateTodoResponse {
+        todo: Some(row.into()),
+      });
+    }
+  }
+
+  let list_id = match params.parent_todo_id.as_str() {
+    "" => list_id.unwrap_or_else(|| DEFAULT_LIST_ID.to_string()),
+    parent_todo_id => {
+      let parent_list_id = check_parent_todo(
+        &mut transaction,
+        &principal,
+        None,
+        parent_todo_id,
+        config.max_subtask_depth,
+      )
+      .await?;
+      if list_id.is_some_and(|list_id| list_id != parent_list_id) {
+        return Err(
+          Status::invalid_argument(
+            "A subtask must be in the same list as its parent todo",
+          )
+          .into(),
+        );
+      }
+      parent_list_id
+    }
+  };
+  check_parent_list(&mut transaction, &principal, &list_id).await?;
 
-  // Insert the todo into the database, returning the result as a TodoRow.
-  let row = query_as!(
-    TodoRow,
+  if let Some(max_todos) = max_todos_per_owner {
+    check_todo_quota(&mut transaction, &principal, max_todos).await?;
+  }
+
+  // Insert the todo into the database, and get it back as a TodoRow. The
+  // owner is always taken from the authenticated caller, rather than from the
+  // request, so that callers cannot create todos on behalf of someone else.
+  let todo_id = query!(
     r#"
-    insert into todos (todo_id, title, description, completed)
-    values ($1, $2, $3, $4)
-    returning *
+    insert into todos (
+      todo_id,
+      title,
+      description,
+      completed,
+      owner_id,
+      create_request_id,
+      due_time,
+      reminder_time,
+      recurrence_rule,
+      priority,
+      list_id,
+      parent_todo_id,
+      search_language
+    )
+    values (
+      $1, $2, $3, $4, $5, nullif($6, ''), $7, $8, $9, $10, $11, nullif($12, ''),
+      $13::text::regconfig
+    )
+    returning todo_id
     "#,
     params.todo_id,
     params.title,
     params.description,
-    params.completed
+    params.completed,
+    principal.subject,
+    request.request_id,
+    due_time,
+    reminder_time,
+    params.recurrence_rule,
+    priority,
+    list_id,
+    params.parent_todo_id,
+    config.search_language
   )
-  .fetch_one(&pool)
-  .await?;
+  .fetch_one(&mut *transaction)
+  .await
+  .map_err(|e| match e.as_database_error() {
+    Some(error) if error.is_unique_violation() => Status::already_exists(
+      format!("Todo with id {} already exists", params.todo_id),
+    )
+    .into(),
+    _ => anyhow::Error::from(e),
+  })?
+  .todo_id;
+  let row = get_todo_row(&mut transaction, &principal, &todo_id).await?;
+
+  check_recurring_todo(&row)?;
+  check_subtask(&row)?;
+  if config.subtask_completion == SubtaskCompletion::RequireSubtasks
+    && !row.completed
+    && row.parent_todo_id.is_some()
+  {
+    reopen_parent_todos(&mut transaction, &principal, &row.todo_id).await?;
+  }
+  let mut row = match row.recurrence_rule.is_empty() {
+    true => row,
+    false => start_series(&mut transaction, &principal, &row.todo_id).await?,
+  };
+
+  if !labels.is_empty() {
+    set_todo_labels(&mut transaction, &principal, &row.todo_id, &labels)
+      .await?;
+    row.labels = labels;
+  }
+
+  transaction.commit().await?;
 
   // Return the todo wrapped in a protobuf response. The TodoRow is
   // automatically converted to a protobuf Todo by the `into()` method because
@@ -47,3 +211,114 @@ pub async fn create_todo(
     todo: Some(row.into()),
   })
 }
+
+/// Find the todo that the caller created with the given request ID, if there is
+/// one.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn find_created_todo(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  request_id: &str,
+) -> anyhow::Result<Option<TodoRow>> {
+  let todo_id = query!(
+    r#"
+    select todo_id
+    from todos
+    where owner_id = $1
+      and create_request_id = $2
+    "#,
+    principal.subject,
+    request_id
+  )
+  .fetch_optional(&mut **transaction)
+  .await?
+  .map(|row| row.todo_id);
+
+  let Some(todo_id) = todo_id else {
+    return Ok(None);
+  };
+  let row = get_todo_row(transaction, principal, &todo_id).await?;
+
+  Ok(Some(row))
+}
+
+/// Check that the caller can create todos in the given list. If the caller
+/// does not have the list, then a `NOT_FOUND` status is returned, and if it is
+/// archived, then a `FAILED_PRECONDITION` status is returned. The inbox does
+/// not need to exist yet, as the database creates it with the first todo in
+/// it.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn check_parent_list(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  list_id: &str,
+) -> anyhow::Result<()> {
+  let list = query!(
+    r#"
+    select archived
+    from todo_lists
+    where owner_id = $1
+      and list_id = $2
+    "#,
+    principal.subject,
+    list_id
+  )
+  .fetch_optional(&mut **transaction)
+  .await?;
+
+  match list {
+    Some(list) if list.archived => Err(
+      Status::failed_precondition(format!(
+        "Cannot create todos in {}, which is archived",
+        get_list_name(list_id)
+      ))
+      .into(),
+    ),
+    None if list_id != DEFAULT_LIST_ID => Err(
+      Status::not_found(format!("{} not found", get_list_name(list_id))).into(),
+    ),
+    _ => Ok(()),
+  }
+}
+
+/// Check that the caller owns fewer than the maximum number of todos, so that
+/// they can create another one. If not, then return a `RESOURCE_EXHAUSTED`
+/// status with a `google.rpc.QuotaFailure` detail.
+///
+/// Concurrent requests from the same caller could otherwise each count the
+/// todos before any of them inserts, so we lock the caller's todos until the
+/// transaction ends.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn check_todo_quota(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  max_todos: i64,
+) -> anyhow::Result<()> {
+  lock_owner_todos(transaction, principal).await?;
+
+  let count = query!(
+    r#"
+    select count(*) as "count!"
+    from todos
+    where owner_id = $1
+    "#,
+    principal.subject
+  )
+  .fetch_one(&mut **transaction)
+  .await?
+  .count;
+
+  if count >= max_todos {
+    let status = Status::with_error_details(
+      Code::ResourceExhausted,
+      format!("Cannot own more than {} todos", max_todos),
+      ErrorDetails::with_quota_failure_violation(
+        format!("user:{}", principal.subject),
+        format!("Each user can own at most {} todos", max_todos),
+      ),
+    );
+    return Err(status.into());
+  }
+
+  Ok(())
+}
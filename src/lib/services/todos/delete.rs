@@ -1,41 +1,67 @@
 //! # Delete Todo
 //!
 //! This module contains the implementation for deleting a todo.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
 use crate::proto;
+use crate::services::todos::common::parse_todo_reference;
+use crate::services::todos::history::set_actor;
 use sqlx::query;
 use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
 
 /// Delete a todo from the database.
 ///
 /// # Arguments
 ///
 /// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
 /// * `request` - The request containing the todo to delete.
 ///
 /// # Returns
 ///
 /// A `DeleteTodoResponse` indicating the todo was deleted.
 ///
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn delete(
   pool: PgPool,
+  principal: Principal,
   request: proto::v1::todos::DeleteTodoRequest,
 ) -> anyhow::Result<proto::v1::todos::DeleteTodoResponse> {
-  let todo_id = request.todo_id;
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+  set_actor(&mut transaction, &principal).await?;
 
   // As this is a hard delete, we do not need to return the payload according to
   // https://google.aip.dev/135#guidance. However, we do return an error if the
-  // record is not found.
+  // record is not found. Todos owned by someone else are reported as not found,
+  // so that callers cannot discover which IDs exist.
   query!(
     r#"
     delete from todos
     where todo_id = $1
+      and owner_id = $2
+      and ($3::text is null or list_id = $3)
     returning 1 as deleted
     "#,
-    todo_id
+    todo_id,
+    principal.subject,
+    list_id
   )
-    .fetch_optional(&pool)
-    .await?
-    .ok_or(anyhow::anyhow!("Todo with id {} not found", todo_id))?;
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Todo with id {} not found",
+    todo_id
+  )))?;
+
+  transaction.commit().await?;
 
   // Return the empty proto as a placeholder.
   Ok(proto::v1::todos::DeleteTodoResponse {})
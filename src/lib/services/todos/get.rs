@@ -1,55 +1,69 @@
 //! # Get Todo
 //!
 //! This module contains the implementation for getting a todo by its ID.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
 use crate::proto;
-use crate::services::todos::common::TodoRow;
-use sqlx::query_as;
+use crate::services::todos::common::find_todo_row;
+use crate::services::todos::common::parse_todo_reference;
+use crate::services::todos::subtasks::get_subtasks;
 use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
 
 /// Get a todo by its ID. This function takes a database pool and a request
-/// object. The request object contains the ID of the todo to retrieve.
-/// If the todo is not found, then the function will return an error.
+/// object. The request object contains the ID or name of the todo to retrieve.
+/// If the todo is not found, is in a different list than its name says, or is
+/// owned by someone other than the caller, then the function will return a
+/// `NOT_FOUND` error. The todo's subtasks are also returned if the request
+/// includes them.
 ///
 /// # Arguments
 ///
 /// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
 /// * `request` - The request containing the ID of the todo to retrieve.
 ///
 /// # Returns
 ///
 /// A `GetTodoResponse` containing the todo.
 ///
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn get_todo(
   pool: PgPool,
+  principal: Principal,
   request: proto::v1::todos::GetTodoRequest,
 ) -> anyhow::Result<proto::v1::todos::GetTodoResponse> {
-  let row = query_as!(
-    TodoRow,
-    r#"
-    select todo_id,
-           title,
-           description,
-           completed,
-           created_at,
-           updated_at
-    from todos
-    where todo_id = $1
-    "#,
-    request.todo_id
-  )
-  .fetch_optional(&pool)
-  .await?;
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row =
+    find_todo_row(&mut transaction, &principal, &todo_id, list_id.as_deref())
+      .await?;
 
   // If the row is not found, then return an error.
-  let row = row.ok_or(anyhow::anyhow!(
+  let row = row.ok_or(Status::not_found(format!(
     "Todo with id {} not found",
-    request.todo_id
-  ))?;
+    todo_id
+  )))?;
+
+  let subtasks = match request.include_subtasks {
+    true => get_subtasks(&mut transaction, &principal, &row.todo_id).await?,
+    false => Vec::new(),
+  };
+
+  transaction.commit().await?;
 
   // Return the todo wrapped in a protobuf response. The TodoRecord is
   // automatically converted to a protobuf Todo, because we have defined the
   // Into trait for this conversion.
   Ok(proto::v1::todos::GetTodoResponse {
     todo: Some(row.into()),
+    subtasks: subtasks.into_iter().map(|r| r.into()).collect(),
   })
 }
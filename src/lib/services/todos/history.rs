@@ -0,0 +1,210 @@
+//! # Todo History
+//!
+//! This module contains the implementation for listing the history of a todo.
+//! The history is recorded in the `todo_events` table by database triggers, in
+//! the same transaction as each change, so that changes the database makes on
+//! its own, such as deleting subtasks, are recorded too. The triggers merge
+//! every change to a todo in one transaction into a single event.
+use crate::auth::Principal;
+use crate::common::sql_datetime_to_proto_timestamp;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::proto::v1::todos::TodoEventAction;
+use crate::services::todos::common::get_next_page_token;
+use crate::services::todos::common::parse_page;
+use crate::services::todos::common::parse_todo_reference;
+use prost_types::value::Kind;
+use prost_types::ListValue;
+use serde_json::Value;
+use sqlx::query;
+use sqlx::query_as;
+use sqlx::PgPool;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::Status;
+use tracing::instrument;
+
+/// Represents a row in the `todo_events` table.
+///
+/// # Fields
+///
+/// * `event_id` - The ID of the event, which increases with each change.
+/// * `todo_id` - The ID of the todo that was changed.
+/// * `actor_id` - The ID of the user that made the change.
+/// * `api_key` - Whether the user made the change with an API key.
+/// * `action` - Whether the todo was created, updated or deleted.
+/// * `changed_fields` - The names of the fields that were changed.
+/// * `before` - The fields of the todo before the change, if it existed.
+/// * `after` - The fields of the todo after the change, if it still exists.
+/// * `event_time` - The time of the change.
+struct TodoEventRow {
+  event_id: i64,
+  todo_id: String,
+  actor_id: String,
+  api_key: bool,
+  action: String,
+  changed_fields: Vec<String>,
+  before: Option<Value>,
+  after: Option<Value>,
+  event_time: sqlx::types::time::OffsetDateTime,
+}
+
+impl From<TodoEventRow> for proto::v1::todos::TodoEvent {
+  fn from(row: TodoEventRow) -> Self {
+    let action = match row.action.as_str() {
+      "create" => TodoEventAction::Create,
+      "update" => TodoEventAction::Update,
+      "delete" => TodoEventAction::Delete,
+      _ => TodoEventAction::Unspecified,
+    };
+    // The snapshots hold every field of the todo, but the event only shows
+    // the ones that changed.
+    let changed = |snapshot: Option<Value>| {
+      let Some(Value::Object(mut fields)) = snapshot else {
+        return None;
+      };
+      let fields = row
+        .changed_fields
+        .iter()
+        .filter_map(|field| {
+          let value = fields.remove(field)?;
+          Some((field.clone(), json_to_proto_value(value)))
+        })
+        .collect();
+      Some(prost_types::Struct { fields })
+    };
+
+    proto::v1::todos::TodoEvent {
+      event_id: row.event_id,
+      todo_id: row.todo_id,
+      action: action.into(),
+      actor_id: row.actor_id,
+      api_key: row.api_key,
+      event_time: Some(sql_datetime_to_proto_timestamp(row.event_time)),
+      before: changed(row.before),
+      after: changed(row.after),
+      changed_fields: row.changed_fields,
+    }
+  }
+}
+
+/// Record the caller as the actor of the changes made in the transaction, so
+/// that the history of each todo shows who changed it. Changes made without
+/// an actor are attributed to the owner of the todo.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn set_actor(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+) -> anyhow::Result<()> {
+  query(
+    r#"
+    select set_config('app.actor_id', $1, true),
+           set_config('app.actor_api_key', $2, true)
+    "#,
+  )
+  .bind(&principal.subject)
+  .bind(principal.is_api_key().to_string())
+  .execute(&mut **transaction)
+  .await?;
+
+  Ok(())
+}
+
+/// List the changes to a todo owned by the caller, most recent first. The
+/// history of a todo outlives the todo, so that callers can see when it was
+/// deleted. If the todo has no history, then a `NOT_FOUND` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo, and the page to list.
+///
+/// # Returns
+///
+/// A `ListTodoHistoryResponse` containing a page of events, and the token of
+/// the next page if there is one.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn list_todo_history(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::ListTodoHistoryRequest,
+) -> anyhow::Result<proto::v1::todos::ListTodoHistoryResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+  let (page_size, after_event_id) =
+    parse_page(request.page_size, &request.page_token)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  // One more event than the page size is fetched, to find out whether there
+  // is another page.
+  let mut rows = query_as!(
+    TodoEventRow,
+    r#"
+    select event_id,
+           todo_id,
+           actor_id,
+           api_key,
+           action,
+           changed_fields,
+           before,
+           after,
+           event_time
+    from todo_events
+    where owner_id = $1
+      and todo_id = $2
+      and ($3::text is null or coalesce(after, before) ->> 'list_id' = $3)
+      and ($4::bigint is null or event_id < $4)
+    order by event_id desc
+    limit $5
+    "#,
+    principal.subject,
+    todo_id,
+    list_id,
+    after_event_id,
+    page_size + 1
+  )
+  .fetch_all(&mut *transaction)
+  .await?;
+
+  transaction.commit().await?;
+
+  if rows.is_empty() && after_event_id.is_none() {
+    return Err(
+      Status::not_found(format!("Todo with id {} not found", todo_id)).into(),
+    );
+  }
+
+  let next_page_token =
+    get_next_page_token(&mut rows, page_size, |row| row.event_id);
+
+  Ok(proto::v1::todos::ListTodoHistoryResponse {
+    events: rows.into_iter().map(|row| row.into()).collect(),
+    next_page_token,
+  })
+}
+
+/// Convert a JSON value from a snapshot of a todo into a protobuf value.
+fn json_to_proto_value(value: Value) -> prost_types::Value {
+  let kind = match value {
+    Value::Null => Kind::NullValue(0),
+    Value::Bool(value) => Kind::BoolValue(value),
+    Value::Number(value) => Kind::NumberValue(value.as_f64().unwrap_or(0.0)),
+    Value::String(value) => Kind::StringValue(value),
+    Value::Array(values) => Kind::ListValue(ListValue {
+      values: values.into_iter().map(json_to_proto_value).collect(),
+    }),
+    Value::Object(fields) => Kind::StructValue(prost_types::Struct {
+      fields: fields
+        .into_iter()
+        .map(|(key, value)| (key, json_to_proto_value(value)))
+        .collect(),
+    }),
+  };
+
+  prost_types::Value { kind: Some(kind) }
+}
@@ -0,0 +1,110 @@
+//! # Todo Labels
+//!
+//! This module contains the functions that attach labels to todos. The labels
+//! of each todo are read with the todo itself, by aggregating the
+//! `todo_labels` table in the same query, so listing todos does not need a
+//! query for each todo.
+use crate::auth::Principal;
+use crate::services::labels::validate_label_name;
+use sqlx::query;
+use sqlx::query_scalar;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::Status;
+use tracing::instrument;
+
+/// Validate the labels of a todo in a request, returning them in alphabetical
+/// order without duplicates. If any name is not valid, then an
+/// `INVALID_ARGUMENT` status is returned.
+pub fn parse_labels(labels: &[String]) -> Result<Vec<String>, Status> {
+  for label in labels {
+    validate_label_name(label)?;
+  }
+
+  let mut labels = labels.to_vec();
+  labels.sort();
+  labels.dedup();
+
+  Ok(labels)
+}
+
+/// Replace the labels of the given todo, which the caller must own, creating
+/// any of the caller's labels that do not exist yet. The labels must have been
+/// validated with [`parse_labels`].
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn set_todo_labels(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  labels: &[String],
+) -> anyhow::Result<()> {
+  query!(
+    r#"
+    insert into labels (owner_id, name)
+    select $1, unnest($2::text[])
+    on conflict do nothing
+    "#,
+    principal.subject,
+    labels
+  )
+  .execute(&mut **transaction)
+  .await?;
+
+  query!(
+    r#"
+    delete from todo_labels
+    where todo_id = $1
+      and owner_id = $2
+      and name <> all($3)
+    "#,
+    todo_id,
+    principal.subject,
+    labels
+  )
+  .execute(&mut **transaction)
+  .await?;
+
+  query!(
+    r#"
+    insert into todo_labels (todo_id, owner_id, name)
+    select $1, $2, unnest($3::text[])
+    on conflict do nothing
+    "#,
+    todo_id,
+    principal.subject,
+    labels
+  )
+  .execute(&mut **transaction)
+  .await?;
+
+  Ok(())
+}
+
+/// Copy the labels of one of the caller's todos to another, returning the
+/// names of the labels in alphabetical order.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn copy_todo_labels(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  from_todo_id: &str,
+  to_todo_id: &str,
+) -> anyhow::Result<Vec<String>> {
+  let mut labels = query_scalar!(
+    r#"
+    insert into todo_labels (todo_id, owner_id, name)
+    select $2, owner_id, name
+    from todo_labels
+    where todo_id = $1
+      and owner_id = $3
+    returning name
+    "#,
+    from_todo_id,
+    to_todo_id,
+    principal.subject
+  )
+  .fetch_all(&mut **transaction)
+  .await?;
+  labels.sort();
+
+  Ok(labels)
+}
@@ -1,46 +1,87 @@
 //! # List Todos
 //!
-//! This module contains the implementation for listing all todos.
+//! This module contains the implementation for listing todos, filtered and
+//! ordered as the request asks.
+use crate::auth::Principal;
+use crate::common::proto_timestamp_to_sql_datetime;
+use crate::database::begin_tenant_transaction;
 use crate::proto;
+use crate::services::todo_lists::parse_list_name;
 use crate::services::todos::common::TodoRow;
-use sqlx::query_as;
+use sqlx::query;
+use sqlx::types::time::OffsetDateTime;
 use sqlx::PgPool;
+use sqlx::Postgres;
+use sqlx::QueryBuilder;
+use sqlx::Transaction;
+use tonic::Status;
+use tracing::instrument;
 
-/// List all todos. This function takes a database pool and a request object.
-/// The request object is not used in this implementation, but it could be used
-/// to implement pagination or filtering in the future.
-///
+/// The fields that todos can be ordered by. Each one is stored in the column
+/// with the same name.
+const ORDER_BY_FIELDS: [&str; 8] = [
+  "title",
+  "due_time",
+  "reminder_time",
+  "created_at",
+  "updated_at",
+  "priority",
+  "position",
+  "complete_time",
+];
+
+/// The order of the todos if the request does not set one, which is the order
+/// that the owner arranged them in.
+const DEFAULT_ORDER_BY: &str = "position";
+
+/// List the todos owned by the caller. This function takes a database pool,
+/// the authenticated principal and a request object. The request object
+/// contains the list to list the todos of, optional ranges of due, reminder and
+/// complete times that the todos must be in, labels that they must have, and
+/// the order to list them in. If the caller does not have the list, then a
+/// `NOT_FOUND` status is returned.
 ///
 /// # Arguments
 ///
 /// * `pool` - The database pool to use.
-/// * `request` - The request containing the todo to list.
+/// * `principal` - The authenticated caller, whose todos will be listed.
+/// * `request` - The request containing the filters and order of the todos.
 ///
 /// # Returns
 ///
 /// A `ListTodosResponse` containing the list of todos.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn list_todos(
   pool: PgPool,
-  _request: proto::v1::todos::ListTodosRequest,
+  principal: Principal,
+  request: proto::v1::todos::ListTodosRequest,
 ) -> anyhow::Result<proto::v1::todos::ListTodosResponse> {
-  // Query the database for all todos. We would normally include things like
-  // pagination and filtering here, using values from the request object.
-  let query = query_as!(
-    TodoRow,
+  let order_by = parse_order_by(&request.order_by)?;
+  let filters = TodoFilters::parse(&request)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  filters.check_list(&mut transaction, &principal).await?;
+
+  // The filters and order depend on the request, so we build the query at
+  // runtime. Only the values are taken from the request, and they are bound
+  // as parameters, while the columns and operators are our own constants.
+  let mut query = QueryBuilder::new(
     r#"
-    select todo_id,
-           title,
-           description,
-           completed,
-           created_at,
-           updated_at
-    from todos
-    order by created_at desc
-    "#
+    select *
+    from todos_view
+    where owner_id = "#,
   );
+  query.push_bind(&principal.subject);
+  filters.push_conditions(&mut query);
+  query.push(format!(" order by {}", order_by));
 
   let result = query
-    .fetch_all(&pool)
+    .build_query_as::<TodoRow>()
+    .fetch_all(&mut *transaction)
     .await?
     .into_iter()
     // Each TodoRecord is automatically converted to a protobuf Todo by the
@@ -50,6 +91,176 @@ pub async fn list_todos(
     // vector type based on the downstream usage.
     .collect();
 
+  transaction.commit().await?;
+
   // Return the todos wrapped in a protobuf response.
   Ok(proto::v1::todos::ListTodosResponse { todos: result })
 }
+
+/// The filters of a request that lists or searches todos, other than the owner,
+/// who is always the caller.
+pub struct TodoFilters {
+  /// The list that was asked for, as the request named it, e.g. `lists/work`.
+  parent: String,
+  /// The ID of the list that the todos must be in, or `None` for every list.
+  list_id: Option<String>,
+  /// The columns that must be in a range, with the comparison and the value
+  /// that each must be compared with.
+  times: Vec<(&'static str, &'static str, OffsetDateTime)>,
+  /// The labels that the todos must all have.
+  labels: Vec<String>,
+}
+
+impl TodoFilters {
+  /// Parse the filters of a `ListTodosRequest`, which `SearchTodosRequest`
+  /// shares. If a filter is invalid, then an `INVALID_ARGUMENT` status is
+  /// returned.
+  pub fn parse(
+    request: &proto::v1::todos::ListTodosRequest,
+  ) -> Result<Self, Status> {
+    // `-` stands for every list, see https://google.aip.dev/159.
+    let list_id = match request.parent.as_str() {
+      "" | "lists/-" => None,
+      parent => Some(parse_list_name(parent)?.to_string()),
+    };
+    let times = [
+      ("due_time", ">=", get_time("due_after", &request.due_after)?),
+      (
+        "due_time",
+        "<",
+        get_time("due_before", &request.due_before)?,
+      ),
+      (
+        "reminder_time",
+        ">=",
+        get_time("reminder_after", &request.reminder_after)?,
+      ),
+      (
+        "reminder_time",
+        "<",
+        get_time("reminder_before", &request.reminder_before)?,
+      ),
+      (
+        "complete_time",
+        ">=",
+        get_time("completed_after", &request.completed_after)?,
+      ),
+      (
+        "complete_time",
+        "<",
+        get_time("completed_before", &request.completed_before)?,
+      ),
+    ];
+
+    Ok(Self {
+      parent: request.parent.clone(),
+      list_id,
+      times: times
+        .into_iter()
+        .filter_map(|(column, operator, value)| {
+          Some((column, operator, value?))
+        })
+        .collect(),
+      labels: request.labels.clone(),
+    })
+  }
+
+  /// Check that the caller has the list that the todos must be in, if there is
+  /// one. If not, then a `NOT_FOUND` status is returned.
+  #[instrument(skip_all, fields(db.system = "postgresql"))]
+  pub async fn check_list(
+    &self,
+    transaction: &mut Transaction<'static, Postgres>,
+    principal: &Principal,
+  ) -> anyhow::Result<()> {
+    let Some(list_id) = &self.list_id else {
+      return Ok(());
+    };
+
+    query!(
+      r#"
+      select 1 as exists
+      from todo_lists
+      where owner_id = $1
+        and list_id = $2
+      "#,
+      principal.subject,
+      list_id
+    )
+    .fetch_optional(&mut **transaction)
+    .await?
+    .ok_or(Status::not_found(format!("{} not found", self.parent)))?;
+
+    Ok(())
+  }
+
+  /// Add the filters as conditions to a query of the `todos_view` view, which
+  /// must end inside its `where` clause. The values are bound as parameters.
+  pub fn push_conditions<'a>(&'a self, query: &mut QueryBuilder<'a, Postgres>) {
+    if let Some(list_id) = &self.list_id {
+      query.push(" and list_id = ");
+      query.push_bind(list_id);
+    }
+    for (column, operator, value) in &self.times {
+      query.push(format!(" and {} {} ", column, operator));
+      query.push_bind(*value);
+    }
+    for label in &self.labels {
+      query.push(
+        " and exists (select from todo_labels where todo_labels.owner_id = \
+         todos_view.owner_id and todo_labels.todo_id = todos_view.todo_id \
+         and todo_labels.name = ",
+      );
+      query.push_bind(label);
+      query.push(")");
+    }
+  }
+}
+
+/// Convert an optional timestamp from the request into a SQL datetime.
+fn get_time(
+  field: &str,
+  time: &Option<prost_types::Timestamp>,
+) -> Result<Option<OffsetDateTime>, Status> {
+  time
+    .as_ref()
+    .map(|time| proto_timestamp_to_sql_datetime(field, time))
+    .transpose()
+}
+
+/// Parse the `order_by` of the request into an SQL `order by` clause. Todos
+/// without a value for a field are always listed last, and todos that are
+/// otherwise equal are ordered by their ID, so that the order is stable. If the
+/// `order_by` names an unknown field, then an `INVALID_ARGUMENT` status is
+/// returned.
+fn parse_order_by(order_by: &str) -> Result<String, Status> {
+  let order_by = match order_by.trim() {
+    "" => DEFAULT_ORDER_BY,
+    order_by => order_by,
+  };
+
+  let mut clauses = Vec::new();
+  for item in order_by.split(',') {
+    let invalid = || {
+      Status::invalid_argument(format!(
+        "Cannot order by {:?}, expected one of {} optionally followed by desc",
+        item.trim(),
+        ORDER_BY_FIELDS.join(", ")
+      ))
+    };
+    let (field, direction) = match item.split_whitespace().collect::<Vec<_>>()[..]
+    {
+      [field] => (field, "asc"),
+      [field, "asc"] => (field, "asc"),
+      [field, "desc"] => (field, "desc"),
+      _ => return Err(invalid()),
+    };
+    if !ORDER_BY_FIELDS.contains(&field) {
+      return Err(invalid());
+    }
+    clauses.push(format!("{} {} nulls last", field, direction));
+  }
+  clauses.push("todo_id".to_string());
+
+  Ok(clauses.join(", "))
+}
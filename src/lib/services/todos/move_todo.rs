@@ -0,0 +1,165 @@
+//! # Move Todo
+//!
+//! This module contains the implementation for moving a todo to another place
+//! in its owner's list.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::position::key_between;
+use crate::proto;
+use crate::proto::v1::todos::move_todo_request::Destination;
+use crate::services::todos::common::get_todo_row;
+use crate::services::todos::common::lock_owner_todos;
+use crate::services::todos::common::parse_todo_reference;
+use crate::services::todos::history::set_actor;
+use sqlx::query;
+use sqlx::PgPool;
+use tonic::Status;
+use tracing::instrument;
+
+/// Move a todo to just before or after another of the caller's todos. The new
+/// position is a key between the other todo and its neighbour, so only the
+/// moved todo is changed. If the other todo is in another list, then an
+/// `INVALID_ARGUMENT` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own both todos.
+/// * `request` - The request containing the ID or name of the todo to move,
+///   and where to.
+///
+/// # Returns
+///
+/// A `MoveTodoResponse` containing the moved todo.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn move_todo(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::MoveTodoRequest,
+) -> anyhow::Result<proto::v1::todos::MoveTodoResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+  let destination = request.destination.ok_or(Status::invalid_argument(
+    "One of before_todo_id or after_todo_id must be set",
+  ))?;
+  let other_todo_id = match &destination {
+    Destination::BeforeTodoId(todo_id) | Destination::AfterTodoId(todo_id) => {
+      todo_id
+    }
+  };
+  if *other_todo_id == todo_id {
+    return Err(
+      Status::invalid_argument("A todo cannot be moved next to itself").into(),
+    );
+  }
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+  set_actor(&mut transaction, &principal).await?;
+
+  // The neighbours of the other todo must not change until the moved todo has
+  // its new position, or two todos could get the same one.
+  lock_owner_todos(&mut transaction, &principal).await?;
+
+  let moved = query!(
+    r#"
+    select list_id
+    from todos
+    where todo_id = $1
+      and owner_id = $2
+      and ($3::text is null or list_id = $3)
+    "#,
+    todo_id,
+    principal.subject,
+    list_id
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Todo with id {} not found",
+    todo_id
+  )))?;
+
+  // Find the positions on either side of the place the todo is moved to. The
+  // moved todo is ignored, in case it is already next to the other todo.
+  let neighbours = query!(
+    r#"
+    select other.position,
+           other.list_id,
+           (
+             select max(todos.position)
+             from todos
+             where todos.owner_id = $2
+               and todos.position < other.position
+               and todos.todo_id <> $3
+           ) as previous,
+           (
+             select min(todos.position)
+             from todos
+             where todos.owner_id = $2
+               and todos.position > other.position
+               and todos.todo_id <> $3
+           ) as next
+    from todos other
+    where other.todo_id = $1
+      and other.owner_id = $2
+    "#,
+    other_todo_id,
+    principal.subject,
+    todo_id
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Todo with id {} not found",
+    other_todo_id
+  )))?;
+  if neighbours.list_id != moved.list_id {
+    return Err(
+      Status::invalid_argument(format!(
+        "Todo with id {} is not in the same list as todo with id {}",
+        other_todo_id, todo_id
+      ))
+      .into(),
+    );
+  }
+
+  let position = match destination {
+    Destination::BeforeTodoId(_) => {
+      key_between(neighbours.previous.as_deref(), Some(&neighbours.position))?
+    }
+    Destination::AfterTodoId(_) => {
+      key_between(Some(&neighbours.position), neighbours.next.as_deref())?
+    }
+  };
+
+  query!(
+    r#"
+    update todos
+    set position = $3
+    where todo_id = $1
+      and owner_id = $2
+      and ($4::text is null or list_id = $4)
+    returning todo_id
+    "#,
+    todo_id,
+    principal.subject,
+    position,
+    list_id
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Todo with id {} not found",
+    todo_id
+  )))?;
+  let todo = get_todo_row(&mut transaction, &principal, &todo_id).await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::MoveTodoResponse {
+    todo: Some(todo.into()),
+  })
+}
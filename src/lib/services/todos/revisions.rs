@@ -0,0 +1,417 @@
+//! # Todo Revisions
+//!
+//! This module contains the implementation for listing, getting and restoring
+//! the revisions of a todo. A revision is the todo as it was after one of the
+//! changes in its history, so the revisions are read from the `after`
+//! snapshots of the `todo_events` table rather than being recorded
+//! separately.
+use crate::auth::Principal;
+use crate::common::sql_datetime_to_proto_timestamp;
+use crate::config::TodosConfig;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todos::common::get_next_page_token;
+use crate::services::todos::common::get_todo_row;
+use crate::services::todos::common::parse_page;
+use crate::services::todos::common::parse_todo_reference;
+use crate::services::todos::common::TodoRow;
+use crate::services::todos::history::set_actor;
+use crate::services::todos::update::apply_todo_update;
+use prost_types::FieldMask;
+use sqlx::query;
+use sqlx::PgPool;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::Status;
+use tracing::instrument;
+
+/// The fields of a todo that restoring a revision sets, which are the fields
+/// that `UpdateTodo` can change. The list and position of the todo are left
+/// as they are.
+const RESTORED_FIELDS: [&str; 9] = [
+  "title",
+  "description",
+  "completed",
+  "due_time",
+  "reminder_time",
+  "recurrence_rule",
+  "priority",
+  "labels",
+  "parent_todo_id",
+];
+
+/// Represents a revision of a todo, read from a row in the `todo_events`
+/// table.
+///
+/// # Fields
+///
+/// * `revision_id` - The ID of the event that recorded the revision.
+/// * `revision_create_time` - The time of the event.
+/// * `actor_id` - The ID of the user that made the change.
+/// * `todo` - The todo as it was after the change. Its `updated_at` is the
+///   time of the event, and its `comment_count` is always 0, as comments are
+///   not recorded in the history.
+struct TodoRevisionRow {
+  revision_id: i64,
+  revision_create_time: sqlx::types::time::OffsetDateTime,
+  actor_id: String,
+  todo: TodoRow,
+}
+
+impl From<TodoRevisionRow> for proto::v1::todos::TodoRevision {
+  fn from(row: TodoRevisionRow) -> Self {
+    proto::v1::todos::TodoRevision {
+      revision_id: row.revision_id,
+      revision_create_time: Some(sql_datetime_to_proto_timestamp(
+        row.revision_create_time,
+      )),
+      actor_id: row.actor_id,
+      todo: Some(row.todo.into()),
+    }
+  }
+}
+
+/// List the revisions of a todo owned by the caller, most recent first. If the
+/// todo has no revisions, then a `NOT_FOUND` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo, and the page to list.
+///
+/// # Returns
+///
+/// A `ListTodoRevisionsResponse` containing a page of revisions, and the token
+/// of the next page if there is one.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn list_todo_revisions(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::ListTodoRevisionsRequest,
+) -> anyhow::Result<proto::v1::todos::ListTodoRevisionsResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+  let (page_size, after_revision_id) =
+    parse_page(request.page_size, &request.page_token)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  // One more revision than the page size is fetched, to find out whether
+  // there is another page.
+  let mut rows = get_revisions(
+    &mut transaction,
+    &principal,
+    &todo_id,
+    list_id.as_deref(),
+    None,
+    after_revision_id,
+    page_size + 1,
+  )
+  .await?;
+
+  transaction.commit().await?;
+
+  if rows.is_empty() && after_revision_id.is_none() {
+    return Err(
+      Status::not_found(format!("Todo with id {} not found", todo_id)).into(),
+    );
+  }
+
+  let next_page_token =
+    get_next_page_token(&mut rows, page_size, |row| row.revision_id);
+
+  Ok(proto::v1::todos::ListTodoRevisionsResponse {
+    revisions: rows.into_iter().map(|row| row.into()).collect(),
+    next_page_token,
+  })
+}
+
+/// Get a revision of a todo owned by the caller. If the todo does not have a
+/// revision with the given ID, then a `NOT_FOUND` status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `request` - The request containing the todo and the ID of the revision.
+///
+/// # Returns
+///
+/// A `GetTodoRevisionResponse` containing the revision.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_todo_revision(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::GetTodoRevisionRequest,
+) -> anyhow::Result<proto::v1::todos::GetTodoRevisionResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  let row = get_revision(
+    &mut transaction,
+    &principal,
+    &todo_id,
+    list_id.as_deref(),
+    request.revision_id,
+  )
+  .await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::GetTodoRevisionResponse {
+    revision: Some(row.into()),
+  })
+}
+
+/// Restore a todo owned by the caller to one of its revisions. The todo is
+/// updated with the fields of the revision in the same way as by `UpdateTodo`,
+/// so the same checks apply, and the change is recorded as a new revision. If
+/// the revision is completed, then the todo's completion time is restored too.
+/// Only todos that still exist can be restored, and the todo is locked while
+/// it is restored, so that the revision is not applied over a change made in
+/// the meantime.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `config` - How subtasks are nested and completed.
+/// * `request` - The request containing the todo and the ID of the revision.
+///
+/// # Returns
+///
+/// A `RestoreTodoRevisionResponse` containing the restored todo, and the next
+/// occurrence if restoring the todo completed it.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn restore_todo_revision(
+  pool: PgPool,
+  principal: Principal,
+  config: TodosConfig,
+  request: proto::v1::todos::RestoreTodoRevisionRequest,
+) -> anyhow::Result<proto::v1::todos::RestoreTodoRevisionResponse> {
+  let (todo_id, list_id) =
+    parse_todo_reference(&request.todo_id, &request.name)?;
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+  set_actor(&mut transaction, &principal).await?;
+
+  query!(
+    r#"
+    select todo_id
+    from todos
+    where todo_id = $1
+      and owner_id = $2
+      and ($3::text is null or list_id = $3)
+    for update
+    "#,
+    todo_id,
+    principal.subject,
+    list_id
+  )
+  .fetch_optional(&mut *transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Todo with id {} not found",
+    todo_id
+  )))?;
+
+  // The todo may have moved since the revision, so the revision is found in
+  // any list, and the update refers to the todo by its ID alone.
+  let revision = get_revision(
+    &mut transaction,
+    &principal,
+    &todo_id,
+    None,
+    request.revision_id,
+  )
+  .await?;
+  let complete_time = revision.todo.complete_time;
+  let response = apply_todo_update(
+    &mut transaction,
+    &principal,
+    &config,
+    proto::v1::todos::UpdateTodoRequest {
+      todo: Some(proto::v1::todos::Todo {
+        name: String::new(),
+        ..revision.todo.into()
+      }),
+      update_mask: Some(FieldMask {
+        paths: RESTORED_FIELDS
+          .iter()
+          .map(|path| path.to_string())
+          .collect(),
+      }),
+      ..Default::default()
+    },
+  )
+  .await?;
+
+  // The database sets the completion time to now when the todo is completed,
+  // so the time of the revision is set back afterwards.
+  query!(
+    r#"
+    update todos
+    set complete_time = coalesce($3, complete_time)
+    where todo_id = $1
+      and owner_id = $2
+      and completed
+    "#,
+    todo_id,
+    principal.subject,
+    complete_time
+  )
+  .execute(&mut *transaction)
+  .await?;
+  let todo = get_todo_row(&mut transaction, &principal, &todo_id).await?;
+
+  transaction.commit().await?;
+
+  Ok(proto::v1::todos::RestoreTodoRevisionResponse {
+    todo: Some(todo.into()),
+    next_occurrence: response.next_occurrence,
+  })
+}
+
+/// Get a revision of a todo of the caller, or a `NOT_FOUND` status if there is
+/// no such revision.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn get_revision(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  list_id: Option<&str>,
+  revision_id: i64,
+) -> anyhow::Result<TodoRevisionRow> {
+  let row = get_revisions(
+    transaction,
+    principal,
+    todo_id,
+    list_id,
+    Some(revision_id),
+    None,
+    1,
+  )
+  .await?
+  .pop()
+  .ok_or(Status::not_found(format!(
+    "Revision {} of todo with id {} not found",
+    revision_id, todo_id
+  )))?;
+
+  Ok(row)
+}
+
+/// Get the revisions of a todo of the caller, most recent first, from its
+/// events that left the todo in existence. The revisions can be limited to
+/// the one with the given ID, or to those before the given ID. The todo of
+/// each revision is read back from the event's snapshot, with the time it was
+/// created taken from the todo if it still exists, and otherwise from its
+/// first event. The comments of the todo are not part of its revisions.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+async fn get_revisions(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+  list_id: Option<&str>,
+  revision_id: Option<i64>,
+  before_revision_id: Option<i64>,
+  limit: i64,
+) -> anyhow::Result<Vec<TodoRevisionRow>> {
+  let rows = query!(
+    r#"
+    select todo_events.event_id as "revision_id!",
+           todo_events.event_time as "revision_create_time!",
+           todo_events.actor_id as "actor_id!",
+           todo_events.todo_id as "todo_id!",
+           after ->> 'title' as "title!",
+           after ->> 'description' as "description!",
+           (after ->> 'completed')::boolean as "completed!",
+           coalesce(
+             todos.created_at,
+             (
+               select min(first_event.event_time)
+               from todo_events first_event
+               where first_event.owner_id = todo_events.owner_id
+                 and first_event.todo_id = todo_events.todo_id
+             )
+           ) as "created_at!",
+           todo_events.owner_id as "owner_id!",
+           todo_events.tenant_id as "tenant_id!",
+           (after ->> 'due_time')::timestamptz as due_time,
+           (after ->> 'reminder_time')::timestamptz as reminder_time,
+           after ->> 'recurrence_rule' as "recurrence_rule!",
+           after ->> 'series_id' as series_id,
+           (after ->> 'priority')::smallint as "priority!",
+           after ->> 'position' as "position!",
+           after ->> 'list_id' as "list_id!",
+           after ->> 'parent_todo_id' as parent_todo_id,
+           (after ->> 'complete_time')::timestamptz as complete_time,
+           array(
+             select jsonb_array_elements_text(after -> 'labels')
+           ) as "labels!"
+    from todo_events
+    left join todos on todos.owner_id = todo_events.owner_id
+                   and todos.todo_id = todo_events.todo_id
+    where todo_events.owner_id = $1
+      and todo_events.todo_id = $2
+      and after is not null
+      and ($3::text is null or after ->> 'list_id' = $3)
+      and ($4::bigint is null or todo_events.event_id = $4)
+      and ($5::bigint is null or todo_events.event_id < $5)
+    order by todo_events.event_id desc
+    limit $6
+    "#,
+    principal.subject,
+    todo_id,
+    list_id,
+    revision_id,
+    before_revision_id,
+    limit
+  )
+  .fetch_all(&mut **transaction)
+  .await?;
+
+  let rows = rows
+    .into_iter()
+    .map(|row| TodoRevisionRow {
+      revision_id: row.revision_id,
+      revision_create_time: row.revision_create_time,
+      actor_id: row.actor_id,
+      todo: TodoRow {
+        todo_id: row.todo_id,
+        title: row.title,
+        description: row.description,
+        completed: row.completed,
+        created_at: row.created_at,
+        updated_at: row.revision_create_time,
+        owner_id: row.owner_id,
+        tenant_id: row.tenant_id,
+        due_time: row.due_time,
+        reminder_time: row.reminder_time,
+        recurrence_rule: row.recurrence_rule,
+        series_id: row.series_id,
+        priority: row.priority,
+        position: row.position,
+        list_id: row.list_id,
+        parent_todo_id: row.parent_todo_id,
+        complete_time: row.complete_time,
+        labels: row.labels,
+        comment_count: 0,
+      },
+    })
+    .collect();
+
+  Ok(rows)
+}
@@ -0,0 +1,226 @@
+//! # Search Todos
+//!
+//! This module contains the implementation for searching the titles and
+//! descriptions of todos. The database keeps a `tsvector` of each todo in its
+//! `search_vector` column, which is indexed, so that todos can be found by the
+//! stems of their words in the todo's search language.
+use crate::auth::Principal;
+use crate::database::begin_tenant_transaction;
+use crate::proto;
+use crate::services::todos::common::get_next_page_token;
+use crate::services::todos::common::parse_page;
+use crate::services::todos::common::TodoRow;
+use crate::services::todos::list::TodoFilters;
+use anyhow::anyhow;
+use sqlx::PgPool;
+use sqlx::QueryBuilder;
+use tonic::Status;
+use tracing::instrument;
+
+/// The text search configurations that PostgreSQL has built in, which are the
+/// languages that todos can be searched in.
+const SEARCH_LANGUAGES: [&str; 29] = [
+  "arabic",
+  "armenian",
+  "basque",
+  "catalan",
+  "danish",
+  "dutch",
+  "english",
+  "finnish",
+  "french",
+  "german",
+  "greek",
+  "hindi",
+  "hungarian",
+  "indonesian",
+  "irish",
+  "italian",
+  "lithuanian",
+  "nepali",
+  "norwegian",
+  "portuguese",
+  "romanian",
+  "russian",
+  "serbian",
+  "simple",
+  "spanish",
+  "swedish",
+  "tamil",
+  "turkish",
+  "yiddish",
+];
+
+/// The options of `ts_headline` for the title, which is highlighted in full.
+const TITLE_HEADLINE_OPTIONS: &str =
+  "StartSel=<mark>, StopSel=</mark>, HighlightAll=true";
+
+/// The options of `ts_headline` for the description, which is cut down to the
+/// fragments that best match the query.
+const DESCRIPTION_HEADLINE_OPTIONS: &str = "StartSel=<mark>, StopSel=</mark>, \
+   MaxFragments=3, MaxWords=20, MinWords=5, FragmentDelimiter=\" ... \"";
+
+/// Escape the text of a column as HTML in SQL, so that the `<mark>` tags of a
+/// snippet are the only markup in it. Entities are single tokens to
+/// `ts_headline`, so the words around them are still highlighted.
+fn escape_html(column: &str) -> String {
+  format!(
+    "replace(replace(replace(replace(replace({}, '&', '&amp;'), '<', '&lt;'), \
+     '>', '&gt;'), '\"', '&quot;'), '''', '&#39;')",
+    column
+  )
+}
+
+/// Parse the name of a text search language, as one of the configurations
+/// that PostgreSQL has built in.
+pub fn parse_search_language(value: &str) -> anyhow::Result<&'static str> {
+  SEARCH_LANGUAGES
+    .iter()
+    .find(|language| **language == value)
+    .copied()
+    .ok_or_else(|| {
+      anyhow!(
+        "Expected a PostgreSQL text search configuration, such as english or \
+         simple, got {}",
+        value
+      )
+    })
+}
+
+/// Represents a todo that matches a search, with how well it matches.
+///
+/// # Fields
+///
+/// * `todo` - The matching todo.
+/// * `rank` - How well the todo matches, where higher is better.
+/// * `title_snippet` - The title as HTML, with the matching words highlighted.
+/// * `description_snippet` - The best matching parts of the description as
+///   HTML, with the matching words highlighted.
+#[derive(sqlx::FromRow)]
+struct TodoSearchResultRow {
+  #[sqlx(flatten)]
+  todo: TodoRow,
+  rank: f32,
+  title_snippet: String,
+  description_snippet: String,
+}
+
+impl From<TodoSearchResultRow> for proto::v1::todos::TodoSearchResult {
+  fn from(row: TodoSearchResultRow) -> Self {
+    proto::v1::todos::TodoSearchResult {
+      todo: Some(row.todo.into()),
+      rank: row.rank,
+      title_snippet: row.title_snippet,
+      description_snippet: row.description_snippet,
+    }
+  }
+}
+
+/// Search the todos owned by the caller, best matches first. The query is
+/// parsed with `websearch_to_tsquery` in the search language of each todo, so
+/// that it is stemmed in the same way as the todo, and it can contain quoted
+/// phrases, `or` and `-`. The todos can be filtered in the same
+/// ways as by `ListTodos`. If the query is empty, then an `INVALID_ARGUMENT`
+/// status is returned.
+///
+/// # Arguments
+///
+/// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, whose todos will be searched.
+/// * `request` - The request containing the query, filters and page.
+///
+/// # Returns
+///
+/// A `SearchTodosResponse` containing a page of results, and the token of the
+/// next page if there is one.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn search_todos(
+  pool: PgPool,
+  principal: Principal,
+  request: proto::v1::todos::SearchTodosRequest,
+) -> anyhow::Result<proto::v1::todos::SearchTodosResponse> {
+  if request.query.trim().is_empty() {
+    return Err(Status::invalid_argument("query cannot be empty").into());
+  }
+  let filters = TodoFilters::parse(&proto::v1::todos::ListTodosRequest {
+    parent: request.parent.clone(),
+    due_after: request.due_after,
+    due_before: request.due_before,
+    reminder_after: request.reminder_after,
+    reminder_before: request.reminder_before,
+    completed_after: request.completed_after,
+    completed_before: request.completed_before,
+    labels: request.labels.clone(),
+    ..Default::default()
+  })?;
+  // The results are ranked rather than ordered by a unique column, so the page
+  // token is the number of results before the page.
+  let (page_size, offset) = parse_page(request.page_size, &request.page_token)?;
+  let offset = offset.unwrap_or_default();
+  if offset < 0 {
+    return Err(Status::invalid_argument("Invalid page_token").into());
+  }
+
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+
+  filters.check_list(&mut transaction, &principal).await?;
+
+  // One more result than the page size is fetched, to find out whether there
+  // is another page.
+  let mut query = QueryBuilder::new(format!(
+    r#"
+    select todos_view.*,
+           ts_rank(search_vector, search_query) as rank,
+           ts_headline(search_language, {}, search_query, "#,
+    escape_html("title")
+  ));
+  query.push_bind(TITLE_HEADLINE_OPTIONS);
+  query.push(format!(
+    r#") as title_snippet,
+           ts_headline(search_language, {}, search_query, "#,
+    escape_html("description")
+  ));
+  query.push_bind(DESCRIPTION_HEADLINE_OPTIONS);
+  query.push(
+    r#") as description_snippet
+    from todos_view
+    join (
+      select owner_id,
+             todo_id,
+             search_language,
+             search_vector,
+             websearch_to_tsquery(search_language, "#,
+  );
+  query.push_bind(&request.query);
+  query.push(
+    r#") as search_query
+      from todos
+    ) as searched using (owner_id, todo_id)
+    where search_vector @@ search_query
+      and owner_id = "#,
+  );
+  query.push_bind(&principal.subject);
+  filters.push_conditions(&mut query);
+  query.push(" order by rank desc, todo_id limit ");
+  query.push_bind(page_size + 1);
+  query.push(" offset ");
+  query.push_bind(offset);
+
+  let mut rows = query
+    .build_query_as::<TodoSearchResultRow>()
+    .fetch_all(&mut *transaction)
+    .await?;
+
+  transaction.commit().await?;
+
+  let next_page_token =
+    get_next_page_token(&mut rows, page_size, |_| offset + page_size);
+
+  Ok(proto::v1::todos::SearchTodosResponse {
+    results: rows.into_iter().map(|row| row.into()).collect(),
+    next_page_token,
+  })
+}
@@ -0,0 +1,300 @@
+//! # Todo Series
+//!
+//! This module contains the functions for recurring todos, each of which is an
+//! occurrence of a series in the `todo_series` table. The series holds the
+//! recurrence rule and the fields that the next occurrence is created with, so
+//! that changes to a single occurrence do not carry over to the next.
+use crate::auth::Principal;
+use crate::recurrence::RecurrenceRule;
+use crate::services::todos::common::get_todo_row;
+use crate::services::todos::common::TodoRow;
+use crate::services::todos::labels::copy_todo_labels;
+use sqlx::query;
+use sqlx::types::time::OffsetDateTime;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::Status;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// The fields of an update that apply to every future occurrence of a series.
+///
+/// # Fields
+///
+/// * `title` - Whether the title was updated.
+/// * `description` - Whether the description was updated.
+/// * `priority` - Whether the priority was updated.
+/// * `reminder_time` - Whether the reminder time was updated, which changes
+///   how long before each occurrence's due time the reminder is.
+/// * `restart` - Whether the due time or recurrence rule was updated, which
+///   restarts the series from the updated occurrence.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SeriesUpdate {
+  pub title: bool,
+  pub description: bool,
+  pub priority: bool,
+  pub reminder_time: bool,
+  pub restart: bool,
+}
+
+/// Parse the recurrence rule of a todo, which is empty if the todo does not
+/// repeat. If the rule is not valid, then an `INVALID_ARGUMENT` status is
+/// returned.
+pub fn parse_recurrence_rule(
+  rule: &str,
+) -> Result<Option<RecurrenceRule>, Status> {
+  if rule.is_empty() {
+    return Ok(None);
+  }
+
+  rule.parse().map(Some).map_err(|e| {
+    Status::invalid_argument(format!("Invalid recurrence_rule: {}", e))
+  })
+}
+
+/// Check that a recurring todo has a due time, which the recurrence rule is
+/// counted from.
+pub fn check_recurring_todo(row: &TodoRow) -> Result<(), Status> {
+  if !row.recurrence_rule.is_empty() && row.due_time.is_none() {
+    return Err(Status::invalid_argument(
+      "A todo with a recurrence_rule must have a due_time",
+    ));
+  }
+
+  Ok(())
+}
+
+/// Start a series with the given todo as its first occurrence, and return the
+/// todo with its series ID set.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn start_series(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+) -> anyhow::Result<TodoRow> {
+  query!(
+    r#"
+    with series as (
+      insert into todo_series (
+        owner_id,
+        title,
+        description,
+        recurrence_rule,
+        priority,
+        start_time,
+        reminder_offset
+      )
+      select owner_id,
+             title,
+             description,
+             recurrence_rule,
+             priority,
+             due_time,
+             due_time - reminder_time
+      from todos
+      where todo_id = $1
+        and owner_id = $2
+      returning series_id, start_time
+    )
+    update todos
+    set series_id = series.series_id,
+        occurrence_time = series.start_time
+    from series
+    where todos.todo_id = $1
+      and todos.owner_id = $2
+    "#,
+    todo_id,
+    principal.subject
+  )
+  .execute(&mut **transaction)
+  .await?;
+
+  get_todo_row(transaction, principal, todo_id).await
+}
+
+/// Apply an update of the given todo to its series, and to the occurrences of
+/// the series after it that have already been created.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn update_series(
+  transaction: &mut Transaction<'static, Postgres>,
+  row: &TodoRow,
+  update: SeriesUpdate,
+) -> anyhow::Result<()> {
+  let series = query!(
+    r#"
+    update todo_series
+    set title = case when $2 then $3 else title end,
+        description = case when $4 then $5 else description end,
+        reminder_offset = case
+          when $6 then $7::timestamptz - $8::timestamptz
+          else reminder_offset
+        end,
+        recurrence_rule = case when $9 then $10 else recurrence_rule end,
+        start_time = case when $9 then coalesce($7, start_time) else start_time end,
+        priority = case when $11 then $12 else priority end
+    where series_id = $1
+    returning start_time
+    "#,
+    row.series_id,
+    update.title,
+    row.title,
+    update.description,
+    row.description,
+    update.reminder_time,
+    row.due_time,
+    row.reminder_time,
+    update.restart,
+    row.recurrence_rule,
+    update.priority,
+    row.priority
+  )
+  .fetch_one(&mut **transaction)
+  .await?;
+
+  // A restarted series is counted from this occurrence.
+  let occurrence_time = query!(
+    r#"
+    update todos
+    set occurrence_time = case when $2 then $3 else occurrence_time end
+    where todo_id = $1
+      and owner_id = $4
+    returning occurrence_time as "occurrence_time!"
+    "#,
+    row.todo_id,
+    update.restart,
+    series.start_time,
+    row.owner_id
+  )
+  .fetch_one(&mut **transaction)
+  .await?
+  .occurrence_time;
+
+  query!(
+    r#"
+    update todos
+    set title = case when $3 then $4 else title end,
+        description = case when $5 then $6 else description end,
+        recurrence_rule = $7,
+        priority = case when $8 then $9 else priority end
+    where series_id = $1
+      and occurrence_time > $2
+      and not completed
+    "#,
+    row.series_id,
+    occurrence_time,
+    update.title,
+    row.title,
+    update.description,
+    row.description,
+    row.recurrence_rule,
+    update.priority,
+    row.priority
+  )
+  .execute(&mut **transaction)
+  .await?;
+
+  Ok(())
+}
+
+/// Create the occurrence of the series that follows the given todo, which has
+/// just been completed, and return it. Occurrences that have already passed
+/// are skipped, so the next occurrence is the first that is after both the
+/// completed one and the current time. The next occurrence is in the same
+/// list, has the same labels and is searched in the same language as the
+/// completed one. Returns `None` if the series has ended, or the next
+/// occurrence already exists.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn create_next_occurrence(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+) -> anyhow::Result<Option<TodoRow>> {
+  let series = query!(
+    r#"
+    select todo_series.series_id,
+           todo_series.recurrence_rule,
+           todo_series.start_time,
+           todos.occurrence_time as "occurrence_time!"
+    from todos
+    join todo_series on todo_series.series_id = todos.series_id
+    where todos.todo_id = $1
+      and todos.owner_id = $2
+    "#,
+    todo_id,
+    principal.subject
+  )
+  .fetch_optional(&mut **transaction)
+  .await?;
+
+  let Some(series) = series else {
+    return Ok(None);
+  };
+  let Some(rule) = parse_recurrence_rule(&series.recurrence_rule)? else {
+    return Ok(None);
+  };
+  let after = series.occurrence_time.max(OffsetDateTime::now_utc());
+  let Some(next_time) = rule.next_after(series.start_time, after) else {
+    return Ok(None);
+  };
+
+  let next_todo_id = query!(
+    r#"
+    insert into todos (
+      todo_id,
+      title,
+      description,
+      owner_id,
+      due_time,
+      reminder_time,
+      recurrence_rule,
+      priority,
+      series_id,
+      occurrence_time,
+      list_id,
+      search_language
+    )
+    select $1,
+           title,
+           description,
+           owner_id,
+           $2::timestamptz,
+           $2::timestamptz - reminder_offset,
+           recurrence_rule,
+           priority,
+           series_id,
+           $2::timestamptz,
+           (
+             select list_id
+             from todos
+             where todo_id = $4
+               and owner_id = todo_series.owner_id
+           ),
+           (
+             select search_language
+             from todos
+             where todo_id = $4
+               and owner_id = todo_series.owner_id
+           )
+    from todo_series
+    where series_id = $3
+    on conflict (series_id, occurrence_time) do nothing
+    returning todo_id
+    "#,
+    Uuid::new_v4().to_string(),
+    next_time,
+    series.series_id,
+    todo_id
+  )
+  .fetch_optional(&mut **transaction)
+  .await?
+  .map(|row| row.todo_id);
+
+  let Some(next_todo_id) = next_todo_id else {
+    return Ok(None);
+  };
+  let mut row = get_todo_row(transaction, principal, &next_todo_id).await?;
+  row.labels =
+    copy_todo_labels(transaction, principal, todo_id, &row.todo_id).await?;
+
+  Ok(Some(row))
+}
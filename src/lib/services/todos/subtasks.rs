@@ -0,0 +1,340 @@
+//! # Subtasks
+//!
+//! This module contains the functions that nest todos as subtasks of other
+//! todos. A subtask refers to its parent with `parent_todo_id`, so the todos
+//! of an owner form a forest, which these functions keep free of cycles and
+//! no deeper than the configured maximum. The database deletes the subtasks
+//! of a todo with it.
+use crate::auth::Principal;
+use crate::services::todos::common::lock_owner_todos;
+use crate::services::todos::common::TodoRow;
+use anyhow::anyhow;
+use sqlx::query;
+use sqlx::query_as;
+use sqlx::query_scalar;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use std::collections::HashMap;
+use std::str::FromStr;
+use tonic::Status;
+use tracing::instrument;
+
+/// What completing a todo that has subtasks does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubtaskCompletion {
+  /// Todos are completed independently of their subtasks.
+  #[default]
+  Independent,
+  /// A todo can only be completed once all of its subtasks are, and reopening
+  /// a subtask reopens the todos above it.
+  RequireSubtasks,
+  /// Completing a todo also completes all of its subtasks.
+  CompleteSubtasks,
+}
+
+impl FromStr for SubtaskCompletion {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "independent" => Ok(SubtaskCompletion::Independent),
+      "require-subtasks" => Ok(SubtaskCompletion::RequireSubtasks),
+      "complete-subtasks" => Ok(SubtaskCompletion::CompleteSubtasks),
+      _ => Err(anyhow!(
+        "Expected independent, require-subtasks or complete-subtasks, got {}",
+        value
+      )),
+    }
+  }
+}
+
+/// Check that a subtask does not repeat, as its occurrences would all share
+/// the same parent. If it does, then an `INVALID_ARGUMENT` status is returned.
+pub fn check_subtask(row: &TodoRow) -> Result<(), Status> {
+  if row.parent_todo_id.is_some() && !row.recurrence_rule.is_empty() {
+    return Err(Status::invalid_argument("A subtask cannot repeat"));
+  }
+
+  Ok(())
+}
+
+/// Check that a todo can become a subtask of the given parent todo, and return
+/// the ID of the parent's list, which the subtask must be in. The todo is
+/// `None` if it is being created. This locks the caller's todos until the
+/// transaction ends, so that concurrent requests cannot create a cycle
+/// between them.
+///
+/// If the caller does not have the parent, then a `NOT_FOUND` status is
+/// returned. If the parent is the todo itself or one of its subtasks, then an
+/// `INVALID_ARGUMENT` status is returned, and if the todo and its subtasks
+/// would be nested deeper than `max_depth`, then a `FAILED_PRECONDITION`
+/// status is returned.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn check_parent_todo(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: Option<&str>,
+  parent_todo_id: &str,
+  max_depth: u32,
+) -> anyhow::Result<String> {
+  lock_owner_todos(transaction, principal).await?;
+
+  // The parent and the todos above it, starting with the parent.
+  let ancestors = query!(
+    r#"
+    with recursive ancestors (todo_id, parent_todo_id, list_id) as (
+      select todo_id, parent_todo_id, list_id
+      from todos
+      where todo_id = $1
+        and owner_id = $2
+      union all
+      select todos.todo_id, todos.parent_todo_id, todos.list_id
+      from todos
+      join ancestors on todos.todo_id = ancestors.parent_todo_id
+      where todos.owner_id = $2
+    )
+    select todo_id as "todo_id!", list_id as "list_id!"
+    from ancestors
+    "#,
+    parent_todo_id,
+    principal.subject
+  )
+  .fetch_all(&mut **transaction)
+  .await?;
+
+  let Some(parent) = ancestors.first() else {
+    return Err(
+      Status::not_found(format!(
+        "Parent todo with id {} not found",
+        parent_todo_id
+      ))
+      .into(),
+    );
+  };
+  let Some(todo_id) = todo_id else {
+    check_depth(ancestors.len(), max_depth)?;
+    return Ok(parent.list_id.clone());
+  };
+  if ancestors.iter().any(|ancestor| ancestor.todo_id == todo_id) {
+    return Err(
+      Status::invalid_argument(
+        "A todo cannot be a subtask of itself or of its own subtasks",
+      )
+      .into(),
+    );
+  }
+
+  // The number of levels of subtasks below the todo, which move with it.
+  let height = query_scalar!(
+    r#"
+    with recursive subtasks (todo_id, depth) as (
+      select todo_id, 0
+      from todos
+      where todo_id = $1
+        and owner_id = $2
+      union all
+      select todos.todo_id, subtasks.depth + 1
+      from todos
+      join subtasks on todos.parent_todo_id = subtasks.todo_id
+      where todos.owner_id = $2
+    )
+    select coalesce(max(depth), 0) as "height!"
+    from subtasks
+    "#,
+    todo_id,
+    principal.subject
+  )
+  .fetch_one(&mut **transaction)
+  .await?;
+  check_depth(ancestors.len() + height as usize, max_depth)?;
+
+  Ok(parent.list_id.clone())
+}
+
+/// Check that subtasks nested to the given depth are allowed. A subtask of a
+/// todo that is not itself a subtask has a depth of 1.
+fn check_depth(depth: usize, max_depth: u32) -> Result<(), Status> {
+  if depth > max_depth as usize {
+    return Err(Status::failed_precondition(format!(
+      "Subtasks cannot be nested more than {} deep",
+      max_depth
+    )));
+  }
+
+  Ok(())
+}
+
+/// Check that every subtask below the todo is complete, so that the todo can
+/// be completed. If not, then a `FAILED_PRECONDITION` status is returned.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn check_subtasks_completed(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+) -> anyhow::Result<()> {
+  let incomplete = query_scalar!(
+    r#"
+    with recursive subtasks (todo_id, completed) as (
+      select todo_id, completed
+      from todos
+      where parent_todo_id = $1
+        and owner_id = $2
+      union all
+      select todos.todo_id, todos.completed
+      from todos
+      join subtasks on todos.parent_todo_id = subtasks.todo_id
+      where todos.owner_id = $2
+    )
+    select count(*) as "count!"
+    from subtasks
+    where not completed
+    "#,
+    todo_id,
+    principal.subject
+  )
+  .fetch_one(&mut **transaction)
+  .await?;
+
+  if incomplete > 0 {
+    return Err(
+      Status::failed_precondition(format!(
+        "Todo with id {} has {} incomplete subtasks",
+        todo_id, incomplete
+      ))
+      .into(),
+    );
+  }
+
+  Ok(())
+}
+
+/// Complete every subtask below the todo.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn complete_subtasks(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+) -> anyhow::Result<()> {
+  query!(
+    r#"
+    with recursive subtasks (todo_id) as (
+      select todo_id
+      from todos
+      where parent_todo_id = $1
+        and owner_id = $2
+      union all
+      select todos.todo_id
+      from todos
+      join subtasks on todos.parent_todo_id = subtasks.todo_id
+      where todos.owner_id = $2
+    )
+    update todos
+    set completed = true
+    where todo_id in (select todo_id from subtasks)
+      and owner_id = $2
+      and not completed
+    "#,
+    todo_id,
+    principal.subject
+  )
+  .execute(&mut **transaction)
+  .await?;
+
+  Ok(())
+}
+
+/// Reopen every completed todo above the given todo, which is incomplete, so
+/// that no todo is complete while one of its subtasks is not.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn reopen_parent_todos(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+) -> anyhow::Result<()> {
+  query!(
+    r#"
+    with recursive ancestors (todo_id, parent_todo_id) as (
+      select todo_id, parent_todo_id
+      from todos
+      where todo_id = $1
+        and owner_id = $2
+      union all
+      select todos.todo_id, todos.parent_todo_id
+      from todos
+      join ancestors on todos.todo_id = ancestors.parent_todo_id
+      where todos.owner_id = $2
+    )
+    update todos
+    set completed = false
+    where todo_id in (select todo_id from ancestors where todo_id <> $1)
+      and owner_id = $2
+      and completed
+    "#,
+    todo_id,
+    principal.subject
+  )
+  .execute(&mut **transaction)
+  .await?;
+
+  Ok(())
+}
+
+/// Get every subtask below the todo, in depth-first order with the subtasks of
+/// each todo ordered by their position.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_subtasks(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  todo_id: &str,
+) -> anyhow::Result<Vec<TodoRow>> {
+  let rows: Vec<TodoRow> = query_as(
+    r#"
+    with recursive subtasks (todo_id) as (
+      select todo_id
+      from todos
+      where parent_todo_id = $1
+        and owner_id = $2
+      union all
+      select todos.todo_id
+      from todos
+      join subtasks on todos.parent_todo_id = subtasks.todo_id
+      where todos.owner_id = $2
+    )
+    select *
+    from todos_view
+    where todo_id in (select todo_id from subtasks)
+      and owner_id = $2
+    "#,
+  )
+  .bind(todo_id)
+  .bind(&principal.subject)
+  .fetch_all(&mut **transaction)
+  .await?;
+
+  // Positions sort byte by byte, which is how Rust compares strings. The
+  // siblings are sorted in reverse, so that the first is popped first.
+  let mut children = HashMap::<String, Vec<TodoRow>>::new();
+  for row in rows {
+    let parent_todo_id = row.parent_todo_id.clone().unwrap_or_default();
+    children.entry(parent_todo_id).or_default().push(row);
+  }
+  for siblings in children.values_mut() {
+    siblings.sort_by(|a, b| {
+      (b.position.as_str(), b.todo_id.as_str())
+        .cmp(&(a.position.as_str(), a.todo_id.as_str()))
+    });
+  }
+
+  // Each todo's subtasks are pushed onto the stack after it is visited, so
+  // that they are visited before its next sibling.
+  let mut subtasks = Vec::new();
+  let mut stack = children.remove(todo_id).unwrap_or_default();
+  while let Some(row) = stack.pop() {
+    if let Some(mut siblings) = children.remove(&row.todo_id) {
+      stack.append(&mut siblings);
+    }
+    subtasks.push(row);
+  }
+
+  Ok(subtasks)
+}
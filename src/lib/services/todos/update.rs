@@ -2,29 +2,92 @@
 //! # Update Todo
 //!
 //! This module contains the implementation for updating a todo.
+use crate::auth::Principal;
+use crate::common::proto_timestamp_to_sql_datetime;
+use crate::config::TodosConfig;
+use crate::database::begin_tenant_transaction;
 use crate::proto;
-use crate::services::todos::common::TodoRow;
+use crate::proto::v1::todos::RecurrenceScope;
+use crate::services::todos::common::get_todo_row;
+use crate::services::todos::common::parse_priority;
+use crate::services::todos::common::parse_todo_reference;
+use crate::services::todos::history::set_actor;
+use crate::services::todos::labels::parse_labels;
+use crate::services::todos::labels::set_todo_labels;
+use crate::services::todos::series::check_recurring_todo;
+use crate::services::todos::series::create_next_occurrence;
+use crate::services::todos::series::parse_recurrence_rule;
+use crate::services::todos::series::start_series;
+use crate::services::todos::series::update_series;
+use crate::services::todos::series::SeriesUpdate;
+use crate::services::todos::subtasks::check_parent_todo;
+use crate::services::todos::subtasks::check_subtask;
+use crate::services::todos::subtasks::check_subtasks_completed;
+use crate::services::todos::subtasks::complete_subtasks;
+use crate::services::todos::subtasks::reopen_parent_todos;
+use crate::services::todos::subtasks::SubtaskCompletion;
 use crate::update_mask_handler::UpdateMaskHandler;
-use anyhow::anyhow;
-use sqlx::query_as;
+use sqlx::query;
 use sqlx::PgPool;
+use sqlx::Postgres;
+use sqlx::Transaction;
+use tonic::Status;
+use tracing::instrument;
 
-/// Update a todo in the database.
+/// Update a todo in the database. If the todo is an occurrence of a recurring
+/// series, then the scope of the request decides whether the update also
+/// applies to the future occurrences of the series, and completing the todo
+/// creates the next occurrence. Completing a todo that has subtasks may
+/// require or complete them, depending on the configuration.
 ///
 /// # Arguments
 ///
 /// * `pool` - The database pool to use.
+/// * `principal` - The authenticated caller, who must own the todo.
+/// * `config` - How subtasks are nested and completed.
 /// * `request` - The request containing the todo to update.
 ///
 /// # Returns
 ///
-/// A `UpdateTodoResponse` containing the updated todo.
+/// A `UpdateTodoResponse` containing the updated todo, and the next occurrence
+/// if one was created.
 ///
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn update_todo(
   pool: PgPool,
+  principal: Principal,
+  config: TodosConfig,
   request: proto::v1::todos::UpdateTodoRequest,
 ) -> anyhow::Result<proto::v1::todos::UpdateTodoResponse> {
-  let params = request.todo.ok_or(anyhow!("Todo not provided"))?;
+  // Run the query in a transaction scoped to the caller's tenant, so that the
+  // database only allows access to that tenant's todos.
+  let mut transaction =
+    begin_tenant_transaction(&pool, &principal.tenant_id).await?;
+  set_actor(&mut transaction, &principal).await?;
+
+  let response =
+    apply_todo_update(&mut transaction, &principal, &config, request).await?;
+
+  transaction.commit().await?;
+
+  Ok(response)
+}
+
+/// Apply an update of a todo in the given transaction, which must be scoped
+/// to the caller's tenant and have the caller set as its actor. The checks and
+/// effects of the update are the same as those of `update_todo`.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn apply_todo_update(
+  transaction: &mut Transaction<'static, Postgres>,
+  principal: &Principal,
+  config: &TodosConfig,
+  request: proto::v1::todos::UpdateTodoRequest,
+) -> anyhow::Result<proto::v1::todos::UpdateTodoResponse> {
+  let all_future = request.scope() == RecurrenceScope::AllFuture;
+  let params = request
+    .todo
+    .ok_or(Status::invalid_argument("Todo not provided"))?;
+  let (todo_id, list_id) = parse_todo_reference(&params.todo_id, &params.name)?;
 
   // We require an update mask to ensure that the update behaviour remains
   // explicit. Otherwise, the default behaviour would be to update all fields,
@@ -32,42 +95,221 @@ pub async fn update_todo(
   // in the future.
   let update_mask_paths = request
     .update_mask
-    .ok_or(anyhow!("Update mask not provided"))?
+    .ok_or(Status::invalid_argument("Update mask not provided"))?
     .paths;
 
   // The update mask handler makes it slightly more convenient to extract the
   // params we want to update.
   let update_mask_handler = UpdateMaskHandler::new(&params, update_mask_paths);
 
-  // Update the todo in the database, returning the result as a TodoRow.
+  // The due and reminder times can be cleared, so unlike the other fields we
+  // need to tell a time that is in the mask but unset from one that is not in
+  // the mask at all.
+  let due_time = update_mask_handler
+    .get_param("due_time", |p| &p.due_time)
+    .map(|time| {
+      time
+        .map(|time| proto_timestamp_to_sql_datetime("due_time", &time))
+        .transpose()
+    })
+    .transpose()?;
+  let reminder_time = update_mask_handler
+    .get_param("reminder_time", |p| &p.reminder_time)
+    .map(|time| {
+      time
+        .map(|time| proto_timestamp_to_sql_datetime("reminder_time", &time))
+        .transpose()
+    })
+    .transpose()?;
+  let priority = update_mask_handler
+    .get_param("priority", |p| &p.priority)
+    .map(parse_priority)
+    .transpose()?;
+  let labels = update_mask_handler
+    .get_param("labels", |p| &p.labels)
+    .map(|labels| parse_labels(&labels))
+    .transpose()?;
+  let recurrence_rule =
+    update_mask_handler.get_param("recurrence_rule", |p| &p.recurrence_rule);
+  if let Some(rule) = &recurrence_rule {
+    parse_recurrence_rule(rule)?;
+  }
+  // An empty parent makes the todo a top-level todo again.
+  let parent_todo_id =
+    update_mask_handler.get_param("parent_todo_id", |p| &p.parent_todo_id);
+  let completed = update_mask_handler.get_param("completed", |p| &p.completed);
+
+  // Lock the todo before updating it, so that we know whether this update is
+  // the one that completed it, even if others are updating it at once.
+  let current = query!(
+    r#"
+    select completed, series_id, list_id
+    from todos
+    where todo_id = $1
+      and owner_id = $2
+      and ($3::text is null or list_id = $3)
+    for update
+    "#,
+    todo_id,
+    principal.subject,
+    list_id
+  )
+  .fetch_optional(&mut **transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Todo with id {} not found",
+    todo_id
+  )))?;
+
+  // The rule belongs to the whole series, so it cannot differ between
+  // occurrences.
+  if recurrence_rule.is_some() && current.series_id.is_some() && !all_future {
+    return Err(
+      Status::invalid_argument(
+        "recurrence_rule can only be updated with RECURRENCE_SCOPE_ALL_FUTURE",
+      )
+      .into(),
+    );
+  }
+
+  // A todo can only become a subtask of a todo in the same list, and not of
+  // one of its own subtasks.
+  if let Some(parent_todo_id) =
+    parent_todo_id.as_deref().filter(|id| !id.is_empty())
+  {
+    let parent_list_id = check_parent_todo(
+      transaction,
+      principal,
+      Some(&todo_id),
+      parent_todo_id,
+      config.max_subtask_depth,
+    )
+    .await?;
+    if parent_list_id != current.list_id {
+      return Err(
+        Status::invalid_argument(
+          "A subtask must be in the same list as its parent todo",
+        )
+        .into(),
+      );
+    }
+  }
+
+  let completing = completed == Some(true) && !current.completed;
+  if completing
+    && config.subtask_completion == SubtaskCompletion::RequireSubtasks
+  {
+    check_subtasks_completed(transaction, principal, &todo_id).await?;
+  }
+
+  // The labels are replaced before the todo is updated, so that the updated
+  // todo is returned with its new labels.
+  if let Some(labels) = &labels {
+    set_todo_labels(transaction, principal, &todo_id, labels).await?;
+  }
+
+  // Update the todo in the database, and get it back as a TodoRow.
   // Note that we use coalesce to handle optional parameters.  If a parameter is
   // not provided in the update mask, then the existing value will be used.
   // This is important because it means that we don't accidentally overwrite
   // values that the client didn't intend to change. For example, if the client
   // only wants to update the title, then the description and completed fields
-  // will remain unchanged.
-  let todo = query_as!(
-    TodoRow,
+  // will remain unchanged. The due and reminder times are only changed if the
+  // matching flag is set, in which case they may be set to null.
+  //
+  // The todo must also be owned by the caller. If it is not, then no row is
+  // updated and we report the todo as not found.
+  query!(
     r#"
     update todos
     set title = coalesce($1, todos.title),
         description = coalesce($2, todos.description),
-        completed = coalesce($3, todos.completed)
+        completed = coalesce($3, todos.completed),
+        due_time = case when $6 then $7 else todos.due_time end,
+        reminder_time = case when $8 then $9 else todos.reminder_time end,
+        recurrence_rule = coalesce($10, todos.recurrence_rule),
+        priority = coalesce($11, todos.priority),
+        parent_todo_id = case when $12 then nullif($13, '') else todos.parent_todo_id end
     where todo_id = $4
-    returning *
+      and owner_id = $5
+    returning todo_id
     "#,
     update_mask_handler.get_param("title", |p| &p.title),
     update_mask_handler.get_param("description", |p| &p.description),
-    update_mask_handler.get_param("completed", |p| &p.completed),
-    params.todo_id
+    completed,
+    todo_id,
+    principal.subject,
+    due_time.is_some(),
+    due_time.flatten(),
+    reminder_time.is_some(),
+    reminder_time.flatten(),
+    recurrence_rule.as_ref(),
+    priority,
+    parent_todo_id.is_some(),
+    parent_todo_id.as_deref()
   )
-  .fetch_one(&pool)
-  .await?;
+  .fetch_optional(&mut **transaction)
+  .await?
+  .ok_or(Status::not_found(format!(
+    "Todo with id {} not found",
+    todo_id
+  )))?;
+  let todo = get_todo_row(transaction, principal, &todo_id).await?;
+
+  // A recurring todo needs a due time to count its occurrences from, but a
+  // single occurrence can have its due time cleared.
+  if recurrence_rule.is_some() || (all_future && due_time.is_some()) {
+    check_recurring_todo(&todo)?;
+  }
+  check_subtask(&todo)?;
+
+  match config.subtask_completion {
+    SubtaskCompletion::CompleteSubtasks if completing => {
+      complete_subtasks(transaction, principal, &todo.todo_id).await?
+    }
+    SubtaskCompletion::RequireSubtasks
+      if !todo.completed
+        && todo.parent_todo_id.is_some()
+        && (completed.is_some() || parent_todo_id.is_some()) =>
+    {
+      reopen_parent_todos(transaction, principal, &todo.todo_id).await?
+    }
+    _ => {}
+  }
+
+  let todo = match &todo.series_id {
+    // Setting a rule on a todo that does not repeat yet starts a series.
+    None if !todo.recurrence_rule.is_empty() => {
+      start_series(transaction, principal, &todo.todo_id).await?
+    }
+    Some(_) if all_future => {
+      let update = SeriesUpdate {
+        title: update_mask_handler
+          .get_param("title", |p| &p.title)
+          .is_some(),
+        description: update_mask_handler
+          .get_param("description", |p| &p.description)
+          .is_some(),
+        priority: priority.is_some(),
+        reminder_time: reminder_time.is_some(),
+        restart: due_time.is_some() || recurrence_rule.is_some(),
+      };
+      update_series(transaction, &todo, update).await?;
+      todo
+    }
+    _ => todo,
+  };
+
+  let next_occurrence = match todo.completed && !current.completed {
+    true => create_next_occurrence(transaction, principal, &todo.todo_id).await?,
+    false => None,
+  };
 
   // Return the todo wrapped in a protobuf response. The TodoRow is
   // automatically converted to a protobuf Todo by the `into()` method because
   // we have defined the Into trait for this conversion.
   Ok(proto::v1::todos::UpdateTodoResponse {
     todo: Some(todo.into()),
+    next_occurrence: next_occurrence.map(|row| row.into()),
   })
 }
@@ -0,0 +1,270 @@
+//! This module sets up structured logging and tracing with the `tracing`
+//! library, and contains a tower layer that creates a span for each RPC.
+//!
+//! Logs are written to stdout, as plain text or as one JSON object per line,
+//! depending on the `logging.format` configuration key. If
+//! `logging.otlp_endpoint` is set, then spans are also exported to an
+//! OpenTelemetry collector at that endpoint using OTLP over gRPC. The
+//! `logging.filter` key filters both. See [`crate::config::LoggingConfig`].
+//!
+//! Each RPC gets a request ID, taken from the `x-request-id` metadata entry if
+//! the client sent one, or generated otherwise. It is recorded on the RPC's
+//! span, so that every log line for the RPC can be correlated, and echoed back
+//! in the response metadata so that clients can report it.
+
+use crate::config::LoggingConfig;
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::runtime;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use std::str::FromStr;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Instant;
+use tonic::body::BoxBody;
+use tonic::codegen::http;
+use tonic::codegen::http::HeaderValue;
+use tonic::server::NamedService;
+use tonic::transport::server::TcpConnectInfo;
+use tower::Layer;
+use tracing::field::Empty;
+use tracing::info;
+use tracing::info_span;
+use tracing::warn;
+use tracing::Instrument;
+use tracing::Span;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+use uuid::Uuid;
+
+/// The metadata key that carries the ID of a request.
+pub const REQUEST_ID_METADATA_KEY: &str = "x-request-id";
+
+/// The format of the logs written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+  /// Human readable text, for local development.
+  #[default]
+  Text,
+  /// One JSON object per line, for log aggregators.
+  Json,
+}
+
+impl FromStr for LogFormat {
+  type Err = anyhow::Error;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "text" => Ok(LogFormat::Text),
+      "json" => Ok(LogFormat::Json),
+      _ => Err(anyhow!(
+        "Expected the log format to be text or json, got {}",
+        value
+      )),
+    }
+  }
+}
+
+/// Flushes any spans that have not been exported when dropped, so it should
+/// be held until the application exits.
+#[must_use = "spans are only flushed when the guard is dropped"]
+pub struct TelemetryGuard {
+  provider: Option<TracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+  fn drop(&mut self) {
+    if let Some(provider) = self.provider.take() {
+      if let Err(e) = provider.shutdown() {
+        warn!(error = %e, "Failed to flush spans");
+      }
+    }
+  }
+}
+
+/// Initialize the global tracing subscriber from the configuration, reporting
+/// spans as coming from the service with the given name. This must be called
+/// from within a tokio runtime, because the OTLP exporter sends spans in a
+/// background task.
+pub fn init_telemetry(
+  config: &LoggingConfig,
+  service_name: &str,
+) -> anyhow::Result<TelemetryGuard> {
+  let format = config.format;
+  let provider = match &config.otlp_endpoint {
+    Some(endpoint) => Some(create_tracer_provider(endpoint, service_name)?),
+    None => None,
+  };
+
+  let filter = EnvFilter::try_new(&config.filter)?;
+  let text_layer =
+    (format == LogFormat::Text).then(tracing_subscriber::fmt::layer);
+  let json_layer = (format == LogFormat::Json).then(|| {
+    tracing_subscriber::fmt::layer()
+      .json()
+      .with_current_span(true)
+      .with_span_list(true)
+  });
+  let otel_layer = provider.as_ref().map(|provider| {
+    tracing_opentelemetry::layer()
+      .with_tracer(provider.tracer(service_name.to_string()))
+  });
+
+  tracing_subscriber::registry()
+    .with(filter)
+    .with(text_layer)
+    .with(json_layer)
+    .with(otel_layer)
+    .try_init()?;
+
+  Ok(TelemetryGuard { provider })
+}
+
+/// Create a tracer provider that exports spans in batches to the OTLP
+/// collector at the given endpoint, e.g. `http://localhost:4317`.
+fn create_tracer_provider(
+  endpoint: &str,
+  service_name: &str,
+) -> anyhow::Result<TracerProvider> {
+  let exporter = opentelemetry_otlp::SpanExporter::builder()
+    .with_tonic()
+    .with_endpoint(endpoint)
+    .build()?;
+
+  Ok(
+    TracerProvider::builder()
+      .with_batch_exporter(exporter, runtime::Tokio)
+      .with_resource(Resource::new([KeyValue::new(
+        "service.name",
+        service_name.to_string(),
+      )]))
+      .build(),
+  )
+}
+
+/// Record the ID of the todo that the current RPC acts on, so that the RPC
+/// can be found from the todo.
+pub fn record_todo_id(todo_id: &str) {
+  Span::current().record("todo_id", todo_id);
+}
+
+/// A tower layer that runs each request in a span, and assigns it a request
+/// ID. It should be the outermost layer, so that the span covers
+/// authentication and rate limiting.
+#[derive(Debug, Clone, Default)]
+pub struct TraceLayer;
+
+impl TraceLayer {
+  /// Create a new layer.
+  pub fn new() -> Self {
+    Self
+  }
+}
+
+impl<S> Layer<S> for TraceLayer {
+  type Service = TraceService<S>;
+
+  fn layer(&self, inner: S) -> Self::Service {
+    TraceService { inner }
+  }
+}
+
+/// The service created by the [`TraceLayer`].
+#[derive(Debug, Clone)]
+pub struct TraceService<S> {
+  inner: S,
+}
+
+impl<S, B> tower::Service<http::Request<B>> for TraceService<S>
+where
+  S: tower::Service<http::Request<B>, Response = http::Response<BoxBody>>,
+  S::Future: Send + 'static,
+{
+  type Response = S::Response;
+  type Error = S::Error;
+  type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+  fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+    self.inner.poll_ready(cx)
+  }
+
+  fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+    let request_id = get_request_id(&request);
+    request
+      .headers_mut()
+      .insert(REQUEST_ID_METADATA_KEY, request_id.clone());
+
+    let method = request.uri().path().to_string();
+    let peer = request
+      .extensions()
+      .get::<TcpConnectInfo>()
+      .and_then(|info| info.remote_addr())
+      .map(|address| address.to_string());
+    let span = info_span!(
+      "rpc",
+      otel.name = %method,
+      otel.kind = "server",
+      rpc.system = "grpc",
+      rpc.method = %method,
+      peer = peer,
+      request_id = request_id.to_str().unwrap_or_default(),
+      todo_id = Empty,
+      grpc.code = Empty,
+    );
+
+    let started_at = Instant::now();
+    let future = self.inner.call(request);
+
+    Box::pin(
+      async move {
+        let mut response = future.await?;
+
+        // Unary RPCs that fail have their status in the headers. Successful
+        // RPCs send their status in the trailers, after the span has closed.
+        let code = response
+          .headers()
+          .get("grpc-status")
+          .map(|value| tonic::Code::from_bytes(value.as_bytes()))
+          .unwrap_or(tonic::Code::Ok);
+        Span::current().record("grpc.code", format!("{:?}", code));
+        info!(
+          elapsed_ms = started_at.elapsed().as_secs_f64() * 1000.0,
+          "Finished request"
+        );
+
+        response
+          .headers_mut()
+          .insert(REQUEST_ID_METADATA_KEY, request_id);
+        Ok(response)
+      }
+      .instrument(span),
+    )
+  }
+}
+
+impl<S: NamedService> NamedService for TraceService<S> {
+  const NAME: &'static str = S::NAME;
+}
+
+/// Get the request ID that the client sent, or generate a new one if the
+/// client did not send a valid ID.
+fn get_request_id<B>(request: &http::Request<B>) -> HeaderValue {
+  request
+    .headers()
+    .get(REQUEST_ID_METADATA_KEY)
+    .filter(|value| {
+      value
+        .to_str()
+        .is_ok_and(|value| !value.is_empty() && value.len() <= 128)
+    })
+    .cloned()
+    .unwrap_or_else(|| {
+      HeaderValue::from_str(&Uuid::new_v4().to_string())
+        .expect("UUIDs are valid header values")
+    })
+}
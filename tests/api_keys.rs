@@ -0,0 +1,343 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_api_key;
+use common::with_test_database;
+use sqlx::PgPool;
+use todos_service::auth::Principal;
+use todos_service::proto::v1::api_keys::api_key_service_client::ApiKeyServiceClient;
+use todos_service::proto::v1::api_keys::ApiKey;
+use todos_service::proto::v1::api_keys::CreateApiKeyRequest;
+use todos_service::proto::v1::api_keys::ListApiKeysRequest;
+use todos_service::proto::v1::api_keys::RevokeApiKeyRequest;
+use todos_service::proto::v1::api_keys::RotateApiKeyRequest;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::services::api_keys::create_api_key;
+use todos_service::services::api_keys::ApiKeyServiceHandler;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+const OTHER_USER_ID: &str = "other-user";
+
+#[test]
+pub fn create_and_list_api_keys() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(ApiKeyServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = ApiKeyServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let response = client
+        .create_api_key(CreateApiKeyRequest {
+          api_key: Some(ApiKey {
+            display_name: "test-key".to_string(),
+            scopes: vec!["todos.write".to_string(), "todos.read".to_string()],
+            ..Default::default()
+          }),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+      let api_key = response.api_key.unwrap();
+      assert!(response.key.starts_with(&api_key.key_prefix));
+      assert_eq!(api_key.scopes, vec!["todos.read", "todos.write"]);
+
+      let api_keys = client
+        .list_api_keys(ListApiKeysRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .api_keys;
+      assert_eq!(api_keys.len(), 1);
+      assert_eq!(api_keys[0].api_key_id, api_key.api_key_id);
+
+      // Other users cannot see the key.
+      let mut other_client = ApiKeyServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, OTHER_USER_ID),
+      );
+      let other_api_keys = other_client
+        .list_api_keys(ListApiKeysRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .api_keys;
+      assert_eq!(other_api_keys.len(), 0);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn create_api_key_with_unknown_scope_is_rejected() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(ApiKeyServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = ApiKeyServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let response = client
+        .create_api_key(CreateApiKeyRequest {
+          api_key: Some(ApiKey {
+            display_name: "test-key".to_string(),
+            scopes: vec!["todos.admin".to_string()],
+            ..Default::default()
+          }),
+        })
+        .await;
+      assert_eq!(response.unwrap_err().code(), Code::InvalidArgument);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn api_key_scopes_are_enforced() {
+  with_test_database(|pool| async move {
+    let key = create_test_api_key(&pool, &["todos.read"]).await;
+
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client =
+        TodoServiceClient::with_interceptor(channel, with_api_key(&key));
+
+      let list_response = client.list_todos(ListTodosRequest::default()).await;
+      assert!(list_response.is_ok());
+
+      let create_response = client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            title: "test-title".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await;
+      assert_eq!(create_response.unwrap_err().code(), Code::PermissionDenied);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn api_keys_act_on_behalf_of_their_owner() {
+  with_test_database(|pool| async move {
+    let key = create_test_api_key(&pool, &["todos.read", "todos.write"]).await;
+
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client =
+        TodoServiceClient::with_interceptor(channel, with_api_key(&key));
+
+      let todo = client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            title: "test-title".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.owner_id, TEST_USER_ID);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn api_keys_cannot_manage_api_keys() {
+  with_test_database(|pool| async move {
+    let key = create_test_api_key(&pool, &["todos.read", "todos.write"]).await;
+
+    let (server_future, channel) =
+      create_test_server(ApiKeyServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client =
+        ApiKeyServiceClient::with_interceptor(channel, with_api_key(&key));
+
+      let response = client.list_api_keys(ListApiKeysRequest {}).await;
+      assert_eq!(response.unwrap_err().code(), Code::PermissionDenied);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn revoked_and_rotated_keys_are_rejected() {
+  with_test_database(|pool| async move {
+    let key = create_test_api_key(&pool, &["todos.read"]).await;
+    let other_key = create_test_api_key(&pool, &["todos.read"]).await;
+
+    let (api_key_server_future, api_key_channel) =
+      create_test_server(ApiKeyServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+    let (todo_server_future, todo_channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = ApiKeyServiceClient::with_interceptor(
+        api_key_channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+      let api_keys = client
+        .list_api_keys(ListApiKeysRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .api_keys;
+      let find_api_key_id = |key: &str| {
+        api_keys
+          .iter()
+          .find(|api_key| key.starts_with(&api_key.key_prefix))
+          .unwrap()
+          .api_key_id
+          .clone()
+      };
+
+      let revoked = client
+        .revoke_api_key(RevokeApiKeyRequest {
+          api_key_id: find_api_key_id(&key),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .api_key
+        .unwrap();
+      assert!(revoked.revoked_at.is_some());
+
+      let rotated = client
+        .rotate_api_key(RotateApiKeyRequest {
+          api_key_id: find_api_key_id(&other_key),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+      // Revoked keys cannot be rotated.
+      let rotate_revoked_response = client
+        .rotate_api_key(RotateApiKeyRequest {
+          api_key_id: revoked.api_key_id,
+        })
+        .await;
+      assert_eq!(rotate_revoked_response.unwrap_err().code(), Code::NotFound);
+
+      let list_todos = |key: String| {
+        let mut client = TodoServiceClient::with_interceptor(
+          todo_channel.clone(),
+          with_api_key(&key),
+        );
+        async move { client.list_todos(ListTodosRequest::default()).await }
+      };
+
+      let revoked_response = list_todos(key).await;
+      assert_eq!(revoked_response.unwrap_err().code(), Code::Unauthenticated);
+
+      let replaced_response = list_todos(other_key).await;
+      assert_eq!(replaced_response.unwrap_err().code(), Code::Unauthenticated);
+
+      let rotated_response = list_todos(rotated.key).await;
+      assert!(rotated_response.is_ok());
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = api_key_server_future => panic!("server returned first"),
+        _ = todo_server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+async fn create_test_api_key(pool: &PgPool, scopes: &[&str]) -> String {
+  create_api_key(
+    pool.clone(),
+    Principal::user(TEST_TENANT_ID, TEST_USER_ID),
+    CreateApiKeyRequest {
+      api_key: Some(ApiKey {
+        display_name: "test-key".to_string(),
+        scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        ..Default::default()
+      }),
+    },
+  )
+  .await
+  .unwrap()
+  .key
+}
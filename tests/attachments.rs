@@ -0,0 +1,480 @@
+mod common;
+
+use crate::common::create_test_server;
+use axum::body::Bytes;
+use axum::extract::Path;
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::routing::put;
+use axum::Router;
+use common::as_user;
+use common::with_test_database;
+use futures::stream;
+use futures::TryStreamExt;
+use sha2::Digest;
+use sha2::Sha256;
+use sqlx::query;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tempfile::TempDir;
+use todos_service::blobs::stores::FilesystemBlobStore;
+use todos_service::blobs::stores::S3BlobStore;
+use todos_service::blobs::sweep_deleted_blobs;
+use todos_service::blobs::BlobStore;
+use todos_service::config::AttachmentsConfig;
+use todos_service::config::S3Config;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::Attachment;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::DeleteAttachmentRequest;
+use todos_service::proto::v1::todos::DeleteTodoRequest;
+use todos_service::proto::v1::todos::DownloadAttachmentRequest;
+use todos_service::proto::v1::todos::DownloadAttachmentResponse;
+use todos_service::proto::v1::todos::GetAttachmentRequest;
+use todos_service::proto::v1::todos::ListAttachmentsRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::UploadAttachmentRequest;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tokio::net::TcpListener;
+use tonic::Code;
+use tonic::Status;
+use tonic::Streaming;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+const OTHER_USER_ID: &str = "other-user";
+
+/// Create options that keep attachments in the given directory, with small
+/// limits so that they are easy to reach.
+fn create_options(directory: &TempDir) -> ServiceOptions {
+  ServiceOptions {
+    attachments: AttachmentsConfig {
+      max_size: 64,
+      chunk_size: 4,
+      ..Default::default()
+    },
+    blob_store: Some(Arc::new(FilesystemBlobStore::new(directory.path()))),
+    ..Default::default()
+  }
+}
+
+/// Create the messages that upload a file to the `groceries` todo, with its
+/// content in chunks of 5 bytes. The size and hash of the attachment can
+/// differ from its content.
+fn upload(
+  content: &[u8],
+  size_bytes: i64,
+  sha256: &str,
+) -> Vec<UploadAttachmentRequest> {
+  let first = UploadAttachmentRequest {
+    todo_id: "groceries".to_string(),
+    attachment: Some(Attachment {
+      filename: "list.txt".to_string(),
+      content_type: "text/plain".to_string(),
+      size_bytes,
+      sha256: sha256.to_string(),
+      ..Default::default()
+    }),
+    ..Default::default()
+  };
+
+  std::iter::once(first)
+    .chain(content.chunks(5).map(|chunk| UploadAttachmentRequest {
+      chunk: chunk.to_vec(),
+      ..Default::default()
+    }))
+    .collect()
+}
+
+/// Read a download, returning the attachment from the first message and the
+/// chunks of the content from the rest.
+async fn read_download(
+  mut responses: Streaming<DownloadAttachmentResponse>,
+) -> Result<(Attachment, Vec<Vec<u8>>), Status> {
+  let attachment = responses.message().await?.unwrap().attachment.unwrap();
+  let mut chunks = Vec::new();
+  while let Some(response) = responses.message().await? {
+    assert_eq!(response.attachment, None);
+    chunks.push(response.chunk);
+  }
+
+  Ok((attachment, chunks))
+}
+
+/// Get the names of the files in a directory.
+fn list_files(directory: &TempDir) -> Vec<String> {
+  std::fs::read_dir(directory.path())
+    .map(|entries| {
+      entries
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+#[test]
+pub fn files_can_be_attached_to_todos() {
+  with_test_database(|pool| async move {
+    let directory = TempDir::new().unwrap();
+    let options = create_options(&directory);
+    let (server_future, channel) = create_test_server(
+      TodoServiceHandler::create_server(pool.clone(), &options),
+    )
+    .await;
+
+    let content = b"milk, eggs, flour, butter";
+    let sha256 = format!("{:x}", Sha256::digest(content));
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy groceries".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      // The hash is computed as the content is uploaded.
+      let attachment = client
+        .upload_attachment(stream::iter(upload(content, 25, "")))
+        .await
+        .unwrap()
+        .into_inner()
+        .attachment
+        .unwrap();
+      assert_eq!(attachment.sha256, sha256);
+      assert_eq!(attachment.size_bytes, 25);
+      assert_eq!(attachment.filename, "list.txt");
+      assert_eq!(attachment.content_type, "text/plain");
+      assert_eq!(attachment.uploader_id, TEST_USER_ID);
+      assert_eq!(
+        attachment.name,
+        format!(
+          "lists/inbox/todos/groceries/attachments/{}",
+          attachment.attachment_id
+        )
+      );
+      assert_eq!(list_files(&directory), [attachment.attachment_id.as_str()]);
+
+      // The content is downloaded in chunks no larger than the chunk size.
+      let responses = client
+        .download_attachment(DownloadAttachmentRequest {
+          name: "lists/inbox/todos/groceries".to_string(),
+          attachment_id: attachment.attachment_id.clone(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+      let (downloaded, chunks) = read_download(responses).await.unwrap();
+      assert_eq!(downloaded, attachment);
+      assert!(chunks.iter().all(|chunk| chunk.len() <= 4));
+      assert_eq!(chunks.concat(), content);
+
+      let attachments = client
+        .list_attachments(ListAttachmentsRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .attachments;
+      assert_eq!(attachments, std::slice::from_ref(&attachment));
+
+      // Uploads that do not match their size or hash, or that are too large,
+      // fail without leaving anything in the store.
+      for messages in [
+        upload(content, 24, ""),
+        upload(content, 26, ""),
+        upload(content, 65, ""),
+        upload(content, -1, ""),
+        upload(content, 25, &"0".repeat(64)),
+        upload(content, 25, "not-a-hash"),
+      ] {
+        let status = client
+          .upload_attachment(stream::iter(messages))
+          .await
+          .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", status);
+      }
+      let status = client
+        .upload_attachment(stream::iter(Vec::new()))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+      assert_eq!(list_files(&directory), [attachment.attachment_id.as_str()]);
+
+      // Content that was changed in the store is not trusted.
+      std::fs::write(
+        directory.path().join(&attachment.attachment_id),
+        b"milk, eggs, flour, bacon!",
+      )
+      .unwrap();
+      let responses = client
+        .download_attachment(DownloadAttachmentRequest {
+          todo_id: "groceries".to_string(),
+          attachment_id: attachment.attachment_id.clone(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+      let status = read_download(responses).await.unwrap_err();
+      assert_eq!(status.code(), Code::DataLoss);
+
+      // Other users cannot see or add to the attachments.
+      let mut other_client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, OTHER_USER_ID),
+      );
+      let status = other_client
+        .get_attachment(GetAttachmentRequest {
+          todo_id: "groceries".to_string(),
+          attachment_id: attachment.attachment_id.clone(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = other_client
+        .upload_attachment(stream::iter(upload(content, 25, "")))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+
+      // Deleted attachments are gone, and their content is removed by the
+      // sweeper.
+      client
+        .delete_attachment(DeleteAttachmentRequest {
+          todo_id: "groceries".to_string(),
+          attachment_id: attachment.attachment_id.clone(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      let status = client
+        .get_attachment(GetAttachmentRequest {
+          todo_id: "groceries".to_string(),
+          attachment_id: attachment.attachment_id.clone(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      assert_eq!(list_files(&directory).len(), 1);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+
+    let store = options.blob_store.unwrap();
+    assert_eq!(sweep_deleted_blobs(&pool, store.as_ref()).await.unwrap(), 1);
+    assert!(list_files(&directory).is_empty());
+  });
+}
+
+#[test]
+pub fn attachments_are_deleted_with_their_todo() {
+  with_test_database(|pool| async move {
+    let directory = TempDir::new().unwrap();
+    let options = create_options(&directory);
+    let (server_future, channel) = create_test_server(
+      TodoServiceHandler::create_server(pool.clone(), &options),
+    )
+    .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy groceries".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      for content in [b"milk".as_slice(), b"eggs".as_slice()] {
+        client
+          .upload_attachment(stream::iter(upload(content, 4, "")))
+          .await
+          .unwrap();
+      }
+
+      client
+        .delete_todo(DeleteTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      let status = client
+        .list_attachments(ListAttachmentsRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+
+    let record = query!(
+      r#"
+      select (select count(*) from todo_attachments) as "attachments!",
+             (select count(*) from deleted_attachment_blobs) as "queued!"
+      "#
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(record.attachments, 0);
+    assert_eq!(record.queued, 2);
+
+    assert_eq!(list_files(&directory).len(), 2);
+    let store = options.blob_store.unwrap();
+    assert_eq!(sweep_deleted_blobs(&pool, store.as_ref()).await.unwrap(), 2);
+    assert_eq!(sweep_deleted_blobs(&pool, store.as_ref()).await.unwrap(), 0);
+    assert!(list_files(&directory).is_empty());
+  });
+}
+
+#[test]
+pub fn attachments_need_a_blob_store() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let status = client
+        .upload_attachment(stream::iter(upload(b"milk", 4, "")))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::Unimplemented);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[tokio::test]
+pub async fn s3_blob_store_signs_and_streams_requests() {
+  // A bucket that keeps objects in memory, and checks that each request is
+  // signed by the expected key.
+  let objects = Arc::new(Mutex::new(HashMap::new()));
+  let check_signature = |headers: &HeaderMap| {
+    let authorization = headers["authorization"].to_str().unwrap();
+    assert!(
+      authorization.starts_with("AWS4-HMAC-SHA256 Credential=test-key/")
+        && authorization.contains("/us-east-1/s3/aws4_request, ")
+        && authorization.contains(
+          "SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature="
+        ),
+      "{}",
+      authorization
+    );
+    assert_eq!(headers["x-amz-content-sha256"], "UNSIGNED-PAYLOAD");
+  };
+  let app = Router::new().route(
+    "/attachments/:key",
+    put({
+      let objects = objects.clone();
+      move |Path(key): Path<String>, headers: HeaderMap, body: Bytes| async move {
+        check_signature(&headers);
+        assert_eq!(headers["content-length"], body.len().to_string());
+        objects.lock().unwrap().insert(key, body);
+        StatusCode::OK
+      }
+    })
+    .get({
+      let objects = objects.clone();
+      move |Path(key): Path<String>, headers: HeaderMap| async move {
+        check_signature(&headers);
+        match objects.lock().unwrap().get(&key) {
+          Some(body) => (StatusCode::OK, body.clone()),
+          None => (StatusCode::NOT_FOUND, Bytes::from("NoSuchKey")),
+        }
+      }
+    })
+    .delete({
+      let objects = objects.clone();
+      move |Path(key): Path<String>, headers: HeaderMap| async move {
+        check_signature(&headers);
+        objects.lock().unwrap().remove(&key);
+        StatusCode::NO_CONTENT
+      }
+    }),
+  );
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let address = listener.local_addr().unwrap();
+  tokio::spawn(async move { axum::serve(listener, app).await });
+
+  let store = S3BlobStore::new(&S3Config {
+    endpoint: format!("http://{}", address),
+    bucket: "attachments".to_string(),
+    region: "us-east-1".to_string(),
+    access_key_id: "test-key".to_string(),
+    secret_access_key: "test-secret".to_string(),
+  })
+  .unwrap();
+
+  let chunks = vec![Ok(Bytes::from("milk, ")), Ok("eggs".into())];
+  store
+    .put("groceries", 10, Box::pin(stream::iter(chunks)))
+    .await
+    .unwrap();
+  assert_eq!(objects.lock().unwrap()["groceries"], "milk, eggs");
+
+  let content = store
+    .get("groceries")
+    .await
+    .unwrap()
+    .map_ok(|chunk| chunk.to_vec())
+    .try_concat()
+    .await
+    .unwrap();
+  assert_eq!(content, b"milk, eggs");
+
+  store.delete("groceries").await.unwrap();
+  let Err(error) = store.get("groceries").await else {
+    panic!("deleted object was found");
+  };
+  assert!(error.to_string().contains("NoSuchKey"), "{}", error);
+}
@@ -0,0 +1,343 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use sqlx::query;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::Comment;
+use todos_service::proto::v1::todos::CreateCommentRequest;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::DeleteCommentRequest;
+use todos_service::proto::v1::todos::DeleteTodoRequest;
+use todos_service::proto::v1::todos::GetCommentRequest;
+use todos_service::proto::v1::todos::GetTodoRequest;
+use todos_service::proto::v1::todos::ListCommentEditsRequest;
+use todos_service::proto::v1::todos::ListCommentsRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::UpdateCommentRequest;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+const OTHER_USER_ID: &str = "other-user";
+
+/// Create a request that comments on the `groceries` todo.
+fn comment(body: &str) -> CreateCommentRequest {
+  CreateCommentRequest {
+    todo_id: "groceries".to_string(),
+    comment: Some(Comment {
+      body: body.to_string(),
+      ..Default::default()
+    }),
+    ..Default::default()
+  }
+}
+
+#[test]
+pub fn todos_can_be_commented_on() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy milk".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      // The author is the caller, whatever the request says.
+      let first = client
+        .create_comment(CreateCommentRequest {
+          comment: Some(Comment {
+            author_id: OTHER_USER_ID.to_string(),
+            body: "Oat or dairy?".to_string(),
+            ..Default::default()
+          }),
+          ..comment("")
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .comment
+        .unwrap();
+      assert_eq!(first.author_id, TEST_USER_ID);
+      assert_eq!(first.todo_id, "groceries");
+      assert_eq!(
+        first.name,
+        format!("lists/inbox/todos/groceries/comments/{}", first.comment_id)
+      );
+      assert_eq!(first.edit_count, 0);
+      let second = client
+        .create_comment(CreateCommentRequest {
+          todo_id: String::new(),
+          name: "lists/inbox/todos/groceries".to_string(),
+          ..comment("Oat, please")
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .comment
+        .unwrap();
+      assert!(second.comment_id > first.comment_id);
+
+      let status = client.create_comment(comment(" ")).await.unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+
+      let todo = client
+        .get_todo(GetTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.comment_count, 2);
+
+      // The comments are listed oldest first, a page at a time.
+      let response = client
+        .list_comments(ListCommentsRequest {
+          todo_id: "groceries".to_string(),
+          page_size: 1,
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+      assert_eq!(response.comments, std::slice::from_ref(&first));
+      let response = client
+        .list_comments(ListCommentsRequest {
+          todo_id: "groceries".to_string(),
+          page_size: 1,
+          page_token: response.next_page_token,
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+      assert_eq!(response.comments, std::slice::from_ref(&second));
+      assert!(response.next_page_token.is_empty());
+
+      // Changing the body records the body it replaced.
+      let updated = client
+        .update_comment(UpdateCommentRequest {
+          todo_id: "groceries".to_string(),
+          comment_id: first.comment_id,
+          comment: Some(Comment {
+            body: "Oat or soy?".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .comment
+        .unwrap();
+      assert_eq!(updated.body, "Oat or soy?");
+      assert_eq!(updated.edit_count, 1);
+      assert_eq!(updated.created_at, first.created_at);
+      // Setting the same body again is not an edit.
+      let unchanged = client
+        .update_comment(UpdateCommentRequest {
+          todo_id: "groceries".to_string(),
+          comment_id: first.comment_id,
+          comment: Some(Comment {
+            body: "Oat or soy?".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .comment
+        .unwrap();
+      assert_eq!(unchanged, updated);
+
+      let edits = client
+        .list_comment_edits(ListCommentEditsRequest {
+          todo_id: "groceries".to_string(),
+          comment_id: first.comment_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .edits;
+      assert_eq!(edits.len(), 1);
+      assert_eq!(edits[0].body, "Oat or dairy?");
+      assert_eq!(edits[0].created_at, first.updated_at);
+      assert_eq!(edits[0].edit_time, updated.updated_at);
+
+      // Deleted comments are gone.
+      client
+        .delete_comment(DeleteCommentRequest {
+          todo_id: "groceries".to_string(),
+          comment_id: second.comment_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      let status = client
+        .get_comment(GetCommentRequest {
+          name: "lists/inbox/todos/groceries".to_string(),
+          comment_id: second.comment_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = client
+        .delete_comment(DeleteCommentRequest {
+          todo_id: "groceries".to_string(),
+          comment_id: second.comment_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+
+      // A comment is only found through its own todo.
+      let status = client
+        .get_comment(GetCommentRequest {
+          name: "lists/work/todos/groceries".to_string(),
+          comment_id: first.comment_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+
+      // Other users cannot see or add to the comments.
+      let mut other_client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, OTHER_USER_ID),
+      );
+      let status = other_client
+        .get_comment(GetCommentRequest {
+          todo_id: "groceries".to_string(),
+          comment_id: first.comment_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = other_client
+        .create_comment(comment("Mine now"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn comments_are_deleted_with_their_todo() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy milk".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      let comment_id = client
+        .create_comment(comment("Oat or dairy?"))
+        .await
+        .unwrap()
+        .into_inner()
+        .comment
+        .unwrap()
+        .comment_id;
+      client
+        .update_comment(UpdateCommentRequest {
+          todo_id: "groceries".to_string(),
+          comment_id,
+          comment: Some(Comment {
+            body: "Oat or soy?".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      client
+        .delete_todo(DeleteTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      let status = client
+        .list_comments(ListCommentsRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+
+    let record = query!(
+      r#"
+      select (select count(*) from todo_comments) as "comments!",
+             (select count(*) from todo_comment_edits) as "edits!"
+      "#
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(record.comments, 0);
+    assert_eq!(record.edits, 0);
+  });
+}
@@ -1,3 +1,10 @@
+// Tonic uses `Status` as the error type for interceptors, even though clippy
+// considers it large.
+#![allow(clippy::result_large_err)]
+// Each test binary includes this module, but not every test binary uses all of
+// its helpers.
+#![allow(dead_code)]
+
 use database::create_database;
 use database::create_database_pool_for_named_database;
 use database::get_database_pool_base_options;
@@ -5,11 +12,18 @@ use hyper_util::rt::TokioIo;
 use sqlx::PgPool;
 use std::convert::Infallible;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tempfile::NamedTempFile;
 use tempfile::TempPath;
+use todos_service::auth::API_KEY_METADATA_KEY;
+use todos_service::auth::TENANT_ID_METADATA_KEY;
+use todos_service::auth::USER_ID_METADATA_KEY;
 use todos_service::database;
 use todos_service::database::drop_database;
+use todos_service::services::build_server;
+use todos_service::services::ServiceOptions;
+use tokio::net::TcpListener;
 use tokio::net::UnixListener;
 use tokio::net::UnixStream;
 use tokio_stream::wrappers::UnixListenerStream;
@@ -17,11 +31,15 @@ use tonic::body::BoxBody;
 use tonic::codegen::http::Request;
 use tonic::codegen::http::Response;
 use tonic::codegen::Service;
+use tonic::metadata::MetadataValue;
 use tonic::server::NamedService;
+use tonic::service::Interceptor;
+use tonic::transport::server::TcpIncoming;
 use tonic::transport::Channel;
 use tonic::transport::Endpoint;
 use tonic::transport::Server;
 use tonic::transport::Uri;
+use tonic::Status;
 use tower::service_fn;
 use uuid::Uuid;
 
@@ -38,7 +56,7 @@ use uuid::Uuid;
 /// the test fails, then an error is logged, and the database is dropped.  If
 /// the test passes, then the database is dropped.  This ensures that we always,
 /// drop the database, even if the test fails.
-pub fn with_test_database<T, U>(test: T) -> ()
+pub fn with_test_database<T, U>(test: T)
 where
   T: (FnOnce(PgPool) -> U) + std::panic::UnwindSafe,
   U: Future<Output = ()>,
@@ -198,3 +216,80 @@ async fn create_uds_client_channel(path: Arc<TempPath>) -> Channel {
     .await
     .unwrap()
 }
+
+/// Create a server with all of the services and layers from [`build_server`],
+/// listening on a random local TCP port, and return the future that runs the
+/// server and its address. Unlike [`create_test_server`], this uses tcp rather
+/// than a unix domain socket, so that HTTP/1.1 clients such as browsers and
+/// REST clients can connect to it. For example:
+/// ```
+/// let (server_future, address) =
+///   create_tcp_test_server(pool, ServiceOptions::default()).await;
+///
+/// let request_future = async {
+///   let response = reqwest::get(format!("http://{address}/v1/todos")).await;
+///   // your test case here
+/// };
+///
+/// // Wait for completion, when the client request future completes
+/// tokio::select! {
+///     _ = server_future => panic!("server returned first"),
+///     _ = request_future => (),
+/// }
+/// ```
+pub async fn create_tcp_test_server(
+  pool: PgPool,
+  options: ServiceOptions,
+) -> (impl Future<Output = ()>, SocketAddr) {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let address = listener.local_addr().unwrap();
+  let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+
+  let server_future = async move {
+    let result = build_server(Server::builder(), &pool, &options)
+      .serve_with_incoming(incoming)
+      .await;
+    // Server must be running fine
+    assert!(result.is_ok());
+  };
+
+  (server_future, address)
+}
+
+/// Create a client interceptor that identifies every request as coming from
+/// the given user and tenant, in the same way as the trusted proxy in front of
+/// the server would. For example:
+/// ```
+/// let mut client = TodoServiceClient::with_interceptor(
+///   channel,
+///   as_user("test-tenant", "test-user"),
+/// );
+/// ```
+pub fn as_user(tenant_id: &str, user_id: &str) -> impl Interceptor {
+  let tenant_id: MetadataValue<_> = tenant_id.parse().unwrap();
+  let user_id: MetadataValue<_> = user_id.parse().unwrap();
+
+  move |mut request: tonic::Request<()>| -> Result<_, Status> {
+    let metadata = request.metadata_mut();
+    metadata.insert(TENANT_ID_METADATA_KEY, tenant_id.clone());
+    metadata.insert(USER_ID_METADATA_KEY, user_id.clone());
+    Ok(request)
+  }
+}
+
+/// Create a client interceptor that authenticates every request with the given
+/// API key. For example:
+/// ```
+/// let mut client =
+///   TodoServiceClient::with_interceptor(channel, with_api_key(&key));
+/// ```
+pub fn with_api_key(key: &str) -> impl Interceptor {
+  let key: MetadataValue<_> = key.parse().unwrap();
+
+  move |mut request: tonic::Request<()>| -> Result<_, Status> {
+    request
+      .metadata_mut()
+      .insert(API_KEY_METADATA_KEY, key.clone());
+    Ok(request)
+  }
+}
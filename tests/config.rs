@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+use tempfile::NamedTempFile;
+use todos_service::config::BlobStoreConfig;
+use todos_service::config::Config;
+use todos_service::config::ConfigArgs;
+use todos_service::config::ConfigErrors;
+use todos_service::config::Listener;
+use todos_service::config::NotifierConfig;
+use todos_service::config::S3Config;
+use todos_service::config::Source;
+use todos_service::services::todos::SubtaskCompletion;
+use todos_service::telemetry::LogFormat;
+
+const TEST_DATABASE_URL: &str = "postgres://env-user@localhost/todos";
+
+/// Write the given TOML to a temporary configuration file.
+fn create_config_file(contents: &str) -> NamedTempFile {
+  let mut file = NamedTempFile::new().unwrap();
+  file.write_all(contents.as_bytes()).unwrap();
+  file
+}
+
+/// Load the configuration with the given environment variables, instead of
+/// the environment of the test process.
+fn load(
+  args: &ConfigArgs,
+  env: &[(&str, &str)],
+) -> Result<Config, ConfigErrors> {
+  let env: HashMap<String, String> = env
+    .iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+  Config::load_with_env(args, |name| env.get(name).cloned())
+}
+
+#[test]
+pub fn defaults_are_used_for_unset_keys() {
+  let config = load(
+    &ConfigArgs::default(),
+    &[("DATABASE_URL", TEST_DATABASE_URL)],
+  )
+  .unwrap();
+
+  assert_eq!(config.server.name, "todos-service");
+  assert_eq!(
+    config.server.listener,
+    Listener::Tcp("0.0.0.0:8080".parse().unwrap())
+  );
+  assert_eq!(config.server.request_timeout, None);
+  assert_eq!(config.server.tls, None);
+  assert_eq!(config.admin.address, None);
+  assert_eq!(config.database.max_connections, 10);
+  assert_eq!(config.database.min_connections, 0);
+  assert_eq!(config.database.acquire_timeout, Duration::from_secs(30));
+  assert_eq!(config.database.idle_timeout, Duration::from_secs(600));
+  assert_eq!(config.database.max_lifetime, Duration::from_secs(1800));
+  assert_eq!(config.database.statement_timeout, None);
+  assert_eq!(config.database.connect_backoff, Duration::from_secs(1));
+  assert_eq!(config.database.connect_max_backoff, Duration::from_secs(30));
+  assert_eq!(
+    config.database.connect_options.get_application_name(),
+    Some("todos-service")
+  );
+  assert!(config.database.read_connect_options.is_none());
+  assert_eq!(config.logging.format, LogFormat::Text);
+  assert_eq!(config.logging.filter, "info");
+  assert_eq!(config.limits.max_todos_per_owner, None);
+  assert_eq!(config.reminders.notifier, NotifierConfig::Log);
+  assert_eq!(config.reminders.lease, Duration::from_secs(300));
+  assert_eq!(config.todos.max_subtask_depth, 3);
+  assert_eq!(
+    config.todos.subtask_completion,
+    SubtaskCompletion::Independent
+  );
+  assert_eq!(config.todos.search_language, "english");
+  assert_eq!(config.server.gateway_max_body_size, 4 * 1024 * 1024);
+  assert_eq!(
+    config.attachments.store,
+    BlobStoreConfig::Filesystem {
+      path: "attachments".into()
+    }
+  );
+  assert_eq!(config.attachments.max_size, 25 * 1024 * 1024);
+  assert_eq!(config.attachments.chunk_size, 64 * 1024);
+}
+
+#[test]
+pub fn flags_override_environment_which_overrides_file() {
+  let file = create_config_file(
+    r#"
+    [server]
+    name = "file-name"
+    port = 1000
+
+    [database]
+    url = "postgres://file-user@localhost/todos"
+    max_connections = 5
+    "#,
+  );
+  let args = ConfigArgs {
+    config_file: Some(file.path().to_path_buf()),
+    overrides: vec!["server.port=3000".to_string()],
+  };
+
+  let config = load(
+    &args,
+    &[
+      ("SERVER_NAME", "env-name"),
+      ("SERVER_PORT", "2000"),
+      ("DATABASE_URL", TEST_DATABASE_URL),
+    ],
+  )
+  .unwrap();
+
+  assert_eq!(config.server.name, "env-name");
+  assert_eq!(
+    config.server.listener,
+    Listener::Tcp("0.0.0.0:3000".parse().unwrap())
+  );
+  assert_eq!(config.database.connect_options.get_username(), "env-user");
+  assert_eq!(
+    config.database.connect_options.get_application_name(),
+    Some("env-name")
+  );
+  assert_eq!(config.database.max_connections, 5);
+}
+
+#[test]
+pub fn config_file_can_be_set_with_environment_variable() {
+  let file = create_config_file(
+    r#"
+    [server]
+    host = "/tmp/todos.sock"
+    request_timeout = "1m 30s"
+    cors_allowed_origins = ["https://todos.example.com", "http://localhost:3000"]
+
+    [admin]
+    port = 9090
+
+    [database]
+    url = "postgres://file-user@localhost/todos"
+    read_url = "postgres://replica-user@replica/todos"
+    min_connections = 2
+    acquire_timeout = 0.5
+    statement_timeout = "5s"
+    connect_backoff = "250ms"
+
+    [logging]
+    format = "json"
+    filter = "todos_service=debug"
+
+    [limits]
+    rate_limits = "*=100:10"
+    peer_rate_limits = "*=1000:100"
+    max_todos_per_owner = 500
+
+    [todos]
+    max_subtask_depth = 5
+    subtask_completion = "require-subtasks"
+    search_language = "german"
+    "#,
+  );
+  let path = file.path().to_str().unwrap();
+
+  let config = load(&ConfigArgs::default(), &[("CONFIG_FILE", path)]).unwrap();
+
+  assert_eq!(
+    config.server.listener,
+    Listener::Unix("/tmp/todos.sock".to_string())
+  );
+  assert_eq!(config.server.request_timeout, Some(Duration::from_secs(90)));
+  assert_eq!(config.server.cors_allowed_origins.len(), 2);
+  assert_eq!(config.admin.address, Some("0.0.0.0:9090".parse().unwrap()));
+  let read_connect_options = config.database.read_connect_options.unwrap();
+  assert_eq!(read_connect_options.get_username(), "replica-user");
+  assert_eq!(
+    read_connect_options.get_application_name(),
+    Some("todos-service")
+  );
+  assert_eq!(config.database.min_connections, 2);
+  assert_eq!(config.database.acquire_timeout, Duration::from_millis(500));
+  assert_eq!(
+    config.database.statement_timeout,
+    Some(Duration::from_secs(5))
+  );
+  assert_eq!(config.database.connect_backoff, Duration::from_millis(250));
+  assert_eq!(config.logging.format, LogFormat::Json);
+  assert_eq!(config.logging.filter, "todos_service=debug");
+  assert!(config.limits.rate_limits.default.is_some());
+  assert!(config.limits.peer_rate_limits.default.is_some());
+  assert_eq!(config.limits.max_todos_per_owner, Some(500));
+  assert_eq!(config.todos.max_subtask_depth, 5);
+  assert_eq!(
+    config.todos.subtask_completion,
+    SubtaskCompletion::RequireSubtasks
+  );
+  assert_eq!(config.todos.search_language, "german");
+}
+
+#[test]
+pub fn every_invalid_key_is_reported() {
+  let file = create_config_file(
+    r#"
+    [server]
+    port = 70000
+    colour = "blue"
+
+    [logging]
+    format = "xml"
+
+    [todos]
+    search_language = "klingon"
+    "#,
+  );
+  let args = ConfigArgs {
+    config_file: Some(file.path().to_path_buf()),
+    overrides: vec!["database.pool_size=10".to_string(), "debug".to_string()],
+  };
+
+  let errors = load(
+    &args,
+    &[
+      ("DATABASE_MAX_CONNECTIONS", "0"),
+      ("TLS_CERT_PATH", "/does/not/exist.pem"),
+    ],
+  )
+  .unwrap_err();
+
+  let keys = errors
+    .0
+    .iter()
+    .map(|error| error.key.as_deref())
+    .collect::<Vec<_>>();
+  for key in [
+    Some("server.port"),
+    Some("server.colour"),
+    Some("logging.format"),
+    Some("database.url"),
+    Some("database.max_connections"),
+    Some("database.pool_size"),
+    Some("server.tls.cert_path"),
+    Some("server.tls.key_path"),
+    Some("todos.search_language"),
+    None,
+  ] {
+    assert!(keys.contains(&key), "{:?} was not reported", key);
+  }
+  assert_eq!(errors.0.len(), 10, "{}", errors);
+
+  let port_error = errors
+    .0
+    .iter()
+    .find(|error| error.key.as_deref() == Some("server.port"))
+    .unwrap();
+  assert_eq!(
+    port_error.source,
+    Some(Source::File(file.path().to_path_buf()))
+  );
+}
+
+#[test]
+pub fn missing_config_file_is_reported() {
+  let args = ConfigArgs {
+    config_file: Some("/does/not/exist.toml".into()),
+    overrides: Vec::new(),
+  };
+
+  let errors = load(&args, &[("DATABASE_URL", TEST_DATABASE_URL)]).unwrap_err();
+
+  assert_eq!(errors.0.len(), 1);
+  assert_eq!(errors.0[0].key, None);
+  assert_eq!(errors.0[0].source, Some(Source::Flag));
+}
+
+#[test]
+pub fn reminder_notifiers_require_their_keys() {
+  let errors = load(
+    &ConfigArgs::default(),
+    &[
+      ("DATABASE_URL", TEST_DATABASE_URL),
+      ("REMINDERS_NOTIFIER", "webhook"),
+      ("REMINDERS_LEASE", "5s"),
+    ],
+  )
+  .unwrap_err();
+
+  let keys = errors
+    .0
+    .iter()
+    .map(|error| error.key.as_deref())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    keys,
+    [Some("reminders.webhook_url"), Some("reminders.lease")],
+    "{}",
+    errors
+  );
+
+  let config = load(
+    &ConfigArgs::default(),
+    &[
+      ("DATABASE_URL", TEST_DATABASE_URL),
+      ("REMINDERS_NOTIFIER", "email"),
+      ("REMINDERS_EMAIL_FROM", "todos@example.com"),
+    ],
+  )
+  .unwrap();
+  assert_eq!(
+    config.reminders.notifier,
+    NotifierConfig::Email {
+      smtp_host: "localhost".to_string(),
+      smtp_port: 25,
+      from: "todos@example.com".to_string(),
+      domain: None,
+    }
+  );
+}
+
+#[test]
+pub fn blob_stores_require_their_keys() {
+  let errors = load(
+    &ConfigArgs::default(),
+    &[
+      ("DATABASE_URL", TEST_DATABASE_URL),
+      ("ATTACHMENTS_STORE", "s3"),
+      ("ATTACHMENTS_S3_ENDPOINT", "localhost:9000"),
+      ("ATTACHMENTS_CHUNK_SIZE", "4194304"),
+    ],
+  )
+  .unwrap_err();
+
+  let keys = errors
+    .0
+    .iter()
+    .map(|error| error.key.as_deref())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    keys,
+    [
+      Some("attachments.s3_endpoint"),
+      Some("attachments.s3_bucket"),
+      Some("attachments.s3_access_key_id"),
+      Some("attachments.s3_secret_access_key"),
+      Some("attachments.chunk_size"),
+    ],
+    "{}",
+    errors
+  );
+
+  let config = load(
+    &ConfigArgs::default(),
+    &[
+      ("DATABASE_URL", TEST_DATABASE_URL),
+      ("ATTACHMENTS_STORE", "s3"),
+      ("ATTACHMENTS_S3_ENDPOINT", "http://localhost:9000"),
+      ("ATTACHMENTS_S3_BUCKET", "attachments"),
+      ("ATTACHMENTS_S3_ACCESS_KEY_ID", "minioadmin"),
+      ("ATTACHMENTS_S3_SECRET_ACCESS_KEY", "minio-secret"),
+    ],
+  )
+  .unwrap();
+  let s3 = S3Config {
+    endpoint: "http://localhost:9000".to_string(),
+    bucket: "attachments".to_string(),
+    region: "us-east-1".to_string(),
+    access_key_id: "minioadmin".to_string(),
+    secret_access_key: "minio-secret".to_string(),
+  };
+  assert_eq!(config.attachments.store, BlobStoreConfig::S3(s3));
+  // The secret is never logged with the rest of the configuration.
+  assert!(!format!("{:?}", config).contains("minio-secret"));
+}
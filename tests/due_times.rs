@@ -0,0 +1,287 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use prost_types::FieldMask;
+use prost_types::Timestamp;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::GetTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::UpdateTodoRequest;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::codegen::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+/// Create a timestamp the given number of days after 2026-01-01.
+fn day(days: i64) -> Timestamp {
+  Timestamp {
+    seconds: 1_767_225_600 + days * 24 * 60 * 60,
+    nanos: 0,
+  }
+}
+
+/// Create a todo with the given due and reminder times.
+fn create_request(
+  todo_id: &str,
+  due_time: Option<Timestamp>,
+  reminder_time: Option<Timestamp>,
+) -> CreateTodoRequest {
+  CreateTodoRequest {
+    todo: Some(Todo {
+      todo_id: todo_id.to_string(),
+      title: todo_id.to_string(),
+      due_time,
+      reminder_time,
+      ..Default::default()
+    }),
+    ..Default::default()
+  }
+}
+
+/// List the todos, and get their IDs in order.
+async fn list<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  request: ListTodosRequest,
+) -> Vec<String> {
+  let response = client.list_todos(request).await.unwrap();
+
+  response
+    .into_inner()
+    .todos
+    .into_iter()
+    .map(|todo| todo.todo_id)
+    .collect()
+}
+
+#[test]
+pub fn due_and_reminder_times_can_be_set_and_cleared() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let todo = client
+        .create_todo(create_request("report", Some(day(7)), Some(day(6))))
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.due_time, Some(day(7)));
+      assert_eq!(todo.reminder_time, Some(day(6)));
+
+      // Clearing the due time does not change the reminder time, which is not
+      // in the mask.
+      let todo = client
+        .update_todo(UpdateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "report".to_string(),
+            due_time: None,
+            reminder_time: Some(day(1)),
+            ..Default::default()
+          }),
+          update_mask: Some(FieldMask {
+            paths: vec!["due_time".to_string()],
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.due_time, None);
+      assert_eq!(todo.reminder_time, Some(day(6)));
+
+      let todo = client
+        .update_todo(UpdateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "report".to_string(),
+            due_time: Some(day(14)),
+            reminder_time: Some(day(13)),
+            ..Default::default()
+          }),
+          update_mask: Some(FieldMask {
+            paths: vec!["due_time".to_string(), "reminder_time".to_string()],
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.due_time, Some(day(14)));
+      assert_eq!(todo.reminder_time, Some(day(13)));
+
+      let todo = client
+        .get_todo(GetTodoRequest {
+          todo_id: "report".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.due_time, Some(day(14)));
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn todos_can_be_filtered_and_ordered_by_due_and_reminder_times() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      for request in [
+        create_request("later", Some(day(10)), None),
+        create_request("undated", None, Some(day(2))),
+        create_request("soon", Some(day(3)), Some(day(1))),
+      ] {
+        client.create_todo(request).await.unwrap();
+      }
+
+      // Todos without a due time are listed last, in either direction.
+      let order_by = |order_by: &str| ListTodosRequest {
+        order_by: order_by.to_string(),
+        ..Default::default()
+      };
+      assert_eq!(
+        list(&mut client, order_by("due_time")).await,
+        ["soon", "later", "undated"]
+      );
+      assert_eq!(
+        list(&mut client, order_by("due_time desc")).await,
+        ["later", "soon", "undated"]
+      );
+      assert_eq!(
+        list(&mut client, order_by(" reminder_time asc, title desc ")).await,
+        ["soon", "undated", "later"]
+      );
+      // By default, todos are listed by position, which is the order they
+      // were created in.
+      assert_eq!(
+        list(&mut client, order_by("")).await,
+        ["later", "undated", "soon"]
+      );
+
+      let todos = list(
+        &mut client,
+        ListTodosRequest {
+          due_after: Some(day(3)),
+          due_before: Some(day(10)),
+          ..Default::default()
+        },
+      )
+      .await;
+      assert_eq!(todos, ["soon"]);
+
+      let todos = list(
+        &mut client,
+        ListTodosRequest {
+          reminder_before: Some(day(5)),
+          order_by: "reminder_time desc".to_string(),
+          ..Default::default()
+        },
+      )
+      .await;
+      assert_eq!(todos, ["undated", "soon"]);
+
+      let todos = list(
+        &mut client,
+        ListTodosRequest {
+          reminder_after: Some(day(2)),
+          ..Default::default()
+        },
+      )
+      .await;
+      assert_eq!(todos, ["undated"]);
+
+      for order_by in ["owner_id", "due_time sideways", "title,"] {
+        let error = client
+          .list_todos(ListTodosRequest {
+            order_by: order_by.to_string(),
+            ..Default::default()
+          })
+          .await
+          .unwrap_err();
+        assert_eq!(error.code(), Code::InvalidArgument, "{}", order_by);
+      }
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn invalid_timestamps_are_rejected() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let invalid = Timestamp {
+        seconds: 0,
+        nanos: -1,
+      };
+      let error = client
+        .create_todo(create_request("invalid", Some(invalid), None))
+        .await
+        .unwrap_err();
+      assert_eq!(error.code(), Code::InvalidArgument);
+      assert!(error.message().contains("due_time"), "{}", error.message());
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
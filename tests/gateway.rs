@@ -0,0 +1,412 @@
+mod common;
+
+use common::create_tcp_test_server;
+use common::with_test_database;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Method;
+use reqwest::RequestBuilder;
+use reqwest::StatusCode;
+use serde_json::json;
+use serde_json::Value;
+use std::net::SocketAddr;
+use todos_service::auth::TENANT_ID_METADATA_KEY;
+use todos_service::auth::USER_ID_METADATA_KEY;
+use todos_service::services::ServiceOptions;
+use todos_service::telemetry::REQUEST_ID_METADATA_KEY;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+/// Create a request to the gateway on behalf of the test user.
+fn request(address: SocketAddr, method: Method, path: &str) -> RequestBuilder {
+  reqwest::Client::new()
+    .request(method, format!("http://{address}{path}"))
+    .header(USER_ID_METADATA_KEY, TEST_USER_ID)
+    .header(TENANT_ID_METADATA_KEY, TEST_TENANT_ID)
+}
+
+/// Send a request and return the status and JSON body of the response.
+async fn send(request: RequestBuilder) -> (StatusCode, Value) {
+  let response = request.send().await.unwrap();
+  let status = response.status();
+  assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+  let body = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+
+  (status, body)
+}
+
+#[test]
+pub fn todos_can_be_managed_with_rest() {
+  with_test_database(|pool| async move {
+    let (server_future, address) =
+      create_tcp_test_server(pool, ServiceOptions::default()).await;
+
+    let request_future = async {
+      let (status, body) =
+        send(request(address, Method::POST, "/v1/todos").body(
+          json!({"todoId": "rest-todo", "title": "Buy milk", "description": "Semi"}).to_string(),
+        ))
+        .await;
+      assert_eq!(status, StatusCode::OK);
+      let todo = &body["todo"];
+      let todo_id = todo["todoId"].as_str().unwrap().to_string();
+      assert_eq!(todo_id, "rest-todo");
+      assert_eq!(todo["title"], "Buy milk");
+      assert_eq!(todo["completed"], false);
+      assert_eq!(todo["ownerId"], TEST_USER_ID);
+      // Timestamps use the proto3 JSON mapping, which is RFC 3339.
+      assert!(todo["createdAt"].as_str().unwrap().ends_with('Z'));
+
+      let (status, body) = send(request(
+        address,
+        Method::GET,
+        &format!("/v1/todos/{todo_id}"),
+      ))
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todo"]["title"], "Buy milk");
+
+      let (status, body) =
+        send(request(address, Method::GET, "/v1/todos")).await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todos"].as_array().unwrap().len(), 1);
+
+      let (status, body) = send(request(
+        address,
+        Method::GET,
+        "/v1/todos:search?query=milk&page_size=10",
+      ))
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["results"][0]["titleSnippet"], "Buy <mark>milk</mark>");
+
+      // Without an update mask, only the fields in the body are updated.
+      let (status, body) = send(
+        request(address, Method::PATCH, &format!("/v1/todos/{todo_id}"))
+          .body(json!({"completed": true}).to_string()),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todo"]["completed"], true);
+      assert_eq!(body["todo"]["title"], "Buy milk");
+      assert_eq!(body["todo"]["description"], "Semi");
+
+      // With an update mask, only the fields in the mask are updated.
+      let (status, body) = send(
+        request(
+          address,
+          Method::PATCH,
+          &format!("/v1/todos/{todo_id}?update_mask=title"),
+        )
+        .body(
+          json!({"title": "Buy oat milk", "description": "Oat"}).to_string(),
+        ),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todo"]["title"], "Buy oat milk");
+      assert_eq!(body["todo"]["description"], "Semi");
+
+      let (status, body) = send(request(
+        address,
+        Method::DELETE,
+        &format!("/v1/todos/{todo_id}"),
+      ))
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body, json!({}));
+
+      let (status, body) = send(request(
+        address,
+        Method::GET,
+        &format!("/v1/todos/{todo_id}"),
+      ))
+      .await;
+      assert_eq!(status, StatusCode::NOT_FOUND);
+      assert_eq!(body["code"], 5);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn todos_can_be_moved_with_a_custom_verb() {
+  with_test_database(|pool| async move {
+    let (server_future, address) =
+      create_tcp_test_server(pool, ServiceOptions::default()).await;
+
+    let request_future = async {
+      for todo_id in ["first", "second"] {
+        let (status, _) = send(
+          request(address, Method::POST, "/v1/todos")
+            .body(json!({"todoId": todo_id, "title": todo_id}).to_string()),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+      }
+
+      let (status, body) = send(
+        request(address, Method::POST, "/v1/todos/second:move")
+          .body(json!({"beforeTodoId": "first"}).to_string()),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todo"]["todoId"], "second");
+
+      let (_, body) = send(request(address, Method::GET, "/v1/todos")).await;
+      let todo_ids = body["todos"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|todo| todo["todoId"].as_str().unwrap())
+        .collect::<Vec<_>>();
+      assert_eq!(todo_ids, ["second", "first"]);
+
+      // The verb is part of the route, so other verbs are not found.
+      let response = request(address, Method::POST, "/v1/todos/second:copy")
+        .send()
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn todos_can_be_managed_by_resource_name() {
+  with_test_database(|pool| async move {
+    let (server_future, address) =
+      create_tcp_test_server(pool, ServiceOptions::default()).await;
+
+    let request_future = async {
+      let (status, body) = send(
+        request(address, Method::POST, "/v1/lists?todo_list_id=work")
+          .body(json!({"displayName": "Work"}).to_string()),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todoList"]["name"], "lists/work");
+
+      let (status, body) =
+        send(request(address, Method::GET, "/v1/lists/work")).await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todoList"]["displayName"], "Work");
+
+      let (status, body) = send(
+        request(address, Method::POST, "/v1/lists/work/todos")
+          .body(json!({"todoId": "report", "title": "Report"}).to_string()),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todo"]["name"], "lists/work/todos/report");
+
+      let (status, body) = send(
+        request(address, Method::PATCH, "/v1/lists/work/todos/report")
+          .body(json!({"completed": true}).to_string()),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todo"]["completed"], true);
+
+      let (status, body) = send(
+        request(address, Method::POST, "/v1/lists/work/todos/report:reopen")
+          .body("{}"),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todo"]["completed"], false);
+      let (status, body) = send(
+        request(address, Method::POST, "/v1/todos/report:complete").body("{}"),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert!(body["todo"]["completeTime"].is_string());
+
+      // The todo can be rolled back to the revision it was created with.
+      let (status, body) = send(request(
+        address,
+        Method::GET,
+        "/v1/lists/work/todos/report/revisions",
+      ))
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      let revisions = body["revisions"].as_array().unwrap();
+      assert_eq!(revisions.len(), 4);
+      let revision_id = revisions[3]["revisionId"].as_str().unwrap();
+      let (status, body) = send(
+        request(
+          address,
+          Method::POST,
+          &format!("/v1/todos/report/revisions/{revision_id}:restore"),
+        )
+        .body("{}"),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["todo"]["completed"], false);
+
+      let (status, body) = send(
+        request(
+          address,
+          Method::POST,
+          "/v1/lists/work/todos/report/comments",
+        )
+        .body(r#"{"body": "Send it to finance"}"#),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      let comment_name = body["comment"]["name"].as_str().unwrap().to_string();
+      let comment_id = body["comment"]["commentId"].as_str().unwrap();
+      assert_eq!(
+        comment_name,
+        format!("lists/work/todos/report/comments/{comment_id}")
+      );
+      let (status, body) = send(
+        request(
+          address,
+          Method::PATCH,
+          &format!("/v1/todos/report/comments/{comment_id}"),
+        )
+        .body(r#"{"body": "Send it to finance and legal"}"#),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["comment"]["editCount"], 1);
+      let (status, body) = send(request(
+        address,
+        Method::GET,
+        &format!("/v1/{comment_name}/edits"),
+      ))
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      assert_eq!(body["edits"][0]["body"], "Send it to finance");
+
+      let (_, body) =
+        send(request(address, Method::GET, "/v1/lists/work/todos")).await;
+      assert_eq!(body["todos"][0]["commentCount"], 1);
+      assert_eq!(body["todos"][0]["name"], "lists/work/todos/report");
+
+      // The list cannot be deleted while it has todos, unless forced.
+      let (status, _) =
+        send(request(address, Method::DELETE, "/v1/lists/work")).await;
+      assert_eq!(status, StatusCode::BAD_REQUEST);
+      let (status, _) = send(request(
+        address,
+        Method::DELETE,
+        "/v1/lists/work/todos/report",
+      ))
+      .await;
+      assert_eq!(status, StatusCode::OK);
+      let (status, _) =
+        send(request(address, Method::DELETE, "/v1/lists/work")).await;
+      assert_eq!(status, StatusCode::OK);
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn errors_are_mapped_to_http_status_codes() {
+  with_test_database(|pool| async move {
+    let (server_future, address) =
+      create_tcp_test_server(pool, ServiceOptions::default()).await;
+
+    let request_future = async {
+      // Unauthenticated requests are rejected by the authentication layer.
+      let response = reqwest::Client::new()
+        .get(format!("http://{address}/v1/todos"))
+        .header(REQUEST_ID_METADATA_KEY, "test-request")
+        .send()
+        .await
+        .unwrap();
+      assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+      assert_eq!(response.headers()[REQUEST_ID_METADATA_KEY], "test-request");
+      let body: Value =
+        serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+      assert_eq!(body["code"], 16);
+
+      let (status, body) =
+        send(request(address, Method::POST, "/v1/todos").body("{")).await;
+      assert_eq!(status, StatusCode::BAD_REQUEST);
+      assert_eq!(body["code"], 3);
+
+      let (status, _) = send(
+        request(address, Method::POST, "/v1/todos")
+          .body(json!({"unknown": true}).to_string()),
+      )
+      .await;
+      assert_eq!(status, StatusCode::BAD_REQUEST);
+
+      let (status, _) =
+        send(request(address, Method::PUT, "/v1/todos/test-id")).await;
+      assert_eq!(status, StatusCode::NOT_FOUND);
+
+      let (status, _) = send(request(address, Method::GET, "/v2/todos")).await;
+      assert_eq!(status, StatusCode::NOT_FOUND);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn bodies_over_the_limit_are_rejected() {
+  with_test_database(|pool| async move {
+    let options = ServiceOptions {
+      gateway_max_body_size: 1024,
+      ..Default::default()
+    };
+    let (server_future, address) = create_tcp_test_server(pool, options).await;
+
+    let request_future = async {
+      let (status, _) = send(
+        request(address, Method::POST, "/v1/todos")
+          .body(json!({"title": "Buy milk"}).to_string()),
+      )
+      .await;
+      assert_eq!(status, StatusCode::OK);
+
+      let title = "a".repeat(2048);
+      let (status, body) = send(
+        request(address, Method::POST, "/v1/todos")
+          .body(json!({ "title": title }).to_string()),
+      )
+      .await;
+      assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+      assert_eq!(body["code"], 3);
+
+      // Bodies without a length are also cut off at the limit.
+      let chunks = (0..64).map(|_| Ok::<_, std::io::Error>(vec![b' '; 1024]));
+      let (status, _) = send(
+        request(address, Method::POST, "/v1/todos")
+          .body(reqwest::Body::wrap_stream(futures::stream::iter(chunks))),
+      )
+      .await;
+      assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
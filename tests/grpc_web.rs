@@ -0,0 +1,220 @@
+mod common;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use common::create_tcp_test_server;
+use common::with_test_database;
+use prost::Message;
+use reqwest::header::ACCEPT;
+use reqwest::header::ACCESS_CONTROL_ALLOW_ORIGIN;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Method;
+use sqlx::PgPool;
+use std::future::Future;
+use std::net::SocketAddr;
+use todos_service::auth::TENANT_ID_METADATA_KEY;
+use todos_service::auth::USER_ID_METADATA_KEY;
+use todos_service::grpc_web::parse_allowed_origins;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::CreateTodoResponse;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::services::ServiceOptions;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+const ALLOWED_ORIGIN: &str = "http://localhost:3000";
+const CREATE_TODO_PATH: &str = "/example.v1.todos.TodoService/CreateTodo";
+
+/// Start the full server, allowing cross-origin requests from the test origin.
+async fn create_web_server(
+  pool: PgPool,
+) -> (impl Future<Output = ()>, SocketAddr) {
+  let options = ServiceOptions {
+    cors_allowed_origins: parse_allowed_origins(ALLOWED_ORIGIN).unwrap(),
+    ..Default::default()
+  };
+  create_tcp_test_server(pool, options).await
+}
+
+/// Encode a message as a single gRPC-Web data frame.
+fn encode_frame(message: &impl Message) -> Vec<u8> {
+  let body = message.encode_to_vec();
+  let mut frame = vec![0];
+  frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+  frame.extend_from_slice(&body);
+  frame
+}
+
+/// Split a gRPC-Web response body into its data frame and its trailers.
+fn decode_frames(body: &[u8]) -> (Vec<u8>, String) {
+  let mut data = Vec::new();
+  let mut trailers = String::new();
+  let mut remaining = body;
+
+  while remaining.len() >= 5 {
+    let length = u32::from_be_bytes(remaining[1..5].try_into().unwrap());
+    let (frame, rest) = remaining[5..].split_at(length as usize);
+    if remaining[0] & 0x80 == 0 {
+      data.extend_from_slice(frame);
+    } else {
+      trailers.push_str(&String::from_utf8_lossy(frame));
+    }
+    remaining = rest;
+  }
+
+  (data, trailers)
+}
+
+/// Decode a gRPC-Web text response body. Each chunk of the response is encoded
+/// separately, so there may be padding in the middle of the body.
+fn decode_text_body(body: &str) -> Vec<u8> {
+  let mut decoded = Vec::new();
+  let mut chunk = String::new();
+  let mut chars = body.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    chunk.push(c);
+    if chars.peek().is_none() || (c == '=' && chars.peek() != Some(&'=')) {
+      decoded.extend(STANDARD.decode(&chunk).unwrap());
+      chunk.clear();
+    }
+  }
+
+  decoded
+}
+
+fn create_todo_request(title: &str) -> CreateTodoRequest {
+  CreateTodoRequest {
+    todo: Some(Todo {
+      title: title.to_string(),
+      description: "Created from a browser".to_string(),
+      ..Default::default()
+    }),
+    ..Default::default()
+  }
+}
+
+#[test]
+pub fn binary_grpc_web_requests_are_handled() {
+  with_test_database(|pool| async move {
+    let (server_future, address) = create_web_server(pool).await;
+
+    let request_future = async {
+      let response = reqwest::Client::new()
+        .post(format!("http://{address}{CREATE_TODO_PATH}"))
+        .header(CONTENT_TYPE, "application/grpc-web+proto")
+        .header(USER_ID_METADATA_KEY, TEST_USER_ID)
+        .header(TENANT_ID_METADATA_KEY, TEST_TENANT_ID)
+        .body(encode_frame(&create_todo_request("Binary")))
+        .send()
+        .await
+        .unwrap();
+
+      assert_eq!(response.version(), reqwest::Version::HTTP_11);
+      assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "application/grpc-web+proto"
+      );
+
+      let body = response.bytes().await.unwrap();
+      let (data, trailers) = decode_frames(&body);
+      let todo = CreateTodoResponse::decode(data.as_slice())
+        .unwrap()
+        .todo
+        .unwrap();
+      assert_eq!(todo.title, "Binary");
+      assert_eq!(todo.owner_id, TEST_USER_ID);
+      assert!(trailers.contains("grpc-status:0"));
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn text_grpc_web_requests_are_handled() {
+  with_test_database(|pool| async move {
+    let (server_future, address) = create_web_server(pool).await;
+
+    let request_future = async {
+      let response = reqwest::Client::new()
+        .post(format!("http://{address}{CREATE_TODO_PATH}"))
+        .header(CONTENT_TYPE, "application/grpc-web-text")
+        .header(ACCEPT, "application/grpc-web-text")
+        .header(USER_ID_METADATA_KEY, TEST_USER_ID)
+        .header(TENANT_ID_METADATA_KEY, TEST_TENANT_ID)
+        .body(STANDARD.encode(encode_frame(&create_todo_request("Text"))))
+        .send()
+        .await
+        .unwrap();
+
+      assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "application/grpc-web-text+proto"
+      );
+
+      let body = decode_text_body(&response.text().await.unwrap());
+      let (data, trailers) = decode_frames(&body);
+      let todo = CreateTodoResponse::decode(data.as_slice())
+        .unwrap()
+        .todo
+        .unwrap();
+      assert_eq!(todo.title, "Text");
+      assert!(trailers.contains("grpc-status:0"));
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn preflight_requests_allow_configured_origins() {
+  with_test_database(|pool| async move {
+    let (server_future, address) = create_web_server(pool).await;
+
+    let request_future = async {
+      let client = reqwest::Client::new();
+      let preflight = |origin: &str| {
+        client
+          .request(
+            Method::OPTIONS,
+            format!("http://{address}{CREATE_TODO_PATH}"),
+          )
+          .header("origin", origin)
+          .header("access-control-request-method", "POST")
+          .header(
+            "access-control-request-headers",
+            "content-type,x-grpc-web,x-api-key",
+          )
+          .send()
+      };
+
+      let response = preflight(ALLOWED_ORIGIN).await.unwrap();
+      assert!(response.status().is_success());
+      assert_eq!(
+        response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+        ALLOWED_ORIGIN
+      );
+
+      let response = preflight("https://evil.example.com").await.unwrap();
+      assert!(response
+        .headers()
+        .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use crate::common::create_test_server;
+use std::net::SocketAddr;
+use std::time::Duration;
+use todos_service::common::load_dotenv;
+use todos_service::config::Config;
+use todos_service::config::ConfigArgs;
+use todos_service::config::DatabaseConfig;
+use todos_service::database::wait_for_database;
+use todos_service::health::create_health_service;
+use todos_service::health::serve_when_database_is_ready;
+use todos_service::proto::v1::todos::todo_service_server::SERVICE_NAME;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+/// Load the database configuration of the test environment, retrying quickly
+/// so that the tests do not have to wait long.
+fn load_database_config() -> DatabaseConfig {
+  load_dotenv().unwrap();
+  let mut config = Config::load(&ConfigArgs::default()).unwrap().database;
+  config.connect_backoff = Duration::from_millis(10);
+  config.connect_max_backoff = Duration::from_millis(50);
+  config
+}
+
+/// Forward every connection to the given address to the database, so that
+/// the database appears to become reachable at that address.
+async fn forward_to_database(address: SocketAddr, config: &DatabaseConfig) {
+  let listener = TcpListener::bind(address).await.unwrap();
+  let database_address = format!(
+    "{}:{}",
+    config.connect_options.get_host(),
+    config.connect_options.get_port()
+  );
+
+  loop {
+    let (mut inbound, _) = listener.accept().await.unwrap();
+    let database_address = database_address.clone();
+    tokio::spawn(async move {
+      let mut outbound = TcpStream::connect(database_address).await.unwrap();
+      let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+    });
+  }
+}
+
+#[tokio::test]
+pub async fn wait_for_database_retries_until_reachable() {
+  let mut config = load_database_config();
+
+  // Find a free port, which refuses connections until we start forwarding it
+  // to the database.
+  let address = TcpListener::bind("127.0.0.1:0")
+    .await
+    .unwrap()
+    .local_addr()
+    .unwrap();
+  config.connect_options = config
+    .connect_options
+    .host(&address.ip().to_string())
+    .port(address.port());
+
+  let wait = tokio::spawn({
+    let config = config.clone();
+    async move { wait_for_database(&config).await }
+  });
+
+  tokio::time::sleep(Duration::from_millis(200)).await;
+  assert!(!wait.is_finished());
+
+  let forward = tokio::spawn({
+    let config = load_database_config();
+    async move { forward_to_database(address, &config).await }
+  });
+
+  tokio::time::timeout(Duration::from_secs(5), wait)
+    .await
+    .expect("Timed out waiting for the database")
+    .unwrap();
+  forward.abort();
+}
+
+#[tokio::test]
+pub async fn health_is_serving_once_database_is_reachable() {
+  let (reporter, service) = create_health_service().await;
+  let (server_future, channel) = create_test_server(service).await;
+
+  let request_future = async {
+    let mut client = HealthClient::new(channel);
+    let check = |service: &str| HealthCheckRequest {
+      service: service.to_string(),
+    };
+
+    for service in ["", SERVICE_NAME] {
+      let response = client.check(check(service)).await.unwrap();
+      assert_eq!(response.into_inner().status(), ServingStatus::NotServing);
+    }
+
+    tokio::spawn(serve_when_database_is_ready(
+      reporter,
+      load_database_config(),
+    ));
+
+    let mut watch = client.watch(check(SERVICE_NAME)).await.unwrap();
+    let serving = async {
+      while let Some(response) = watch.get_mut().message().await.unwrap() {
+        if response.status() == ServingStatus::Serving {
+          return;
+        }
+      }
+      panic!("health stream ended");
+    };
+    tokio::time::timeout(Duration::from_secs(5), serving)
+      .await
+      .expect("Timed out waiting for SERVING");
+
+    let response = client.check(check("")).await.unwrap();
+    assert_eq!(response.into_inner().status(), ServingStatus::Serving);
+  };
+
+  tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+  }
+}
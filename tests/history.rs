@@ -0,0 +1,394 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_api_key;
+use common::with_test_database;
+use prost_types::value::Kind;
+use prost_types::FieldMask;
+use todos_service::auth::Principal;
+use todos_service::proto::v1::api_keys::ApiKey;
+use todos_service::proto::v1::api_keys::CreateApiKeyRequest;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CompleteTodoRequest;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::DeleteTodoRequest;
+use todos_service::proto::v1::todos::ListTodoHistoryRequest;
+use todos_service::proto::v1::todos::ReopenTodoRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::TodoEvent;
+use todos_service::proto::v1::todos::TodoEventAction;
+use todos_service::proto::v1::todos::UpdateTodoRequest;
+use todos_service::services::api_keys::create_api_key;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+const OTHER_USER_ID: &str = "other-user";
+
+/// Get a field of the before or after values of an event as a string, or
+/// `None` if the field is not set or is null.
+fn get_string(
+  values: &Option<prost_types::Struct>,
+  field: &str,
+) -> Option<String> {
+  let value = values.as_ref()?.fields.get(field)?;
+  match value.kind.as_ref()? {
+    Kind::StringValue(value) => Some(value.clone()),
+    Kind::BoolValue(value) => Some(value.to_string()),
+    Kind::ListValue(list) => Some(
+      list
+        .values
+        .iter()
+        .filter_map(|value| match value.kind.as_ref()? {
+          Kind::StringValue(value) => Some(value.as_str()),
+          _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(","),
+    ),
+    _ => None,
+  }
+}
+
+#[test]
+pub fn changes_to_a_todo_are_recorded() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy milk".to_string(),
+            labels: vec!["home".to_string()],
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      client
+        .update_todo(UpdateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy oat milk".to_string(),
+            labels: vec!["errands".to_string(), "home".to_string()],
+            ..Default::default()
+          }),
+          update_mask: Some(FieldMask {
+            paths: vec!["title".to_string(), "labels".to_string()],
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      client
+        .complete_todo(CompleteTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      // Completing the todo again does not change it, so is not recorded.
+      client
+        .complete_todo(CompleteTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      client
+        .delete_todo(DeleteTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      // The history is listed most recent first, and outlives the todo.
+      let events = client
+        .list_todo_history(ListTodoHistoryRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .events;
+      let actions = events
+        .iter()
+        .map(|event| event.action())
+        .collect::<Vec<_>>();
+      assert_eq!(
+        actions,
+        [
+          TodoEventAction::Delete,
+          TodoEventAction::Update,
+          TodoEventAction::Update,
+          TodoEventAction::Create,
+        ]
+      );
+      assert!(events
+        .iter()
+        .all(|event| event.actor_id == TEST_USER_ID && !event.api_key));
+
+      // The todo and its labels were created together.
+      let created = &events[3];
+      assert!(created.before.is_none());
+      assert_eq!(get_string(&created.after, "title").unwrap(), "Buy milk");
+      assert_eq!(get_string(&created.after, "labels").unwrap(), "home");
+
+      let updated = &events[2];
+      assert_eq!(updated.changed_fields, ["labels", "title"]);
+      assert_eq!(get_string(&updated.before, "title").unwrap(), "Buy milk");
+      assert_eq!(get_string(&updated.after, "title").unwrap(), "Buy oat milk");
+      assert_eq!(
+        get_string(&updated.after, "labels").unwrap(),
+        "errands,home"
+      );
+
+      let completed = &events[1];
+      assert_eq!(completed.changed_fields, ["complete_time", "completed"]);
+      assert_eq!(get_string(&completed.before, "completed").unwrap(), "false");
+      assert_eq!(get_string(&completed.after, "completed").unwrap(), "true");
+
+      let deleted = &events[0];
+      assert!(deleted.after.is_none());
+      assert_eq!(
+        get_string(&deleted.before, "title").unwrap(),
+        "Buy oat milk"
+      );
+
+      // The history can be listed a page at a time.
+      let mut page_token = String::new();
+      let mut paged_events: Vec<TodoEvent> = Vec::new();
+      loop {
+        let response = client
+          .list_todo_history(ListTodoHistoryRequest {
+            todo_id: "groceries".to_string(),
+            page_size: 1,
+            page_token,
+            ..Default::default()
+          })
+          .await
+          .unwrap()
+          .into_inner();
+        assert_eq!(response.events.len(), 1);
+        paged_events.extend(response.events);
+        if response.next_page_token.is_empty() {
+          break;
+        }
+        page_token = response.next_page_token;
+      }
+      assert_eq!(paged_events, events);
+
+      let status = client
+        .list_todo_history(ListTodoHistoryRequest {
+          todo_id: "unknown".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn completing_and_reopening_are_recorded() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy milk".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      client
+        .complete_todo(CompleteTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      client
+        .reopen_todo(ReopenTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      client
+        .complete_todo(CompleteTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      // A change to the completion time alone is recorded too.
+      sqlx::query!(
+        r#"
+        update todos
+        set complete_time = '2026-02-01T00:00:00Z'
+        where todo_id = 'groceries'
+        "#
+      )
+      .execute(&pool)
+      .await
+      .unwrap();
+
+      let events = client
+        .list_todo_history(ListTodoHistoryRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .events;
+      assert_eq!(events.len(), 5);
+
+      let completed = &events[3];
+      assert_eq!(completed.changed_fields, ["complete_time", "completed"]);
+      assert!(get_string(&completed.before, "complete_time").is_none());
+      assert!(get_string(&completed.after, "complete_time").is_some());
+
+      let reopened = &events[2];
+      assert_eq!(reopened.changed_fields, ["complete_time", "completed"]);
+      assert_eq!(
+        get_string(&reopened.before, "complete_time"),
+        get_string(&completed.after, "complete_time")
+      );
+      assert_eq!(get_string(&reopened.after, "completed").unwrap(), "false");
+      assert!(get_string(&reopened.after, "complete_time").is_none());
+
+      let moved = &events[0];
+      assert_eq!(moved.changed_fields, ["complete_time"]);
+      assert_eq!(
+        get_string(&moved.before, "complete_time"),
+        get_string(&events[1].after, "complete_time")
+      );
+      assert!(get_string(&moved.after, "complete_time")
+        .unwrap()
+        .starts_with("2026-02-01"));
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn history_records_api_keys_and_is_private() {
+  with_test_database(|pool| async move {
+    let key = create_api_key(
+      pool.clone(),
+      Principal::user(TEST_TENANT_ID, TEST_USER_ID),
+      CreateApiKeyRequest {
+        api_key: Some(ApiKey {
+          display_name: "test-key".to_string(),
+          scopes: vec!["todos.write".to_string(), "todos.read".to_string()],
+          ..Default::default()
+        }),
+      },
+    )
+    .await
+    .unwrap()
+    .key;
+
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        with_api_key(&key),
+      );
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "report".to_string(),
+            title: "Send report".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      let events = client
+        .list_todo_history(ListTodoHistoryRequest {
+          name: "lists/inbox/todos/report".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .events;
+      assert_eq!(events.len(), 1);
+      assert_eq!(events[0].actor_id, TEST_USER_ID);
+      assert!(events[0].api_key);
+
+      // Other users cannot see the history of the todo.
+      let mut other_client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, OTHER_USER_ID),
+      );
+      let status = other_client
+        .list_todo_history(ListTodoHistoryRequest {
+          todo_id: "report".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
@@ -0,0 +1,406 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use prost_types::FieldMask;
+use prost_types::Timestamp;
+use todos_service::proto::v1::labels::label_service_client::LabelServiceClient;
+use todos_service::proto::v1::labels::CreateLabelRequest;
+use todos_service::proto::v1::labels::DeleteLabelRequest;
+use todos_service::proto::v1::labels::GetLabelRequest;
+use todos_service::proto::v1::labels::Label;
+use todos_service::proto::v1::labels::ListLabelsRequest;
+use todos_service::proto::v1::labels::RenameLabelRequest;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::DeleteTodoRequest;
+use todos_service::proto::v1::todos::GetTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::UpdateTodoRequest;
+use todos_service::services::labels::LabelServiceHandler;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::codegen::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+/// Create a todo with the given ID and labels.
+async fn create<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo_id: &str,
+  labels: &[&str],
+) -> Todo {
+  let request = CreateTodoRequest {
+    todo: Some(Todo {
+      todo_id: todo_id.to_string(),
+      title: todo_id.to_string(),
+      labels: labels.iter().map(|label| label.to_string()).collect(),
+      ..Default::default()
+    }),
+    ..Default::default()
+  };
+
+  client
+    .create_todo(request)
+    .await
+    .unwrap()
+    .into_inner()
+    .todo
+    .unwrap()
+}
+
+/// Get the labels of the todo with the given ID.
+async fn get_labels<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo_id: &str,
+) -> Vec<String> {
+  let request = GetTodoRequest {
+    todo_id: todo_id.to_string(),
+    ..Default::default()
+  };
+
+  client
+    .get_todo(request)
+    .await
+    .unwrap()
+    .into_inner()
+    .todo
+    .unwrap()
+    .labels
+}
+
+/// List the IDs of the todos that have all of the given labels.
+async fn list_with_labels<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  labels: &[&str],
+) -> Vec<String> {
+  let request = ListTodosRequest {
+    labels: labels.iter().map(|label| label.to_string()).collect(),
+    ..Default::default()
+  };
+
+  client
+    .list_todos(request)
+    .await
+    .unwrap()
+    .into_inner()
+    .todos
+    .into_iter()
+    .map(|todo| todo.todo_id)
+    .collect()
+}
+
+/// List the names of the caller's labels.
+async fn list_label_names<T: Interceptor>(
+  client: &mut LabelServiceClient<InterceptedService<Channel, T>>,
+) -> Vec<String> {
+  client
+    .list_labels(ListLabelsRequest {})
+    .await
+    .unwrap()
+    .into_inner()
+    .labels
+    .into_iter()
+    .map(|label| label.name)
+    .collect()
+}
+
+#[test]
+pub fn todos_can_be_labelled_and_filtered_by_label() {
+  with_test_database(|pool| async move {
+    let (todo_server_future, todo_channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+    let (label_server_future, label_channel) =
+      create_test_server(LabelServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut todos = TodoServiceClient::with_interceptor(
+        todo_channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+      let mut labels = LabelServiceClient::with_interceptor(
+        label_channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      // Labels are sorted, and created when they are first attached.
+      let todo = create(&mut todos, "milk", &["shop", "home", "shop"]).await;
+      assert_eq!(todo.labels, ["home", "shop"]);
+      create(&mut todos, "desk", &["home", "work"]).await;
+      create(&mut todos, "report", &["work"]).await;
+      create(&mut todos, "walk", &[]).await;
+      assert_eq!(
+        list_label_names(&mut labels).await,
+        ["home", "shop", "work"]
+      );
+
+      assert_eq!(list_with_labels(&mut todos, &[]).await.len(), 4);
+      assert_eq!(
+        list_with_labels(&mut todos, &["home"]).await,
+        ["milk", "desk"]
+      );
+      assert_eq!(
+        list_with_labels(&mut todos, &["home", "work"]).await,
+        ["desk"]
+      );
+      assert!(list_with_labels(&mut todos, &["unknown"]).await.is_empty());
+
+      // The labels in the update mask replace the todo's labels.
+      let update = |labels: &[&str], paths: &[&str]| UpdateTodoRequest {
+        todo: Some(Todo {
+          todo_id: "milk".to_string(),
+          title: "Buy milk".to_string(),
+          labels: labels.iter().map(|label| label.to_string()).collect(),
+          ..Default::default()
+        }),
+        update_mask: Some(FieldMask {
+          paths: paths.iter().map(|path| path.to_string()).collect(),
+        }),
+        ..Default::default()
+      };
+      let todo = todos
+        .update_todo(update(&["errands", "shop"], &["labels"]))
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.labels, ["errands", "shop"]);
+      todos.update_todo(update(&[], &["title"])).await.unwrap();
+      assert_eq!(get_labels(&mut todos, "milk").await, ["errands", "shop"]);
+      todos.update_todo(update(&[], &["labels"])).await.unwrap();
+      assert!(get_labels(&mut todos, "milk").await.is_empty());
+
+      for invalid in ["", " padded", "line\nbreak", &"x".repeat(65)] {
+        let status = todos
+          .update_todo(update(&[invalid], &["labels"]))
+          .await
+          .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{:?}", invalid);
+      }
+
+      // Other users have their own labels.
+      let mut other_todos = TodoServiceClient::with_interceptor(
+        todo_channel,
+        as_user(TEST_TENANT_ID, "other-user"),
+      );
+      create(&mut other_todos, "other", &["home"]).await;
+      assert_eq!(list_with_labels(&mut todos, &["home"]).await, ["desk"]);
+    };
+
+    tokio::select! {
+      _ = todo_server_future => panic!("server returned first"),
+      _ = label_server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn labels_can_be_managed() {
+  with_test_database(|pool| async move {
+    let (todo_server_future, todo_channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+    let (label_server_future, label_channel) =
+      create_test_server(LabelServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut todos = TodoServiceClient::with_interceptor(
+        todo_channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+      let mut labels = LabelServiceClient::with_interceptor(
+        label_channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let create_label = |name: &str| CreateLabelRequest {
+        label: Some(Label {
+          name: name.to_string(),
+          ..Default::default()
+        }),
+      };
+      let label = labels
+        .create_label(create_label("garden"))
+        .await
+        .unwrap()
+        .into_inner()
+        .label
+        .unwrap();
+      assert_eq!(label.name, "garden");
+      assert!(label.created_at.is_some());
+      let status = labels
+        .create_label(create_label("garden"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::AlreadyExists);
+      let status = labels.create_label(create_label("")).await.unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+
+      create(&mut todos, "weeds", &["garden", "weekend"]).await;
+      create(&mut todos, "hedge", &["garden"]).await;
+
+      // Renaming a label renames it on every todo that has it.
+      let rename = |name: &str, new_name: &str| RenameLabelRequest {
+        name: name.to_string(),
+        new_name: new_name.to_string(),
+      };
+      let label = labels
+        .rename_label(rename("garden", "yard"))
+        .await
+        .unwrap()
+        .into_inner()
+        .label
+        .unwrap();
+      assert_eq!(label.name, "yard");
+      assert_eq!(get_labels(&mut todos, "weeds").await, ["weekend", "yard"]);
+      assert_eq!(get_labels(&mut todos, "hedge").await, ["yard"]);
+      assert_eq!(
+        list_with_labels(&mut todos, &["yard"]).await,
+        ["weeds", "hedge"]
+      );
+
+      let status = labels
+        .rename_label(rename("yard", "weekend"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::AlreadyExists);
+      let status = labels
+        .rename_label(rename("garden", "lawn"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = labels
+        .rename_label(rename("yard", "yard\t"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+
+      // Deleting a label removes it from its todos, but keeps the todos.
+      labels
+        .delete_label(DeleteLabelRequest {
+          name: "yard".to_string(),
+        })
+        .await
+        .unwrap();
+      assert_eq!(get_labels(&mut todos, "weeds").await, ["weekend"]);
+      assert!(get_labels(&mut todos, "hedge").await.is_empty());
+      let status = labels
+        .get_label(GetLabelRequest {
+          name: "yard".to_string(),
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = labels
+        .delete_label(DeleteLabelRequest {
+          name: "yard".to_string(),
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      assert_eq!(list_label_names(&mut labels).await, ["weekend"]);
+
+      // Deleting a todo keeps its labels.
+      todos
+        .delete_todo(DeleteTodoRequest {
+          todo_id: "weeds".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      assert_eq!(list_label_names(&mut labels).await, ["weekend"]);
+    };
+
+    tokio::select! {
+      _ = todo_server_future => panic!("server returned first"),
+      _ = label_server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn the_next_occurrence_of_a_recurring_todo_keeps_its_labels() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "bins".to_string(),
+            title: "Take out the bins".to_string(),
+            // Monday 2030-01-07 at 09:00 UTC.
+            due_time: Some(Timestamp {
+              seconds: 1_894_006_800,
+              nanos: 0,
+            }),
+            recurrence_rule: "FREQ=WEEKLY".to_string(),
+            labels: vec!["home".to_string()],
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      let next = client
+        .update_todo(UpdateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "bins".to_string(),
+            completed: true,
+            ..Default::default()
+          }),
+          update_mask: Some(FieldMask {
+            paths: vec!["completed".to_string()],
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .next_occurrence
+        .unwrap();
+      assert_eq!(next.labels, ["home"]);
+      assert_eq!(get_labels(&mut client, &next.todo_id).await, ["home"]);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
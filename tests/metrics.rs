@@ -0,0 +1,99 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use std::time::Duration;
+use todos_service::metrics::MetricsLayer;
+use todos_service::metrics::METRICS;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::GetTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tower::Layer;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+#[test]
+pub fn requests_are_recorded_in_the_metrics() {
+  with_test_database(|pool| async move {
+    let service = MetricsLayer::new().layer(TodoServiceHandler::create_server(
+      pool.clone(),
+      &ServiceOptions::default(),
+    ));
+    let (server_future, channel) = create_test_server(service).await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+      client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
+      client
+        .get_todo(GetTodoRequest {
+          todo_id: "test-id".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+
+      // Unauthenticated requests are recorded too.
+      let mut anonymous_client = TodoServiceClient::new(channel);
+      anonymous_client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap_err();
+
+      let service = "grpc_service=\"example.v1.todos.TodoService\"";
+      let expected = [
+        format!(
+          "grpc_server_started_total{{grpc_method=\"ListTodos\",{service}}} 2"
+        ),
+        format!(
+          "grpc_server_handled_total{{grpc_code=\"OK\",\
+           grpc_method=\"ListTodos\",{service}}} 1"
+        ),
+        format!(
+          "grpc_server_handled_total{{grpc_code=\"Unauthenticated\",\
+           grpc_method=\"ListTodos\",{service}}} 1"
+        ),
+        format!(
+          "grpc_server_handled_total{{grpc_code=\"NotFound\",\
+           grpc_method=\"GetTodo\",{service}}} 1"
+        ),
+        format!(
+          "grpc_server_handling_seconds_count{{grpc_method=\"GetTodo\",\
+           {service}}} 1"
+        ),
+        "db_pool_acquire_seconds_count 2".to_string(),
+        "db_pool_acquire_waiting 0".to_string(),
+        "db_pool_connections".to_string(),
+      ];
+
+      // The status of each RPC is recorded when the server drops the response
+      // body, which can happen just after the client has received it.
+      let mut metrics = String::new();
+      for _ in 0..50 {
+        metrics = METRICS.render(&pool).unwrap();
+        if expected.iter().all(|line| metrics.contains(line)) {
+          break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+      }
+      for line in expected {
+        assert!(metrics.contains(&line), "{line} not in {metrics}");
+      }
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
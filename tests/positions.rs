@@ -0,0 +1,329 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use prost_types::FieldMask;
+use todos_service::position::key_between;
+use todos_service::proto::v1::todos::move_todo_request::Destination;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::proto::v1::todos::MoveTodoRequest;
+use todos_service::proto::v1::todos::Priority;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::UpdateTodoRequest;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::codegen::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::Code;
+use tonic::Status;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+/// Create a todo with the given ID and priority.
+async fn create<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo_id: &str,
+  priority: Priority,
+) -> Todo {
+  let request = CreateTodoRequest {
+    todo: Some(Todo {
+      todo_id: todo_id.to_string(),
+      title: todo_id.to_string(),
+      priority: priority.into(),
+      ..Default::default()
+    }),
+    ..Default::default()
+  };
+
+  client
+    .create_todo(request)
+    .await
+    .unwrap()
+    .into_inner()
+    .todo
+    .unwrap()
+}
+
+/// Move a todo, returning the moved todo.
+async fn move_todo<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo_id: &str,
+  destination: Destination,
+) -> Result<Todo, Status> {
+  let request = MoveTodoRequest {
+    todo_id: todo_id.to_string(),
+    destination: Some(destination),
+    ..Default::default()
+  };
+
+  client
+    .move_todo(request)
+    .await
+    .map(|response| response.into_inner().todo.unwrap())
+}
+
+/// List the todos in the given order, and get their IDs.
+async fn list<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  order_by: &str,
+) -> Vec<String> {
+  let request = ListTodosRequest {
+    order_by: order_by.to_string(),
+    ..Default::default()
+  };
+
+  client
+    .list_todos(request)
+    .await
+    .unwrap()
+    .into_inner()
+    .todos
+    .into_iter()
+    .map(|todo| todo.todo_id)
+    .collect()
+}
+
+#[test]
+pub fn keys_sort_between_their_neighbours() {
+  assert_eq!(key_between(None, None).unwrap(), "a0");
+  assert_eq!(key_between(Some("a0"), None).unwrap(), "a1");
+  assert_eq!(key_between(None, Some("a0")).unwrap(), "Zz");
+  assert_eq!(key_between(Some("a0"), Some("a1")).unwrap(), "a0V");
+  assert_eq!(key_between(Some("az"), None).unwrap(), "b00");
+  assert!(key_between(Some("a1"), Some("a0")).is_err());
+  assert!(key_between(Some("a10"), None).is_err());
+  assert!(key_between(Some("!"), None).is_err());
+
+  // Moving todos into the same gap repeatedly grows the keys by a character
+  // every five moves.
+  let mut before = "a0".to_string();
+  let after = "a1".to_string();
+  for _ in 0..1000 {
+    let key = key_between(Some(&before), Some(&after)).unwrap();
+    assert!(before < key && key < after, "{} {} {}", before, key, after);
+    before = key;
+  }
+  assert_eq!(before.len(), 202);
+
+  let mut after = "a0".to_string();
+  for _ in 0..1000 {
+    let key = key_between(None, Some(&after)).unwrap();
+    assert!(key < after, "{} {}", key, after);
+    after = key;
+  }
+  assert!(after.len() < 10);
+}
+
+#[test]
+pub fn todos_can_be_moved() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      // New todos are added to the end of the list.
+      for todo_id in ["a", "b", "c", "d"] {
+        create(&mut client, todo_id, Priority::Unspecified).await;
+      }
+      assert_eq!(list(&mut client, "").await, ["a", "b", "c", "d"]);
+
+      let before = |todo_id: &str| Destination::BeforeTodoId(todo_id.into());
+      let after = |todo_id: &str| Destination::AfterTodoId(todo_id.into());
+      move_todo(&mut client, "d", before("a")).await.unwrap();
+      assert_eq!(list(&mut client, "").await, ["d", "a", "b", "c"]);
+      move_todo(&mut client, "d", after("b")).await.unwrap();
+      assert_eq!(list(&mut client, "").await, ["a", "b", "d", "c"]);
+      move_todo(&mut client, "a", after("c")).await.unwrap();
+      assert_eq!(list(&mut client, "position").await, ["b", "d", "c", "a"]);
+      assert_eq!(
+        list(&mut client, "position desc").await,
+        ["a", "c", "d", "b"]
+      );
+
+      // A todo that is already in place can be moved there again.
+      move_todo(&mut client, "d", after("b")).await.unwrap();
+      assert_eq!(list(&mut client, "").await, ["b", "d", "c", "a"]);
+
+      // Only the moved todo changes.
+      let select_positions = || {
+        sqlx::query_scalar!(
+          "select position from todos where todo_id <> 'b' order by todo_id"
+        )
+        .fetch_all(&pool)
+      };
+      let positions = select_positions().await.unwrap();
+      move_todo(&mut client, "b", before("a")).await.unwrap();
+      assert_eq!(select_positions().await.unwrap(), positions);
+      assert_eq!(list(&mut client, "").await, ["d", "c", "b", "a"]);
+
+      // Todos can also be moved by name, but only within their list.
+      let move_by_name = |name: &str| MoveTodoRequest {
+        name: name.to_string(),
+        destination: Some(before("d")),
+        ..Default::default()
+      };
+      let todo = client
+        .move_todo(move_by_name("lists/inbox/todos/a"))
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.todo_id, "a");
+      assert_eq!(list(&mut client, "").await, ["a", "d", "c", "b"]);
+      let status = client
+        .move_todo(move_by_name("lists/work/todos/b"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+
+      let status = move_todo(&mut client, "a", before("a")).await.unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+      let status = move_todo(&mut client, "a", before("missing"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = move_todo(&mut client, "missing", before("a"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = client
+        .move_todo(MoveTodoRequest {
+          todo_id: "a".to_string(),
+          destination: None,
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+
+      // Todos cannot be moved next to todos in another list.
+      sqlx::query!(
+        r#"
+        insert into todo_lists (tenant_id, owner_id, list_id, display_name)
+        values ($1, $2, 'work', 'Work')
+        "#,
+        TEST_TENANT_ID,
+        TEST_USER_ID
+      )
+      .execute(&pool)
+      .await
+      .unwrap();
+      client
+        .create_todo(CreateTodoRequest {
+          parent: "lists/work".to_string(),
+          todo: Some(Todo {
+            todo_id: "e".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      let status = move_todo(&mut client, "e", before("a")).await.unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+      let status = move_todo(&mut client, "a", after("e")).await.unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+      assert_eq!(list(&mut client, "").await, ["a", "d", "c", "b", "e"]);
+
+      // Todos cannot be moved next to another user's todos.
+      let mut other_client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, "other-user"),
+      );
+      let status = move_todo(&mut other_client, "a", before("b"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn todos_have_a_priority() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let todo = create(&mut client, "low", Priority::Low).await;
+      assert_eq!(todo.priority(), Priority::Low);
+      create(&mut client, "urgent", Priority::Urgent).await;
+      create(&mut client, "none", Priority::Unspecified).await;
+      assert_eq!(
+        list(&mut client, "priority desc").await,
+        ["urgent", "low", "none"]
+      );
+
+      let todo = client
+        .update_todo(UpdateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "none".to_string(),
+            priority: Priority::High.into(),
+            ..Default::default()
+          }),
+          update_mask: Some(FieldMask {
+            paths: vec!["priority".to_string()],
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.priority(), Priority::High);
+      assert_eq!(
+        list(&mut client, "priority desc").await,
+        ["urgent", "none", "low"]
+      );
+
+      let status = client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "invalid".to_string(),
+            title: "Invalid".to_string(),
+            priority: 99,
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_api_key;
+use common::with_test_database;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::GetTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::Code;
+use tonic_types::StatusExt;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+const OTHER_USER_ID: &str = "other-user";
+
+#[test]
+pub fn requests_over_the_limit_are_rejected() {
+  with_test_database(|pool| async move {
+    let options = ServiceOptions {
+      rate_limits: "ListTodos=2:0.01".parse().unwrap(),
+      ..Default::default()
+    };
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(pool, &options))
+        .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      assert!(client.list_todos(ListTodosRequest::default()).await.is_ok());
+      assert!(client.list_todos(ListTodosRequest::default()).await.is_ok());
+
+      let status = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::ResourceExhausted);
+      let retry_delay = status
+        .get_details_retry_info()
+        .and_then(|retry_info| retry_info.retry_delay)
+        .unwrap();
+      assert!(retry_delay.as_secs() > 0);
+
+      // Other methods are not limited.
+      let get_status = client
+        .get_todo(GetTodoRequest {
+          todo_id: "test-id".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(get_status.code(), Code::NotFound);
+
+      // Other users have their own limit.
+      let mut other_client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, OTHER_USER_ID),
+      );
+      assert!(other_client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .is_ok());
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn default_limit_applies_to_every_method() {
+  with_test_database(|pool| async move {
+    let options = ServiceOptions {
+      rate_limits: "*=1:0.01".parse().unwrap(),
+      ..Default::default()
+    };
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(pool, &options))
+        .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      assert!(client.list_todos(ListTodosRequest::default()).await.is_ok());
+
+      let list_status = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap_err();
+      assert_eq!(list_status.code(), Code::ResourceExhausted);
+
+      // Each method has its own bucket.
+      let get_status = client
+        .get_todo(GetTodoRequest {
+          todo_id: "test-id".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(get_status.code(), Code::NotFound);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn peers_are_limited_before_they_authenticate() {
+  with_test_database(|pool| async move {
+    let options = ServiceOptions {
+      peer_rate_limits: "ListTodos=3:0.01".parse().unwrap(),
+      ..Default::default()
+    };
+    let (server_future, channel) = create_test_server(
+      TodoServiceHandler::create_server(pool.clone(), &options),
+    )
+    .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        with_api_key("tk_invalid"),
+      );
+
+      for _ in 0..3 {
+        let status = client
+          .list_todos(ListTodosRequest::default())
+          .await
+          .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+      }
+
+      // Once the peer is over its limit, its requests are rejected before
+      // their key is looked up, so the database is not needed to reject them.
+      pool.close().await;
+      let status = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::ResourceExhausted);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn invalid_rate_limits_are_rejected() {
+  let invalid = ["ListTodos", "ListTodos=10", "ListTodos=0:1", "*=10:-1"];
+
+  for value in invalid {
+    let result = value.parse::<todos_service::rate_limit::RateLimitConfig>();
+    assert!(result.is_err(), "{} should be invalid", value);
+  }
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::time::Duration;
+use todos_service::database::READ_CONSISTENCY_METADATA_KEY;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::GetTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::Code;
+use tonic::Request;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+/// Create a pool for a read replica that cannot be reached, so that any
+/// request routed to the replica fails quickly.
+fn create_unreachable_read_pool(pool: &PgPool) -> PgPool {
+  let options = pool.connect_options().as_ref().clone().port(1);
+
+  PgPoolOptions::new()
+    .acquire_timeout(Duration::from_millis(200))
+    .connect_lazy_with(options)
+}
+
+/// Create a request with the given read consistency.
+fn with_consistency<T>(message: T, consistency: &str) -> Request<T> {
+  let mut request = Request::new(message);
+  request
+    .metadata_mut()
+    .insert(READ_CONSISTENCY_METADATA_KEY, consistency.parse().unwrap());
+  request
+}
+
+#[test]
+pub fn reads_use_read_replica_unless_consistency_is_strong() {
+  with_test_database(|pool| async move {
+    let options = ServiceOptions {
+      read_pool: Some(create_unreachable_read_pool(&pool)),
+      ..Default::default()
+    };
+    let (server_future, channel) = create_test_server(
+      TodoServiceHandler::create_server(pool.clone(), &options),
+    )
+    .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      // Writes always use the primary.
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "replica-todo".to_string(),
+            title: "Replica todo".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      let error = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap_err();
+      assert_eq!(error.code(), Code::Internal);
+      let error = client
+        .list_todos(with_consistency(ListTodosRequest::default(), "eventual"))
+        .await
+        .unwrap_err();
+      assert_eq!(error.code(), Code::Internal);
+
+      let response = client
+        .list_todos(with_consistency(ListTodosRequest::default(), "strong"))
+        .await
+        .unwrap();
+      assert_eq!(response.into_inner().todos.len(), 1);
+
+      let request = GetTodoRequest {
+        todo_id: "replica-todo".to_string(),
+        ..Default::default()
+      };
+      let response = client
+        .get_todo(with_consistency(request, "strong"))
+        .await
+        .unwrap();
+      assert_eq!(response.into_inner().todo.unwrap().title, "Replica todo");
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn reads_from_read_replica_are_isolated_by_tenant() {
+  with_test_database(|pool| async move {
+    // The test database stands in for a replica that is up to date.
+    let options = ServiceOptions {
+      read_pool: Some(pool.clone()),
+      ..Default::default()
+    };
+    let (server_future, channel) = create_test_server(
+      TodoServiceHandler::create_server(pool.clone(), &options),
+    )
+    .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "replica-todo".to_string(),
+            title: "Replica todo".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      let response = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
+      assert_eq!(response.into_inner().todos.len(), 1);
+
+      let mut other_client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user("other-tenant", TEST_USER_ID),
+      );
+      let response = other_client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
+      assert_eq!(response.into_inner().todos.len(), 0);
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn invalid_read_consistency_is_rejected() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let error = client
+        .list_todos(with_consistency(
+          ListTodosRequest::default(),
+          "linearizable",
+        ))
+        .await
+        .unwrap_err();
+      assert_eq!(error.code(), Code::InvalidArgument);
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
@@ -0,0 +1,397 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use prost_types::FieldMask;
+use prost_types::Timestamp;
+use time::Date;
+use time::Month;
+use time::OffsetDateTime;
+use time::Time;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::RecurrenceScope;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::UpdateTodoRequest;
+use todos_service::proto::v1::todos::UpdateTodoResponse;
+use todos_service::recurrence::RecurrenceRule;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::codegen::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::Code;
+use tonic::Status;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+/// Create a time at 09:00 UTC on the given date.
+fn at(year: i32, month: u8, day: u8) -> OffsetDateTime {
+  let month = Month::try_from(month).unwrap();
+  Date::from_calendar_date(year, month, day)
+    .unwrap()
+    .with_time(Time::from_hms(9, 0, 0).unwrap())
+    .assume_utc()
+}
+
+/// Get the first occurrences of a rule, for a series that starts at `start`.
+fn occurrences(rule: &str, start: OffsetDateTime, limit: usize) -> Vec<Date> {
+  let rule = rule.parse::<RecurrenceRule>().unwrap();
+  let mut dates = vec![start.date()];
+  let mut after = start;
+
+  while dates.len() < limit {
+    let Some(next) = rule.next_after(start, after) else {
+      break;
+    };
+    assert_eq!(next.time(), start.time());
+    dates.push(next.date());
+    after = next;
+  }
+
+  dates
+}
+
+/// Create a timestamp the given number of weeks after Monday 2030-01-07 at
+/// 09:00 UTC, which is in the future so that no occurrences are skipped.
+fn week(weeks: i64) -> Timestamp {
+  Timestamp {
+    seconds: 1_894_006_800 + weeks * 7 * 24 * 60 * 60,
+    nanos: 0,
+  }
+}
+
+/// Update the todo with the given fields in the mask.
+async fn update<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo: Todo,
+  paths: &[&str],
+  scope: RecurrenceScope,
+) -> Result<UpdateTodoResponse, Status> {
+  let request = UpdateTodoRequest {
+    todo: Some(todo),
+    update_mask: Some(FieldMask {
+      paths: paths.iter().map(|path| path.to_string()).collect(),
+    }),
+    scope: scope.into(),
+  };
+
+  client
+    .update_todo(request)
+    .await
+    .map(|response| response.into_inner())
+}
+
+/// Complete the todo with the given ID, returning the next occurrence.
+async fn complete<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo_id: &str,
+) -> Option<Todo> {
+  let todo = Todo {
+    todo_id: todo_id.to_string(),
+    completed: true,
+    ..Default::default()
+  };
+
+  update(client, todo, &["completed"], RecurrenceScope::Unspecified)
+    .await
+    .unwrap()
+    .next_occurrence
+}
+
+#[test]
+pub fn rules_are_evaluated_from_the_start_of_the_series() {
+  let dates = |dates: &[(i32, u8, u8)]| {
+    dates
+      .iter()
+      .map(|(year, month, day)| at(*year, *month, *day).date())
+      .collect::<Vec<_>>()
+  };
+
+  assert_eq!(
+    occurrences("FREQ=WEEKLY;BYDAY=MO,TH", at(2026, 10, 19), 4),
+    dates(&[
+      (2026, 10, 19),
+      (2026, 10, 22),
+      (2026, 10, 26),
+      (2026, 10, 29)
+    ])
+  );
+  assert_eq!(
+    occurrences("RRULE:FREQ=WEEKLY;INTERVAL=2", at(2026, 10, 19), 3),
+    dates(&[(2026, 10, 19), (2026, 11, 2), (2026, 11, 16)])
+  );
+  // Months without a 31st are skipped.
+  assert_eq!(
+    occurrences("FREQ=MONTHLY", at(2026, 1, 31), 3),
+    dates(&[(2026, 1, 31), (2026, 3, 31), (2026, 5, 31)])
+  );
+  assert_eq!(
+    occurrences("FREQ=MONTHLY;BYMONTHDAY=-1", at(2026, 1, 31), 3),
+    dates(&[(2026, 1, 31), (2026, 2, 28), (2026, 3, 31)])
+  );
+  assert_eq!(
+    occurrences("FREQ=MONTHLY;BYDAY=-1FR", at(2026, 10, 30), 3),
+    dates(&[(2026, 10, 30), (2026, 11, 27), (2026, 12, 25)])
+  );
+  assert_eq!(
+    occurrences("FREQ=YEARLY;BYMONTH=11;BYDAY=4TH", at(2026, 11, 26), 2),
+    dates(&[(2026, 11, 26), (2027, 11, 25)])
+  );
+  assert_eq!(
+    occurrences("FREQ=DAILY;BYDAY=MO,TU,WE,TH,FR", at(2026, 10, 23), 3),
+    dates(&[(2026, 10, 23), (2026, 10, 26), (2026, 10, 27)])
+  );
+  assert_eq!(
+    occurrences("FREQ=DAILY;COUNT=3", at(2026, 10, 19), 10).len(),
+    3
+  );
+  assert_eq!(
+    occurrences("FREQ=DAILY;UNTIL=20261021", at(2026, 10, 19), 10).len(),
+    3
+  );
+  assert_eq!(
+    occurrences("FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=30", at(2026, 1, 30), 2)
+      .len(),
+    1
+  );
+}
+
+#[test]
+pub fn unsupported_rules_are_rejected() {
+  for rule in [
+    "",
+    "INTERVAL=2",
+    "FREQ=HOURLY",
+    "FREQ=DAILY;INTERVAL=0",
+    "FREQ=DAILY;COUNT=2;UNTIL=20261231",
+    "FREQ=WEEKLY;BYDAY=1MO",
+    "FREQ=YEARLY;BYDAY=MO",
+    "FREQ=MONTHLY;BYMONTHDAY=32",
+    "FREQ=MONTHLY;BYSETPOS=1",
+    "FREQ=DAILY;FREQ=WEEKLY",
+  ] {
+    assert!(
+      rule.parse::<RecurrenceRule>().is_err(),
+      "{:?} was parsed",
+      rule
+    );
+  }
+}
+
+#[test]
+pub fn completing_a_recurring_todo_creates_the_next_occurrence() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let todo = Todo {
+        todo_id: "report".to_string(),
+        title: "Weekly report".to_string(),
+        recurrence_rule: "FREQ=WEEKLY;COUNT=2".to_string(),
+        ..Default::default()
+      };
+      let status = client
+        .create_todo(CreateTodoRequest {
+          todo: Some(todo.clone()),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+
+      let first = client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            due_time: Some(week(0)),
+            reminder_time: Some(Timestamp {
+              seconds: week(0).seconds - 60 * 60,
+              nanos: 0,
+            }),
+            ..todo
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert!(!first.series_id.is_empty());
+
+      let second = complete(&mut client, "report").await.unwrap();
+      assert_eq!(second.title, "Weekly report");
+      assert_eq!(second.recurrence_rule, "FREQ=WEEKLY;COUNT=2");
+      assert_eq!(second.series_id, first.series_id);
+      assert_eq!(second.due_time, Some(week(1)));
+      assert_eq!(
+        second.reminder_time.unwrap().seconds,
+        week(1).seconds - 3600
+      );
+      assert!(!second.completed);
+
+      // Completing the same occurrence again does not create another.
+      let reopen = Todo {
+        todo_id: "report".to_string(),
+        ..Default::default()
+      };
+      update(
+        &mut client,
+        reopen,
+        &["completed"],
+        RecurrenceScope::Unspecified,
+      )
+      .await
+      .unwrap();
+      assert_eq!(complete(&mut client, "report").await, None);
+
+      // The series only has two occurrences.
+      assert_eq!(complete(&mut client, &second.todo_id).await, None);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn updates_apply_to_one_or_all_future_occurrences() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "invoice".to_string(),
+            title: "Send invoice".to_string(),
+            due_time: Some(week(0)),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      // Setting a rule on a todo starts a series.
+      let rule = Todo {
+        todo_id: "invoice".to_string(),
+        recurrence_rule: "FREQ=WEEKLY".to_string(),
+        ..Default::default()
+      };
+      let todo = update(
+        &mut client,
+        rule.clone(),
+        &["recurrence_rule"],
+        RecurrenceScope::Unspecified,
+      )
+      .await
+      .unwrap()
+      .todo
+      .unwrap();
+      assert!(!todo.series_id.is_empty());
+
+      // A change to one occurrence is not copied to the next.
+      let title = Todo {
+        todo_id: "invoice".to_string(),
+        title: "Send the late invoice".to_string(),
+        ..Default::default()
+      };
+      update(
+        &mut client,
+        title,
+        &["title"],
+        RecurrenceScope::ThisOccurrence,
+      )
+      .await
+      .unwrap();
+      let second = complete(&mut client, "invoice").await.unwrap();
+      assert_eq!(second.title, "Send invoice");
+
+      // A change to all future occurrences is.
+      let title = Todo {
+        todo_id: second.todo_id.clone(),
+        title: "Send the invoice".to_string(),
+        ..Default::default()
+      };
+      update(&mut client, title, &["title"], RecurrenceScope::AllFuture)
+        .await
+        .unwrap();
+      let third = complete(&mut client, &second.todo_id).await.unwrap();
+      assert_eq!(third.title, "Send the invoice");
+      assert_eq!(third.due_time, Some(week(2)));
+
+      // The rule can only be changed for the whole series, and restarts it from
+      // the occurrence that was updated.
+      let rule = Todo {
+        todo_id: third.todo_id.clone(),
+        recurrence_rule: "FREQ=WEEKLY;INTERVAL=2".to_string(),
+        ..rule
+      };
+      let status = update(
+        &mut client,
+        rule.clone(),
+        &["recurrence_rule"],
+        RecurrenceScope::ThisOccurrence,
+      )
+      .await
+      .unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+      update(
+        &mut client,
+        rule.clone(),
+        &["recurrence_rule"],
+        RecurrenceScope::AllFuture,
+      )
+      .await
+      .unwrap();
+      let fourth = complete(&mut client, &third.todo_id).await.unwrap();
+      assert_eq!(fourth.due_time, Some(week(4)));
+      assert_eq!(fourth.recurrence_rule, "FREQ=WEEKLY;INTERVAL=2");
+
+      // Clearing the rule ends the series.
+      let clear = Todo {
+        todo_id: fourth.todo_id.clone(),
+        ..Default::default()
+      };
+      update(
+        &mut client,
+        clear,
+        &["recurrence_rule"],
+        RecurrenceScope::AllFuture,
+      )
+      .await
+      .unwrap();
+      assert_eq!(complete(&mut client, &fourth.todo_id).await, None);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
@@ -0,0 +1,376 @@
+mod common;
+
+use axum::http::HeaderMap;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use common::with_test_database;
+use sqlx::query;
+use sqlx::types::time::OffsetDateTime;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use todos_service::config::NotifierConfig;
+use todos_service::config::RemindersConfig;
+use todos_service::reminders::notifiers::EmailNotifier;
+use todos_service::reminders::notifiers::WebhookNotifier;
+use todos_service::reminders::notifiers::IDEMPOTENCY_KEY_HEADER;
+use todos_service::reminders::send_due_reminders;
+use todos_service::reminders::Notifier;
+use todos_service::reminders::Reminder;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::TcpListener;
+
+/// A notifier that records the reminders it is asked to send, and fails if
+/// `fail` is set.
+#[derive(Default)]
+struct RecordingNotifier {
+  sent: Mutex<Vec<Reminder>>,
+  fail: bool,
+  delay: Duration,
+}
+
+impl RecordingNotifier {
+  fn sent_todo_ids(&self) -> Vec<String> {
+    let sent = self.sent.lock().unwrap();
+    sent
+      .iter()
+      .map(|reminder| reminder.todo_id.clone())
+      .collect()
+  }
+}
+
+#[async_trait::async_trait]
+impl Notifier for RecordingNotifier {
+  fn name(&self) -> &'static str {
+    "recording"
+  }
+
+  async fn notify(&self, reminder: &Reminder) -> anyhow::Result<()> {
+    tokio::time::sleep(self.delay).await;
+    self.sent.lock().unwrap().push(reminder.clone());
+    match self.fail {
+      true => Err(anyhow::anyhow!("Notifier is down")),
+      false => Ok(()),
+    }
+  }
+}
+
+/// A configuration that retries immediately, so that the tests do not have to
+/// wait for a retry.
+fn create_test_config() -> RemindersConfig {
+  RemindersConfig {
+    notifier: NotifierConfig::Log,
+    poll_interval: Duration::from_millis(10),
+    batch_size: 10,
+    lease: Duration::from_secs(60),
+    timeout: Duration::from_secs(1),
+    max_attempts: 2,
+    retry_backoff: Duration::ZERO,
+  }
+}
+
+/// Create a todo, as the owner of the table, with a reminder the given number
+/// of minutes from now.
+async fn create_todo(
+  pool: &PgPool,
+  tenant_id: &str,
+  todo_id: &str,
+  reminder_in_minutes: Option<i32>,
+) {
+  query(
+    r#"
+    insert into todos (todo_id, title, description, owner_id, tenant_id, reminder_time)
+    values ($1, $1, '', 'test-user', $2, now() + make_interval(mins => $3))
+    "#,
+  )
+  .bind(todo_id)
+  .bind(tenant_id)
+  .bind(reminder_in_minutes)
+  .execute(pool)
+  .await
+  .unwrap();
+}
+
+/// Get the time that a todo's reminder is next due to be sent.
+async fn get_remind_at(pool: &PgPool, todo_id: &str) -> Option<OffsetDateTime> {
+  query!("select remind_at from todos where todo_id = $1", todo_id)
+    .fetch_one(pool)
+    .await
+    .unwrap()
+    .remind_at
+}
+
+/// Create a reminder to send directly with a notifier.
+fn create_reminder() -> Reminder {
+  Reminder {
+    todo_id: "report".to_string(),
+    tenant_id: "test-tenant".to_string(),
+    owner_id: "test-user".to_string(),
+    title: "Send the report".to_string(),
+    description: ".hidden line".to_string(),
+    due_time: Some(OffsetDateTime::from_unix_timestamp(1_800_000_000).unwrap()),
+    reminder_time: OffsetDateTime::from_unix_timestamp(1_799_990_000).unwrap(),
+    attempt: 1,
+  }
+}
+
+#[test]
+pub fn due_reminders_of_every_tenant_are_sent_once() {
+  with_test_database(|pool| async move {
+    create_todo(&pool, "tenant-1", "due-1", Some(-5)).await;
+    create_todo(&pool, "tenant-2", "due-2", Some(-1)).await;
+    create_todo(&pool, "tenant-1", "later", Some(60)).await;
+    create_todo(&pool, "tenant-1", "no-reminder", None).await;
+    create_todo(&pool, "tenant-1", "completed", Some(-1)).await;
+    query("update todos set completed = true where todo_id = 'completed'")
+      .execute(&pool)
+      .await
+      .unwrap();
+    let updated_at =
+      query!("select updated_at from todos where todo_id = 'due-1'")
+        .fetch_one(&pool)
+        .await
+        .unwrap()
+        .updated_at;
+
+    let notifier = RecordingNotifier::default();
+    let config = create_test_config();
+
+    let count = send_due_reminders(&pool, &config, &notifier).await.unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(notifier.sent_todo_ids(), ["due-1", "due-2"]);
+    assert_eq!(notifier.sent.lock().unwrap()[1].tenant_id, "tenant-2");
+    assert_eq!(get_remind_at(&pool, "due-1").await, None);
+    assert!(get_remind_at(&pool, "later").await.is_some());
+
+    let count = send_due_reminders(&pool, &config, &notifier).await.unwrap();
+    assert_eq!(count, 0);
+
+    let deliveries = query!(
+      "select todo_id, attempt, notifier, error from reminder_deliveries order by todo_id"
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(deliveries.len(), 2);
+    assert_eq!(deliveries[0].todo_id, "due-1");
+    assert_eq!(deliveries[0].attempt, 1);
+    assert_eq!(deliveries[0].notifier, "recording");
+    assert_eq!(deliveries[0].error, None);
+
+    // Sending the reminder is not a change to the todo.
+    let record = query!("select updated_at from todos where todo_id = 'due-1'")
+      .fetch_one(&pool)
+      .await
+      .unwrap();
+    assert_eq!(record.updated_at, updated_at);
+  });
+}
+
+#[test]
+pub fn concurrent_schedulers_do_not_send_a_reminder_twice() {
+  with_test_database(|pool| async move {
+    for i in 0..20 {
+      create_todo(&pool, "tenant-1", &format!("todo-{}", i), Some(-1)).await;
+    }
+
+    let notifier = RecordingNotifier {
+      delay: Duration::from_millis(20),
+      ..Default::default()
+    };
+    let config = RemindersConfig {
+      batch_size: 3,
+      ..create_test_config()
+    };
+
+    // Each scheduler keeps going until it finds no more due reminders.
+    let scheduler = || async {
+      while send_due_reminders(&pool, &config, &notifier).await.unwrap() > 0 {}
+    };
+    tokio::join!(scheduler(), scheduler(), scheduler());
+
+    let sent = notifier.sent_todo_ids();
+    let unique = sent.iter().collect::<HashSet<_>>();
+    assert_eq!(sent.len(), 20);
+    assert_eq!(unique.len(), 20);
+  });
+}
+
+#[test]
+pub fn failed_reminders_are_retried_until_attempts_run_out() {
+  with_test_database(|pool| async move {
+    create_todo(&pool, "tenant-1", "flaky", Some(-1)).await;
+
+    let notifier = RecordingNotifier {
+      fail: true,
+      ..Default::default()
+    };
+    let config = create_test_config();
+
+    assert_eq!(
+      send_due_reminders(&pool, &config, &notifier).await.unwrap(),
+      1
+    );
+    assert!(get_remind_at(&pool, "flaky").await.is_some());
+    assert_eq!(
+      send_due_reminders(&pool, &config, &notifier).await.unwrap(),
+      1
+    );
+    assert_eq!(get_remind_at(&pool, "flaky").await, None);
+    assert_eq!(
+      send_due_reminders(&pool, &config, &notifier).await.unwrap(),
+      0
+    );
+
+    let attempts = notifier
+      .sent
+      .lock()
+      .unwrap()
+      .iter()
+      .map(|reminder| reminder.attempt)
+      .collect::<Vec<_>>();
+    assert_eq!(attempts, [1, 2]);
+
+    let errors =
+      query!("select error from reminder_deliveries order by attempt")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[1].error.as_deref(), Some("Notifier is down"));
+  });
+}
+
+#[test]
+pub fn changing_the_reminder_time_schedules_it_again() {
+  with_test_database(|pool| async move {
+    create_todo(&pool, "tenant-1", "weekly", Some(-1)).await;
+    let notifier = RecordingNotifier::default();
+    let config = create_test_config();
+
+    send_due_reminders(&pool, &config, &notifier).await.unwrap();
+    assert_eq!(get_remind_at(&pool, "weekly").await, None);
+
+    // Changing other fields does not send the reminder again.
+    query("update todos set title = 'renamed' where todo_id = 'weekly'")
+      .execute(&pool)
+      .await
+      .unwrap();
+    assert_eq!(get_remind_at(&pool, "weekly").await, None);
+
+    query(
+      "update todos set reminder_time = now() - interval '1 second' where todo_id = 'weekly'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    send_due_reminders(&pool, &config, &notifier).await.unwrap();
+
+    let sent = notifier.sent.lock().unwrap();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[1].title, "renamed");
+    assert_eq!(sent[1].attempt, 1);
+  });
+}
+
+#[tokio::test]
+pub async fn webhook_notifier_posts_the_reminder() {
+  let received = Arc::new(Mutex::new(Vec::new()));
+  let app = Router::new()
+    .route(
+      "/reminders",
+      post({
+        let received = received.clone();
+        move |headers: HeaderMap, body: String| async move {
+          let key = headers[IDEMPOTENCY_KEY_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+          received.lock().unwrap().push((key, body));
+          StatusCode::NO_CONTENT
+        }
+      }),
+    )
+    .route("/down", post(|| async { StatusCode::SERVICE_UNAVAILABLE }));
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let address = listener.local_addr().unwrap();
+  tokio::spawn(async move { axum::serve(listener, app).await });
+
+  let notifier = WebhookNotifier::new(
+    &format!("http://{}/reminders", address),
+    Duration::from_secs(1),
+  )
+  .unwrap();
+  notifier.notify(&create_reminder()).await.unwrap();
+
+  let (key, body) = received.lock().unwrap().remove(0);
+  let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+  assert_eq!(key, "test-tenant/test-user/report/2027-01-15T05:13:20Z");
+  assert_eq!(body["todoId"], "report");
+  assert_eq!(body["ownerId"], "test-user");
+  assert_eq!(body["dueTime"], "2027-01-15T08:00:00Z");
+  assert_eq!(body["attempt"], 1);
+
+  let notifier = WebhookNotifier::new(
+    &format!("http://{}/down", address),
+    Duration::from_secs(1),
+  )
+  .unwrap();
+  assert!(notifier.notify(&create_reminder()).await.is_err());
+}
+
+#[tokio::test]
+pub async fn email_notifier_sends_mail_to_the_relay() {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let address = listener.local_addr().unwrap();
+
+  // A relay that accepts every command, and returns the commands and data
+  // that it received.
+  let relay = tokio::spawn(async move {
+    let (stream, _) = listener.accept().await.unwrap();
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut received = Vec::new();
+
+    writer.write_all(b"220 relay ready\r\n").await.unwrap();
+    while let Some(line) = lines.next_line().await.unwrap() {
+      let reply: &[u8] = match line.as_str() {
+        "DATA" => b"354 go ahead\r\n",
+        "." => b"250 queued\r\n",
+        "QUIT" => b"221 bye\r\n",
+        line if line.starts_with("EHLO") => b"250-relay\r\n250 8BITMIME\r\n",
+        line if line.starts_with("MAIL") || line.starts_with("RCPT") => {
+          b"250 ok\r\n"
+        }
+        _ => b"",
+      };
+      received.push(line.clone());
+      writer.write_all(reply).await.unwrap();
+      if line == "QUIT" {
+        break;
+      }
+    }
+    received
+  });
+
+  let notifier = EmailNotifier::new(
+    "127.0.0.1",
+    address.port(),
+    "todos@example.com",
+    Some("example.com"),
+  );
+  notifier.notify(&create_reminder()).await.unwrap();
+
+  let received = relay.await.unwrap();
+  assert!(received.contains(&"MAIL FROM:<todos@example.com>".to_string()));
+  assert!(received.contains(&"RCPT TO:<test-user@example.com>".to_string()));
+  assert!(received.contains(&"Subject: Reminder: Send the report".to_string()));
+  assert!(received.contains(&"Due: 2027-01-15T08:00:00Z".to_string()));
+  // The line of the description that starts with a dot is escaped.
+  assert!(received.contains(&"..hidden line".to_string()));
+}
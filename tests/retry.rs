@@ -0,0 +1,210 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use sqlx::PgPool;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::retry::is_transient;
+use todos_service::retry::RetryPolicy;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::Status;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+/// A policy that retries quickly, so that the tests do not have to wait long.
+fn create_test_policy() -> RetryPolicy {
+  RetryPolicy {
+    attempts: 3,
+    backoff: Duration::from_millis(1),
+    max_backoff: Duration::from_millis(5),
+  }
+}
+
+/// Raise an exception with the given SQLSTATE in the database, so that the
+/// error is the same as the one that the database would report.
+async fn raise(pool: &PgPool, sqlstate: &str) -> anyhow::Result<()> {
+  sqlx::query(&format!(
+    "do $$ begin raise exception 'test' using errcode = '{}'; end $$",
+    sqlstate
+  ))
+  .execute(pool)
+  .await?;
+
+  Ok(())
+}
+
+#[test]
+pub fn transient_errors_are_retried() {
+  with_test_database(|pool| async move {
+    let attempts = AtomicU32::new(0);
+
+    let result = create_test_policy()
+      .run(|| async {
+        // Fail with a serialization failure and then a deadlock, before
+        // succeeding on the last attempt.
+        match attempts.fetch_add(1, Ordering::SeqCst) {
+          0 => raise(&pool, "40001").await?,
+          1 => raise(&pool, "40P01").await?,
+          _ => (),
+        }
+        Ok("done")
+      })
+      .await;
+
+    assert_eq!(result.unwrap(), "done");
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+  });
+}
+
+#[test]
+pub fn retries_stop_after_the_last_attempt() {
+  with_test_database(|pool| async move {
+    let attempts = AtomicU32::new(0);
+
+    let error = create_test_policy()
+      .run(|| async {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        raise(&pool, "40001").await
+      })
+      .await
+      .unwrap_err();
+
+    assert!(is_transient(&error));
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+  });
+}
+
+#[test]
+pub fn other_errors_are_not_retried() {
+  with_test_database(|pool| async move {
+    // A unique violation will fail again, however many times it is retried.
+    let attempts = AtomicU32::new(0);
+    let error = create_test_policy()
+      .run(|| async {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        raise(&pool, "23505").await
+      })
+      .await
+      .unwrap_err();
+    assert!(!is_transient(&error));
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+    // Statuses returned by the handlers are never retried.
+    let attempts = AtomicU32::new(0);
+    let error = create_test_policy()
+      .run(|| async {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        Err::<(), _>(Status::not_found("Todo not found").into())
+      })
+      .await
+      .unwrap_err();
+    assert!(!is_transient(&error));
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+  });
+}
+
+#[test]
+pub fn dropped_connections_are_transient() {
+  with_test_database(|pool| async move {
+    let error = sqlx::query("select pg_terminate_backend(pg_backend_pid())")
+      .execute(&pool)
+      .await
+      .unwrap_err();
+
+    assert!(is_transient(&error.into()), "not transient");
+  });
+}
+
+#[test]
+pub fn never_policy_does_not_retry() {
+  with_test_database(|pool| async move {
+    let attempts = AtomicU32::new(0);
+
+    RetryPolicy::never()
+      .run(|| async {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        raise(&pool, "40001").await
+      })
+      .await
+      .unwrap_err();
+
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+  });
+}
+
+#[test]
+pub fn create_with_request_id_is_idempotent() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+      let create_request =
+        |todo_id: &str, request_id: &str| CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: todo_id.to_string(),
+            title: "Idempotent todo".to_string(),
+            ..Default::default()
+          }),
+          request_id: request_id.to_string(),
+          ..Default::default()
+        };
+
+      let first = client
+        .create_todo(create_request("first-todo", "request-1"))
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+
+      // A retry of the same request returns the todo that was created, even
+      // though it would otherwise conflict with it.
+      let retried = client
+        .create_todo(create_request("first-todo", "request-1"))
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(retried, first);
+
+      client
+        .create_todo(create_request("second-todo", "request-2"))
+        .await
+        .unwrap();
+      client
+        .create_todo(create_request("third-todo", ""))
+        .await
+        .unwrap();
+
+      let response = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
+      assert_eq!(response.into_inner().todos.len(), 3);
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
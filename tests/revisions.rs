@@ -0,0 +1,387 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use prost_types::FieldMask;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::Comment;
+use todos_service::proto::v1::todos::CompleteTodoRequest;
+use todos_service::proto::v1::todos::CreateCommentRequest;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::DeleteTodoRequest;
+use todos_service::proto::v1::todos::GetTodoRevisionRequest;
+use todos_service::proto::v1::todos::ListTodoRevisionsRequest;
+use todos_service::proto::v1::todos::Priority;
+use todos_service::proto::v1::todos::RestoreTodoRevisionRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::UpdateTodoRequest;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+const OTHER_TENANT_ID: &str = "other-tenant";
+const OTHER_USER_ID: &str = "other-user";
+
+#[test]
+pub fn todos_can_be_restored_to_a_revision() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let created = client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy milk".to_string(),
+            labels: vec!["home".to_string()],
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      client
+        .update_todo(UpdateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy oat milk".to_string(),
+            priority: Priority::High.into(),
+            ..Default::default()
+          }),
+          update_mask: Some(FieldMask {
+            paths: vec![
+              "title".to_string(),
+              "priority".to_string(),
+              "labels".to_string(),
+            ],
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      // Each change is a revision, most recent first.
+      let revisions = client
+        .list_todo_revisions(ListTodoRevisionsRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .revisions;
+      assert_eq!(revisions.len(), 2);
+      assert!(revisions[0].revision_id > revisions[1].revision_id);
+      assert_eq!(revisions[0].actor_id, TEST_USER_ID);
+      let todo = revisions[0].todo.as_ref().unwrap();
+      assert_eq!(todo.title, "Buy oat milk");
+      assert_eq!(todo.priority(), Priority::High);
+      assert!(todo.labels.is_empty());
+
+      // A revision holds the whole todo as it was.
+      let first_revision_id = revisions[1].revision_id;
+      let revision = client
+        .get_todo_revision(GetTodoRevisionRequest {
+          name: "lists/inbox/todos/groceries".to_string(),
+          revision_id: first_revision_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .revision
+        .unwrap();
+      assert_eq!(revision.todo.unwrap(), created);
+
+      // Restoring a revision records a new one.
+      let todo = client
+        .restore_todo_revision(RestoreTodoRevisionRequest {
+          todo_id: "groceries".to_string(),
+          revision_id: first_revision_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.title, "Buy milk");
+      assert_eq!(todo.priority(), Priority::Unspecified);
+      assert_eq!(todo.labels, ["home"]);
+      let response = client
+        .list_todo_revisions(ListTodoRevisionsRequest {
+          todo_id: "groceries".to_string(),
+          page_size: 1,
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+      assert!(!response.next_page_token.is_empty());
+      let restored = response.revisions[0].todo.as_ref().unwrap();
+      assert_eq!(restored.title, "Buy milk");
+      assert_eq!(restored.labels, ["home"]);
+
+      let status = client
+        .get_todo_revision(GetTodoRevisionRequest {
+          todo_id: "groceries".to_string(),
+          revision_id: first_revision_id - 1,
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+
+      // The revisions of a deleted todo can be listed, but not restored.
+      client
+        .delete_todo(DeleteTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      let revisions = client
+        .list_todo_revisions(ListTodoRevisionsRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .revisions;
+      assert_eq!(revisions.len(), 3);
+      let status = client
+        .restore_todo_revision(RestoreTodoRevisionRequest {
+          todo_id: "groceries".to_string(),
+          revision_id: first_revision_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn restoring_a_revision_undoes_later_changes() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(CreateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy milk".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      let completed = client
+        .complete_todo(CompleteTodoRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      client
+        .create_comment(CreateCommentRequest {
+          todo_id: "groceries".to_string(),
+          comment: Some(Comment {
+            body: "Oat or dairy?".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      client
+        .update_todo(UpdateTodoRequest {
+          todo: Some(Todo {
+            todo_id: "groceries".to_string(),
+            title: "Buy oat milk".to_string(),
+            completed: false,
+            ..Default::default()
+          }),
+          update_mask: Some(FieldMask {
+            paths: vec!["title".to_string(), "completed".to_string()],
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+
+      // Comments are not recorded in the revisions.
+      let revisions = client
+        .list_todo_revisions(ListTodoRevisionsRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .revisions;
+      assert_eq!(revisions.len(), 3);
+      assert!(revisions.iter().all(|revision| revision
+        .todo
+        .as_ref()
+        .unwrap()
+        .comment_count
+        == 0));
+
+      // Restoring the completed revision undoes the later edit, and keeps the
+      // time that the todo was completed at.
+      let todo = client
+        .restore_todo_revision(RestoreTodoRevisionRequest {
+          name: "lists/inbox/todos/groceries".to_string(),
+          revision_id: revisions[1].revision_id,
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.title, "Buy milk");
+      assert!(todo.completed);
+      assert_eq!(todo.complete_time, completed.complete_time);
+      assert_eq!(todo.comment_count, 1);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn only_known_revisions_of_the_callers_todos_can_be_restored() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      for todo_id in ["groceries", "laundry"] {
+        client
+          .create_todo(CreateTodoRequest {
+            todo: Some(Todo {
+              todo_id: todo_id.to_string(),
+              title: todo_id.to_string(),
+              ..Default::default()
+            }),
+            ..Default::default()
+          })
+          .await
+          .unwrap();
+      }
+      let revision_id = client
+        .list_todo_revisions(ListTodoRevisionsRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .revisions[0]
+        .revision_id;
+
+      let request = |todo_id: &str, revision_id| RestoreTodoRevisionRequest {
+        todo_id: todo_id.to_string(),
+        revision_id,
+        ..Default::default()
+      };
+
+      // The revision must exist, and be a revision of the same todo.
+      let status = client
+        .restore_todo_revision(request("groceries", revision_id + 100))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = client
+        .restore_todo_revision(request("laundry", revision_id))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+
+      // Other users and tenants cannot restore the todo.
+      for (tenant_id, user_id) in [
+        (TEST_TENANT_ID, OTHER_USER_ID),
+        (OTHER_TENANT_ID, TEST_USER_ID),
+      ] {
+        let mut other_client = TodoServiceClient::with_interceptor(
+          channel.clone(),
+          as_user(tenant_id, user_id),
+        );
+        let status = other_client
+          .restore_todo_revision(request("groceries", revision_id))
+          .await
+          .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+      }
+
+      // Failed restores do not record a revision.
+      let revisions = client
+        .list_todo_revisions(ListTodoRevisionsRequest {
+          todo_id: "groceries".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .revisions;
+      assert_eq!(revisions.len(), 1);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
@@ -0,0 +1,329 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use todos_service::config::TodosConfig;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::SearchTodosRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::codegen::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+const OTHER_USER_ID: &str = "other-user";
+
+/// Create a todo with the given ID, title, description and labels.
+async fn create<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo_id: &str,
+  title: &str,
+  description: &str,
+  labels: &[&str],
+) {
+  let request = CreateTodoRequest {
+    todo: Some(Todo {
+      todo_id: todo_id.to_string(),
+      title: title.to_string(),
+      description: description.to_string(),
+      labels: labels.iter().map(|label| label.to_string()).collect(),
+      ..Default::default()
+    }),
+    ..Default::default()
+  };
+
+  client.create_todo(request).await.unwrap();
+}
+
+/// Search the caller's todos, returning the IDs of the todos found.
+async fn search<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  request: SearchTodosRequest,
+) -> Result<Vec<String>, tonic::Status> {
+  let results = client.search_todos(request).await?.into_inner().results;
+
+  Ok(
+    results
+      .into_iter()
+      .map(|result| result.todo.unwrap().todo_id)
+      .collect(),
+  )
+}
+
+/// Create a search request for the given query.
+fn query(query: &str) -> SearchTodosRequest {
+  SearchTodosRequest {
+    query: query.to_string(),
+    ..Default::default()
+  }
+}
+
+#[test]
+pub fn todos_can_be_searched() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create(&mut client, "milk", "Buy milk", "Semi-skimmed", &["home"]).await;
+      create(&mut client, "groceries", "Buying groceries", "", &[]).await;
+      create(
+        &mut client,
+        "dog",
+        "Walk the dog",
+        "Buy dog food on the way back from the park",
+        &["home"],
+      )
+      .await;
+      create(&mut client, "call", "Call the plumber", "", &[]).await;
+
+      // Words match by their stems, and matches in the title rank above
+      // matches in the description.
+      let response = client
+        .search_todos(query("buy"))
+        .await
+        .unwrap()
+        .into_inner();
+      let todo_ids = response
+        .results
+        .iter()
+        .map(|result| result.todo.as_ref().unwrap().todo_id.as_str())
+        .collect::<Vec<_>>();
+      assert_eq!(todo_ids.len(), 3);
+      assert_eq!(todo_ids[2], "dog");
+      assert!(response.results[0].rank >= response.results[1].rank);
+      assert!(response.results[1].rank > response.results[2].rank);
+
+      let milk = &response
+        .results
+        .iter()
+        .find(|result| result.todo.as_ref().unwrap().todo_id == "milk")
+        .unwrap();
+      assert_eq!(milk.title_snippet, "<mark>Buy</mark> milk");
+      assert_eq!(milk.description_snippet, "Semi-skimmed");
+      let dog = &response.results[2];
+      assert_eq!(dog.title_snippet, "Walk the dog");
+      assert!(dog
+        .description_snippet
+        .contains("<mark>Buy</mark> dog food"));
+
+      // Queries use web search syntax.
+      let todo_ids = search(&mut client, query("\"buy milk\"")).await.unwrap();
+      assert_eq!(todo_ids, ["milk"]);
+      let mut todo_ids = search(&mut client, query("buy -milk")).await.unwrap();
+      todo_ids.sort();
+      assert_eq!(todo_ids, ["dog", "groceries"]);
+      let todo_ids =
+        search(&mut client, query("plumber or park")).await.unwrap();
+      assert_eq!(todo_ids, ["call", "dog"]);
+
+      // The same filters as ListTodos apply.
+      let todo_ids = search(
+        &mut client,
+        SearchTodosRequest {
+          labels: vec!["home".to_string()],
+          ..query("buy")
+        },
+      )
+      .await
+      .unwrap();
+      assert_eq!(todo_ids, ["milk", "dog"]);
+      let status = search(
+        &mut client,
+        SearchTodosRequest {
+          parent: "lists/unknown".to_string(),
+          ..query("buy")
+        },
+      )
+      .await
+      .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+
+      // The results can be listed a page at a time.
+      let mut page_token = String::new();
+      let mut todo_ids = Vec::new();
+      loop {
+        let response = client
+          .search_todos(SearchTodosRequest {
+            page_size: 2,
+            page_token,
+            ..query("buy")
+          })
+          .await
+          .unwrap()
+          .into_inner();
+        todo_ids.extend(
+          response
+            .results
+            .into_iter()
+            .map(|result| result.todo.unwrap().todo_id),
+        );
+        if response.next_page_token.is_empty() {
+          break;
+        }
+        page_token = response.next_page_token;
+      }
+      assert_eq!(todo_ids.len(), 3);
+      assert_eq!(todo_ids[2], "dog");
+
+      let status = search(&mut client, query(" ")).await.unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+
+      // Other users cannot find the todos.
+      let mut other_client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, OTHER_USER_ID),
+      );
+      let todo_ids = search(&mut other_client, query("buy")).await.unwrap();
+      assert!(todo_ids.is_empty());
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn search_language_can_be_configured() {
+  with_test_database(|pool| async move {
+    let options = ServiceOptions {
+      todos: TodosConfig {
+        search_language: "simple",
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let (server_future, channel) = create_test_server(
+      TodoServiceHandler::create_server(pool.clone(), &options),
+    )
+    .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create(&mut client, "groceries", "Buying groceries", "", &[]).await;
+      create(&mut client, "water", "Water the plants", "", &[]).await;
+
+      // The simple configuration neither stems words nor ignores stop words.
+      let todo_ids = search(&mut client, query("buy")).await.unwrap();
+      assert!(todo_ids.is_empty());
+      let todo_ids = search(&mut client, query("buying")).await.unwrap();
+      assert_eq!(todo_ids, ["groceries"]);
+      let todo_ids = search(&mut client, query("the")).await.unwrap();
+      assert_eq!(todo_ids, ["water"]);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn queries_are_parsed_in_the_language_of_each_todo() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create(&mut client, "groceries", "Buying groceries", "", &[]).await;
+      create(&mut client, "water", "Buying water", "", &[]).await;
+      sqlx::query!(
+        "update todos set search_language = 'simple' where todo_id = 'water'"
+      )
+      .execute(&pool)
+      .await
+      .unwrap();
+
+      // The query is stemmed for the English todo, but not the simple one.
+      let mut todo_ids = search(&mut client, query("buying")).await.unwrap();
+      todo_ids.sort();
+      assert_eq!(todo_ids, ["groceries", "water"]);
+      let todo_ids = search(&mut client, query("buy")).await.unwrap();
+      assert_eq!(todo_ids, ["groceries"]);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn snippets_are_escaped_as_html() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create(
+        &mut client,
+        "script",
+        "<script>alert(1)</script> Buy bread",
+        "<b>Butter</b> & Tom's \"bread\"",
+        &[],
+      )
+      .await;
+
+      let response = client
+        .search_todos(query("bread"))
+        .await
+        .unwrap()
+        .into_inner();
+      let result = &response.results[0];
+      assert_eq!(
+        result.title_snippet,
+        "&lt;script&gt;alert(1)&lt;/script&gt; Buy <mark>bread</mark>"
+      );
+      assert_eq!(
+        result.description_snippet,
+        "Butter&lt;/b&gt; &amp; Tom&#39;s &quot;<mark>bread</mark>"
+      );
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
@@ -0,0 +1,327 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use prost_types::FieldMask;
+use prost_types::Timestamp;
+use todos_service::config::TodosConfig;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::DeleteTodoRequest;
+use todos_service::proto::v1::todos::GetTodoRequest;
+use todos_service::proto::v1::todos::GetTodoResponse;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::UpdateTodoRequest;
+use todos_service::services::todos::SubtaskCompletion;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::codegen::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+/// Create the options for a server with the given subtask rules.
+fn create_options(
+  max_subtask_depth: u32,
+  subtask_completion: SubtaskCompletion,
+) -> ServiceOptions {
+  ServiceOptions {
+    todos: TodosConfig {
+      max_subtask_depth,
+      subtask_completion,
+      ..Default::default()
+    },
+    ..Default::default()
+  }
+}
+
+/// Create a todo with the given ID as a subtask of the given parent, or as a
+/// top-level todo if the parent is empty.
+async fn create<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo_id: &str,
+  parent_todo_id: &str,
+) -> Result<Todo, tonic::Status> {
+  let request = CreateTodoRequest {
+    todo: Some(Todo {
+      todo_id: todo_id.to_string(),
+      title: todo_id.to_string(),
+      parent_todo_id: parent_todo_id.to_string(),
+      ..Default::default()
+    }),
+    ..Default::default()
+  };
+
+  Ok(
+    client
+      .create_todo(request)
+      .await?
+      .into_inner()
+      .todo
+      .unwrap(),
+  )
+}
+
+/// Update a single field of the todo with the given ID.
+async fn update<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo: Todo,
+  path: &str,
+) -> Result<Todo, tonic::Status> {
+  let request = UpdateTodoRequest {
+    todo: Some(todo),
+    update_mask: Some(FieldMask {
+      paths: vec![path.to_string()],
+    }),
+    ..Default::default()
+  };
+
+  Ok(
+    client
+      .update_todo(request)
+      .await?
+      .into_inner()
+      .todo
+      .unwrap(),
+  )
+}
+
+/// Set whether the todo with the given ID is completed.
+async fn set_completed<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo_id: &str,
+  completed: bool,
+) -> Result<Todo, tonic::Status> {
+  let todo = Todo {
+    todo_id: todo_id.to_string(),
+    completed,
+    ..Default::default()
+  };
+
+  update(client, todo, "completed").await
+}
+
+/// Get the todo with the given ID and its subtasks.
+async fn get_with_subtasks<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  todo_id: &str,
+) -> Result<GetTodoResponse, tonic::Status> {
+  let request = GetTodoRequest {
+    todo_id: todo_id.to_string(),
+    include_subtasks: true,
+    ..Default::default()
+  };
+
+  Ok(client.get_todo(request).await?.into_inner())
+}
+
+#[test]
+pub fn subtasks_form_a_tree() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &create_options(2, SubtaskCompletion::Independent),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create(&mut client, "trip", "").await.unwrap();
+      create(&mut client, "pack", "trip").await.unwrap();
+      create(&mut client, "book", "trip").await.unwrap();
+      let todo = create(&mut client, "socks", "pack").await.unwrap();
+      assert_eq!(todo.parent_todo_id, "pack");
+
+      // The subtasks are listed depth first, in the order of their positions.
+      let response = get_with_subtasks(&mut client, "trip").await.unwrap();
+      let subtasks = response
+        .subtasks
+        .iter()
+        .map(|todo| (todo.todo_id.as_str(), todo.parent_todo_id.as_str()))
+        .collect::<Vec<_>>();
+      assert_eq!(
+        subtasks,
+        [("pack", "trip"), ("socks", "pack"), ("book", "trip")]
+      );
+      let response = client
+        .get_todo(GetTodoRequest {
+          todo_id: "trip".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner();
+      assert!(response.subtasks.is_empty());
+
+      // Subtasks cannot be nested deeper than the maximum.
+      let status = create(&mut client, "wool", "socks").await.unwrap_err();
+      assert_eq!(status.code(), Code::FailedPrecondition);
+      let status = create(&mut client, "lost", "unknown").await.unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+
+      // A todo cannot become a subtask of itself or of its own subtasks.
+      let reparent = |todo_id: &str, parent_todo_id: &str| Todo {
+        todo_id: todo_id.to_string(),
+        parent_todo_id: parent_todo_id.to_string(),
+        ..Default::default()
+      };
+      for parent_todo_id in ["pack", "socks"] {
+        let status = update(
+          &mut client,
+          reparent("pack", parent_todo_id),
+          "parent_todo_id",
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{}", parent_todo_id);
+      }
+      // Moving pack below book would nest socks too deep.
+      let status =
+        update(&mut client, reparent("pack", "book"), "parent_todo_id")
+          .await
+          .unwrap_err();
+      assert_eq!(status.code(), Code::FailedPrecondition);
+      let todo =
+        update(&mut client, reparent("socks", "book"), "parent_todo_id")
+          .await
+          .unwrap();
+      assert_eq!(todo.parent_todo_id, "book");
+      let todo = update(&mut client, reparent("socks", ""), "parent_todo_id")
+        .await
+        .unwrap();
+      assert_eq!(todo.parent_todo_id, "");
+
+      // Subtasks cannot repeat.
+      let todo = Todo {
+        todo_id: "book".to_string(),
+        due_time: Some(Timestamp {
+          seconds: 4_000_000_000,
+          nanos: 0,
+        }),
+        recurrence_rule: "FREQ=WEEKLY".to_string(),
+        ..Default::default()
+      };
+      let request = UpdateTodoRequest {
+        todo: Some(todo),
+        update_mask: Some(FieldMask {
+          paths: vec!["due_time".to_string(), "recurrence_rule".to_string()],
+        }),
+        ..Default::default()
+      };
+      let status = client.update_todo(request).await.unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+
+      // Deleting a todo deletes its subtasks.
+      client
+        .delete_todo(DeleteTodoRequest {
+          todo_id: "trip".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      let status = get_with_subtasks(&mut client, "pack").await.unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      get_with_subtasks(&mut client, "socks").await.unwrap();
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn completing_a_todo_can_require_its_subtasks() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &create_options(3, SubtaskCompletion::RequireSubtasks),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create(&mut client, "trip", "").await.unwrap();
+      create(&mut client, "pack", "trip").await.unwrap();
+      create(&mut client, "socks", "pack").await.unwrap();
+
+      let status = set_completed(&mut client, "trip", true).await.unwrap_err();
+      assert_eq!(status.code(), Code::FailedPrecondition);
+      set_completed(&mut client, "socks", true).await.unwrap();
+      set_completed(&mut client, "pack", true).await.unwrap();
+      set_completed(&mut client, "trip", true).await.unwrap();
+
+      // Reopening or adding a subtask reopens the todos above it.
+      set_completed(&mut client, "socks", false).await.unwrap();
+      let response = get_with_subtasks(&mut client, "trip").await.unwrap();
+      assert!(!response.todo.unwrap().completed);
+      assert!(!response.subtasks[0].completed);
+
+      set_completed(&mut client, "socks", true).await.unwrap();
+      set_completed(&mut client, "pack", true).await.unwrap();
+      set_completed(&mut client, "trip", true).await.unwrap();
+      create(&mut client, "book", "trip").await.unwrap();
+      let response = get_with_subtasks(&mut client, "trip").await.unwrap();
+      assert!(!response.todo.unwrap().completed);
+      assert!(response.subtasks[0].completed);
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn completing_a_todo_can_complete_its_subtasks() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &create_options(3, SubtaskCompletion::CompleteSubtasks),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create(&mut client, "trip", "").await.unwrap();
+      create(&mut client, "pack", "trip").await.unwrap();
+      create(&mut client, "socks", "pack").await.unwrap();
+
+      set_completed(&mut client, "trip", true).await.unwrap();
+      let response = get_with_subtasks(&mut client, "trip").await.unwrap();
+      assert!(response.subtasks.iter().all(|todo| todo.completed));
+
+      // Reopening a todo leaves its subtasks as they are.
+      set_completed(&mut client, "trip", false).await.unwrap();
+      let response = get_with_subtasks(&mut client, "trip").await.unwrap();
+      assert!(!response.todo.unwrap().completed);
+      assert!(response.subtasks.iter().all(|todo| todo.completed));
+    };
+
+    tokio::select! {
+      _ = server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
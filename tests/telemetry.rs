@@ -0,0 +1,115 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::GetTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use todos_service::telemetry::TraceLayer;
+use todos_service::telemetry::REQUEST_ID_METADATA_KEY;
+use tonic::Code;
+use tonic::Request;
+use tower::Layer;
+use uuid::Uuid;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+#[test]
+pub fn request_id_is_generated_when_absent() {
+  with_test_database(|pool| async move {
+    let service = TraceLayer::new().layer(TodoServiceHandler::create_server(
+      pool,
+      &ServiceOptions::default(),
+    ));
+    let (server_future, channel) = create_test_server(service).await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let first = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
+      let second = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
+      let first_id = first.metadata().get(REQUEST_ID_METADATA_KEY).unwrap();
+      let second_id = second.metadata().get(REQUEST_ID_METADATA_KEY).unwrap();
+
+      assert!(Uuid::parse_str(first_id.to_str().unwrap()).is_ok());
+      assert_ne!(first_id, second_id);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn request_id_is_echoed_back() {
+  with_test_database(|pool| async move {
+    let service = TraceLayer::new().layer(TodoServiceHandler::create_server(
+      pool,
+      &ServiceOptions::default(),
+    ));
+    let (server_future, channel) = create_test_server(service).await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let mut request = Request::new(ListTodosRequest::default());
+      request
+        .metadata_mut()
+        .insert(REQUEST_ID_METADATA_KEY, "test-request".parse().unwrap());
+      let response = client.list_todos(request).await.unwrap();
+      assert_eq!(
+        response.metadata().get(REQUEST_ID_METADATA_KEY).unwrap(),
+        "test-request"
+      );
+
+      // Failed requests also include the request ID.
+      let mut request = Request::new(GetTodoRequest {
+        todo_id: "test-id".to_string(),
+        ..Default::default()
+      });
+      request
+        .metadata_mut()
+        .insert(REQUEST_ID_METADATA_KEY, "test-failure".parse().unwrap());
+      let status = client.get_todo(request).await.unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      assert_eq!(
+        status.metadata().get(REQUEST_ID_METADATA_KEY).unwrap(),
+        "test-failure"
+      );
+
+      // Including requests rejected by the authentication layer.
+      let mut anonymous_client = TodoServiceClient::new(channel);
+      let status = anonymous_client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::Unauthenticated);
+      assert!(status.metadata().get(REQUEST_ID_METADATA_KEY).is_some());
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
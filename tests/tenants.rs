@@ -0,0 +1,224 @@
+mod common;
+
+use common::as_user;
+use common::create_test_server;
+use common::with_test_database;
+use sqlx::query;
+use sqlx::PgPool;
+use todos_service::database::begin_tenant_transaction;
+use todos_service::proto;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const OTHER_TENANT_ID: &str = "other-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+#[test]
+pub fn queries_only_see_rows_of_their_tenant() {
+  with_test_database(|pool| async move {
+    create_test_record(&pool, "test-id", TEST_TENANT_ID).await;
+    create_test_record(&pool, "other-id", OTHER_TENANT_ID).await;
+
+    let mut transaction = begin_tenant_transaction(&pool, TEST_TENANT_ID)
+      .await
+      .unwrap();
+
+    // There is deliberately no tenant filter in this query.
+    let todo_ids = query!("select todo_id from todos")
+      .fetch_all(&mut *transaction)
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|r| r.todo_id)
+      .collect::<Vec<_>>();
+
+    assert_eq!(todo_ids, vec!["test-id".to_string()]);
+  });
+}
+
+#[test]
+pub fn queries_cannot_modify_rows_of_other_tenants() {
+  with_test_database(|pool| async move {
+    create_test_record(&pool, "other-id", OTHER_TENANT_ID).await;
+
+    let mut transaction = begin_tenant_transaction(&pool, TEST_TENANT_ID)
+      .await
+      .unwrap();
+
+    let updated = query!("update todos set title = 'updated-title'")
+      .execute(&mut *transaction)
+      .await
+      .unwrap();
+    assert_eq!(updated.rows_affected(), 0);
+
+    let deleted = query!("delete from todos")
+      .execute(&mut *transaction)
+      .await
+      .unwrap();
+    assert_eq!(deleted.rows_affected(), 0);
+
+    transaction.commit().await.unwrap();
+
+    let title = query!("select title from todos where todo_id = 'other-id'")
+      .fetch_one(&pool)
+      .await
+      .unwrap()
+      .title;
+    assert_eq!(title, "test-title");
+  });
+}
+
+#[test]
+pub fn queries_cannot_insert_rows_into_other_tenants() {
+  with_test_database(|pool| async move {
+    let mut transaction = begin_tenant_transaction(&pool, TEST_TENANT_ID)
+      .await
+      .unwrap();
+
+    let result = query!(
+      r#"
+      insert into todos (title, description, owner_id, tenant_id)
+      values ('test-title', 'test-description', 'test-user', $1)
+      "#,
+      OTHER_TENANT_ID
+    )
+    .execute(&mut *transaction)
+    .await;
+    assert!(result.is_err());
+  });
+}
+
+#[test]
+pub fn inserted_rows_default_to_the_current_tenant() {
+  with_test_database(|pool| async move {
+    let mut transaction = begin_tenant_transaction(&pool, TEST_TENANT_ID)
+      .await
+      .unwrap();
+
+    let tenant_id = query!(
+      r#"
+      insert into todos (title, description, owner_id)
+      values ('test-title', 'test-description', 'test-user')
+      returning tenant_id
+      "#
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .unwrap()
+    .tenant_id;
+    assert_eq!(tenant_id, TEST_TENANT_ID);
+  });
+}
+
+#[test]
+pub fn queries_without_a_tenant_see_no_rows() {
+  with_test_database(|pool| async move {
+    create_test_record(&pool, "test-id", TEST_TENANT_ID).await;
+
+    let mut transaction = pool.begin().await.unwrap();
+    query!("set local role todos_tenant")
+      .execute(&mut *transaction)
+      .await
+      .unwrap();
+
+    let count = query!(r#"select count(*) as "count!" from todos"#)
+      .fetch_one(&mut *transaction)
+      .await
+      .unwrap()
+      .count;
+    assert_eq!(count, 0);
+  });
+}
+
+#[test]
+pub fn todo_ids_of_other_tenants_can_be_reused() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+      let mut other_client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(OTHER_TENANT_ID, TEST_USER_ID),
+      );
+
+      client
+        .create_todo(create_todo_request("test-title"))
+        .await
+        .unwrap();
+
+      // The other tenant cannot tell that the ID is used in this tenant.
+      let other_todo = other_client
+        .create_todo(create_todo_request("other-title"))
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(other_todo.todo_id, "test-id");
+      assert_eq!(other_todo.title, "other-title");
+
+      let todo = client
+        .get_todo(proto::v1::todos::GetTodoRequest {
+          todo_id: "test-id".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.title, "test-title");
+
+      let status = client
+        .create_todo(create_todo_request("duplicate-title"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::AlreadyExists);
+      assert_eq!(status.message(), "Todo with id test-id already exists");
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+fn create_todo_request(title: &str) -> proto::v1::todos::CreateTodoRequest {
+  proto::v1::todos::CreateTodoRequest {
+    todo: Some(proto::v1::todos::Todo {
+      todo_id: "test-id".to_string(),
+      title: title.to_string(),
+      ..Default::default()
+    }),
+    ..Default::default()
+  }
+}
+
+async fn create_test_record(pool: &PgPool, todo_id: &str, tenant_id: &str) {
+  query!(
+    r#"
+    insert into todos
+      (todo_id, title, description, completed, owner_id, tenant_id)
+    values ($1, 'test-title', 'test-description', false, 'test-user', $2)
+    "#,
+    todo_id,
+    tenant_id
+  )
+  .execute(pool)
+  .await
+  .unwrap();
+}
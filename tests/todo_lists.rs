@@ -0,0 +1,358 @@
+mod common;
+
+use crate::common::create_test_server;
+use common::as_user;
+use common::with_test_database;
+use prost_types::FieldMask;
+use todos_service::proto::v1::todo_lists::todo_list_service_client::TodoListServiceClient;
+use todos_service::proto::v1::todo_lists::CreateTodoListRequest;
+use todos_service::proto::v1::todo_lists::DeleteTodoListRequest;
+use todos_service::proto::v1::todo_lists::GetTodoListRequest;
+use todos_service::proto::v1::todo_lists::ListTodoListsRequest;
+use todos_service::proto::v1::todo_lists::TodoList;
+use todos_service::proto::v1::todo_lists::UpdateTodoListRequest;
+use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
+use todos_service::proto::v1::todos::CreateTodoRequest;
+use todos_service::proto::v1::todos::DeleteTodoRequest;
+use todos_service::proto::v1::todos::GetTodoRequest;
+use todos_service::proto::v1::todos::ListTodosRequest;
+use todos_service::proto::v1::todos::Todo;
+use todos_service::proto::v1::todos::UpdateTodoRequest;
+use todos_service::services::todo_lists::TodoListServiceHandler;
+use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::codegen::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::transport::Channel;
+use tonic::Code;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const TEST_USER_ID: &str = "test-user";
+
+/// Create a list with the given ID and display name.
+async fn create_list<T: Interceptor>(
+  client: &mut TodoListServiceClient<InterceptedService<Channel, T>>,
+  list_id: &str,
+  display_name: &str,
+) -> Result<TodoList, tonic::Status> {
+  let request = CreateTodoListRequest {
+    todo_list: Some(TodoList {
+      display_name: display_name.to_string(),
+      ..Default::default()
+    }),
+    todo_list_id: list_id.to_string(),
+  };
+
+  Ok(
+    client
+      .create_todo_list(request)
+      .await?
+      .into_inner()
+      .todo_list
+      .unwrap(),
+  )
+}
+
+/// Create a todo with the given ID in the given parent list.
+async fn create_todo<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  parent: &str,
+  todo_id: &str,
+) -> Result<Todo, tonic::Status> {
+  let request = CreateTodoRequest {
+    todo: Some(Todo {
+      todo_id: todo_id.to_string(),
+      title: todo_id.to_string(),
+      ..Default::default()
+    }),
+    parent: parent.to_string(),
+    ..Default::default()
+  };
+
+  Ok(
+    client
+      .create_todo(request)
+      .await?
+      .into_inner()
+      .todo
+      .unwrap(),
+  )
+}
+
+/// List the names of the todos in the given parent list.
+async fn list_todo_names<T: Interceptor>(
+  client: &mut TodoServiceClient<InterceptedService<Channel, T>>,
+  parent: &str,
+) -> Result<Vec<String>, tonic::Status> {
+  let request = ListTodosRequest {
+    parent: parent.to_string(),
+    ..Default::default()
+  };
+
+  Ok(
+    client
+      .list_todos(request)
+      .await?
+      .into_inner()
+      .todos
+      .into_iter()
+      .map(|todo| todo.name)
+      .collect(),
+  )
+}
+
+/// List the names of the caller's lists.
+async fn list_list_names<T: Interceptor>(
+  client: &mut TodoListServiceClient<InterceptedService<Channel, T>>,
+  show_archived: bool,
+) -> Vec<String> {
+  client
+    .list_todo_lists(ListTodoListsRequest { show_archived })
+    .await
+    .unwrap()
+    .into_inner()
+    .todo_lists
+    .into_iter()
+    .map(|list| list.name)
+    .collect()
+}
+
+#[test]
+pub fn todos_are_addressed_by_their_list() {
+  with_test_database(|pool| async move {
+    let (todo_server_future, todo_channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+    let (list_server_future, list_channel) =
+      create_test_server(TodoListServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut todos = TodoServiceClient::with_interceptor(
+        todo_channel.clone(),
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+      let mut lists = TodoListServiceClient::with_interceptor(
+        list_channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      // Todos without a parent are created in the inbox, which is created
+      // with the first of them.
+      let todo = create_todo(&mut todos, "", "milk").await.unwrap();
+      assert_eq!(todo.name, "lists/inbox/todos/milk");
+      assert_eq!(list_list_names(&mut lists, false).await, ["lists/inbox"]);
+
+      let list = create_list(&mut lists, "work", "Work").await.unwrap();
+      assert_eq!(list.name, "lists/work");
+      let todo = create_todo(&mut todos, "lists/work", "report")
+        .await
+        .unwrap();
+      assert_eq!(todo.name, "lists/work/todos/report");
+
+      assert_eq!(
+        list_todo_names(&mut todos, "lists/work").await.unwrap(),
+        ["lists/work/todos/report"]
+      );
+      assert_eq!(
+        list_todo_names(&mut todos, "lists/-").await.unwrap().len(),
+        2
+      );
+      assert_eq!(list_todo_names(&mut todos, "").await.unwrap().len(), 2);
+      let status = list_todo_names(&mut todos, "lists/unknown")
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = create_todo(&mut todos, "lists/unknown", "lost")
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+
+      // Todos can be addressed by name, which must include their own list.
+      let get = |name: &str| GetTodoRequest {
+        name: name.to_string(),
+        ..Default::default()
+      };
+      let todo = todos
+        .get_todo(get("lists/work/todos/report"))
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.todo_id, "report");
+      let status = todos
+        .get_todo(get("lists/inbox/todos/report"))
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      let status = todos.get_todo(get("todos/report")).await.unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+
+      let todo = todos
+        .update_todo(UpdateTodoRequest {
+          todo: Some(Todo {
+            name: "lists/work/todos/report".to_string(),
+            title: "Write the report".to_string(),
+            ..Default::default()
+          }),
+          update_mask: Some(FieldMask {
+            paths: vec!["title".to_string()],
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.title, "Write the report");
+
+      todos
+        .delete_todo(DeleteTodoRequest {
+          name: "lists/work/todos/report".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap();
+      assert!(list_todo_names(&mut todos, "lists/work")
+        .await
+        .unwrap()
+        .is_empty());
+
+      // Other users cannot see or add to the caller's lists.
+      let mut other_todos = TodoServiceClient::with_interceptor(
+        todo_channel,
+        as_user(TEST_TENANT_ID, "other-user"),
+      );
+      let status = create_todo(&mut other_todos, "lists/work", "intruder")
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+    };
+
+    tokio::select! {
+      _ = todo_server_future => panic!("server returned first"),
+      _ = list_server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn todo_lists_can_be_managed() {
+  with_test_database(|pool| async move {
+    let (todo_server_future, todo_channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+    let (list_server_future, list_channel) =
+      create_test_server(TodoListServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut todos = TodoServiceClient::with_interceptor(
+        todo_channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+      let mut lists = TodoListServiceClient::with_interceptor(
+        list_channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create_list(&mut lists, "home", "Home").await.unwrap();
+      let status = create_list(&mut lists, "home", "Home again")
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::AlreadyExists);
+      for invalid in ["Home", "1st", "trailing-", "has_underscore"] {
+        let status = create_list(&mut lists, invalid, "Invalid")
+          .await
+          .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument, "{:?}", invalid);
+      }
+      let status = create_list(&mut lists, "blank", " ").await.unwrap_err();
+      assert_eq!(status.code(), Code::InvalidArgument);
+      let generated = create_list(&mut lists, "", "Generated").await.unwrap();
+      assert!(generated.name.starts_with("lists/"));
+
+      // Archived lists are hidden unless requested, and do not accept todos.
+      let list = lists
+        .update_todo_list(UpdateTodoListRequest {
+          todo_list: Some(TodoList {
+            name: "lists/home".to_string(),
+            description: "Chores".to_string(),
+            archived: true,
+            ..Default::default()
+          }),
+          update_mask: Some(FieldMask {
+            paths: vec!["archived".to_string(), "description".to_string()],
+          }),
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo_list
+        .unwrap();
+      assert_eq!(list.display_name, "Home");
+      assert_eq!(list.description, "Chores");
+      assert!(list.archived);
+      assert_eq!(
+        list_list_names(&mut lists, false).await,
+        [generated.name.as_str()]
+      );
+      assert_eq!(list_list_names(&mut lists, true).await.len(), 2);
+      let status = create_todo(&mut todos, "lists/home", "dishes")
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::FailedPrecondition);
+
+      // A list with todos can only be deleted with its todos.
+      create_todo(&mut todos, &generated.name, "kept")
+        .await
+        .unwrap();
+      let delete = |force: bool| DeleteTodoListRequest {
+        name: generated.name.clone(),
+        force,
+      };
+      let status = lists.delete_todo_list(delete(false)).await.unwrap_err();
+      assert_eq!(status.code(), Code::FailedPrecondition);
+      lists.delete_todo_list(delete(true)).await.unwrap();
+      let status = lists
+        .get_todo_list(GetTodoListRequest {
+          name: generated.name.clone(),
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+      assert!(list_todo_names(&mut todos, "").await.unwrap().is_empty());
+
+      // An empty list can be deleted without force.
+      lists
+        .delete_todo_list(DeleteTodoListRequest {
+          name: "lists/home".to_string(),
+          force: false,
+        })
+        .await
+        .unwrap();
+      assert!(list_list_names(&mut lists, true).await.is_empty());
+    };
+
+    tokio::select! {
+      _ = todo_server_future => panic!("server returned first"),
+      _ = list_server_future => panic!("server returned first"),
+      _ = request_future => (),
+    }
+  });
+}
@@ -1,6 +1,7 @@
 mod common;
 
 use crate::common::create_test_server;
+use common::as_user;
 use common::with_test_database;
 use sqlx::query;
 use sqlx::query_as;
@@ -10,24 +11,43 @@ use todos_service::proto::v1::todos::todo_service_client::TodoServiceClient;
 use todos_service::proto::v1::todos::ListTodosRequest;
 use todos_service::services::todos::TodoRow;
 use todos_service::services::todos::TodoServiceHandler;
+use todos_service::services::ServiceOptions;
+use tonic::Code;
+use tonic_types::StatusExt;
+
+const TEST_TENANT_ID: &str = "test-tenant";
+const OTHER_TENANT_ID: &str = "other-tenant";
+const TEST_USER_ID: &str = "test-user";
+const OTHER_USER_ID: &str = "other-user";
 
 #[test]
 pub fn list_todos() {
   with_test_database(|pool| async move {
     let (server_future, channel) =
-      create_test_server(TodoServiceHandler::create_server(pool.clone())).await;
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
 
     let request_future = async {
-      let mut client = TodoServiceClient::new(channel);
-
-      let response_before =
-        client.list_todos(ListTodosRequest {}).await.unwrap();
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      let response_before = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
       assert_eq!(response_before.into_inner().todos.len(), 0);
 
       create_test_record(&pool).await;
 
-      let response_after =
-        client.list_todos(ListTodosRequest {}).await.unwrap();
+      let response_after = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
       assert_eq!(response_after.into_inner().todos.len(), 1);
     };
 
@@ -43,10 +63,17 @@ pub fn list_todos() {
 pub fn create_todo() {
   with_test_database(|pool| async move {
     let (server_future, channel) =
-      create_test_server(TodoServiceHandler::create_server(pool.clone())).await;
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
 
     let request_future = async {
-      let mut client = TodoServiceClient::new(channel);
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
 
       let exists_before = does_test_record_exist(&pool).await;
       assert!(!exists_before);
@@ -60,7 +87,10 @@ pub fn create_todo() {
             completed: false,
             created_at: None,
             updated_at: None,
+            owner_id: String::new(),
+            ..Default::default()
           }),
+          ..Default::default()
         })
         .await;
 
@@ -68,6 +98,9 @@ pub fn create_todo() {
 
       let exists_after = does_test_record_exist(&pool).await;
       assert!(exists_after);
+
+      let record = select_test_record(&pool).await.unwrap();
+      assert_eq!(record.owner_id, TEST_USER_ID);
     };
 
     // Wait for completion, when the client request future completes
@@ -82,10 +115,17 @@ pub fn create_todo() {
 pub fn update_todo() {
   with_test_database(|pool| async move {
     let (server_future, channel) =
-      create_test_server(TodoServiceHandler::create_server(pool.clone())).await;
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
 
     let request_future = async {
-      let mut client = TodoServiceClient::new(channel);
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
 
       create_test_record(&pool).await;
 
@@ -98,11 +138,13 @@ pub fn update_todo() {
             completed: false,
             created_at: None,
             updated_at: None,
+            owner_id: String::new(),
+            ..Default::default()
           }),
           update_mask: Some(prost_types::FieldMask {
             paths: vec!["title".to_string()],
-            ..Default::default()
           }),
+          ..Default::default()
         })
         .await;
 
@@ -123,14 +165,111 @@ pub fn update_todo() {
   })
 }
 
+#[test]
+pub fn complete_and_reopen_todo() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create_test_record(&pool).await;
+
+      let complete = || proto::v1::todos::CompleteTodoRequest {
+        todo_id: "test-id".to_string(),
+        ..Default::default()
+      };
+      let todo = client
+        .complete_todo(complete())
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert!(todo.completed);
+      let complete_time = todo.complete_time.unwrap();
+
+      // Completing the todo again does not change when it was completed.
+      let todo = client
+        .complete_todo(complete())
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.complete_time, Some(complete_time));
+
+      let list = |completed_after| ListTodosRequest {
+        completed_after: Some(completed_after),
+        order_by: "complete_time desc".to_string(),
+        ..Default::default()
+      };
+      let response = client.list_todos(list(complete_time)).await.unwrap();
+      assert_eq!(response.into_inner().todos.len(), 1);
+      let later = prost_types::Timestamp {
+        seconds: complete_time.seconds + 1,
+        nanos: 0,
+      };
+      let response = client.list_todos(list(later)).await.unwrap();
+      assert!(response.into_inner().todos.is_empty());
+
+      let todo = client
+        .reopen_todo(proto::v1::todos::ReopenTodoRequest {
+          name: "lists/inbox/todos/test-id".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert!(!todo.completed);
+      assert_eq!(todo.complete_time, None);
+
+      let record = select_test_record(&pool).await.unwrap();
+      assert!(!record.completed);
+      assert!(record.complete_time.is_none());
+
+      let status = client
+        .complete_todo(proto::v1::todos::CompleteTodoRequest {
+          todo_id: "unknown".to_string(),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::NotFound);
+    };
+
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  })
+}
+
 #[test]
 pub fn delete_todo() {
   with_test_database(|pool| async move {
     let (server_future, channel) =
-      create_test_server(TodoServiceHandler::create_server(pool.clone())).await;
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
 
     let request_future = async {
-      let mut client = TodoServiceClient::new(channel);
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
 
       create_test_record(&pool).await;
 
@@ -140,6 +279,7 @@ pub fn delete_todo() {
       let response = client
         .delete_todo(proto::v1::todos::DeleteTodoRequest {
           todo_id: "test-id".to_string(),
+          ..Default::default()
         })
         .await;
 
@@ -157,6 +297,249 @@ pub fn delete_todo() {
   })
 }
 
+#[test]
+pub fn unauthenticated_request_is_rejected() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::new(channel);
+
+      let response = client.list_todos(ListTodosRequest::default()).await;
+      assert_eq!(response.unwrap_err().code(), Code::Unauthenticated);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn todos_of_other_owners_are_not_accessible() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, OTHER_USER_ID),
+      );
+
+      create_test_record(&pool).await;
+
+      let list_response = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
+      assert_eq!(list_response.into_inner().todos.len(), 0);
+
+      let get_response = client
+        .get_todo(proto::v1::todos::GetTodoRequest {
+          todo_id: "test-id".to_string(),
+          ..Default::default()
+        })
+        .await;
+      assert_eq!(get_response.unwrap_err().code(), Code::NotFound);
+
+      let update_response = client
+        .update_todo(proto::v1::todos::UpdateTodoRequest {
+          todo: Some(proto::v1::todos::Todo {
+            todo_id: "test-id".to_string(),
+            title: "updated-title".to_string(),
+            ..Default::default()
+          }),
+          update_mask: Some(prost_types::FieldMask {
+            paths: vec!["title".to_string()],
+          }),
+          ..Default::default()
+        })
+        .await;
+      assert_eq!(update_response.unwrap_err().code(), Code::NotFound);
+
+      let delete_response = client
+        .delete_todo(proto::v1::todos::DeleteTodoRequest {
+          todo_id: "test-id".to_string(),
+          ..Default::default()
+        })
+        .await;
+      assert_eq!(delete_response.unwrap_err().code(), Code::NotFound);
+
+      let record = select_test_record(&pool).await.unwrap();
+      assert_eq!(record.title, "test-title");
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn todo_ids_of_other_owners_can_be_reused() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, OTHER_USER_ID),
+      );
+
+      create_test_record(&pool).await;
+
+      // The other owner cannot tell that the ID is used by the test user.
+      let todo = client
+        .create_todo(proto::v1::todos::CreateTodoRequest {
+          todo: Some(proto::v1::todos::Todo {
+            todo_id: "test-id".to_string(),
+            title: "other-title".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .todo
+        .unwrap();
+      assert_eq!(todo.todo_id, "test-id");
+      assert_eq!(todo.owner_id, OTHER_USER_ID);
+
+      let delete_response = client
+        .delete_todo(proto::v1::todos::DeleteTodoRequest {
+          todo_id: "test-id".to_string(),
+          ..Default::default()
+        })
+        .await;
+      assert!(delete_response.is_ok());
+
+      let record = select_test_record(&pool).await.unwrap();
+      assert_eq!(record.owner_id, TEST_USER_ID);
+      assert_eq!(record.title, "test-title");
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn todos_of_other_tenants_are_not_accessible() {
+  with_test_database(|pool| async move {
+    let (server_future, channel) =
+      create_test_server(TodoServiceHandler::create_server(
+        pool.clone(),
+        &ServiceOptions::default(),
+      ))
+      .await;
+
+    let request_future = async {
+      // The same user ID in a different tenant is a different user.
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(OTHER_TENANT_ID, TEST_USER_ID),
+      );
+
+      create_test_record(&pool).await;
+
+      let list_response = client
+        .list_todos(ListTodosRequest::default())
+        .await
+        .unwrap();
+      assert_eq!(list_response.into_inner().todos.len(), 0);
+
+      let get_response = client
+        .get_todo(proto::v1::todos::GetTodoRequest {
+          todo_id: "test-id".to_string(),
+          ..Default::default()
+        })
+        .await;
+      assert_eq!(get_response.unwrap_err().code(), Code::NotFound);
+
+      let delete_response = client
+        .delete_todo(proto::v1::todos::DeleteTodoRequest {
+          todo_id: "test-id".to_string(),
+          ..Default::default()
+        })
+        .await;
+      assert_eq!(delete_response.unwrap_err().code(), Code::NotFound);
+
+      assert!(does_test_record_exist(&pool).await);
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
+#[test]
+pub fn create_todo_over_quota_is_rejected() {
+  with_test_database(|pool| async move {
+    let options = ServiceOptions {
+      max_todos_per_owner: Some(1),
+      ..Default::default()
+    };
+    let (server_future, channel) = create_test_server(
+      TodoServiceHandler::create_server(pool.clone(), &options),
+    )
+    .await;
+
+    let request_future = async {
+      let mut client = TodoServiceClient::with_interceptor(
+        channel,
+        as_user(TEST_TENANT_ID, TEST_USER_ID),
+      );
+
+      create_test_record(&pool).await;
+
+      let status = client
+        .create_todo(proto::v1::todos::CreateTodoRequest {
+          todo: Some(proto::v1::todos::Todo {
+            title: "test-title".to_string(),
+            ..Default::default()
+          }),
+          ..Default::default()
+        })
+        .await
+        .unwrap_err();
+      assert_eq!(status.code(), Code::ResourceExhausted);
+      assert!(status.get_details_quota_failure().is_some());
+    };
+
+    // Wait for completion, when the client request future completes
+    tokio::select! {
+        _ = server_future => panic!("server returned first"),
+        _ = request_future => (),
+    }
+  });
+}
+
 async fn does_test_record_exist(pool: &PgPool) -> bool {
   query!(
     r#"
@@ -175,9 +558,12 @@ async fn does_test_record_exist(pool: &PgPool) -> bool {
 async fn create_test_record(pool: &PgPool) {
   query!(
     r#"
-    insert into todos (todo_id, title, description, completed)
-    values ('test-id', 'test-title', 'test-description', false)
-    "#
+    insert into todos
+      (todo_id, title, description, completed, owner_id, tenant_id)
+    values ('test-id', 'test-title', 'test-description', false, $1, $2)
+    "#,
+    TEST_USER_ID,
+    TEST_TENANT_ID
   )
   .execute(pool)
   .await
@@ -193,7 +579,31 @@ async fn select_test_record(pool: &PgPool) -> Option<TodoRow> {
            description,
            completed,
            created_at,
-           updated_at
+           updated_at,
+           owner_id,
+           tenant_id,
+           due_time,
+           reminder_time,
+           recurrence_rule,
+           series_id,
+           priority,
+           position,
+           list_id,
+           parent_todo_id,
+           complete_time,
+           array(
+             select name
+             from todo_labels
+             where todo_labels.owner_id = todos.owner_id
+               and todo_labels.todo_id = todos.todo_id
+             order by name
+           ) as "labels!",
+           (
+             select count(*)::integer
+             from todo_comments
+             where todo_comments.owner_id = todos.owner_id
+               and todo_comments.todo_id = todos.todo_id
+           ) as "comment_count!"
     from todos
     where todo_id = 'test-id'
     "#